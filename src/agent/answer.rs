@@ -2,19 +2,29 @@ use std::{sync::Arc, time::Duration};
 
 use crate::{
     agent::{
+        cache::ToolCache,
         command::{Elevate, Explore},
+        custom_tool::CustomTool,
+        env::ChildEnv,
+        grant::{ElevateGrant, ElevateGrants},
+        limits::{Budget, LimitHit},
+        parallel::ParallelTools,
+        policy::CommandPolicy,
+        script_hook::ScriptHooks,
+        search_favorites::SearchFavorites,
         stream::{self, StreamOutcome},
         submit_commands::{CommandItem, CommandSubmissions, SubmitCommands},
     },
-    config::{AppConfig, profile::AnswerProfile},
+    config::{AppConfig, LlmConfig, profile::AnswerProfile},
     error::{Error, Result},
+    favorites::FavoriteStore,
     shell::Shell,
 };
 use reqwest::header::HeaderMap;
 use rig_core::{
     agent::{
         Agent as RigAgent, HookAction, InvalidToolCallContext, InvalidToolCallHookAction,
-        PromptHook,
+        PromptHook, ToolCallHookAction,
     },
     client::CompletionClient,
     message::Message,
@@ -36,12 +46,24 @@ struct RetryContext {
     history: Vec<Message>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 struct HarnessHook {
     retry_context: Arc<Mutex<Option<RetryContext>>>,
+    budget: Arc<Budget>,
+    parallel: Arc<ParallelTools>,
+    scripts: Arc<ScriptHooks>,
 }
 
 impl HarnessHook {
+    fn new(budget: Budget, parallel: ParallelTools, scripts: Arc<ScriptHooks>) -> Self {
+        Self {
+            retry_context: Arc::default(),
+            budget: Arc::new(budget),
+            parallel: Arc::new(parallel),
+            scripts,
+        }
+    }
+
     async fn clear_retry_context(&self) {
         *self.retry_context.lock().await = None;
     }
@@ -68,6 +90,64 @@ impl PromptHook<CompletionModel> for HarnessHook {
             prompt: prompt.clone(),
             history: history.to_vec(),
         });
+        self.parallel.reset();
+        match self.budget.start_turn() {
+            Some(hit) => HookAction::terminate(format!("Agent limit reached: {hit}.")),
+            None => HookAction::cont(),
+        }
+    }
+
+    async fn on_tool_call_delta(
+        &self,
+        _tool_call_id: &str,
+        internal_call_id: &str,
+        tool_name: Option<&str>,
+        tool_call_delta: &str,
+    ) -> HookAction {
+        self.parallel.record(internal_call_id, tool_name, tool_call_delta);
+        HookAction::cont()
+    }
+
+    async fn on_tool_call(
+        &self,
+        tool_name: &str,
+        _tool_call_id: Option<String>,
+        internal_call_id: &str,
+        args: &str,
+    ) -> ToolCallHookAction {
+        if let Some(hit) = self.budget.start_tool(tool_name, internal_call_id) {
+            self.parallel.statuses().done(internal_call_id);
+            return ToolCallHookAction::skip(format!(
+                "Tool call skipped: limit {hit} reached. No more tools can be used for this request."
+            ));
+        }
+        // 已在后台并发执行的调用直接返回其结果, rig 会把它原样作为工具结果.
+        // 这些调用在提交到后台前已经过脚本钩子.
+        if let Some(result) = self.parallel.take(tool_name, internal_call_id, args).await {
+            self.budget.finish_tool(internal_call_id);
+            return ToolCallHookAction::skip(result);
+        }
+        let verdict = self.scripts.before_tool(tool_name, args);
+        match self.scripts.apply(tool_name, verdict).await {
+            Some(result) => {
+                self.budget.finish_tool(internal_call_id);
+                self.parallel.statuses().done(internal_call_id);
+                ToolCallHookAction::skip(result)
+            }
+            None => ToolCallHookAction::cont(),
+        }
+    }
+
+    async fn on_tool_result(
+        &self,
+        _tool_name: &str,
+        _tool_call_id: Option<String>,
+        internal_call_id: &str,
+        _args: &str,
+        _result: &str,
+    ) -> HookAction {
+        self.budget.finish_tool(internal_call_id);
+        self.parallel.statuses().done(internal_call_id);
         HookAction::cont()
     }
 
@@ -98,12 +178,60 @@ impl PromptHook<CompletionModel> for HarnessHook {
     }
 }
 
+/// 按 `[llm]` 配置创建模型客户端, 只对瞬时 HTTP 错误自动重试.
+pub(super) fn completion_model(llm: &LlmConfig) -> Result<CompletionModel> {
+    let base_host = reqwest::Url::parse(&llm.base_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned));
+    let mut http_client_builder = reqwest::Client::builder()
+        .default_headers({
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                reqwest::header::CONTENT_TYPE,
+                "application/json".parse().expect("valid content type"),
+            );
+            headers
+        });
+    if let Some(host) = base_host {
+        // Chat Completions 请求没有本地副作用, 只重试瞬时 HTTP 错误.
+        http_client_builder = http_client_builder.retry(
+            reqwest::retry::for_host(host)
+                .max_retries_per_request(2)
+                .no_budget()
+                .classify_fn(|request| {
+                    if request.status().is_some_and(|status| {
+                        status.is_server_error()
+                            || matches!(
+                                status,
+                                reqwest::StatusCode::REQUEST_TIMEOUT
+                                    | reqwest::StatusCode::TOO_MANY_REQUESTS
+                            )
+                    }) {
+                        request.retryable()
+                    } else {
+                        request.success()
+                    }
+                }),
+        );
+    }
+    let http_client = http_client_builder.build()?;
+    Ok(openai::Client::<reqwest::Client>::builder()
+        .base_url(&llm.base_url)
+        .api_key(&llm.api_key)
+        .http_client(http_client)
+        .build()?
+        .completions_api()
+        .completion_model(&llm.model))
+}
+
 pub struct AnswerAgent {
     profile: AnswerProfile,
     agent: RigAgent<CompletionModel, HarnessHook>,
     finalizer: RigAgent<CompletionModel>,
     submissions: Arc<CommandSubmissions>,
+    grants: Arc<ElevateGrants>,
     hook: HarnessHook,
+    scripts: Arc<ScriptHooks>,
     scroll_char_speed_limit: usize,
 }
 
@@ -112,6 +240,8 @@ pub struct AnswerAgentResponse {
     pub messages: Vec<Message>,
     pub final_text: String,
     pub commands: Vec<CommandItem>,
+    /// 本会话中记住的 elevate 授权.
+    pub grants: Vec<ElevateGrant>,
 }
 
 #[derive(Debug)]
@@ -128,14 +258,16 @@ impl ModifyOption {
 
 #[bon::bon]
 impl AnswerAgent {
+    /// `favorites` 为 [`Some`] 时注册 `search_favorites` 工具.
     #[builder]
     pub fn builder(
         os: String,
         shell: &Shell,
         profile: AnswerProfile,
         config: AppConfig,
+        favorites: Option<FavoriteStore>,
     ) -> Result<Self> {
-        Self::new(os, shell, profile, config)
+        Self::new(os, shell, profile, config, favorites)
     }
 }
 
@@ -143,7 +275,7 @@ impl AnswerAgent {
     #[tracing::instrument(
         name = "AnswerAgent",
         level = "info",
-        skip(profile, config, shell),
+        skip(profile, config, shell, favorites),
         fields(shell = shell.name())
     )]
     pub fn new(
@@ -151,49 +283,9 @@ impl AnswerAgent {
         shell: &Shell,
         profile: AnswerProfile,
         config: AppConfig,
+        favorites: Option<FavoriteStore>,
     ) -> Result<Self> {
-        let base_host = reqwest::Url::parse(&config.llm.base_url)
-            .ok()
-            .and_then(|url| url.host_str().map(str::to_owned));
-        let mut http_client_builder = reqwest::Client::builder()
-            .default_headers({
-                let mut headers = HeaderMap::new();
-                headers.insert(
-                    reqwest::header::CONTENT_TYPE,
-                    "application/json".parse().expect("valid content type"),
-                );
-                headers
-            });
-        if let Some(host) = base_host {
-            // Chat Completions 请求没有本地副作用, 只重试瞬时 HTTP 错误.
-            http_client_builder = http_client_builder.retry(
-                reqwest::retry::for_host(host)
-                    .max_retries_per_request(2)
-                    .no_budget()
-                    .classify_fn(|request| {
-                        if request.status().is_some_and(|status| {
-                            status.is_server_error()
-                                || matches!(
-                                    status,
-                                    reqwest::StatusCode::REQUEST_TIMEOUT
-                                        | reqwest::StatusCode::TOO_MANY_REQUESTS
-                                )
-                        }) {
-                            request.retryable()
-                        } else {
-                            request.success()
-                        }
-                    }),
-            );
-        }
-        let http_client = http_client_builder.build()?;
-        let model = openai::Client::<reqwest::Client>::builder()
-            .base_url(&config.llm.base_url)
-            .api_key(&config.llm.api_key)
-            .http_client(http_client)
-            .build()?
-            .completions_api()
-            .completion_model(&config.llm.model);
+        let model = completion_model(&config.llm)?;
 
         let output_n = config.agent.answer.output_n as usize;
         let scroll_char_speed_limit = config.agent.scroll_char_speed_limit;
@@ -205,8 +297,69 @@ impl AnswerAgent {
             .text_lang(&config.agent.language)
             .maybe_max_tokens(config.llm.max_tokens)
             .output_n(config.agent.answer.output_n)
+            .explore(config.agent.use_tool_explore)
+            .elevate(config.agent.use_tool_elevate)
+            .favorites(favorites.is_some())
             .finish();
-        let hook = HarnessHook::default();
+        let shell_path = shell.path().to_path_buf();
+        let policy = Arc::new(CommandPolicy::new(&config.agent.policy)?);
+        let cache = Arc::new(ToolCache::new(config.agent.cache));
+        let grants = Arc::new(ElevateGrants::default());
+        let env = ChildEnv::new(&shell_path, &config.agent.env);
+        let explore = Explore::new(
+            shell_path.clone(),
+            policy.clone(),
+            cache.clone(),
+            config.agent.sandbox.clone(),
+            env.clone(),
+        );
+        let elevate = Elevate::new(
+            shell_path.clone(),
+            policy,
+            grants.clone(),
+            cache,
+            config.agent.preview,
+            config.agent.interactive_elevate,
+            env.elevate(),
+            &config.agent.sandbox.resources,
+        );
+        let custom_tools =
+            CustomTool::from_configs(&config.agent.tools, shell.name(), &explore, &elevate)?;
+        let explore = config.agent.use_tool_explore.then_some(explore);
+        let elevate = config.agent.use_tool_elevate.then_some(elevate);
+        let favorites = favorites.map(SearchFavorites::new);
+        // 脚本钩子改写参数后自行执行工具, 因此需要一份相同的工具.
+        let tool_set = || {
+            let mut tools: Vec<Box<dyn ToolDyn>> = Vec::new();
+            if let Some(explore) = &explore {
+                tools.push(Box::new(explore.clone()));
+            }
+            if let Some(elevate) = &elevate {
+                tools.push(Box::new(elevate.clone()));
+            }
+            if let Some(favorites) = &favorites {
+                tools.push(Box::new(favorites.clone()));
+            }
+            for tool in &custom_tools {
+                tools.push(Box::new(tool.clone()));
+            }
+            tools.push(Box::new(SubmitCommands::new(
+                shell_path.clone(),
+                output_n,
+                submissions.clone(),
+            )));
+            tools
+        };
+        let scripts = Arc::new(ScriptHooks::load(&config.agent.hooks, tool_set())?);
+        let hook = HarnessHook::new(
+            Budget::new(config.agent.limits),
+            ParallelTools::new(
+                explore.clone(),
+                config.agent.tool_concurrency,
+                scripts.clone(),
+            ),
+            scripts.clone(),
+        );
         let mut builder = rig_core::agent::AgentBuilder::new(model.clone())
             .preamble(&system_prompt)
             .hook(hook.clone());
@@ -217,19 +370,7 @@ impl AnswerAgent {
             builder = builder.temperature(temperature);
         }
 
-        let shell_path = shell.path().to_path_buf();
-        let mut tools: Vec<Box<dyn ToolDyn>> = Vec::new();
-        if config.agent.use_tool_explore {
-            tools.push(Box::new(Explore::new(shell_path.clone())));
-        }
-        if config.agent.use_tool_elevate {
-            tools.push(Box::new(Elevate::new(shell_path.clone())));
-        }
-        tools.push(Box::new(SubmitCommands::new(
-            shell_path,
-            output_n,
-            submissions.clone(),
-        )));
+        let tools = tool_set();
         let agent = builder.tools(tools).build();
 
         let finalizer_prompt = format!(
@@ -251,11 +392,18 @@ impl AnswerAgent {
             agent,
             finalizer: finalizer_builder.build(),
             submissions,
+            grants,
             hook,
+            scripts,
             scroll_char_speed_limit,
         })
     }
 
+    /// 会话内记住的 elevate 授权, 恢复会话时用 [`ElevateGrants::replace`] 还原.
+    pub fn grants(&self) -> &ElevateGrants {
+        &self.grants
+    }
+
     fn retryable_provider_error(error: &Error) -> bool {
         let message = error.to_string();
         message.contains("Invalid status code 408")
@@ -271,6 +419,7 @@ impl AnswerAgent {
     async fn primary_chat(&self, prompt: String, history: Vec<Message>) -> Result<StreamOutcome> {
         let mut next_prompt = Message::user(prompt);
         let mut next_history = history;
+        self.hook.budget.reset();
         for attempt in 0..=PROVIDER_RETRY_ATTEMPTS {
             self.hook.clear_retry_context().await;
             let stream = self
@@ -279,8 +428,27 @@ impl AnswerAgent {
                 .multi_turn(EFFECTIVELY_UNLIMITED_TURNS)
                 .max_invalid_tool_call_retries(UNKNOWN_TOOL_RETRIES)
                 .await;
-            match stream::collect(stream, "Resolving", self.scroll_char_speed_limit).await {
+            let collected = stream::collect(
+                stream,
+                "Resolving",
+                self.scroll_char_speed_limit,
+                Some(self.hook.parallel.statuses()),
+            );
+            let collected = match self.hook.budget.remaining_wall() {
+                Some(remaining) => match tokio::time::timeout(remaining, collected).await {
+                    Ok(collected) => collected,
+                    Err(_) => {
+                        self.hook.budget.expire();
+                        Err(Error::AgentResponse("Agent request timed out.".to_string()))
+                    }
+                },
+                None => collected.await,
+            };
+            match collected {
                 Ok(outcome) => return Ok(outcome),
+                Err(_) if let Some(hit) = self.hook.budget.hit() => {
+                    return self.finalize_after_limit(hit, next_prompt, next_history).await;
+                }
                 Err(error)
                     if attempt < PROVIDER_RETRY_ATTEMPTS
                         && Self::retryable_provider_error(&error) =>
@@ -304,6 +472,22 @@ impl AnswerAgent {
         unreachable!("provider retry loop must return a result")
     }
 
+    /// 触发预算限制后, 用最近一次对话请求的上下文生成最终回答.
+    async fn finalize_after_limit(
+        &self,
+        hit: LimitHit,
+        prompt: Message,
+        history: Vec<Message>,
+    ) -> Result<StreamOutcome> {
+        warn!(limit = %hit, "Agent limit reached, forcing a final answer.");
+        let (prompt, mut history) = match self.hook.take_retry_context().await {
+            Some(context) => (context.prompt, context.history),
+            None => (prompt, history),
+        };
+        history.push(prompt);
+        self.finalize_empty_response(history).await
+    }
+
     async fn finalize_empty_response(&self, history: Vec<Message>) -> Result<StreamOutcome> {
         for attempt in 0..=PROVIDER_RETRY_ATTEMPTS {
             let stream = self
@@ -313,7 +497,7 @@ impl AnswerAgent {
                     history.clone(),
                 )
                 .await;
-            match stream::collect(stream, "Finalizing", self.scroll_char_speed_limit).await {
+            match stream::collect(stream, "Finalizing", self.scroll_char_speed_limit, None).await {
                 Ok(outcome) => return Ok(outcome),
                 Err(error)
                     if attempt < PROVIDER_RETRY_ATTEMPTS
@@ -356,8 +540,9 @@ impl AnswerAgent {
         }
         history.extend(attached_messages);
 
+        let prompt = self.scripts.before_prompt(prompt)?;
         let mut outcome = self.primary_chat(prompt, history).await?;
-        let commands = self.submissions.snapshot().await;
+        let commands = self.scripts.after_submit(self.submissions.snapshot().await)?;
         if outcome.final_text.trim().is_empty() && commands.is_empty() {
             warn!("Agent returned neither final text nor command candidates.");
             outcome = self.finalize_empty_response(outcome.messages).await?;
@@ -368,16 +553,19 @@ impl AnswerAgent {
             }
         }
 
+        let final_text = self.scripts.after_response(outcome.final_text)?;
+
         debug!(usage = ?outcome.usage, "AnswerAgent completed.");
         info!(
             commands = commands.len(),
-            has_text = !outcome.final_text.trim().is_empty(),
+            has_text = !final_text.trim().is_empty(),
             "AnswerAgent produced a response."
         );
         Ok(AnswerAgentResponse {
             messages: outcome.messages,
-            final_text: outcome.final_text,
+            final_text,
             commands,
+            grants: self.grants.snapshot().await,
         })
     }
 }
@@ -0,0 +1,336 @@
+//! 会话内的 explore 结果缓存.
+//!
+//! 模型经常在同一会话中重复执行相同的只读命令 (`git status`, `cargo --help`).
+//! 缓存以命令文本和工作目录为键, 并记录相关文件的修改时间: 工作目录本身, 命令参数中
+//! 出现的路径, 以及所在 git 仓库的 index 与 HEAD. 这些修改时间不变时直接返回上次的结果.
+//! 递归读取目录树的命令 (`rg`, `grep -r`, `find`, `git diff` 等) 的结果取决于任意深处的文件,
+//! 编辑已有的文件不会改变上面这些修改时间, 因此这类命令不缓存. 输出随时间或系统状态变化的命令
+//! (`date`, `ps`, `df`, `docker ps`, 读取 `/proc` 的命令等) 也不缓存.
+//! 批准执行的 elevate 命令可能改变任何文件, 会清空同一目录树下的缓存.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use tokio::sync::RwLock;
+
+use crate::{
+    agent::command::{CommandResult, CommandStatus},
+    shell::parse,
+};
+
+/// 每条命令最多检查的参数路径数.
+const MAX_FINGERPRINT_PATHS: usize = 32;
+const GIT_FILES: [&str; 3] = ["index", "HEAD", "logs/HEAD"];
+/// 总是递归读取目录树的程序.
+const TREE_PROGRAMS: [&str; 7] = ["rg", "ag", "ack", "find", "fd", "tree", "du"];
+/// 读取整个工作区的 git 子命令.
+const TREE_GIT_COMMANDS: [&str; 7] = [
+    "diff", "status", "grep", "ls-files", "stash", "describe", "add",
+];
+/// 让 `grep`, `ls`, `diff` 等程序递归读取的选项. `grep` 的短选项也可以合并, 如 `-rn`.
+const RECURSIVE_FLAGS: [&str; 4] = ["-r", "-R", "--recursive", "--dereference-recursive"];
+
+/// 输出随时间或系统状态变化, 与文件修改时间无关的程序.
+const VOLATILE_PROGRAMS: [&str; 36] = [
+    "date", "uptime", "ps", "pgrep", "pidof", "top", "htop", "free", "df", "vmstat", "iostat",
+    "mpstat", "who", "w", "last", "users", "lsof", "ss", "netstat", "ip", "ifconfig", "sensors",
+    "nvidia-smi", "docker", "podman", "kubectl", "systemctl", "journalctl", "dmesg", "loginctl",
+    "launchctl", "jobs", "env", "printenv", "tmux", "screen",
+];
+/// 内容由内核实时生成的文件系统.
+const VOLATILE_PATHS: [&str; 3] = ["/proc", "/sys", "/dev"];
+
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    command: String,
+    cwd: PathBuf,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    fingerprint: Fingerprint,
+    result: CommandResult,
+}
+
+#[derive(Debug, Default)]
+pub struct ToolCache {
+    enabled: bool,
+    entries: RwLock<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl ToolCache {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            entries: RwLock::default(),
+        }
+    }
+
+    /// 相关文件没有变化时返回上次的结果, 并标记为缓存.
+    pub async fn get(&self, command: &str, cwd: &Path) -> Option<CommandResult> {
+        if !self.enabled {
+            return None;
+        }
+        let key = CacheKey {
+            command: command.to_string(),
+            cwd: cwd.to_path_buf(),
+        };
+        let entries = self.entries.read().await;
+        let entry = entries.get(&key)?;
+        if entry.fingerprint != fingerprint(command, cwd) {
+            return None;
+        }
+        let mut result = entry.result.clone();
+        result.cached = true;
+        Some(result)
+    }
+
+    /// 记录命令结果. 超时, 被拒绝和未能启动 (没有退出码) 的结果不缓存,
+    /// 递归读取目录树和输出随系统状态变化的命令也不缓存.
+    pub async fn insert(&self, command: &str, cwd: &Path, result: &CommandResult) {
+        if !self.enabled
+            || reads_tree(command)
+            || volatile(command)
+            || !matches!(
+                result.status,
+                CommandStatus::Success | CommandStatus::Failed
+            )
+            || result.exit_code.is_none()
+        {
+            return;
+        }
+        let key = CacheKey {
+            command: command.to_string(),
+            cwd: cwd.to_path_buf(),
+        };
+        let entry = CacheEntry {
+            fingerprint: fingerprint(command, cwd),
+            result: result.clone(),
+        };
+        self.entries.write().await.insert(key, entry);
+    }
+
+    /// 清空工作目录位于 `cwd` 之内或之上的缓存.
+    pub async fn invalidate(&self, cwd: &Path) {
+        self.entries
+            .write()
+            .await
+            .retain(|key, _| !key.cwd.starts_with(cwd) && !cwd.starts_with(&key.cwd));
+    }
+}
+
+/// 命令的某个片段是否递归读取目录树, 这样的结果无法用修改时间判断是否过期.
+fn reads_tree(command: &str) -> bool {
+    parse::segments(command).iter().any(|segment| {
+        let words = segment.split_whitespace().collect::<Vec<_>>();
+        match parse::program(segment) {
+            Some("git") => words.iter().any(|word| TREE_GIT_COMMANDS.contains(word)),
+            Some(program) => {
+                TREE_PROGRAMS.contains(&program)
+                    || words.iter().skip(1).any(|word| {
+                        RECURSIVE_FLAGS.contains(word)
+                            || (program.ends_with("grep")
+                                && word.starts_with('-')
+                                && !word.starts_with("--")
+                                && word.contains(['r', 'R']))
+                    })
+            }
+            None => false,
+        }
+    })
+}
+
+/// 命令的某个片段是否运行 [`VOLATILE_PROGRAMS`] 中的程序或读取 [`VOLATILE_PATHS`] 下的文件.
+fn volatile(command: &str) -> bool {
+    parse::segments(command).iter().any(|segment| {
+        parse::program(segment).is_some_and(|program| VOLATILE_PROGRAMS.contains(&program))
+            || segment.split_whitespace().skip(1).any(|word| {
+                let path = Path::new(word.trim_matches(['"', '\'']));
+                VOLATILE_PATHS.iter().any(|volatile| path.starts_with(volatile))
+            })
+    })
+}
+
+/// 命令结果可能依赖的文件及其修改时间, 不存在的文件记为 None.
+fn fingerprint(command: &str, cwd: &Path) -> Fingerprint {
+    let mut paths = vec![cwd.to_path_buf()];
+    let arguments = parse::segments(command)
+        .into_iter()
+        .flat_map(|segment| {
+            segment
+                .split_whitespace()
+                .skip(1)
+                .map(|word| word.trim_matches(['"', '\'']).to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|word| !word.is_empty() && !word.starts_with('-'))
+        .take(MAX_FINGERPRINT_PATHS);
+    for argument in arguments {
+        let path = cwd.join(argument);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    if let Some(git_dir) = cwd
+        .ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|dir| dir.is_dir())
+    {
+        paths.extend(GIT_FILES.iter().map(|file| git_dir.join(file)));
+    }
+    paths
+        .into_iter()
+        .map(|path| {
+            let modified = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn result(stdout: &str) -> CommandResult {
+        CommandResult {
+            status: CommandStatus::Success,
+            exit_code: Some(0),
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+            truncated: false,
+            duration_ms: 1,
+            rejection: None,
+            changes: Vec::new(),
+            cached: false,
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("howlto-cache-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn repeated_command_is_served_from_cache_until_a_file_changes() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("notes.txt"), "a").unwrap();
+        let cache = ToolCache::new(true);
+        cache.insert("cat notes.txt", &dir, &result("a")).await;
+
+        let cached = cache.get("cat notes.txt", &dir).await.unwrap();
+        assert!(cached.cached);
+        assert_eq!(cached.stdout, "a");
+        assert!(
+            cache
+                .get("cat notes.txt", &dir.join("other"))
+                .await
+                .is_none()
+        );
+
+        let file = File::options()
+            .write(true)
+            .open(dir.join("notes.txt"))
+            .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        assert!(cache.get("cat notes.txt", &dir).await.is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn commands_reading_the_whole_tree_are_not_cached() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        let cache = ToolCache::new(true);
+        let commands = [
+            "git diff",
+            "git -C . status --short",
+            "rg main",
+            "grep -rn main .",
+            "grep --recursive main src",
+            "ls -R",
+            "find . -name '*.rs' | xargs wc -l",
+            "cat $(fd rs)",
+        ];
+        for command in commands {
+            cache.insert(command, &dir, &result("old")).await;
+        }
+        // 编辑已有的嵌套文件不会改变工作目录和参数路径的修改时间.
+        std::fs::write(dir.join("src/main.rs"), "fn main() { todo!() }").unwrap();
+        for command in commands {
+            assert!(cache.get(command, &dir).await.is_none(), "{command}");
+        }
+        cache.insert("git log -1", &dir, &result("x")).await;
+        cache.insert("grep main src/main.rs", &dir, &result("x")).await;
+        assert!(cache.get("git log -1", &dir).await.is_some());
+        assert!(cache.get("grep main src/main.rs", &dir).await.is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn volatile_commands_are_not_cached() {
+        let dir = temp_dir();
+        let cache = ToolCache::new(true);
+        let commands = [
+            "date",
+            "echo $(date +%s)",
+            "uptime",
+            "ps aux | grep cargo",
+            "df -h",
+            "docker ps",
+            "cat /proc/meminfo",
+            "cat '/sys/class/power_supply/BAT0/capacity'",
+        ];
+        for command in commands {
+            cache.insert(command, &dir, &result("old")).await;
+            assert!(cache.get(command, &dir).await.is_none(), "{command}");
+        }
+        cache.insert("cat procedures.txt", &dir, &result("x")).await;
+        assert!(cache.get("cat procedures.txt", &dir).await.is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalidation_clears_the_directory_tree() {
+        let dir = temp_dir();
+        let cache = ToolCache::new(true);
+        cache.insert("ls", &dir, &result("x")).await;
+        cache.insert("ls", Path::new("/"), &result("y")).await;
+        cache
+            .insert("ls", Path::new("/nonexistent-sibling"), &result("z"))
+            .await;
+        cache.invalidate(&dir).await;
+        assert!(cache.get("ls", &dir).await.is_none());
+        assert!(cache.get("ls", Path::new("/")).await.is_none());
+        assert_eq!(cache.entries.read().await.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn disabled_cache_and_timeouts_are_not_stored() {
+        let dir = temp_dir();
+        let disabled = ToolCache::default();
+        disabled.insert("ls", &dir, &result("x")).await;
+        assert!(disabled.get("ls", &dir).await.is_none());
+
+        let cache = ToolCache::new(true);
+        let mut timed_out = result("partial");
+        timed_out.status = CommandStatus::TimedOut;
+        cache.insert("ls", &dir, &timed_out).await;
+        assert!(cache.get("ls", &dir).await.is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
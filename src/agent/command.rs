@@ -1,22 +1,39 @@
 use std::{
     convert::Infallible,
-    path::PathBuf,
-    process::{Output, Stdio},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output, Stdio},
+    sync::Arc,
     time::{Duration, Instant},
 };
 
+use crossterm::tty::IsTty;
 use rig_core::{completion::ToolDefinition, tool::Tool};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::process::Command;
-use tracing::{debug, warn};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::{Child, Command},
+};
+use tracing::{debug, info, warn};
 
 use crate::{
     agent::{
-        sandbox::{self, Sandbox},
+        cache::ToolCache,
+        env::ChildEnv,
+        grant::{self, ElevateGrant, ElevateGrants},
+        live_output::{LiveOutput, TailToggle},
+        policy::{CommandPolicy, RuleMatch, Verdict},
+        preview::{FileChange, Preview},
+        pty,
+        sandbox::{self, ResourceHit, ResourceLimits, Sandbox, SandboxOptions},
         tool_schema::parameters_for,
     },
-    tui::elevate,
+    config::{
+        NonTtyDecision, PreviewConfig, ResourcesConfig, SandboxBackend, SandboxConfig,
+        SandboxPolicy,
+    },
+    shell::parse,
+    tui::elevate::{self, Approval, ChangeDecision},
 };
 
 pub const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 30;
@@ -34,6 +51,16 @@ pub struct CommandArgs {
     #[serde(default, alias = "timeout")]
     #[schemars(description = "Optional timeout in seconds, from 1 to 600. Defaults to 30.")]
     pub timeout_secs: Option<u64>,
+    #[serde(default, alias = "cwd")]
+    #[schemars(
+        description = "Optional working directory, absolute or relative to the current directory. Defaults to the current directory."
+    )]
+    pub workdir: Option<String>,
+    #[serde(default)]
+    #[schemars(
+        description = "Optional names of environment variables the command needs. When given, only these variables plus PATH and HOME are passed to the command. Variables outside the user's allowlist are never passed."
+    )]
+    pub env: Option<Vec<String>>,
 }
 
 impl CommandArgs {
@@ -44,6 +71,43 @@ impl CommandArgs {
                 .clamp(1, MAX_TOOL_TIMEOUT_SECS),
         )
     }
+
+    /// 命令的工作目录, 相对路径相对于当前目录.
+    fn working_dir(&self) -> std::io::Result<PathBuf> {
+        let cwd = std::env::current_dir()?;
+        let Some(workdir) = &self.workdir else {
+            return Ok(cwd);
+        };
+        let dir = cwd.join(shellexpand::tilde(workdir).as_ref());
+        match dir.canonicalize() {
+            Ok(dir) if dir.is_dir() => Ok(dir),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Working directory `{}` does not exist.", dir.display()),
+            )),
+        }
+    }
+}
+
+/// explore 的参数, 在 [`CommandArgs`] 之外可以请求沙箱策略.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ExploreArgs {
+    #[serde(flatten)]
+    pub command: CommandArgs,
+    #[serde(default)]
+    #[schemars(
+        description = "Optional sandbox policy: `strict` (only the working directory and system directories are readable), `default` (the whole filesystem is read-only) or `build` (like default, plus writable /tmp and ~/.cache; with the bubblewrap, nsjail and firejail backends these are scratch copies discarded afterwards, with landlock and seatbelt they are the real directories and the user must confirm the command first). Omit it to use the user's default."
+    )]
+    pub sandbox: Option<SandboxPolicy>,
+}
+
+impl From<CommandArgs> for ExploreArgs {
+    fn from(command: CommandArgs) -> Self {
+        Self {
+            command,
+            sandbox: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
@@ -52,9 +116,69 @@ pub enum CommandStatus {
     Success,
     Failed,
     TimedOut,
+    /// 超出 `[agent.sandbox.resources]` 中的资源限制而终止.
+    CpuLimit,
+    MemoryLimit,
+    ProcessLimit,
+    OutputLimit,
     Rejected,
 }
 
+impl From<ResourceHit> for CommandStatus {
+    fn from(hit: ResourceHit) -> Self {
+        match hit {
+            ResourceHit::Cpu => Self::CpuLimit,
+            ResourceHit::Memory => Self::MemoryLimit,
+            ResourceHit::Processes => Self::ProcessLimit,
+            ResourceHit::Output => Self::OutputLimit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectedBy {
+    /// `[agent.policy]` 中的规则.
+    Policy,
+    /// 用户在确认对话框中拒绝.
+    User,
+    /// 无法弹出确认对话框, 按 `non_tty` 配置拒绝.
+    NonInteractive,
+    /// `[agent] hooks` 中脚本的 `before_tool` 钩子.
+    Hook,
+}
+
+/// 结构化的拒绝原因, 让模型能区分是谁拒绝以及命中了哪条规则.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rejection {
+    pub by: RejectedBy,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<RuleMatch>,
+}
+
+impl Rejection {
+    fn policy(matched: RuleMatch) -> Self {
+        Self {
+            by: RejectedBy::Policy,
+            matched: Some(matched),
+        }
+    }
+
+    fn user(matched: Option<RuleMatch>) -> Self {
+        Self {
+            by: RejectedBy::User,
+            matched,
+        }
+    }
+
+    fn non_interactive(matched: Option<RuleMatch>) -> Self {
+        Self {
+            by: RejectedBy::NonInteractive,
+            matched,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct CommandResult {
     pub status: CommandStatus,
@@ -63,10 +187,18 @@ pub struct CommandResult {
     pub stderr: String,
     pub truncated: bool,
     pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejection: Option<Rejection>,
+    /// 经预演确认后应用到工作目录的文件变更.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FileChange>,
+    /// 结果来自会话内缓存, 命令没有重新执行.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
 }
 
 impl CommandResult {
-    fn failed(error: impl ToString, started_at: Instant) -> Self {
+    pub(super) fn failed(error: impl ToString, started_at: Instant) -> Self {
         Self {
             status: CommandStatus::Failed,
             exit_code: None,
@@ -74,10 +206,13 @@ impl CommandResult {
             stderr: error.to_string(),
             truncated: false,
             duration_ms: elapsed_ms(started_at),
+            rejection: None,
+            changes: Vec::new(),
+            cached: false,
         }
     }
 
-    fn rejected(reason: String, started_at: Instant) -> Self {
+    fn rejected(reason: String, rejection: Rejection, started_at: Instant) -> Self {
         Self {
             status: CommandStatus::Rejected,
             exit_code: None,
@@ -85,9 +220,34 @@ impl CommandResult {
             stderr: reason,
             truncated: false,
             duration_ms: elapsed_ms(started_at),
+            rejection: Some(rejection),
+            changes: Vec::new(),
+            cached: false,
         }
     }
 
+    pub(super) fn hook_denied(reason: String, started_at: Instant) -> Self {
+        let rejection = Rejection {
+            by: RejectedBy::Hook,
+            matched: None,
+        };
+        Self::rejected(reason, rejection, started_at)
+    }
+
+    fn denied(matched: RuleMatch, started_at: Instant) -> Self {
+        let reason = match &matched.reason {
+            Some(reason) => format!(
+                "Denied by policy rule `{}` for `{}`: {reason}",
+                matched.rule, matched.segment
+            ),
+            None => format!(
+                "Denied by policy rule `{}` for `{}`.",
+                matched.rule, matched.segment
+            ),
+        };
+        Self::rejected(reason, Rejection::policy(matched), started_at)
+    }
+
     fn timed_out(timeout: Duration, started_at: Instant) -> Self {
         Self {
             status: CommandStatus::TimedOut,
@@ -96,7 +256,22 @@ impl CommandResult {
             stderr: format!("Command timed out after {} seconds.", timeout.as_secs()),
             truncated: false,
             duration_ms: elapsed_ms(started_at),
+            rejection: None,
+            changes: Vec::new(),
+            cached: false,
+        }
+    }
+
+    /// 把命令中途终止前已经产生的输出放进结果, 原有的 stderr 说明放在最后.
+    fn with_partial_output(mut self, stdout: &[u8], stderr: &[u8]) -> Self {
+        let (partial_stdout, stdout_truncated) = truncate_stream(stdout);
+        let (partial_stderr, stderr_truncated) = truncate_stream(stderr);
+        self.stdout = partial_stdout;
+        if !partial_stderr.is_empty() {
+            self.stderr = format!("{}\n{}", partial_stderr.trim_end(), self.stderr);
         }
+        self.truncated = stdout_truncated || stderr_truncated;
+        self
     }
 
     fn from_output(output: Output, started_at: Instant) -> Self {
@@ -113,6 +288,9 @@ impl CommandResult {
             stderr,
             truncated: stdout_truncated || stderr_truncated,
             duration_ms: elapsed_ms(started_at),
+            rejection: None,
+            changes: Vec::new(),
+            cached: false,
         }
     }
 }
@@ -190,30 +368,324 @@ pub(crate) fn truncate_stream(bytes: &[u8]) -> (String, bool) {
     (output, true)
 }
 
-async fn run_command(mut command: Command, timeout: Duration) -> CommandResult {
+/// 执行命令, 同时把输出实时显示在进度区域. `display` 是显示给用户的命令文本.
+///
+/// `limits` 中的 rlimit 需要调用方在包装命令时设置, 这里负责输出大小限制,
+/// 并把命令因资源限制终止的情况报告为对应的 [`CommandStatus`].
+async fn run_command(
+    mut command: Command,
+    display: &str,
+    timeout: Duration,
+    limits: &ResourceLimits,
+) -> CommandResult {
     let started_at = Instant::now();
     command.kill_on_drop(true);
     command
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
-    match tokio::time::timeout(timeout, command.output()).await {
-        Ok(Ok(output)) => CommandResult::from_output(output, started_at),
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(error) => return CommandResult::failed(error, started_at),
+    };
+    let mut toggle = TailToggle::open();
+    let mut live = LiveOutput::start(display, toggle.is_some());
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let status = tokio::time::timeout(
+        timeout,
+        pump(
+            &mut child,
+            &mut stdout,
+            &mut stderr,
+            &mut live,
+            &mut toggle,
+            limits.max_output(),
+        ),
+    )
+    .await;
+    drop(toggle);
+    match status {
+        Ok(Ok(Some(status))) => {
+            let hit = limits.hit(status, &stderr);
+            let mut result = CommandResult::from_output(
+                Output {
+                    status,
+                    stdout,
+                    stderr,
+                },
+                started_at,
+            );
+            if let Some(hit) = hit {
+                result.status = hit.into();
+                result.stderr = format!("{}\n{}", result.stderr.trim_end(), limits.message(hit))
+                    .trim_start()
+                    .to_string();
+            }
+            result
+        }
+        Ok(Ok(None)) => {
+            let hit = ResourceHit::Output;
+            let mut result = CommandResult::failed(limits.message(hit), started_at);
+            result.status = hit.into();
+            result.with_partial_output(&stdout, &stderr)
+        }
         Ok(Err(error)) => CommandResult::failed(error, started_at),
-        Err(_) => CommandResult::timed_out(timeout, started_at),
+        // 超时前已经产生的输出仍然交给模型.
+        Err(_) => {
+            CommandResult::timed_out(timeout, started_at).with_partial_output(&stdout, &stderr)
+        }
     }
 }
 
+/// 读取子进程输出直到两个管道都关闭, 然后等待其退出.
+/// 输出总量超过 `max_output` 时终止子进程并返回 None.
+async fn pump(
+    child: &mut Child,
+    stdout: &mut Vec<u8>,
+    stderr: &mut Vec<u8>,
+    live: &mut LiveOutput,
+    toggle: &mut Option<TailToggle>,
+    max_output: usize,
+) -> std::io::Result<Option<ExitStatus>> {
+    let mut stdout_pipe = child.stdout.take();
+    let mut stderr_pipe = child.stderr.take();
+    let mut stdout_buffer = [0_u8; 8192];
+    let mut stderr_buffer = [0_u8; 8192];
+    while stdout_pipe.is_some() || stderr_pipe.is_some() {
+        tokio::select! {
+            read = read_some(&mut stdout_pipe, &mut stdout_buffer) => match read? {
+                0 => stdout_pipe = None,
+                size => {
+                    stdout.extend_from_slice(&stdout_buffer[..size]);
+                    live.push_stdout(&stdout_buffer[..size]);
+                }
+            },
+            read = read_some(&mut stderr_pipe, &mut stderr_buffer) => match read? {
+                0 => stderr_pipe = None,
+                size => {
+                    stderr.extend_from_slice(&stderr_buffer[..size]);
+                    live.push_stderr(&stderr_buffer[..size]);
+                }
+            },
+            _ = async {
+                match toggle {
+                    Some(toggle) => toggle.next().await,
+                    None => std::future::pending().await,
+                }
+            } => live.toggle_tail(),
+        }
+        if stdout.len() + stderr.len() > max_output {
+            child.kill().await?;
+            return Ok(None);
+        }
+    }
+    child.wait().await.map(Some)
+}
+
+/// 从尚未关闭的管道读取, 管道已关闭时永远挂起.
+async fn read_some(
+    pipe: &mut Option<impl AsyncRead + Unpin>,
+    buffer: &mut [u8],
+) -> std::io::Result<usize> {
+    match pipe {
+        Some(pipe) => pipe.read(buffer).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 向用户确认命令, 无法弹出对话框时按 `non_tty` 配置处理.
+/// 未获批准时返回应交给模型的拒绝结果.
+///
+/// 传入 `grants` 时 (仅 elevate 的默认确认), 对话框提供记住授权的选项,
+/// 用户选择后授权会被记录到本会话中, 按程序授权时限定在命令的工作目录.
+/// 命令含有 `env`, `xargs` 等能执行任意命令的程序时不提供按程序授权.
+/// 模型指定的 `workdir` 和 `env` 会改变命令的行为, 因此与命中的规则和 `note` 一并展示给用户.
+async fn confirm(
+    args: &CommandArgs,
+    sandboxed: bool,
+    matched: Option<RuleMatch>,
+    policy: &CommandPolicy,
+    grants: Option<(&ElevateGrants, &Path)>,
+    note: Option<&str>,
+    started_at: Instant,
+) -> Option<CommandResult> {
+    let command = args.command.as_str();
+    if !std::io::stderr().is_tty() {
+        return match policy.non_tty() {
+            NonTtyDecision::Approve => {
+                info!(command, "Approved without a terminal by the non_tty policy.");
+                None
+            }
+            NonTtyDecision::Reject => Some(CommandResult::rejected(
+                "Confirmation is required, but no terminal is available to ask the user."
+                    .to_string(),
+                Rejection::non_interactive(matched),
+                started_at,
+            )),
+        };
+    }
+    let mut details = confirm_details(args);
+    if let Some(matched) = &matched {
+        details.push(format!("policy rule: {}", matched.rule));
+    }
+    details.extend(note.map(str::to_string));
+    let decision = if sandboxed {
+        elevate::confirm_sandboxed(command, details)
+            .await
+            .map(|_| Approval::Once)
+    } else {
+        let by_program = grant::grantable(command);
+        elevate::confirm_elevate(command, details, grants.is_some(), by_program).await
+    };
+    match decision {
+        Ok(approval) => {
+            if let Some((grants, dir)) = grants {
+                remember(grants, args, dir, approval).await;
+            }
+            None
+        }
+        Err(reason) => Some(CommandResult::rejected(
+            reason,
+            Rejection::user(matched),
+            started_at,
+        )),
+    }
+}
+
+/// 确认对话框中命令下方的工作目录和环境变量, 模型没有指定时为空.
+fn confirm_details(args: &CommandArgs) -> Vec<String> {
+    let mut details = Vec::new();
+    if let Some(workdir) = &args.workdir {
+        let workdir = args
+            .working_dir()
+            .map_or_else(|_| workdir.clone(), |dir| dir.display().to_string());
+        details.push(format!("workdir: {workdir}"));
+    }
+    if let Some(env) = &args.env {
+        details.push(format!("env: only {} (plus PATH, HOME)", env.join(", ")));
+    }
+    details
+}
+
+async fn remember(grants: &ElevateGrants, args: &CommandArgs, dir: &Path, approval: Approval) {
+    match approval {
+        Approval::Once => {}
+        Approval::Command => {
+            grants
+                .add(ElevateGrant::Command {
+                    command: args.command.clone(),
+                    env: args.env.clone(),
+                })
+                .await;
+        }
+        Approval::Program => {
+            let dir = dir.to_string_lossy().into_owned();
+            for program in grant::programs(&args.command) {
+                grants
+                    .add(ElevateGrant::Program {
+                        program,
+                        dir: dir.clone(),
+                    })
+                    .await;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Explore {
     sandbox: Option<Sandbox>,
+    /// 默认的沙箱策略和允许模型请求的策略.
+    policies: SandboxConfig,
+    /// 沙箱内不可读的敏感路径.
+    masked: Vec<PathBuf>,
     shell_path: PathBuf,
+    policy: Arc<CommandPolicy>,
+    cache: Arc<ToolCache>,
+    /// 传给命令的环境变量白名单.
+    env: ChildEnv,
 }
 
 impl Explore {
-    pub fn new(shell_path: PathBuf) -> Self {
+    pub fn new(
+        shell_path: PathBuf,
+        policy: Arc<CommandPolicy>,
+        cache: Arc<ToolCache>,
+        policies: SandboxConfig,
+        env: ChildEnv,
+    ) -> Self {
+        let masked = policies
+            .masked_paths
+            .iter()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect();
+        let sandbox = match policies.backend {
+            SandboxBackend::Auto => sandbox::detect(),
+            SandboxBackend::None => None,
+        };
         Self {
-            sandbox: sandbox::detect(),
+            sandbox,
+            masked,
+            policies,
             shell_path,
+            policy,
+            cache,
+            env,
+        }
+    }
+
+    /// 执行前是否需要弹出确认对话框.
+    pub(super) fn needs_confirmation(&self, args: &ExploreArgs) -> bool {
+        let command = args.command.command.as_str();
+        match self.policy.evaluate(Self::NAME, command) {
+            Verdict::Confirm(_) => true,
+            Verdict::Deny(_) => false,
+            Verdict::Default if self.unsandboxed() && !side_effect_free(command) => true,
+            Verdict::Allow | Verdict::Default => self
+                .sandbox_policy(args.sandbox)
+                .is_ok_and(|policy| self.writes_persist(policy)),
+        }
+    }
+
+    /// `policy` 下的写入是否会留在真实的 `/tmp` 和 `~/.cache` 中, 见
+    /// [`Sandbox::discards_build_writes`]. 这样的调用需要用户确认.
+    fn writes_persist(&self, policy: SandboxPolicy) -> bool {
+        policy == SandboxPolicy::Build
+            && self
+                .sandbox
+                .as_ref()
+                .is_some_and(|sandbox| !sandbox.discards_build_writes())
+    }
+
+    /// 用户是否用 `backend = "none"` 关闭了沙箱.
+    fn unsandboxed(&self) -> bool {
+        self.policies.backend == SandboxBackend::None
+    }
+
+    /// 模型可以使用的沙箱策略名称, 默认策略在前.
+    fn allowed_policies(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        for policy in std::iter::once(self.policies.policy)
+            .chain(self.policies.allowed_policies.iter().copied())
+        {
+            if !names.contains(&policy.name()) {
+                names.push(policy.name());
+            }
+        }
+        names
+    }
+
+    /// 本次调用使用的沙箱策略. 请求了用户未允许的策略时返回错误说明.
+    fn sandbox_policy(&self, requested: Option<SandboxPolicy>) -> Result<SandboxPolicy, String> {
+        match requested {
+            None => Ok(self.policies.policy),
+            Some(policy) if self.allowed_policies().contains(&policy.name()) => Ok(policy),
+            Some(policy) => Err(format!(
+                "Sandbox policy `{}` is not allowed by the user's configuration. Allowed policies: {}.",
+                policy.name(),
+                self.allowed_policies().join(", ")
+            )),
         }
     }
 }
@@ -222,49 +694,416 @@ impl Tool for Explore {
     const NAME: &'static str = "explore";
 
     type Error = Infallible;
-    type Args = CommandArgs;
+    type Args = ExploreArgs;
     type Output = CommandResult;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let description = if self.unsandboxed() {
+            "Run one information-gathering shell command. There is no sandbox on this machine, so the user must approve every command except `<program> --version`, `man <page>` and `tldr <page>`; prefer those and keep commands few and obviously read-only. Never use it for writes or network access. Tool failures are recoverable; inspect the structured result and correct the next call.".to_string()
+        } else {
+            format!(
+                "Run one information-gathering shell command in a read-only, network-disabled OS sandbox. Use it for help, inspection, search, version checks, and other operations that do not need writes or network access. Tool failures are recoverable; inspect the structured result and correct the next call. Sandbox policies you may request: {} (default: {}). The sandbox backend is {}.",
+                self.allowed_policies().join(", "),
+                self.policies.policy.name(),
+                self.sandbox.as_ref().map_or("unavailable", Sandbox::name)
+            )
+        };
         ToolDefinition {
             name: self.name(),
-            description: "Run one information-gathering shell command in a read-only, network-disabled OS sandbox. Use it for help, inspection, search, version checks, and other operations that do not need writes or network access. Tool failures are recoverable; inspect the structured result and correct the next call."
-                .into(),
-            parameters: parameters_for::<CommandArgs>(),
+            description,
+            parameters: parameters_for::<ExploreArgs>(),
         }
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         let started_at = Instant::now();
-        let Some(sandbox) = &self.sandbox else {
+        let ExploreArgs {
+            command: args,
+            sandbox: requested,
+        } = args;
+        let policy = match self.sandbox_policy(requested) {
+            Ok(policy) => policy,
+            Err(message) => return Ok(CommandResult::failed(message, started_at)),
+        };
+        let workdir = match args.working_dir() {
+            Ok(workdir) => workdir,
+            Err(error) => return Ok(CommandResult::failed(error, started_at)),
+        };
+        // 需要确认的命令不缓存, 否则再次调用时会绕过确认.
+        // 缓存只以命令和目录为键, 因此也不缓存指定了策略或环境变量的调用.
+        let cacheable = requested.is_none() && args.env.is_none();
+        let mut cwd = None;
+        let sandboxed = !self.unsandboxed();
+        let persist = self.writes_persist(policy);
+        match self.policy.evaluate(Self::NAME, &args.command) {
+            Verdict::Deny(matched) => return Ok(CommandResult::denied(matched, started_at)),
+            Verdict::Confirm(matched) => {
+                if let Some(rejected) = confirm(
+                    &args,
+                    sandboxed,
+                    Some(matched),
+                    &self.policy,
+                    None,
+                    None,
+                    started_at,
+                )
+                .await
+                {
+                    return Ok(rejected);
+                }
+            }
+            // 没有沙箱时, 除了已知无副作用的命令都需要用户确认, 与 elevate 相同.
+            Verdict::Default if !sandboxed && !side_effect_free(&args.command) => {
+                if let Some(rejected) =
+                    confirm(&args, false, None, &self.policy, None, None, started_at).await
+                {
+                    return Ok(rejected);
+                }
+            }
+            // `build` 策略会写入真实的 `/tmp` 和 `~/.cache`, 即使规则允许也需要确认.
+            Verdict::Allow | Verdict::Default if persist => {
+                if let Some(rejected) = confirm(
+                    &args,
+                    true,
+                    None,
+                    &self.policy,
+                    None,
+                    Some(PERSISTENT_BUILD_NOTE),
+                    started_at,
+                )
+                .await
+                {
+                    return Ok(rejected);
+                }
+            }
+            Verdict::Allow | Verdict::Default => cwd = cacheable.then(|| workdir.clone()),
+        }
+        if let Some(cwd) = &cwd
+            && let Some(cached) = self.cache.get(&args.command, cwd).await
+        {
+            debug!(target: "tool-explore", command = %args.command, "Using cached result.");
+            return Ok(cached);
+        }
+        let shell_args = vec!["-c".to_string(), args.command.clone()];
+        let mut command = if let Some(sandbox) = &self.sandbox {
+            let options = SandboxOptions {
+                policy,
+                cwd: &workdir,
+                network: self.policies.build_network,
+                masked: &self.masked,
+            };
+            match sandbox.wrap(&self.shell_path, &shell_args, &options) {
+                Ok(command) => command,
+                Err(error) => return Ok(CommandResult::failed(error, started_at)),
+            }
+        } else if !sandboxed {
+            let mut command = tokio::process::Command::new(&self.shell_path);
+            command.args(&shell_args).current_dir(&workdir);
+            command
+        } else {
             return Ok(CommandResult::failed(
-                "No read-only sandbox backend is available on this platform.",
+                "No read-only sandbox backend is available on this platform. The user can set `backend = \"none\"` in `[agent.sandbox]` to confirm each command instead.",
                 started_at,
             ));
         };
-        let shell_args = vec!["-c".to_string(), args.command.clone()];
-        let mut command = match sandbox.wrap(&self.shell_path, &shell_args) {
-            Ok(command) => command,
-            Err(error) => return Ok(CommandResult::failed(error, started_at)),
+        let limits = ResourceLimits::from(&self.policies.resources);
+        limits.apply(command.as_std_mut());
+        // 允许联网的 `build` 策略需要代理设置, 其他策略下网络本来就不可用.
+        let env = if policy == SandboxPolicy::Build && self.policies.build_network {
+            self.env.clone().proxied()
+        } else {
+            self.env.clone()
         };
+        env.apply(command.as_std_mut(), args.env.as_deref());
         command.env("GIT_OPTIONAL_LOCKS", "0");
         debug!(
             target: "tool-explore",
-            sandbox = sandbox.name(),
+            sandbox = self.sandbox.as_ref().map_or("none", Sandbox::name),
+            policy = policy.name(),
             command = %args.command,
             "Running command."
         );
-        Ok(run_command(command, args.timeout()).await)
+        let result = run_command(command, &args.command, args.timeout(), &limits).await;
+        if let Some(cwd) = &cwd {
+            self.cache.insert(&args.command, cwd, &result).await;
+        }
+        Ok(result)
     }
 }
 
+/// `build` 策略写入真实目录时确认对话框中的说明.
+const PERSISTENT_BUILD_NOTE: &str =
+    "sandbox: build, writes to the real /tmp and ~/.cache are kept";
+
+/// 没有沙箱时无需确认就能执行的程序, 参数中不能有选项 (`man -P <pager>` 会执行任意命令).
+const UNCONFIRMED_PROGRAMS: [&str; 2] = ["man", "tldr"];
+
+/// `command` 是否只由已知无副作用的调用组成: `<program> --version`, `man <page>` 和
+/// `tldr <page>`. 带重定向, 变量赋值 (`LD_PRELOAD=...`) 或路径形式程序名的命令都不算.
+fn side_effect_free(command: &str) -> bool {
+    if command.contains(['>', '<', '=']) {
+        return false;
+    }
+    let segments = parse::segments(command);
+    !segments.is_empty()
+        && segments.iter().all(|segment| {
+            let mut words = segment.split(' ');
+            let Some(program) = words.next().filter(|program| !program.contains('/')) else {
+                return false;
+            };
+            let args = words.collect::<Vec<_>>();
+            if UNCONFIRMED_PROGRAMS.contains(&program) {
+                !args.is_empty() && args.iter().all(|arg| !arg.starts_with('-'))
+            } else {
+                args == ["--version"]
+            }
+        })
+}
+
+#[derive(Debug, Clone)]
 pub struct Elevate {
     shell_path: PathBuf,
+    policy: Arc<CommandPolicy>,
+    grants: Arc<ElevateGrants>,
+    /// explore 的结果缓存, 执行命令后清空当前目录的部分.
+    cache: Arc<ToolCache>,
+    /// 启用预演时使用的沙箱与配置.
+    preview: Option<(Sandbox, PreviewConfig)>,
+    /// 是否在伪终端中交互式执行.
+    interactive: bool,
+    /// 传给命令的环境变量白名单.
+    env: ChildEnv,
+    /// elevate 只受 `[agent.sandbox.resources]` 中的输出大小限制.
+    limits: ResourceLimits,
 }
 
 impl Elevate {
-    pub fn new(shell_path: PathBuf) -> Self {
-        Self { shell_path }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        shell_path: PathBuf,
+        policy: Arc<CommandPolicy>,
+        grants: Arc<ElevateGrants>,
+        cache: Arc<ToolCache>,
+        preview: PreviewConfig,
+        interactive: bool,
+        env: ChildEnv,
+        resources: &ResourcesConfig,
+    ) -> Self {
+        let preview = if !preview.enabled {
+            None
+        } else if !cfg!(target_os = "linux") {
+            warn!("Elevate preview is only supported on Linux, running commands directly.");
+            None
+        } else if let Some(sandbox) = sandbox::detect().filter(Sandbox::supports_overlay) {
+            Some((sandbox, preview))
+        } else {
+            warn!("Elevate preview requires bwrap, running commands directly.");
+            None
+        };
+        Self {
+            shell_path,
+            policy,
+            grants,
+            cache,
+            preview,
+            interactive,
+            env,
+            limits: ResourceLimits {
+                max_output_mb: resources.max_output_mb,
+                ..ResourceLimits::default()
+            },
+        }
+    }
+
+    /// 不经沙箱执行已批准的命令.
+    async fn run_direct(&self, args: &CommandArgs, workdir: &Path) -> CommandResult {
+        if self.interactive && std::io::stderr().is_tty() {
+            return self.run_interactive(args, workdir);
+        }
+        let mut command = Command::new(&self.shell_path);
+        command.arg("-c").arg(&args.command).current_dir(workdir);
+        self.env.apply(command.as_std_mut(), args.env.as_deref());
+        debug!(target: "tool-elevate", command = %args.command, "Running approved command.");
+        run_command(command, &args.command, args.timeout(), &self.limits).await
+    }
+
+    /// 在伪终端中执行, 用户直接与程序交互, transcript 作为 stdout 返回.
+    fn run_interactive(&self, args: &CommandArgs, workdir: &Path) -> CommandResult {
+        let started_at = Instant::now();
+        let deadline = started_at + args.timeout();
+        info!(target: "tool-elevate", command = %args.command, "Running interactively.");
+        let result = tokio::task::block_in_place(|| {
+            tracing_indicatif::suspend_tracing_indicatif(|| {
+                pty::run_interactive(
+                    &self.shell_path,
+                    &args.command,
+                    deadline,
+                    self.limits.max_output(),
+                    |command| {
+                        command.current_dir(workdir);
+                        self.env.apply(command, args.env.as_deref());
+                    },
+                )
+            })
+        });
+        let (outcome, transcript) = match result {
+            Ok(result) => result,
+            Err(error) => return CommandResult::failed(error, started_at),
+        };
+        let transcript = pty::clean_transcript(&transcript);
+        match outcome {
+            pty::Outcome::Exited(status) => CommandResult::from_output(
+                Output {
+                    status,
+                    stdout: transcript.into_bytes(),
+                    stderr: Vec::new(),
+                },
+                started_at,
+            ),
+            pty::Outcome::TimedOut => CommandResult::timed_out(args.timeout(), started_at)
+                .with_partial_output(transcript.as_bytes(), &[]),
+            pty::Outcome::OutputLimit => {
+                let hit = ResourceHit::Output;
+                let mut result = CommandResult::failed(self.limits.message(hit), started_at);
+                result.status = hit.into();
+                result.with_partial_output(transcript.as_bytes(), &[])
+            }
+        }
+    }
+
+    /// 在 overlay 上预演命令, 有文件变更时再次确认, 然后应用变更或不经沙箱重新执行;
+    /// 没有捕获到变更时确认后不经沙箱执行.
+    async fn run_previewed(
+        &self,
+        sandbox: &Sandbox,
+        config: PreviewConfig,
+        args: &CommandArgs,
+        workdir: &Path,
+        started_at: Instant,
+    ) -> CommandResult {
+        let mut preview = match Preview::create(workdir) {
+            Ok(preview) => preview,
+            Err(error) => return CommandResult::failed(error, started_at),
+        };
+        let shell_args = vec!["-c".to_string(), args.command.clone()];
+        let overlay = preview.overlay(config.network);
+        let mut command = match sandbox.wrap_overlay(&self.shell_path, &shell_args, &overlay) {
+            Ok(command) => command,
+            Err(error) => return CommandResult::failed(error, started_at),
+        };
+        self.env.apply(command.as_std_mut(), args.env.as_deref());
+        debug!(target: "tool-elevate", command = %args.command, "Previewing approved command.");
+        let mut result = run_command(command, &args.command, args.timeout(), &self.limits).await;
+        if let Err(error) = preview.scan() {
+            return CommandResult::failed(
+                format!("Failed to inspect preview changes: {error}"),
+                started_at,
+            );
+        }
+        // 预演只保留当前目录中的变更: 写到 tmpfs `/tmp` 的内容被丢弃, 写其他路径则因为只读而失败.
+        // 没有捕获到变更时预演的结果不能当作真实执行的结果, 需要确认后不经沙箱执行.
+        if preview.changes().is_empty() {
+            drop(preview);
+            const NOTHING_CAPTURED: &str = "The preview changed no files in the working directory, so nothing was applied and the command was not run for real.";
+            let details = vec![match result.exit_code {
+                Some(code) => format!("preview exit code: {code}"),
+                None => "preview did not exit normally".to_string(),
+            }];
+            let rejection = if std::io::stderr().is_tty() {
+                elevate::confirm_rerun(&args.command, details)
+                    .await
+                    .err()
+                    .map(|reason| (reason, Rejection::user(None)))
+            } else {
+                match self.policy.non_tty() {
+                    NonTtyDecision::Approve => None,
+                    NonTtyDecision::Reject => Some((
+                        "No terminal is available to confirm running it.".to_string(),
+                        Rejection::non_interactive(None),
+                    )),
+                }
+            };
+            let Some((reason, rejection)) = rejection else {
+                debug!(target: "tool-elevate", command = %args.command, "Preview captured nothing, running for real.");
+                return self.run_direct(args, workdir).await;
+            };
+            let mut rejected = CommandResult::rejected(
+                format!("{NOTHING_CAPTURED} {reason}"),
+                rejection,
+                started_at,
+            );
+            rejected.stdout = result.stdout;
+            return rejected;
+        }
+        // 超时, 超出资源限制或以非零状态退出的命令只留下了一半的变更, 不应用到真实目录,
+        // 也不交给 `non_tty = approve` 自动批准.
+        if result.status != CommandStatus::Success {
+            warn!(
+                target: "tool-elevate",
+                command = %args.command,
+                status = ?result.status,
+                changes = preview.changes().len(),
+                "Preview did not succeed, discarding its changes."
+            );
+            if !result.stderr.is_empty() && !result.stderr.ends_with('\n') {
+                result.stderr.push('\n');
+            }
+            result.stderr.push_str(&format!(
+                "The command did not succeed in the preview, so its {} file change(s) were discarded and nothing was applied.",
+                preview.changes().len()
+            ));
+            return result;
+        }
+
+        let decision = if std::io::stderr().is_tty() {
+            elevate::confirm_changes(&args.command, preview.summary()).await
+        } else {
+            match self.policy.non_tty() {
+                NonTtyDecision::Approve => Ok(ChangeDecision::Apply),
+                NonTtyDecision::Reject => {
+                    let mut rejected = CommandResult::rejected(
+                        "The preview changed files, but no terminal is available to confirm them. Changes were discarded."
+                            .to_string(),
+                        Rejection::non_interactive(None),
+                        started_at,
+                    );
+                    rejected.stdout = result.stdout;
+                    return rejected;
+                }
+            }
+        };
+        match decision {
+            Ok(ChangeDecision::Apply) => {
+                if let Err(error) = preview.apply() {
+                    return CommandResult::failed(
+                        format!("Failed to apply preview changes: {error}"),
+                        started_at,
+                    );
+                }
+                info!(
+                    target: "tool-elevate",
+                    command = %args.command,
+                    changes = preview.changes().len(),
+                    "Applied preview changes."
+                );
+                result.changes = preview.changes().to_vec();
+                result
+            }
+            Ok(ChangeDecision::Rerun) => {
+                drop(preview);
+                debug!(target: "tool-elevate", command = %args.command, "Re-running without preview.");
+                self.run_direct(args, workdir).await
+            }
+            Err(reason) => {
+                let mut rejected = CommandResult::rejected(
+                    format!("Preview changes were discarded. {reason}"),
+                    Rejection::user(None),
+                    started_at,
+                );
+                rejected.stdout = result.stdout;
+                rejected
+            }
+        }
     }
 }
 
@@ -286,17 +1125,65 @@ impl Tool for Elevate {
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         let started_at = Instant::now();
-        if let Err(reason) = elevate::confirm_elevate(&args.command).await {
-            return Ok(CommandResult::rejected(reason, started_at));
+        let workdir = match args.working_dir() {
+            Ok(workdir) => workdir,
+            Err(error) => return Ok(CommandResult::failed(error, started_at)),
+        };
+        match self.policy.evaluate(Self::NAME, &args.command) {
+            Verdict::Deny(matched) => return Ok(CommandResult::denied(matched, started_at)),
+            Verdict::Allow => {
+                info!(target: "tool-elevate", command = %args.command, "Approved by policy.");
+            }
+            Verdict::Confirm(matched) => {
+                if let Some(rejected) = confirm(
+                    &args,
+                    false,
+                    Some(matched),
+                    &self.policy,
+                    None,
+                    None,
+                    started_at,
+                )
+                .await
+                {
+                    return Ok(rejected);
+                }
+            }
+            Verdict::Default => {
+                if self
+                    .grants
+                    .covers(&args.command, args.env.as_deref(), &workdir)
+                    .await
+                {
+                    info!(target: "tool-elevate", command = %args.command, "Approved by session grant.");
+                } else if let Some(rejected) = confirm(
+                    &args,
+                    false,
+                    None,
+                    &self.policy,
+                    Some((&self.grants, &workdir)),
+                    None,
+                    started_at,
+                )
+                .await
+                {
+                    return Ok(rejected);
+                }
+            }
         }
 
-        let mut command = Command::new(&self.shell_path);
-        command.arg("-c").arg(&args.command);
-        debug!(target: "tool-elevate", command = %args.command, "Running approved command.");
-        let result = run_command(command, args.timeout()).await;
+        let result = if let Some((sandbox, config)) = &self.preview {
+            self.run_previewed(sandbox, *config, &args, &workdir, started_at)
+                .await
+        } else {
+            self.run_direct(&args, &workdir).await
+        };
         if result.status == CommandStatus::TimedOut {
             warn!(target: "tool-elevate", command = %args.command, "Command timed out.");
         }
+        if result.status != CommandStatus::Rejected {
+            self.cache.invalidate(&workdir).await;
+        }
         Ok(result)
     }
 }
@@ -317,15 +1204,36 @@ mod tests {
         assert_eq!(args.timeout_secs, Some(12));
     }
 
+    #[test]
+    fn confirmation_shows_model_chosen_workdir_and_env() {
+        let mut args = CommandArgs {
+            command: "make install".to_string(),
+            timeout_secs: None,
+            workdir: None,
+            env: None,
+        };
+        assert!(confirm_details(&args).is_empty());
+        args.workdir = Some("/".to_string());
+        args.env = Some(vec!["LD_PRELOAD".to_string(), "PREFIX".to_string()]);
+        assert_eq!(
+            confirm_details(&args),
+            ["workdir: /", "env: only LD_PRELOAD, PREFIX (plus PATH, HOME)"]
+        );
+    }
+
     #[test]
     fn command_timeout_uses_default_and_maximum() {
         let default = CommandArgs {
             command: "true".into(),
             timeout_secs: None,
+            workdir: None,
+            env: None,
         };
         let maximum = CommandArgs {
             command: "true".into(),
             timeout_secs: Some(MAX_TOOL_TIMEOUT_SECS + 1),
+            workdir: None,
+            env: None,
         };
         assert_eq!(default.timeout(), Duration::from_secs(DEFAULT_TOOL_TIMEOUT_SECS));
         assert_eq!(maximum.timeout(), Duration::from_secs(MAX_TOOL_TIMEOUT_SECS));
@@ -333,29 +1241,277 @@ mod tests {
 
     #[tokio::test]
     async fn generated_schema_matches_command_args() {
-        let definition = Explore::new(PathBuf::from("/bin/sh"))
-            .definition(String::new())
-            .await;
+        let definition = Explore::new(
+            PathBuf::from("/bin/sh"),
+            Arc::default(),
+            Arc::default(),
+            SandboxConfig::default(),
+            ChildEnv::default(),
+        )
+        .definition(String::new())
+        .await;
         let required = definition.parameters["required"].as_array().unwrap();
         assert!(required.iter().any(|field| field == "command"));
         assert!(!required.iter().any(|field| field == "timeout_secs"));
-        assert!(definition.parameters["properties"]["command"].is_object());
+        let properties = &definition.parameters["properties"];
+        for field in ["command", "workdir", "env", "sandbox"] {
+            assert!(properties[field].is_object(), "missing `{field}`");
+        }
+    }
+
+    #[tokio::test]
+    async fn workdir_and_env_allowlist_apply_to_the_command() {
+        let args: CommandArgs = serde_json::from_str(
+            r#"{"command":"pwd; printf '%s|%s' \"$CARGO_PKG_NAME\" \"$CARGO_MANIFEST_DIR\"","cwd":"src","env":["CARGO_PKG_NAME"]}"#,
+        )
+        .unwrap();
+        let workdir = args.working_dir().unwrap();
+        assert!(workdir.ends_with("src"));
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(&args.command).current_dir(&workdir);
+        let env = ChildEnv::new(Path::new("/bin/sh"), &["CARGO_*".to_string()]);
+        env.apply(command.as_std_mut(), args.env.as_deref());
+        let result = run_command(
+            command,
+            &args.command,
+            Duration::from_secs(5),
+            &ResourceLimits::default(),
+        )
+        .await;
+        assert_eq!(
+            result.stdout,
+            format!("{}\n{}|", workdir.display(), env!("CARGO_PKG_NAME"))
+        );
+
+        let missing = CommandArgs {
+            workdir: Some("no-such-dir".to_string()),
+            ..args
+        };
+        assert!(missing.working_dir().is_err());
+    }
+
+    /// 在设置了凭据的子进程中重新运行, 避免修改测试进程自身的环境.
+    #[tokio::test]
+    async fn secrets_are_absent_inside_tool_commands() {
+        const SECRETS: [(&str, &str); 3] = [
+            ("HOWLTO_API_KEY", "howlto-secret"),
+            ("OPENAI_API_KEY", "openai-secret"),
+            ("AWS_SECRET_ACCESS_KEY", "aws-secret"),
+        ];
+        if std::env::var_os("HOWLTO_TEST_SECRETS").is_none() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "agent::command::tests::secrets_are_absent_inside_tool_commands"])
+                .env("HOWLTO_TEST_SECRETS", "1")
+                .envs(SECRETS)
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stdout)
+            );
+            return;
+        }
+        let shell = PathBuf::from("/bin/sh");
+        // 用户把 howlto 的变量加入白名单时, 凭据仍然不能传递.
+        let env = ChildEnv::new(&shell, &["HOWLTO_*".to_string()]);
+        let policy = CommandPolicy::new(&crate::config::PolicyConfig {
+            non_tty: NonTtyDecision::Reject,
+            rules: vec![crate::config::PolicyRuleConfig {
+                glob: Some("env".to_string()),
+                regex: None,
+                action: crate::config::PolicyAction::Allow,
+                tools: Vec::new(),
+                reason: None,
+            }],
+        })
+        .unwrap();
+        let policy = Arc::new(policy);
+        let explore = Explore::new(
+            shell.clone(),
+            policy.clone(),
+            Arc::default(),
+            SandboxConfig::default(),
+            env.clone(),
+        );
+        let elevate = Elevate::new(
+            shell,
+            policy,
+            Arc::default(),
+            Arc::default(),
+            PreviewConfig::default(),
+            false,
+            env,
+            &ResourcesConfig::default(),
+        );
+        let args = || CommandArgs {
+            command: "env".to_string(),
+            timeout_secs: None,
+            workdir: None,
+            env: None,
+        };
+        let mut outputs = vec![elevate.call(args()).await.unwrap()];
+        if explore.sandbox.is_some() {
+            outputs.push(explore.call(args().into()).await.unwrap());
+        }
+        for result in outputs {
+            assert_eq!(result.status, CommandStatus::Success, "{}", result.stderr);
+            assert!(result.stdout.contains("HOWLTO_TEST_SECRETS=1"));
+            for (name, value) in SECRETS {
+                assert!(!result.stdout.contains(name), "{name} leaked");
+                assert!(!result.stdout.contains(value), "{name} leaked");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn explore_refuses_sandbox_policies_the_user_did_not_allow() {
+        let explore = Explore::new(
+            PathBuf::from("/bin/sh"),
+            Arc::default(),
+            Arc::default(),
+            SandboxConfig {
+                policy: SandboxPolicy::Strict,
+                allowed_policies: vec![SandboxPolicy::Default],
+                ..SandboxConfig::default()
+            },
+            ChildEnv::default(),
+        );
+        assert_eq!(explore.sandbox_policy(None), Ok(SandboxPolicy::Strict));
+        assert_eq!(
+            explore.sandbox_policy(Some(SandboxPolicy::Default)),
+            Ok(SandboxPolicy::Default)
+        );
+        let args: ExploreArgs =
+            serde_json::from_str(r#"{"command":"cargo metadata","sandbox":"build"}"#).unwrap();
+        let result = explore.call(args).await.unwrap();
+        assert_eq!(result.status, CommandStatus::Failed);
+        assert_eq!(
+            result.stderr,
+            "Sandbox policy `build` is not allowed by the user's configuration. Allowed policies: strict, default."
+        );
+    }
+
+    #[test]
+    fn only_known_invocations_are_side_effect_free() {
+        for command in [
+            "git --version",
+            "man tar",
+            "man 5 crontab && tldr rsync",
+            "rg --version; fd --version",
+        ] {
+            assert!(side_effect_free(command), "{command}");
+        }
+        for command in [
+            "ls",
+            "man -P 'rm -rf ~' ls",
+            "tldr --update",
+            "./build.sh --version",
+            "git --version > out",
+            "LD_PRELOAD=evil.so man ls",
+            "man $(touch x)",
+            "git --version --exec-path",
+            "",
+        ] {
+            assert!(!side_effect_free(command), "{command}");
+        }
+    }
+
+    #[tokio::test]
+    async fn unsandboxed_explore_confirms_all_but_known_commands() {
+        let explore = Explore::new(
+            PathBuf::from("/bin/sh"),
+            Arc::default(),
+            Arc::default(),
+            SandboxConfig {
+                backend: SandboxBackend::None,
+                ..SandboxConfig::default()
+            },
+            ChildEnv::default(),
+        );
+        assert!(explore.sandbox.is_none());
+        let args = |command: &str| {
+            ExploreArgs::from(CommandArgs {
+                command: command.to_string(),
+                timeout_secs: None,
+                workdir: None,
+                env: None,
+            })
+        };
+        assert!(!explore.needs_confirmation(&args("ls --version")));
+        assert!(explore.needs_confirmation(&args("ls")));
+        let result = explore.call(args("ls --version")).await.unwrap();
+        assert_eq!(result.status, CommandStatus::Success, "{}", result.stderr);
+        if !std::io::stderr().is_tty() {
+            // 没有终端时按默认的 non_tty = reject 拒绝, 命令不会执行.
+            let result = explore.call(args("printf ran")).await.unwrap();
+            assert_eq!(result.status, CommandStatus::Rejected);
+            assert!(result.stdout.is_empty());
+        }
     }
 
     #[tokio::test]
     async fn command_timeout_is_structured() {
         let mut command = Command::new("/bin/sh");
         command.arg("-c").arg("sleep 1");
-        let result = run_command(command, Duration::from_millis(5)).await;
+        let result = run_command(
+            command,
+            "sleep 1",
+            Duration::from_millis(5),
+            &ResourceLimits::default(),
+        )
+        .await;
         assert_eq!(result.status, CommandStatus::TimedOut);
         assert_eq!(result.exit_code, None);
     }
 
+    #[tokio::test]
+    async fn timed_out_command_keeps_partial_output() {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg("printf started; printf warn >&2; sleep 5");
+        let result = run_command(
+            command,
+            "sleep",
+            Duration::from_millis(300),
+            &ResourceLimits::default(),
+        )
+        .await;
+        assert_eq!(result.status, CommandStatus::TimedOut);
+        assert_eq!(result.stdout, "started");
+        assert!(result.stderr.starts_with("warn\nCommand timed out"));
+    }
+
+    #[tokio::test]
+    async fn output_limit_stops_the_command() {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg("printf warn >&2; yes");
+        let limits = ResourceLimits {
+            max_output_mb: 1,
+            ..ResourceLimits::default()
+        };
+        let result = run_command(command, "yes", Duration::from_secs(5), &limits).await;
+        assert_eq!(result.status, CommandStatus::OutputLimit);
+        assert!(result.truncated);
+        assert!(result.stdout.starts_with("y\n"));
+        assert_eq!(
+            result.stderr,
+            "warn\nCommand output exceeded 1 MiB and the command was stopped."
+        );
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["status"], "output_limit");
+    }
+
     #[tokio::test]
     async fn nonzero_exit_is_failed() {
         let mut command = Command::new("/bin/sh");
         command.arg("-c").arg("printf failure >&2; exit 7");
-        let result = run_command(command, Duration::from_secs(1)).await;
+        let result = run_command(
+            command,
+            "exit 7",
+            Duration::from_secs(1),
+            &ResourceLimits::default(),
+        )
+        .await;
         assert_eq!(result.status, CommandStatus::Failed);
         assert_eq!(result.exit_code, Some(7));
         assert_eq!(result.stderr, "failure");
@@ -385,13 +1541,69 @@ mod tests {
 
     #[test]
     fn command_result_serializes_the_stable_contract() {
-        let result = CommandResult::rejected("no".to_string(), Instant::now());
+        let result = CommandResult::rejected(
+            "no".to_string(),
+            Rejection::user(None),
+            Instant::now(),
+        );
         let value = serde_json::to_value(result).unwrap();
         assert_eq!(value["status"], "rejected");
+        assert_eq!(value["rejection"]["by"], "user");
         assert!(value["exit_code"].is_null());
         assert!(value["stdout"].is_string());
         assert!(value["stderr"].is_string());
         assert!(value["truncated"].is_boolean());
         assert!(value["duration_ms"].is_number());
     }
+
+    #[test]
+    fn policy_denial_names_the_rule_and_segment() {
+        let result = CommandResult::denied(
+            RuleMatch {
+                rule: "git push*".to_string(),
+                segment: "git push origin".to_string(),
+                reason: Some("pushes need review".to_string()),
+            },
+            Instant::now(),
+        );
+        let value = serde_json::to_value(result).unwrap();
+        assert_eq!(value["status"], "rejected");
+        assert_eq!(value["rejection"]["by"], "policy");
+        assert_eq!(value["rejection"]["rule"], "git push*");
+        assert_eq!(value["rejection"]["segment"], "git push origin");
+        assert!(value["stderr"].as_str().unwrap().contains("pushes need review"));
+    }
+
+    #[tokio::test]
+    async fn denied_explore_never_runs() {
+        let policy = CommandPolicy::new(&crate::config::PolicyConfig {
+            non_tty: NonTtyDecision::Reject,
+            rules: vec![crate::config::PolicyRuleConfig {
+                glob: Some("touch *".to_string()),
+                regex: None,
+                action: crate::config::PolicyAction::Deny,
+                tools: Vec::new(),
+                reason: None,
+            }],
+        })
+        .unwrap();
+        let explore = Explore::new(
+            PathBuf::from("/bin/sh"),
+            Arc::new(policy),
+            Arc::default(),
+            SandboxConfig::default(),
+            ChildEnv::default(),
+        );
+        let result = explore
+            .call(ExploreArgs::from(CommandArgs {
+                command: "echo ok | touch file".to_string(),
+                timeout_secs: None,
+                workdir: None,
+                env: None,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(result.status, CommandStatus::Rejected);
+        assert_eq!(result.rejection.unwrap().by, RejectedBy::Policy);
+    }
 }
@@ -0,0 +1,347 @@
+//! 补全脚本生成: 在沙箱中阅读程序的 `--help`, 子命令帮助和 man 手册,
+//! 为 fish, bash, zsh 或 nushell 生成补全脚本, 并用对应 shell 的语法检查验证.
+
+use std::{
+    env,
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    agent::answer::AnswerAgent,
+    config::{AppConfig, profile::AnswerProfile},
+    error::{Error, Result},
+    shell::Shell,
+};
+
+/// 语法检查失败后请模型修正的次数.
+const MAX_FIX_ATTEMPTS: usize = 2;
+const SYNTAX_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 支持生成补全脚本的 shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionShell {
+    Fish,
+    Bash,
+    Zsh,
+    Nushell,
+}
+
+impl Display for CompletionShell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fish => "fish",
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Nushell => "nushell",
+        })
+    }
+}
+
+impl CompletionShell {
+    pub fn from_shell(shell: &Shell) -> Option<Self> {
+        match shell.name().trim_end_matches(".exe") {
+            "fish" => Some(Self::Fish),
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "nu" => Some(Self::Nushell),
+            _ => None,
+        }
+    }
+
+    /// 补全脚本的安装路径, 按 `XDG_CONFIG_HOME`, `XDG_DATA_HOME` 和 `HOME` 推断.
+    /// `program` 是路径时只取文件名.
+    pub fn install_path(self, program: &str) -> Result<PathBuf> {
+        let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+        let home = PathBuf::from(var("HOME").ok_or_else(|| {
+            Error::InvalidInput("HOME is not set, use --print to output the script.".to_string())
+        })?);
+        let config_home =
+            var("XDG_CONFIG_HOME").map_or_else(|| home.join(".config"), PathBuf::from);
+        let data_home =
+            var("XDG_DATA_HOME").map_or_else(|| home.join(".local/share"), PathBuf::from);
+        self.install_path_in(program, &home, &config_home, &data_home)
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "`{program}` is not a valid program name, use --print to output the script."
+                ))
+            })
+    }
+
+    /// `program` 没有文件名 (如 `..`) 时返回 [`None`], 避免写到补全目录之外.
+    fn install_path_in(
+        self,
+        program: &str,
+        home: &Path,
+        config_home: &Path,
+        data_home: &Path,
+    ) -> Option<PathBuf> {
+        let program = Path::new(program).file_name()?.to_str()?;
+        let path = match self {
+            Self::Fish => config_home.join(format!("fish/completions/{program}.fish")),
+            Self::Bash => data_home.join(format!("bash-completion/completions/{program}")),
+            Self::Zsh => home.join(format!(".zfunc/_{program}")),
+            Self::Nushell => config_home.join(format!("nushell/autoload/{program}-completions.nu")),
+        };
+        Some(path)
+    }
+
+    /// 安装后还需要用户完成的配置.
+    pub fn install_note(self) -> Option<&'static str> {
+        match self {
+            Self::Zsh => Some("zsh 需要在 ~/.zshrc 中 compinit 之前加入: fpath=(~/.zfunc $fpath)"),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Fish => "fish",
+            Self::Bash | Self::Zsh => "sh",
+            Self::Nushell => "nu",
+        }
+    }
+
+    /// 只解析不执行 `path` 的参数. nushell 通过环境变量传入路径, 避免引用问题.
+    fn check_args(self, path: &Path) -> Vec<OsString> {
+        match self {
+            Self::Fish => vec!["--no-execute".into(), path.into()],
+            Self::Bash | Self::Zsh => vec!["-n".into(), path.into()],
+            Self::Nushell => vec![
+                "--no-config-file".into(),
+                "--commands".into(),
+                "nu-check --debug $env.HOWLTO_COMPLETION_SCRIPT".into(),
+            ],
+        }
+    }
+}
+
+pub struct CompletionAgent {
+    agent: AnswerAgent,
+    shell_path: PathBuf,
+    kind: CompletionShell,
+}
+
+#[bon::bon]
+impl CompletionAgent {
+    /// `shell` 是补全脚本的目标 shell, 也用于执行 `explore` 和语法检查.
+    #[builder]
+    pub fn new(
+        os: String,
+        shell: &Shell,
+        profile: AnswerProfile,
+        config: AppConfig,
+    ) -> Result<Self> {
+        let kind = CompletionShell::from_shell(shell).ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "Completion scripts for `{}` are not supported, use fish, bash, zsh or nushell.",
+                shell.name()
+            ))
+        })?;
+        Ok(Self {
+            agent: AnswerAgent::builder()
+                .os(os)
+                .shell(shell)
+                .profile(profile)
+                .config(config)
+                .build()?,
+            shell_path: shell.path().to_path_buf(),
+            kind,
+        })
+    }
+}
+
+impl CompletionAgent {
+    pub fn kind(&self) -> CompletionShell {
+        self.kind
+    }
+
+    /// 生成 `program` 的补全脚本. 语法检查失败时把错误交给模型修正, 仍然失败时返回错误.
+    pub async fn generate(&self, program: &str) -> Result<String> {
+        let mut response = self
+            .agent
+            .resolve()
+            .prompt(format!(
+                "Generate a {} completion script for `{program}`.",
+                self.kind
+            ))
+            .call()
+            .await?;
+        for attempt in 0..=MAX_FIX_ATTEMPTS {
+            let script = extract_script(&response.final_text);
+            if script.trim().is_empty() {
+                return Err(Error::AgentResponse(
+                    "Agent returned no completion script.".to_string(),
+                ));
+            }
+            let Some(error) = self.syntax_error(&script).await else {
+                info!(program, shell = %self.kind, lines = script.lines().count(), "Completion script generated.");
+                return Ok(script);
+            };
+            if attempt == MAX_FIX_ATTEMPTS {
+                return Err(Error::AgentResponse(format!(
+                    "Completion script failed the {} syntax check: {error}",
+                    self.kind
+                )));
+            }
+            warn!(attempt = attempt + 1, %error, "Completion script failed the syntax check.");
+            response = self
+                .agent
+                .resolve()
+                .prompt(format!(
+                    "The script failed the {} syntax check:\n{error}\n\nReply with the complete corrected script in one code block.",
+                    self.kind
+                ))
+                .history(response.messages)
+                .call()
+                .await?;
+        }
+        unreachable!("the last attempt always returns")
+    }
+
+    /// 把脚本写入临时文件后用 shell 检查语法, 返回错误信息.
+    pub async fn syntax_error(&self, script: &str) -> Option<String> {
+        let path = env::temp_dir().join(format!(
+            "howlto-completion-{}.{}",
+            Uuid::new_v4(),
+            self.kind.extension()
+        ));
+        if let Err(error) = tokio::fs::write(&path, script).await {
+            return Some(format!("Could not write the script for checking: {error}"));
+        }
+        let mut command = tokio::process::Command::new(&self.shell_path);
+        command
+            .args(self.kind.check_args(&path))
+            .env("HOWLTO_COMPLETION_SCRIPT", &path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let result = tokio::time::timeout(SYNTAX_CHECK_TIMEOUT, command.output()).await;
+        tokio::fs::remove_file(&path).await.ok();
+        match result {
+            Ok(Ok(output)) if output.status.success() => None,
+            Ok(Ok(output)) => {
+                let message = [output.stderr, output.stdout]
+                    .iter()
+                    .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())
+                    .find(|message| !message.is_empty());
+                Some(message.unwrap_or_else(|| {
+                    format!("Syntax check failed with status {}.", output.status)
+                }))
+            }
+            Ok(Err(error)) => Some(format!("Could not run the syntax check: {error}")),
+            Err(_) => Some("Syntax check timed out.".to_string()),
+        }
+    }
+}
+
+/// 取出回答中第一个代码块的内容, 没有代码块时使用整个回答. 结果以换行结尾.
+fn extract_script(text: &str) -> String {
+    let mut lines = text.lines();
+    let fenced = lines.by_ref().find_map(|line| {
+        let trimmed = line.trim_start();
+        ["```", "~~~"]
+            .into_iter()
+            .find(|fence| trimmed.starts_with(fence))
+            .map(|fence| {
+                let count = trimmed
+                    .chars()
+                    .take_while(|c| fence.starts_with(*c))
+                    .count();
+                fence[..1].repeat(count)
+            })
+    });
+    let body = match fenced {
+        Some(fence) => lines
+            .take_while(|line| {
+                let trimmed = line.trim();
+                !(trimmed.starts_with(&fence) && trimmed.trim_start_matches(&fence[..1]).is_empty())
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => text.trim().to_string(),
+    };
+    let mut script = body.trim_matches('\n').to_string();
+    script.push('\n');
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{CompletionShell, extract_script};
+
+    #[test]
+    fn script_is_taken_from_the_first_code_block() {
+        let text = "Here it is:\n\n```fish\ncomplete -c foo -s h -l help\n```\n\nInstall it.";
+        assert_eq!(extract_script(text), "complete -c foo -s h -l help\n");
+        assert_eq!(
+            extract_script("````zsh\n#compdef foo\n```\n_foo() {}\n````"),
+            "#compdef foo\n```\n_foo() {}\n"
+        );
+        assert_eq!(
+            extract_script("\ncomplete -F _foo foo"),
+            "complete -F _foo foo\n"
+        );
+    }
+
+    #[test]
+    fn completion_files_go_to_each_shells_completion_directory() {
+        let path = |kind: CompletionShell| {
+            kind.install_path_in(
+                "deployctl",
+                Path::new("/home/u"),
+                Path::new("/home/u/.config"),
+                Path::new("/home/u/.local/share"),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            path(CompletionShell::Fish),
+            Path::new("/home/u/.config/fish/completions/deployctl.fish")
+        );
+        assert_eq!(
+            path(CompletionShell::Bash),
+            Path::new("/home/u/.local/share/bash-completion/completions/deployctl")
+        );
+        assert_eq!(
+            path(CompletionShell::Zsh),
+            Path::new("/home/u/.zfunc/_deployctl")
+        );
+        assert_eq!(
+            path(CompletionShell::Nushell),
+            Path::new("/home/u/.config/nushell/autoload/deployctl-completions.nu")
+        );
+    }
+
+    #[test]
+    fn install_paths_stay_in_the_completion_directory() {
+        let path = |program| {
+            CompletionShell::Fish.install_path_in(
+                program,
+                Path::new("/home/u"),
+                Path::new("/home/u/.config"),
+                Path::new("/home/u/.local/share"),
+            )
+        };
+        assert_eq!(
+            path("./tools/deployctl").unwrap(),
+            Path::new("/home/u/.config/fish/completions/deployctl.fish")
+        );
+        assert_eq!(
+            path("../../.config/fish/config").unwrap(),
+            Path::new("/home/u/.config/fish/completions/config.fish")
+        );
+        assert!(path("..").is_none());
+        assert!(path("tools/..").is_none());
+        assert!(path("/").is_none());
+    }
+}
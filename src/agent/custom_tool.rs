@@ -0,0 +1,467 @@
+//! 在配置中声明的命令工具.
+//!
+//! 每个工具有自己的名称, 说明和参数 JSON Schema. 调用时先按 schema 校验参数,
+//! 再把参数按当前 shell 的语法引用后填入命令模板, 最后交给内置的 [`Explore`] 或
+//! [`Elevate`] 执行, 因此命令策略, 确认, 缓存和返回的 [`CommandResult`] 都与内置工具一致.
+
+use std::{convert::Infallible, time::Instant};
+
+use rig_core::{completion::ToolDefinition, tool::Tool};
+use serde_json::{Map, Value, json};
+use tracing::debug;
+
+use crate::{
+    agent::command::{CommandArgs, CommandResult, Elevate, Explore},
+    config::{CustomToolConfig, CustomToolMode},
+    error::{Error, Result},
+    shell,
+};
+
+const RESERVED_NAMES: [&str; 4] = [
+    "explore",
+    "elevate",
+    "search_favorites",
+    "submit_commands",
+];
+const MAX_NAME_LENGTH: usize = 64;
+const SUPPORTED_TYPES: [&str; 5] = ["string", "integer", "number", "boolean", "array"];
+
+#[derive(Debug, Clone)]
+enum Runner {
+    Explore(Explore),
+    Elevate(Elevate),
+}
+
+#[derive(Debug, Clone)]
+pub struct CustomTool {
+    name: String,
+    description: String,
+    parameters: Value,
+    template: Template,
+    timeout_secs: Option<u64>,
+    shell_name: String,
+    runner: Runner,
+}
+
+impl CustomTool {
+    /// 校验配置并创建工具. `explore` 和 `elevate` 是与内置工具共享策略与缓存的执行器.
+    pub fn new(
+        config: &CustomToolConfig,
+        shell_name: &str,
+        explore: &Explore,
+        elevate: &Elevate,
+    ) -> Result<Self> {
+        let invalid = |message: String| Error::CustomTool {
+            tool: config.name.clone(),
+            message,
+        };
+        check_name(&config.name).map_err(invalid)?;
+        let parameters = config.parameters.clone().unwrap_or_else(|| {
+            json!({
+                "type": "object",
+                "properties": {},
+            })
+        });
+        check_schema(&parameters).map_err(invalid)?;
+        let template = Template::parse(&config.command).map_err(invalid)?;
+        for placeholder in template.placeholders() {
+            if parameters["properties"].get(placeholder).is_none() {
+                return Err(invalid(format!(
+                    "placeholder `{{{{{placeholder}}}}}` is not declared in `parameters`"
+                )));
+            }
+        }
+        let runner = match config.mode {
+            CustomToolMode::Explore => Runner::Explore(explore.clone()),
+            CustomToolMode::Elevate => Runner::Elevate(elevate.clone()),
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            description: config.description.clone(),
+            parameters,
+            template,
+            timeout_secs: config.timeout_secs,
+            shell_name: shell_name.to_string(),
+            runner,
+        })
+    }
+
+    /// 校验配置中的所有工具, 名称不能重复.
+    pub fn from_configs(
+        configs: &[CustomToolConfig],
+        shell_name: &str,
+        explore: &Explore,
+        elevate: &Elevate,
+    ) -> Result<Vec<Self>> {
+        let mut tools = Vec::<Self>::with_capacity(configs.len());
+        for config in configs {
+            if tools.iter().any(|tool| tool.name == config.name) {
+                return Err(Error::CustomTool {
+                    tool: config.name.clone(),
+                    message: "the name is declared more than once".to_string(),
+                });
+            }
+            tools.push(Self::new(config, shell_name, explore, elevate)?);
+        }
+        Ok(tools)
+    }
+}
+
+impl Tool for CustomTool {
+    const NAME: &'static str = "custom";
+
+    type Error = Infallible;
+    type Args = Value;
+    type Output = CommandResult;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.parameters.clone(),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> std::result::Result<Self::Output, Self::Error> {
+        let args = match args {
+            Value::Null => Value::Object(Map::new()),
+            args => args,
+        };
+        if let Err(message) = validate(&self.parameters, &args) {
+            return Ok(CommandResult::failed(
+                format!("Invalid arguments for `{}`: {message}", self.name),
+                Instant::now(),
+            ));
+        }
+        let command = self.template.render(&args, &self.shell_name);
+        debug!(tool = %self.name, command = %command, "Running custom tool.");
+        let args = CommandArgs {
+            command,
+            timeout_secs: self.timeout_secs,
+            workdir: None,
+            env: None,
+        };
+        match &self.runner {
+            Runner::Explore(explore) => explore.call(args.into()).await,
+            Runner::Elevate(elevate) => elevate.call(args).await,
+        }
+    }
+}
+
+fn check_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "the name must be 1 to {MAX_NAME_LENGTH} characters"
+        ));
+    }
+    if !name
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric() || matches!(character, '_' | '-'))
+    {
+        return Err("the name may only contain ASCII letters, digits, `_` and `-`".to_string());
+    }
+    if RESERVED_NAMES.contains(&name) {
+        return Err("the name is used by a built-in tool".to_string());
+    }
+    Ok(())
+}
+
+/// 只支持 schema 的一个子集: 顶层 object, 属性为基本类型或基本类型的数组.
+fn check_schema(schema: &Value) -> std::result::Result<(), String> {
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        return Err("`parameters` must be a JSON Schema with `type = \"object\"`".to_string());
+    }
+    let properties = match schema.get("properties") {
+        None => return Ok(()),
+        Some(Value::Object(properties)) => properties,
+        Some(_) => return Err("`parameters.properties` must be a table".to_string()),
+    };
+    for (name, property) in properties {
+        let kind = property
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !SUPPORTED_TYPES.contains(&kind) {
+            return Err(format!(
+                "argument `{name}` must have one of the types {}",
+                SUPPORTED_TYPES.join(", ")
+            ));
+        }
+        if kind == "array" {
+            let item = property["items"]["type"].as_str().unwrap_or_default();
+            if item == "array" || !SUPPORTED_TYPES.contains(&item) {
+                return Err(format!(
+                    "array argument `{name}` needs `items` of a basic type"
+                ));
+            }
+        }
+    }
+    match schema.get("required") {
+        None => Ok(()),
+        Some(Value::Array(required)) => {
+            for name in required {
+                let declared = name
+                    .as_str()
+                    .is_some_and(|name| properties.contains_key(name));
+                if !declared {
+                    return Err(format!("required argument {name} is not declared"));
+                }
+            }
+            Ok(())
+        }
+        Some(_) => Err("`parameters.required` must be an array".to_string()),
+    }
+}
+
+/// 按 [`check_schema`] 支持的子集校验参数. 未声明的参数一律拒绝.
+fn validate(schema: &Value, args: &Value) -> std::result::Result<(), String> {
+    let Value::Object(args) = args else {
+        return Err("arguments must be a JSON object".to_string());
+    };
+    let empty = Map::new();
+    let properties = schema["properties"].as_object().unwrap_or(&empty);
+    for name in schema["required"].as_array().into_iter().flatten() {
+        let name = name.as_str().unwrap_or_default();
+        if args.get(name).is_none_or(Value::is_null) {
+            return Err(format!("missing required argument `{name}`"));
+        }
+    }
+    for (name, value) in args {
+        let Some(property) = properties.get(name) else {
+            return Err(format!("unknown argument `{name}`"));
+        };
+        if value.is_null() {
+            continue;
+        }
+        check_value(name, property, value)?;
+        if let Value::Array(items) = value {
+            for item in items {
+                check_value(name, &property["items"], item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_value(name: &str, property: &Value, value: &Value) -> std::result::Result<(), String> {
+    let kind = property["type"].as_str().unwrap_or_default();
+    let matches = match kind {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        _ => false,
+    };
+    if !matches {
+        return Err(format!("argument `{name}` must be of type {kind}"));
+    }
+    if let Some(allowed) = property["enum"].as_array()
+        && !allowed.contains(value)
+    {
+        return Err(format!(
+            "argument `{name}` must be one of {}",
+            Value::Array(allowed.clone())
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder(String),
+}
+
+/// 命令模板, `{{name}}` 为参数占位符.
+#[derive(Debug, Clone)]
+struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    fn parse(template: &str) -> std::result::Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            let Some(length) = rest[start + 2..].find("}}") else {
+                return Err("unclosed `{{` in `command`".to_string());
+            };
+            let name = rest[start + 2..start + 2 + length].trim();
+            if name.is_empty() {
+                return Err("empty placeholder in `command`".to_string());
+            }
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            parts.push(Part::Placeholder(name.to_string()));
+            rest = &rest[start + 2 + length + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Placeholder(name) => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// 填入参数. 字符串按 shell 语法引用, 数组展开为多个参数, 缺省的参数替换为空.
+    fn render(&self, args: &Value, shell_name: &str) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                Part::Placeholder(name) => output.push_str(&render_value(&args[name], shell_name)),
+            }
+        }
+        output
+    }
+}
+
+fn render_value(value: &Value, shell_name: &str) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => shell::quote(shell_name, text),
+        Value::Number(number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| render_value(item, shell_name))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Value::Object(_) => shell::quote(shell_name, &value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use crate::config::PreviewConfig;
+
+    use super::*;
+
+    fn config(toml: &str) -> CustomToolConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn build(config: &CustomToolConfig) -> Result<CustomTool> {
+        let shell = PathBuf::from("/bin/sh");
+        let explore = Explore::new(
+            shell.clone(),
+            Arc::default(),
+            Arc::default(),
+            Default::default(),
+            Default::default(),
+        );
+        let elevate = Elevate::new(
+            shell,
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            PreviewConfig::default(),
+            false,
+            Default::default(),
+            &Default::default(),
+        );
+        CustomTool::new(config, "bash", &explore, &elevate)
+    }
+
+    const CONTEXT_TOOL: &str = r#"
+        name = "kubectl-context"
+        description = "Switch the kubectl context."
+        command = "kubectl config use-context {{ context }} {{flags}}"
+        mode = "elevate"
+
+        [parameters]
+        type = "object"
+        required = ["context"]
+
+        [parameters.properties.context]
+        type = "string"
+        enum = ["dev", "prod's"]
+
+        [parameters.properties.flags]
+        type = "array"
+        items = { type = "string" }
+    "#;
+
+    #[test]
+    fn template_quotes_arguments_for_the_shell() {
+        let tool = build(&config(CONTEXT_TOOL)).unwrap();
+        assert!(matches!(tool.runner, Runner::Elevate(_)));
+        let command = tool.template.render(
+            &json!({"context": "prod's", "flags": ["--a", "b c"]}),
+            "bash",
+        );
+        assert_eq!(
+            command,
+            r"kubectl config use-context 'prod'\''s' '--a' 'b c'"
+        );
+        assert_eq!(
+            tool.template.render(&json!({"context": "dev"}), "bash"),
+            "kubectl config use-context 'dev' "
+        );
+    }
+
+    #[test]
+    fn arguments_are_validated_against_the_schema() {
+        let tool = build(&config(CONTEXT_TOOL)).unwrap();
+        let schema = &tool.parameters;
+        assert!(validate(schema, &json!({"context": "dev"})).is_ok());
+        let errors = [
+            (json!({}), "missing required argument `context`"),
+            (json!({"context": 1}), "must be of type string"),
+            (json!({"context": "qa"}), "must be one of"),
+            (
+                json!({"context": "dev", "flags": [1]}),
+                "must be of type string",
+            ),
+            (
+                json!({"context": "dev", "extra": true}),
+                "unknown argument `extra`",
+            ),
+            (json!([]), "must be a JSON object"),
+        ];
+        for (args, expected) in errors {
+            let error = validate(schema, &args).unwrap_err();
+            assert!(error.contains(expected), "{error}");
+        }
+    }
+
+    #[test]
+    fn invalid_declarations_are_rejected_at_load() {
+        let cases = [
+            (r#"name = "explore""#, "built-in"),
+            (r#"name = "has space""#, "may only contain"),
+            (
+                r#"name = "x"
+                command = "echo {{missing}}""#,
+                "not declared",
+            ),
+            (
+                r#"name = "x"
+                command = "echo {{oops""#,
+                "unclosed",
+            ),
+        ];
+        for (fields, expected) in cases {
+            let mut declaration = format!("description = \"d\"\n{fields}");
+            if !fields.contains("command") {
+                declaration.push_str("\ncommand = \"true\"");
+            }
+            let error = build(&config(&declaration)).unwrap_err();
+            assert!(matches!(error, Error::CustomTool { .. }));
+            assert!(error.to_string().contains(expected), "{error}");
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! 传给工具子进程的环境变量.
+//!
+//! `explore` 和 `elevate` 的命令不继承 howlto 的完整环境, 否则模型用一条 `env`
+//! 就能读到用户 shell 中的所有密钥. 子进程只得到白名单中的变量: 内置的通用变量,
+//! 当前 shell 需要的变量, 以及用户在 `[agent] env` 中追加的变量. ssh, gpg 和 D-Bus
+//! 代理的套接字地址只传给 `elevate`; 网络代理的设置只传给 `elevate` 和允许联网的
+//! `build` 策略.
+//! howlto 自己的凭据变量无论如何都不会传递.
+
+use std::{ffi::OsStr, path::Path, process::Command};
+
+/// 始终传递的变量. 以 `*` 结尾的表示前缀匹配.
+const DEFAULT_ENV: [&str; 18] = [
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LANGUAGE",
+    "LC_*",
+    "TERM",
+    "COLORTERM",
+    "NO_COLOR",
+    "TZ",
+    "TMPDIR",
+    "XDG_*",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "GNUPGHOME",
+    "EDITOR",
+];
+
+/// 只传给 `elevate` 的代理套接字. 沙箱中的只读命令拿到它们就能用用户的 ssh 密钥认证,
+/// 或者通过会话总线调用任意服务; 经用户确认的 `git push` 等命令才需要它们.
+const ELEVATE_ENV: [&str; 3] = [
+    "SSH_AUTH_SOCK",
+    "GPG_AGENT_INFO",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// 网络代理的设置, 只传给能联网的命令. 代理地址中可能带有认证信息.
+const PROXY_ENV: [&str; 8] = [
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "ALL_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "all_proxy",
+    "no_proxy",
+];
+
+/// 模型用 `env` 参数收窄环境时仍然保留的变量.
+const BASE_ENV: [&str; 2] = ["PATH", "HOME"];
+
+/// howlto 读取的凭据, 即使用户把它们加入白名单也不会传递.
+pub const CREDENTIAL_ENV: [&str; 2] = ["HOWLTO_API_KEY", "OPENAI_API_KEY"];
+
+/// 子进程环境变量的白名单.
+#[derive(Debug, Clone)]
+pub struct ChildEnv {
+    allowed: Vec<String>,
+}
+
+impl Default for ChildEnv {
+    fn default() -> Self {
+        Self::new(Path::new("/bin/sh"), &[])
+    }
+}
+
+impl ChildEnv {
+    /// 由内置白名单, `shell` 需要的变量和用户追加的 `extra` 组成.
+    pub fn new(shell: &Path, extra: &[String]) -> Self {
+        let allowed = DEFAULT_ENV
+            .iter()
+            .chain(shell_env(shell))
+            .map(ToString::to_string)
+            .chain(extra.iter().cloned())
+            .collect();
+        Self { allowed }
+    }
+
+    /// 在白名单中加入 [`ELEVATE_ENV`] 和 [`PROXY_ENV`], 用于 `elevate`.
+    pub fn elevate(self) -> Self {
+        let mut env = self.proxied();
+        env.allowed.extend(ELEVATE_ENV.iter().map(ToString::to_string));
+        env
+    }
+
+    /// 在白名单中加入 [`PROXY_ENV`], 用于能联网的命令.
+    pub fn proxied(mut self) -> Self {
+        self.allowed.extend(PROXY_ENV.iter().map(ToString::to_string));
+        self
+    }
+
+    fn allows(&self, name: &str) -> bool {
+        !CREDENTIAL_ENV.contains(&name)
+            && self
+                .allowed
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => name == pattern,
+                })
+    }
+
+    /// 清空 `command` 的环境, 只传入白名单中的变量.
+    ///
+    /// `requested` 是模型在调用中列出的变量名, 指定时只传递其中的变量和 [`BASE_ENV`],
+    /// 只能收窄白名单而不能扩大.
+    pub fn apply(&self, command: &mut Command, requested: Option<&[String]>) {
+        command.env_clear();
+        for (name, value) in std::env::vars_os() {
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let requested = requested.is_none_or(|requested| {
+                BASE_ENV.contains(&name) || requested.iter().any(|item| item == name)
+            });
+            if requested && self.allows(name) {
+                command.env(name, value);
+            }
+        }
+    }
+}
+
+/// 各 shell 读取配置所需的变量.
+fn shell_env(shell: &Path) -> &'static [&'static str] {
+    match shell.file_name().and_then(OsStr::to_str) {
+        Some("zsh") => &["ZDOTDIR"],
+        Some("fish") => &["fish_*", "__fish_*"],
+        Some("nu" | "nu.exe") => &["NU_LIB_DIRS", "NU_PLUGIN_DIRS"],
+        Some("xonsh") => &["XONSHRC", "XONSH_*"],
+        Some("elvish") => &["ELVISH_*"],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(command: &Command) -> Vec<String> {
+        command
+            .get_envs()
+            .filter_map(|(name, value)| value.and(name.to_str()))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn only_allowlisted_variables_are_passed() {
+        let env = ChildEnv::new(Path::new("/usr/bin/zsh"), &["CARGO_*".to_string()]);
+        assert!(env.allows("LC_ALL"));
+        assert!(env.allows("ZDOTDIR"));
+        assert!(env.allows("CARGO_PKG_NAME"));
+        assert!(!env.allows("AWS_SECRET_ACCESS_KEY"));
+        assert!(!env.allows("fish_color_normal"));
+
+        let mut command = Command::new("/bin/sh");
+        env.apply(&mut command, None);
+        let names = env_of(&command);
+        assert!(names.iter().any(|name| name == "PATH"));
+        assert!(names.iter().any(|name| name == "CARGO_PKG_NAME"));
+        assert!(names.iter().all(|name| env.allows(name)));
+    }
+
+    #[test]
+    fn agent_sockets_are_only_passed_to_elevate() {
+        let env = ChildEnv::new(Path::new("/bin/sh"), &[]);
+        let elevate = env.clone().elevate();
+        for name in ELEVATE_ENV {
+            assert!(!env.allows(name), "{name} must not reach explore");
+            assert!(elevate.allows(name), "{name} must reach elevate");
+        }
+    }
+
+    #[test]
+    fn proxies_are_only_passed_to_networked_commands() {
+        let env = ChildEnv::new(Path::new("/bin/sh"), &[]);
+        let proxied = env.clone().proxied();
+        let elevate = env.clone().elevate();
+        for name in PROXY_ENV {
+            assert!(!env.allows(name), "{name} must not reach offline commands");
+            assert!(proxied.allows(name), "{name} must reach networked builds");
+            assert!(elevate.allows(name), "{name} must reach elevate");
+        }
+    }
+
+    #[test]
+    fn credentials_are_never_passed() {
+        let extra = ["HOWLTO_*".to_string(), "OPENAI_API_KEY".to_string()];
+        let env = ChildEnv::new(Path::new("/bin/sh"), &extra);
+        for name in CREDENTIAL_ENV {
+            assert!(!env.allows(name), "{name} must not be passed");
+        }
+        assert!(env.allows("HOWLTO_LANGUAGE"));
+    }
+
+    #[test]
+    fn requested_variables_narrow_the_allowlist() {
+        let env = ChildEnv::new(Path::new("/bin/sh"), &["CARGO_*".to_string()]);
+        let mut command = Command::new("/bin/sh");
+        let requested = ["CARGO_PKG_NAME".to_string(), "RUSTFLAGS".to_string()];
+        env.apply(&mut command, Some(&requested));
+        let mut names = env_of(&command);
+        names.sort();
+        let mut expected = vec!["CARGO_PKG_NAME".to_string()];
+        expected.extend(
+            BASE_ENV
+                .iter()
+                .filter(|name| std::env::var_os(name).is_some())
+                .map(ToString::to_string),
+        );
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+}
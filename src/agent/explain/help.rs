@@ -0,0 +1,304 @@
+//! 从 `--help` 输出和 man 手册中提取选项说明.
+
+use super::tree::Source;
+
+/// 描述超过这个字符数时只保留第一句.
+const MAX_DESCRIPTION_CHARS: usize = 160;
+
+/// 帮助文本中的一个选项条目.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct OptionDoc {
+    /// 选项名, 如 `-x` 和 `--extract`.
+    pub names: Vec<String>,
+    /// 选项是否需要单独的参数值, 如 `-C DIR`. 可选的 `--color[=WHEN]` 不算.
+    pub takes_value: bool,
+    pub description: String,
+}
+
+/// 一个程序 (或子命令) 的帮助文本和 man 手册.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Docs {
+    pub help: Option<String>,
+    pub man: Option<String>,
+    options: Vec<(OptionDoc, Source)>,
+}
+
+impl Docs {
+    pub fn new(help: Option<String>, man: Option<String>) -> Self {
+        let help = help.map(|text| strip_overstrike(&text));
+        let man = man.map(|text| strip_overstrike(&text));
+        let mut options = Vec::new();
+        for (text, source) in [(&help, Source::Help), (&man, Source::Man)] {
+            if let Some(text) = text {
+                options.extend(self::options(text).into_iter().map(|doc| (doc, source)));
+            }
+        }
+        Self { help, man, options }
+    }
+
+    /// 查找选项, `--help` 中的说明优先.
+    pub fn option(&self, name: &str) -> Option<(&OptionDoc, Source)> {
+        self.options
+            .iter()
+            .find(|(doc, _)| doc.names.iter().any(|option| option == name))
+            .map(|(doc, source)| (doc, *source))
+    }
+
+    /// man 手册 NAME 一节中的简介.
+    pub fn summary(&self) -> Option<String> {
+        summary(self.man.as_deref()?)
+    }
+
+    /// 帮助中列出的子命令 `word` 的说明, 如 `build    Compile the current package`.
+    pub fn subcommand(&self, word: &str) -> Option<(String, Source)> {
+        [(&self.help, Source::Help), (&self.man, Source::Man)]
+            .into_iter()
+            .find_map(|(text, source)| Some((listed(text.as_deref()?, word)?, source)))
+    }
+}
+
+/// 去掉 man 输出中用退格表示的粗体和下划线.
+fn strip_overstrike(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for character in text.chars() {
+        if character == '\u{8}' {
+            output.pop();
+        } else {
+            output.push(character);
+        }
+    }
+    output
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_option_line(trimmed: &str) -> bool {
+    let mut characters = trimmed.chars();
+    characters.next() == Some('-')
+        && characters
+            .next()
+            .is_some_and(|character| character == '-' || character.is_ascii_alphanumeric())
+}
+
+/// 说明与选项之间至少隔两个空格或一个制表符, 返回说明开始的位置.
+fn description_start(line: &str) -> Option<usize> {
+    let gap = line.find("  ").into_iter().chain(line.find('\t')).min()?;
+    let start = gap + line[gap..].len() - line[gap..].trim_start().len();
+    (start < line.len()).then_some(start)
+}
+
+/// 提取所有形如 `-x, --extract  说明` 的选项条目. 说明写在下一行的 (man 手册常见) 也可以识别.
+pub(super) fn options(text: &str) -> Vec<OptionDoc> {
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut docs = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if !is_option_line(trimmed) {
+            continue;
+        }
+        let (spec, mut description) = match description_start(trimmed) {
+            Some(start) => (&trimmed[..start], vec![trimmed[start..].trim()]),
+            None => (trimmed, Vec::new()),
+        };
+        // 续行: 缩进比选项更深, 直到空行或下一个选项.
+        for next in &lines[index + 1..] {
+            let next_trimmed = next.trim_start();
+            if next_trimmed.is_empty()
+                || is_option_line(next_trimmed)
+                || indent(next) <= indent(line)
+            {
+                break;
+            }
+            description.push(next_trimmed.trim_end());
+        }
+        let (names, takes_value) = parse_spec(spec);
+        if names.is_empty() || description.is_empty() {
+            continue;
+        }
+        docs.push(OptionDoc {
+            names,
+            takes_value,
+            description: shorten(&description.join(" ")),
+        });
+    }
+    docs
+}
+
+/// 解析 `-f, --file=ARCHIVE`, `-C DIR`, `--color[=WHEN]`, `-n <num>` 这样的选项写法.
+fn parse_spec(spec: &str) -> (Vec<String>, bool) {
+    let mut names = Vec::new();
+    let mut takes_value = false;
+    let pieces = spec
+        .split([',', ' ', '|'])
+        .filter(|piece| !piece.is_empty())
+        .collect::<Vec<_>>();
+    for (index, piece) in pieces.iter().enumerate() {
+        if !is_option_line(piece) {
+            continue;
+        }
+        let end = piece.find(['=', '[', '<']).unwrap_or(piece.len());
+        let name = &piece[..end];
+        if piece[end..].starts_with('=') || piece[end..].starts_with('<') {
+            takes_value = true;
+        }
+        if let Some(next) = pieces.get(index + 1)
+            && !next.starts_with('-')
+            && !next.starts_with('[')
+            && next
+                .trim_matches(['<', '>'])
+                .chars()
+                .all(|character| character.is_ascii_uppercase() || "_-".contains(character))
+        {
+            takes_value = true;
+        }
+        if let Some(next) = pieces.get(index + 1)
+            && next.starts_with('<')
+        {
+            takes_value = true;
+        }
+        names.push(name.to_string());
+    }
+    (names, takes_value)
+}
+
+/// 压缩空白, 过长时只保留第一句.
+fn shorten(description: &str) -> String {
+    let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    if description.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return description;
+    }
+    match description.find(". ") {
+        Some(end) => description[..=end].to_string(),
+        None => description,
+    }
+}
+
+/// man 手册 NAME 一节的简介, 如 `tar - an archiving utility` 中的 `an archiving utility`.
+fn summary(man: &str) -> Option<String> {
+    let mut lines = man.lines().skip_while(|line| line.trim() != "NAME");
+    lines.next()?;
+    let mut text = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            if text.is_empty() {
+                continue;
+            }
+            break;
+        }
+        if indent(line) == 0 {
+            break;
+        }
+        text.push(line.trim());
+    }
+    let text = text.join(" ");
+    let (_, description) = [" - ", " — ", " -- "]
+        .into_iter()
+        .find_map(|separator| text.split_once(separator))?;
+    Some(shorten(description.trim()))
+}
+
+/// 帮助中缩进列出的 `word` (可以带别名, 如 `build, b`) 后面的说明.
+fn listed(text: &str, word: &str) -> Option<String> {
+    text.lines().find_map(|line| {
+        let trimmed = line.trim_start();
+        let rest = trimmed.strip_prefix(word)?;
+        if indent(line) == 0 || !(rest.starts_with([' ', '\t', ','])) {
+            return None;
+        }
+        let start = description_start(trimmed)?;
+        Some(shorten(&trimmed[start..]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Docs, Source, listed, options, parse_spec, summary};
+
+    const TAR_HELP: &str = "\
+Usage: tar [OPTION...] [FILE]...
+
+  -x, --extract, --get       extract files from an archive
+  -z, --gzip, --gunzip, --ungzip   filter the archive through gzip
+  -f, --file=ARCHIVE         use archive file or device ARCHIVE
+  -C, --directory=DIR        change to directory DIR
+      --color[=WHEN]         colorize the output; WHEN can be
+                             'always' or 'never'
+";
+
+    const GREP_MAN: &str = "\
+GREP(1)                     User Commands                    GREP(1)
+
+NAME
+       grep, egrep, fgrep - print lines that match patterns
+
+OPTIONS
+       -v, --invert-match
+              Invert the sense of matching, to select non-matching
+              lines.
+
+       -m NUM, --max-count=NUM
+              Stop reading a file after NUM matching lines.
+";
+
+    #[test]
+    fn options_are_extracted_from_help_and_man_pages() {
+        let docs = options(TAR_HELP);
+        assert_eq!(docs.len(), 5);
+        assert_eq!(docs[0].names, ["-x", "--extract", "--get"]);
+        assert!(!docs[0].takes_value);
+        assert_eq!(docs[2].description, "use archive file or device ARCHIVE");
+        assert!(docs[2].takes_value);
+        assert_eq!(docs[4].names, ["--color"]);
+        assert!(!docs[4].takes_value);
+        assert_eq!(
+            docs[4].description,
+            "colorize the output; WHEN can be 'always' or 'never'"
+        );
+
+        let docs = options(GREP_MAN);
+        assert_eq!(docs[0].names, ["-v", "--invert-match"]);
+        assert_eq!(
+            docs[0].description,
+            "Invert the sense of matching, to select non-matching lines."
+        );
+        assert_eq!(docs[1].names, ["-m", "--max-count"]);
+        assert!(docs[1].takes_value);
+    }
+
+    #[test]
+    fn option_specs_record_names_and_values() {
+        assert_eq!(parse_spec("-n <num>"), (vec!["-n".to_string()], true));
+        assert_eq!(
+            parse_spec("-h, --help"),
+            (vec!["-h".into(), "--help".into()], false)
+        );
+        assert_eq!(
+            parse_spec("--level=<n>"),
+            (vec!["--level".to_string()], true)
+        );
+    }
+
+    #[test]
+    fn summaries_and_subcommands_are_found() {
+        assert_eq!(
+            summary(GREP_MAN).as_deref(),
+            Some("print lines that match patterns")
+        );
+        let cargo = "Commands:\n    build, b    Compile the current package\n    check, c    Analyze the current package\n";
+        assert_eq!(
+            listed(cargo, "check").as_deref(),
+            Some("Analyze the current package")
+        );
+        assert_eq!(listed(cargo, "bui"), None);
+
+        let docs = Docs::new(
+            Some(TAR_HELP.to_string()),
+            Some(GREP_MAN.replace("-v", "-x")),
+        );
+        assert_eq!(docs.option("-x").unwrap().1, Source::Help);
+        assert_eq!(docs.option("--invert-match").unwrap().1, Source::Man);
+        assert!(docs.option("-q").is_none());
+    }
+}
@@ -0,0 +1,296 @@
+//! 静态解释命令: 把命令拆成管道, 程序, 选项和重定向, 像 explainshell 一样
+//! 用沙箱中读到的 `--help` 和 man 手册为每个选项配上说明, 只把本地无法解析的部分交给模型.
+
+mod help;
+mod tree;
+
+use std::{collections::HashMap, sync::Arc};
+
+use rig_core::{
+    agent::{Agent as RigAgent, MultiTurnStreamItem},
+    message::Message,
+    providers::openai::CompletionModel,
+    streaming::{StreamedAssistantContent, StreamingChat},
+    tool::Tool,
+};
+use tokio_stream::StreamExt;
+use tracing::{debug, info, warn};
+
+pub use tree::{Explanation, Part, PartKind, Source};
+
+use self::{
+    help::Docs,
+    tree::{Segment, SimpleCommand},
+};
+use crate::{
+    agent::{
+        answer::completion_model,
+        cache::ToolCache,
+        command::{CommandArgs, CommandStatus, Explore, ExploreArgs},
+        env::ChildEnv,
+        policy::CommandPolicy,
+    },
+    config::{AppConfig, DEFAULT_OPENAI_BASE_URL, SandboxBackend},
+    error::{Error, Result},
+    shell::Shell,
+};
+
+/// 读取一份帮助或 man 手册的超时时间.
+const DOCS_TIMEOUT_SECS: u64 = 10;
+
+pub struct ExplainAgent {
+    explore: Explore,
+    /// 没有配置模型 (base_url 为空, 或使用 OpenAI 却没有 API key) 时为 [`None`],
+    /// 此时只输出本地能解析的说明.
+    agent: Option<RigAgent<CompletionModel>>,
+    /// 没有沙箱时只读取不需要确认的 man 手册, 不执行 `--help`.
+    run_help: bool,
+}
+
+#[bon::bon]
+impl ExplainAgent {
+    #[builder]
+    pub fn new(shell: &Shell, config: AppConfig) -> Result<Self> {
+        let shell_path = shell.path().to_path_buf();
+        let explore = Explore::new(
+            shell_path.clone(),
+            Arc::new(CommandPolicy::new(&config.agent.policy)?),
+            Arc::new(ToolCache::new(config.agent.cache)),
+            config.agent.sandbox.clone(),
+            ChildEnv::new(&shell_path, &config.agent.env),
+        );
+        let preamble = format!(
+            "You annotate shell commands in the style of explainshell. For each numbered part of the command, write a one-line description of what it does in this command, in {}. Reply with only a JSON object that maps each number to its description, like {{\"1\": \"...\", \"2\": \"...\"}}.",
+            config.agent.language
+        );
+        let llm = &config.llm;
+        let agent = if llm.base_url.is_empty()
+            || (llm.api_key.is_empty()
+                && llm.base_url.trim_end_matches('/') == DEFAULT_OPENAI_BASE_URL)
+        {
+            None
+        } else {
+            let mut builder =
+                rig_core::agent::AgentBuilder::new(completion_model(llm)?).preamble(&preamble);
+            if let Some(max_tokens) = llm.max_tokens {
+                builder = builder.max_tokens(max_tokens);
+            }
+            if let Some(temperature) = llm.temperature {
+                builder = builder.temperature(temperature);
+            }
+            Some(builder.build())
+        };
+        Ok(Self {
+            explore,
+            agent,
+            run_help: config.agent.sandbox.backend != SandboxBackend::None,
+        })
+    }
+}
+
+impl ExplainAgent {
+    /// 解释 `command`. 模型请求失败时保留没有说明的部分, 不返回错误.
+    pub async fn explain(&self, command: &str) -> Result<Explanation> {
+        let command = command.trim();
+        if command.is_empty() {
+            return Err(Error::InvalidInput("Nothing to explain.".to_string()));
+        }
+        let mut cache = HashMap::new();
+        let mut explanation = Explanation {
+            command: command.to_string(),
+            parts: self.parts(tree::segments(command), &mut cache).await,
+        };
+        self.describe_unresolved(&mut explanation).await;
+        Ok(explanation)
+    }
+
+    async fn parts(
+        &self,
+        segments: Vec<Segment>,
+        cache: &mut HashMap<Vec<String>, Docs>,
+    ) -> Vec<tree::Part> {
+        let mut parts = Vec::new();
+        for segment in segments {
+            match segment {
+                Segment::Operator(operator) => parts.push(tree::operator(&operator)),
+                Segment::Command(simple) => parts.extend(self.command_parts(&simple, cache).await),
+                Segment::Group { subshell, segments } => {
+                    let children = Box::pin(self.parts(segments, cache)).await;
+                    parts.push(tree::group(subshell, children));
+                }
+            }
+        }
+        parts
+    }
+
+    async fn command_parts(
+        &self,
+        command: &SimpleCommand,
+        cache: &mut HashMap<Vec<String>, Docs>,
+    ) -> Vec<tree::Part> {
+        let mut words = command.words();
+        let Some(program) = words.next().filter(|word| documentable(word)) else {
+            return tree::command_parts(command, &Docs::default(), None);
+        };
+        let docs = self.docs(cache, vec![program.to_string()]).await;
+        let subcommand = match words.next() {
+            Some(word) if documentable(word) && docs.subcommand(word).is_some() => Some(
+                self.docs(cache, vec![program.to_string(), word.to_string()])
+                    .await,
+            ),
+            _ => None,
+        };
+        tree::command_parts(command, &docs, subcommand.as_ref())
+    }
+
+    /// 读取程序 (或子命令) 的 `--help` 和 man 手册, 同一次解释中只读取一次.
+    async fn docs(&self, cache: &mut HashMap<Vec<String>, Docs>, words: Vec<String>) -> Docs {
+        if let Some(docs) = cache.get(&words) {
+            return docs.clone();
+        }
+        let help = match self.run_help {
+            true => self.run(format!("{} --help", words.join(" ")), true).await,
+            false => None,
+        };
+        // 子命令的 man 手册按惯例命名为 `git-commit`.
+        let man = self.run(format!("man {}", words.join("-")), false).await;
+        debug!(
+            program = words.join(" "),
+            help = help.is_some(),
+            man = man.is_some(),
+            "Read documentation."
+        );
+        let docs = Docs::new(help, man);
+        cache.insert(words, docs.clone());
+        docs
+    }
+
+    /// 用 explore 执行只读命令, 返回其输出. 很多程序打印帮助后以非零状态退出, `lenient` 时也接受.
+    async fn run(&self, command: String, lenient: bool) -> Option<String> {
+        let args = ExploreArgs::from(CommandArgs {
+            command,
+            timeout_secs: Some(DOCS_TIMEOUT_SECS),
+            workdir: None,
+            env: None,
+        });
+        let Ok(result) = self.explore.call(args).await;
+        let accepted = match result.status {
+            CommandStatus::Success => true,
+            // 127: 找不到程序.
+            CommandStatus::Failed => lenient && result.exit_code != Some(127),
+            _ => false,
+        };
+        if !accepted {
+            return None;
+        }
+        let text = match result.stdout.trim().is_empty() {
+            true => result.stderr,
+            false => result.stdout,
+        };
+        (!text.trim().is_empty()).then_some(text)
+    }
+
+    /// 把本地无法解析的程序, 子命令和选项一次性交给模型解释.
+    async fn describe_unresolved(&self, explanation: &mut Explanation) {
+        let mut pending = Vec::new();
+        tree::unresolved(&mut explanation.parts, None, &mut pending);
+        if pending.is_empty() {
+            return;
+        }
+        let Some(agent) = &self.agent else {
+            info!(
+                parts = pending.len(),
+                "No model is configured, leaving the remaining parts undescribed."
+            );
+            return;
+        };
+        let list = pending
+            .iter()
+            .enumerate()
+            .map(|(index, part)| format!("{}. {}", index + 1, part.label))
+            .collect::<Vec<_>>()
+            .join("\n");
+        info!(
+            parts = pending.len(),
+            "Asking the model for the remaining descriptions."
+        );
+        let prompt = format!(
+            "Command:\n```\n{}\n```\n\nParts:\n{list}",
+            explanation.command
+        );
+        let reply = ask(agent, prompt).await;
+        let mut descriptions = match reply.map(|text| parse_descriptions(&text)) {
+            Ok(Some(descriptions)) => descriptions,
+            Ok(None) => {
+                warn!("Model did not reply with a JSON object of descriptions.");
+                return;
+            }
+            Err(error) => {
+                warn!(%error, "Could not describe the remaining parts.");
+                return;
+            }
+        };
+        for (index, part) in pending.into_iter().enumerate() {
+            if let Some(description) = descriptions.remove(&(index + 1).to_string())
+                && !description.trim().is_empty()
+            {
+                part.resolve(description.trim().to_string());
+            }
+        }
+    }
+}
+
+/// 向模型提问, 返回回答的全文.
+async fn ask(agent: &RigAgent<CompletionModel>, prompt: String) -> Result<String> {
+    let mut stream = agent.stream_chat(prompt, Vec::<Message>::new()).await;
+    let mut output = String::new();
+    while let Some(item) = stream.next().await {
+        let item = item.map_err(|error| Error::StreamingError(error.to_string()))?;
+        if let MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text)) = item
+        {
+            output.push_str(&text.text);
+        }
+    }
+    Ok(output)
+}
+
+/// 只为普通的程序名读取文档, 路径, 变量和引号都不执行.
+fn documentable(word: &str) -> bool {
+    !word.starts_with('-')
+        && !word.is_empty()
+        && word
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "._+-".contains(character))
+}
+
+/// 取出回答中的 JSON 对象, 允许外面包着代码块或说明文字.
+fn parse_descriptions(text: &str) -> Option<HashMap<String, String>> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    serde_json::from_str(text.get(start..=end)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{documentable, parse_descriptions};
+
+    #[test]
+    fn descriptions_are_parsed_from_fenced_json() {
+        let descriptions =
+            parse_descriptions("```json\n{\"1\": \"archive files\", \"2\": \"quiet\"}\n```")
+                .unwrap();
+        assert_eq!(descriptions["1"], "archive files");
+        assert_eq!(descriptions.len(), 2);
+        assert!(parse_descriptions("no idea").is_none());
+    }
+
+    #[test]
+    fn only_plain_program_names_are_documented() {
+        assert!(documentable("git"));
+        assert!(documentable("g++"));
+        assert!(!documentable("./build.sh"));
+        assert!(!documentable("$EDITOR"));
+        assert!(!documentable("-v"));
+        assert!(!documentable("a;b"));
+    }
+}
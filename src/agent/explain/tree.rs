@@ -0,0 +1,670 @@
+//! 解释树: 把命令拆成管道, 子 shell 和命令组, 程序, 子命令, 选项和重定向,
+//! 并渲染成带说明的树.
+
+use std::fmt::Display;
+
+use unicode_width::UnicodeWidthStr;
+
+use super::help::{Docs, OptionDoc};
+use crate::shell::parse::{Token, is_assignment, tokens};
+
+/// 说明的来源.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Help,
+    Man,
+    /// 内置的 shell 语法说明.
+    Shell,
+    /// 本地无法解析, 由模型补充.
+    Model,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Help => "help",
+            Self::Man => "man",
+            Self::Shell => "shell",
+            Self::Model => "model",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartKind {
+    Program,
+    Subcommand,
+    Flag,
+    Argument,
+    Redirection,
+    Operator,
+    Assignment,
+    /// 子 shell `( ... )` 或命令组 `{ ...; }`, 子节点是其中的命令.
+    Group,
+}
+
+/// 解释树中的一个节点. 程序的子节点是它的子命令, 选项, 参数和重定向,
+/// 合并写法的短选项 (`-xzf`) 的子节点是其中的每个选项.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub text: String,
+    pub kind: PartKind,
+    pub description: Option<String>,
+    pub source: Option<Source>,
+    pub children: Vec<Part>,
+}
+
+impl Part {
+    fn new(text: impl Into<String>, kind: PartKind) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+            description: None,
+            source: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn described(mut self, description: Option<(String, Source)>) -> Self {
+        if let Some((description, source)) = description {
+            self.description = Some(description);
+            self.source = Some(source);
+        }
+        self
+    }
+}
+
+/// 一条命令的解释, [`Display`] 输出带说明的树.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub command: String,
+    pub parts: Vec<Part>,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.command)?;
+        let mut lines = Vec::new();
+        tree_lines(&self.parts, "", &mut lines);
+        let width = lines
+            .iter()
+            .map(|(left, _)| left.width())
+            .max()
+            .unwrap_or(0);
+        for (left, part) in lines {
+            match (&part.description, part.source) {
+                (Some(description), source) => {
+                    let padding = " ".repeat(width - left.width() + 2);
+                    write!(f, "{left}{padding}{description}")?;
+                    if let Some(source) = source {
+                        write!(f, "  [{source}]")?;
+                    }
+                    writeln!(f)?;
+                }
+                (None, _) => writeln!(f, "{left}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn tree_lines<'a>(parts: &'a [Part], prefix: &str, lines: &mut Vec<(String, &'a Part)>) {
+    for (index, part) in parts.iter().enumerate() {
+        let last = index + 1 == parts.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let text = part.text.lines().collect::<Vec<_>>().join(" ");
+        lines.push((format!("{prefix}{branch}{text}"), part));
+        tree_lines(&part.children, &format!("{prefix}{indent}"), lines);
+    }
+}
+
+/// 没有说明, 需要交给模型解释的程序, 子命令或选项.
+pub(super) struct Unresolved<'a> {
+    /// 提供给模型的描述, 如 ``-q` (option of `tar`)`.
+    pub label: String,
+    description: &'a mut Option<String>,
+    source: &'a mut Option<Source>,
+}
+
+impl Unresolved<'_> {
+    pub fn resolve(self, description: String) {
+        *self.description = Some(description);
+        *self.source = Some(Source::Model);
+    }
+}
+
+/// 按树的顺序收集没有说明的部分. 参数属于用户数据, 不需要解释.
+pub(super) fn unresolved<'a>(
+    parts: &'a mut [Part],
+    program: Option<&str>,
+    output: &mut Vec<Unresolved<'a>>,
+) {
+    for part in parts {
+        let Part {
+            text,
+            kind,
+            description,
+            source,
+            children,
+        } = part;
+        let label = match (*kind, program) {
+            (PartKind::Program, _) => Some(format!("`{text}` (program)")),
+            (PartKind::Subcommand, Some(program)) => {
+                Some(format!("`{text}` (subcommand of `{program}`)"))
+            }
+            // 合并写法的短选项由子节点分别解释.
+            (PartKind::Flag, Some(program)) if children.is_empty() => {
+                Some(format!("`{text}` (option of `{program}`)"))
+            }
+            _ => None,
+        };
+        if let Some(label) = label
+            && description.is_none()
+        {
+            output.push(Unresolved {
+                label,
+                description,
+                source,
+            });
+        }
+        let program = if *kind == PartKind::Program {
+            Some(text.as_str())
+        } else {
+            program
+        };
+        unresolved(children, program, output);
+    }
+}
+
+/// 单词或带目标的重定向.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Item {
+    Word(String),
+    Redirection {
+        operator: String,
+        target: Option<String>,
+    },
+}
+
+/// 一条简单命令: 开头的变量赋值, 之后的单词和重定向.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct SimpleCommand {
+    pub assignments: Vec<String>,
+    pub items: Vec<Item>,
+}
+
+impl SimpleCommand {
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Word(word) => Some(word.as_str()),
+            Item::Redirection { .. } => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Segment {
+    Command(SimpleCommand),
+    Operator(String),
+    /// 子 shell (`subshell`) 或命令组中的命令.
+    Group {
+        subshell: bool,
+        segments: Vec<Segment>,
+    },
+}
+
+/// 按控制操作符把命令切分成简单命令, 子 shell 和命令组切分成嵌套的片段.
+pub(super) fn segments(command: &str) -> Vec<Segment> {
+    let mut tokens = tokens(command).into_iter().peekable();
+    let mut output = Vec::new();
+    // 多余的 `)` 或 `}` 结束不了最外层, 其后的命令照常切分.
+    while tokens.peek().is_some() {
+        output.extend(split_group(&mut tokens, None));
+    }
+    output
+}
+
+/// 切分到 `close` (`)` 或 `}`) 为止, 最外层为 [`None`].
+fn split_group(
+    tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+    close: Option<&str>,
+) -> Vec<Segment> {
+    let mut output = Vec::new();
+    let mut current = SimpleCommand::default();
+    while let Some(token) = tokens.next() {
+        let starts_command = current == SimpleCommand::default();
+        match token {
+            Token::Operator(operator) if operator == "(" && starts_command => {
+                let segments = split_group(tokens, Some(")"));
+                output.push(Segment::Group {
+                    subshell: true,
+                    segments,
+                });
+            }
+            Token::Word(word) if word == "{" && starts_command => {
+                let segments = split_group(tokens, Some("}"));
+                output.push(Segment::Group {
+                    subshell: false,
+                    segments,
+                });
+            }
+            Token::Operator(operator) if operator == ")" => {
+                if close == Some(")") {
+                    break;
+                }
+            }
+            Token::Word(word) if word == "}" && starts_command && close == Some("}") => break,
+            Token::Operator(operator) => {
+                if current != SimpleCommand::default() {
+                    output.push(Segment::Command(std::mem::take(&mut current)));
+                }
+                output.push(Segment::Operator(operator));
+            }
+            Token::Redirection(operator) => {
+                let target = if duplicates(&operator) {
+                    None
+                } else {
+                    tokens.next_if(|token| matches!(token, Token::Word(_)))
+                };
+                let target = target.map(|token| match token {
+                    Token::Word(word) => word,
+                    _ => unreachable!("only words are taken as targets"),
+                });
+                current.items.push(Item::Redirection { operator, target });
+            }
+            Token::Word(word) => {
+                if current.items.is_empty() && is_assignment(&word) {
+                    current.assignments.push(word);
+                } else {
+                    current.items.push(Item::Word(word));
+                }
+            }
+        }
+    }
+    if current != SimpleCommand::default() {
+        output.push(Segment::Command(current));
+    }
+    // 命令组的 `}` 前必须有 `;` 或换行, 这个 `;` 不需要解释.
+    if close == Some("}")
+        && matches!(output.last(), Some(Segment::Operator(operator)) if operator == ";")
+    {
+        output.pop();
+    }
+    output
+}
+
+/// `2>&1`, `>&-` 这样复制或关闭文件描述符的重定向, 没有单独的目标单词.
+fn duplicates(operator: &str) -> bool {
+    operator
+        .rsplit_once('&')
+        .is_some_and(|(head, tail)| head.ends_with(['<', '>']) && !tail.is_empty())
+}
+
+/// 按帮助和 man 手册为一条简单命令建立解释树. `subcommand` 是第二个单词作为子命令时的文档.
+pub(super) fn command_parts(
+    command: &SimpleCommand,
+    program: &Docs,
+    subcommand: Option<&Docs>,
+) -> Vec<Part> {
+    let mut children = command
+        .assignments
+        .iter()
+        .map(|assignment| {
+            let name = assignment.split_once('=').map_or("", |(name, _)| name);
+            Part::new(assignment, PartKind::Assignment).described(Some((
+                format!("set the environment variable {name} for this command"),
+                Source::Shell,
+            )))
+        })
+        .collect::<Vec<_>>();
+    let lookup = |name: &str| {
+        subcommand
+            .and_then(|docs| docs.option(name))
+            .or_else(|| program.option(name))
+    };
+    let mut root = None;
+    let mut end_of_options = false;
+    let mut items = command.items.iter().peekable();
+    while let Some(item) = items.next() {
+        let word = match item {
+            Item::Redirection { operator, target } => {
+                children.push(redirection(operator, target.as_deref()));
+                continue;
+            }
+            Item::Word(word) => word,
+        };
+        if root.is_none() {
+            root = Some(word.clone());
+            if let Some(docs) = subcommand
+                && let Some(Item::Word(name)) = items.next_if(|item| matches!(item, Item::Word(_)))
+            {
+                let description = program
+                    .subcommand(name)
+                    .or_else(|| docs.summary().map(|summary| (summary, Source::Man)));
+                children.push(Part::new(name, PartKind::Subcommand).described(description));
+            }
+            continue;
+        }
+        if end_of_options || word.len() < 2 || !word.starts_with('-') {
+            children.push(Part::new(word, PartKind::Argument));
+            continue;
+        }
+        if word == "--" {
+            end_of_options = true;
+            children.push(Part::new(word, PartKind::Flag).described(Some((
+                "end of options, the following words are arguments".to_string(),
+                Source::Shell,
+            ))));
+            continue;
+        }
+        let (mut part, takes_value) = flag(word, lookup);
+        if takes_value
+            && let Some(Item::Word(value)) = items.next_if(|item| matches!(item, Item::Word(_)))
+        {
+            part.text = format!("{} {value}", part.text);
+        }
+        children.push(part);
+    }
+    match root {
+        Some(text) => {
+            let summary = program.summary().map(|summary| (summary, Source::Man));
+            let mut part = Part::new(text, PartKind::Program).described(summary);
+            part.children = children;
+            vec![part]
+        }
+        None => children,
+    }
+}
+
+/// 解释一个选项, 返回节点和它是否还需要下一个单词作为参数值.
+fn flag<'a>(word: &str, lookup: impl Fn(&str) -> Option<(&'a OptionDoc, Source)>) -> (Part, bool) {
+    let described = |doc: &OptionDoc, source| Some((doc.description.clone(), source));
+    if let Some((name, _)) = word.split_once('=')
+        && word.starts_with("--")
+    {
+        let doc = lookup(name);
+        let part = Part::new(word, PartKind::Flag)
+            .described(doc.and_then(|(doc, source)| described(doc, source)));
+        return (part, false);
+    }
+    if let Some((doc, source)) = lookup(word) {
+        let part = Part::new(word, PartKind::Flag).described(described(doc, source));
+        return (part, doc.takes_value);
+    }
+    if word.starts_with("--") {
+        return (Part::new(word, PartKind::Flag), false);
+    }
+    let letters = word[1..]
+        .chars()
+        .map(|letter| format!("-{letter}"))
+        .collect::<Vec<_>>();
+    // `-n5`: 需要参数值的短选项后面直接跟着值.
+    if let Some((doc, source)) = lookup(&letters[0])
+        && doc.takes_value
+    {
+        let part = Part::new(word, PartKind::Flag).described(described(doc, source));
+        return (part, false);
+    }
+    let mut part = Part::new(word, PartKind::Flag);
+    let mut takes_value = false;
+    let mut resolved = false;
+    for (index, letter) in letters.iter().enumerate() {
+        let doc = lookup(letter);
+        resolved |= doc.is_some();
+        // 需要参数值的选项之后的字母属于参数值, 如 `-xfarchive.tar`.
+        let value = doc.is_some_and(|(doc, _)| doc.takes_value);
+        if value && index + 1 < letters.len() {
+            part.children.push(
+                Part::new(format!("{letter}{}", &word[index + 2..]), PartKind::Flag)
+                    .described(doc.and_then(|(doc, source)| described(doc, source))),
+            );
+            break;
+        }
+        takes_value = value;
+        part.children.push(
+            Part::new(letter, PartKind::Flag)
+                .described(doc.and_then(|(doc, source)| described(doc, source))),
+        );
+    }
+    if !resolved {
+        part.children.clear();
+        takes_value = false;
+    }
+    (part, takes_value)
+}
+
+/// 文件描述符的名称.
+fn descriptor(fd: &str) -> String {
+    match fd {
+        "0" => "standard input".to_string(),
+        "1" => "standard output".to_string(),
+        "2" => "standard error".to_string(),
+        fd => format!("file descriptor {fd}"),
+    }
+}
+
+fn redirection(operator: &str, target: Option<&str>) -> Part {
+    let text = match target {
+        Some(target) => format!("{operator} {target}"),
+        None => operator.to_string(),
+    };
+    let digits = operator.chars().take_while(char::is_ascii_digit).count();
+    let (fd, symbol) = operator.split_at(digits);
+    let output = || descriptor(if fd.is_empty() { "1" } else { fd });
+    let input = || descriptor(if fd.is_empty() { "0" } else { fd });
+    let description = match symbol {
+        ">" => format!("write {} to the file, truncating it", output()),
+        ">|" => format!(
+            "write {} to the file, truncating it even with noclobber set",
+            output()
+        ),
+        ">>" => format!("append {} to the file", output()),
+        "<" => format!("read {} from the file", input()),
+        "<>" => format!("open the file for reading and writing as {}", input()),
+        "&>" | ">&" => "write standard output and standard error to the file".to_string(),
+        "&>>" => "append standard output and standard error to the file".to_string(),
+        "<<" => format!(
+            "here-document: read {} from the lines up to the delimiter",
+            input()
+        ),
+        "<<-" => format!(
+            "here-document: read {} from the lines up to the delimiter, stripping leading tabs",
+            input()
+        ),
+        "<<<" => format!("here-string: read {} from the word", input()),
+        symbol => match symbol.split_once('&') {
+            Some((_, "-")) if symbol.starts_with('<') => format!("close {}", input()),
+            Some((_, "-")) => format!("close {}", output()),
+            Some((_, target)) if symbol.starts_with('<') => {
+                format!("read {} from {}", input(), descriptor(target))
+            }
+            Some((_, target)) => format!("send {} to {}", output(), descriptor(target)),
+            None => return Part::new(text, PartKind::Redirection),
+        },
+    };
+    Part::new(text, PartKind::Redirection).described(Some((description, Source::Shell)))
+}
+
+/// 子 shell 或命令组, `children` 是其中命令的解释.
+pub(super) fn group(subshell: bool, children: Vec<Part>) -> Part {
+    let (text, description) = if subshell {
+        (
+            "( ... )",
+            "run the commands in a subshell, so directory and variable changes do not leak out",
+        )
+    } else {
+        ("{ ...; }", "run the commands as one group in the current shell")
+    };
+    let mut part = Part::new(text, PartKind::Group)
+        .described(Some((description.to_string(), Source::Shell)));
+    part.children = children;
+    part
+}
+
+pub(super) fn operator(operator: &str) -> Part {
+    let description = match operator {
+        "|" => "pipe standard output of the previous command into the next one",
+        "|&" => "pipe standard output and standard error of the previous command into the next one",
+        "&&" => "run the next command only if the previous one succeeds",
+        "||" => "run the next command only if the previous one fails",
+        ";" => "run the next command after the previous one finishes",
+        "&" => "run the previous command in the background",
+        _ => return Part::new(operator, PartKind::Operator),
+    };
+    Part::new(operator, PartKind::Operator)
+        .described(Some((description.to_string(), Source::Shell)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Explanation, Item, Part, PartKind, Segment, command_parts, group, operator, segments,
+        unresolved,
+    };
+    use crate::agent::explain::help::Docs;
+
+    const TAR_HELP: &str = "\
+  -x, --extract              extract files from an archive
+  -z, --gzip                 filter the archive through gzip
+  -f, --file=ARCHIVE         use archive file or device ARCHIVE
+  -C, --directory=DIR        change to directory DIR
+";
+
+    fn parts(segments: Vec<Segment>, docs: &Docs) -> Vec<Part> {
+        segments
+            .into_iter()
+            .flat_map(|segment| match segment {
+                Segment::Command(command) => command_parts(&command, docs, None),
+                Segment::Operator(text) => vec![operator(&text)],
+                Segment::Group { subshell, segments } => {
+                    vec![group(subshell, parts(segments, docs))]
+                }
+            })
+            .collect()
+    }
+
+    fn explain(command: &str, docs: &Docs) -> Explanation {
+        Explanation {
+            command: command.to_string(),
+            parts: parts(segments(command), docs),
+        }
+    }
+
+    #[test]
+    fn commands_are_split_into_assignments_words_and_redirections() {
+        let segments = segments("LANG=C sort -u < in.txt 2>&1 | head >> out.txt");
+        let Segment::Command(sort) = &segments[0] else {
+            panic!("expected a command");
+        };
+        assert_eq!(sort.assignments, ["LANG=C"]);
+        assert_eq!(sort.words().collect::<Vec<_>>(), ["sort", "-u"]);
+        assert_eq!(
+            sort.items[2],
+            Item::Redirection {
+                operator: "<".to_string(),
+                target: Some("in.txt".to_string()),
+            }
+        );
+        assert_eq!(
+            sort.items[3],
+            Item::Redirection {
+                operator: "2>&1".to_string(),
+                target: None,
+            }
+        );
+        assert_eq!(segments[1], Segment::Operator("|".to_string()));
+        assert_eq!(segments.len(), 3);
+    }
+
+    #[test]
+    fn combined_flags_and_values_are_annotated_from_help() {
+        let docs = Docs::new(Some(TAR_HELP.to_string()), None);
+        let explanation = explain("tar -xzf a.tgz -C out --quiet", &docs);
+        let tar = &explanation.parts[0];
+        assert_eq!(tar.kind, PartKind::Program);
+        let texts = tar
+            .children
+            .iter()
+            .map(|part| part.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["-xzf a.tgz", "-C out", "--quiet"]);
+        assert_eq!(tar.children[0].children.len(), 3);
+        assert_eq!(
+            tar.children[0].children[2].description.as_deref(),
+            Some("use archive file or device ARCHIVE")
+        );
+        assert_eq!(
+            tar.children[1].description.as_deref(),
+            Some("change to directory DIR")
+        );
+        assert!(tar.children[2].description.is_none());
+
+        let mut parts = explanation.parts.clone();
+        let mut pending = Vec::new();
+        unresolved(&mut parts, None, &mut pending);
+        let labels = pending
+            .iter()
+            .map(|part| part.label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["`tar` (program)", "`--quiet` (option of `tar`)"]);
+        pending
+            .pop()
+            .unwrap()
+            .resolve("suppress output".to_string());
+        assert_eq!(
+            parts[0].children[2].description.as_deref(),
+            Some("suppress output")
+        );
+    }
+
+    #[test]
+    fn explanations_render_as_aligned_trees() {
+        let docs = Docs::new(Some(TAR_HELP.to_string()), None);
+        let explanation = explain("tar -xf a.tar 2>/dev/null && ls", &docs);
+        assert_eq!(
+            explanation.to_string(),
+            "\
+tar -xf a.tar 2>/dev/null && ls
+├── tar
+│   ├── -xf a.tar
+│   │   ├── -x        extract files from an archive  [help]
+│   │   └── -f        use archive file or device ARCHIVE  [help]
+│   └── 2> /dev/null  write standard error to the file, truncating it  [shell]
+├── &&                run the next command only if the previous one succeeds  [shell]
+└── ls
+"
+        );
+    }
+
+    #[test]
+    fn subshells_and_groups_become_nodes() {
+        let command = "( cd / && ls ) | wc -l; { echo a; echo b; } > out";
+        let explanation = explain(command, &Docs::default());
+        assert_eq!(
+            explanation.to_string(),
+            "\
+( cd / && ls ) | wc -l; { echo a; echo b; } > out
+├── ( ... )    run the commands in a subshell, so directory and variable changes do not leak out  [shell]
+│   ├── cd
+│   │   └── /
+│   ├── &&     run the next command only if the previous one succeeds  [shell]
+│   └── ls
+├── |          pipe standard output of the previous command into the next one  [shell]
+├── wc
+│   └── -l
+├── ;          run the next command after the previous one finishes  [shell]
+├── { ...; }   run the commands as one group in the current shell  [shell]
+│   ├── echo
+│   │   └── a
+│   ├── ;      run the next command after the previous one finishes  [shell]
+│   └── echo
+│       └── b
+└── > out      write standard output to the file, truncating it  [shell]
+"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! 会话内记住的 elevate 授权.
+//!
+//! 用户在确认对话框中可以选择只批准一次, 在本会话中批准完全相同的命令,
+//! 或者在当前目录中批准某个程序. 后两种授权随会话一起保存, 恢复会话时重新生效.
+//! 能够代为执行任意命令的程序 (见 [`UNGRANTABLE`]) 不能按程序授权.
+
+use std::{fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::shell::parse;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ElevateGrant {
+    /// 本会话中完全相同的命令文本和 `env` 参数.
+    Command {
+        command: String,
+        /// 模型用 `env` 参数指定的变量名, 变量不同的调用需要重新确认.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        env: Option<Vec<String>>,
+    },
+    /// 在 `dir` 目录中执行的某个程序, 参数不限.
+    Program { program: String, dir: String },
+}
+
+impl fmt::Display for ElevateGrant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Command { command, env: None } => write!(f, "command `{command}`"),
+            Self::Command {
+                command,
+                env: Some(env),
+            } => write!(f, "command `{command}` with env {}", env.join(", ")),
+            Self::Program { program, dir } => write!(f, "program `{program}` in {dir}"),
+        }
+    }
+}
+
+/// 参数中可以携带另一条命令的程序. 授权 `env` 或 `sh` 等于授权任意命令,
+/// 因此它们只能批准一次或按完整命令授权.
+const UNGRANTABLE: [&str; 40] = [
+    "env", "sudo", "doas", "su", "pkexec", "runuser", "xargs", "parallel", "find", "fd", "exec",
+    "eval", "command", "builtin", "nohup", "nice", "ionice", "timeout", "stdbuf", "setsid",
+    "chroot", "unshare", "nsenter", "strace", "watch", "sh", "bash", "dash", "zsh", "fish", "ksh",
+    "nu", "busybox", "python", "python3", "perl", "ruby", "node", "awk", "ssh",
+];
+
+/// 命令中的每个程序是否都可以按程序授权. 带变量赋值的片段 (`LD_PRELOAD=... git fetch`)
+/// 会改变程序的行为, 也不能按程序授权.
+pub fn grantable(command: &str) -> bool {
+    let programs = programs(command);
+    !programs.is_empty()
+        && programs
+            .iter()
+            .all(|program| !UNGRANTABLE.contains(&program.as_str()))
+        && parse::split(command)
+            .iter()
+            .all(|segment| segment.assignments.is_empty())
+}
+
+/// 命令中每个片段对应的程序名, 去重并保持顺序.
+pub fn programs(command: &str) -> Vec<String> {
+    let mut programs = Vec::<String>::new();
+    for segment in parse::segments(command) {
+        if let Some(program) = parse::program(&segment)
+            && !programs.iter().any(|known| known == program)
+        {
+            programs.push(program.to_string());
+        }
+    }
+    programs
+}
+
+#[derive(Debug, Default)]
+pub struct ElevateGrants {
+    grants: RwLock<Vec<ElevateGrant>>,
+}
+
+impl ElevateGrants {
+    pub async fn snapshot(&self) -> Vec<ElevateGrant> {
+        self.grants.read().await.clone()
+    }
+
+    pub async fn replace(&self, grants: Vec<ElevateGrant>) {
+        *self.grants.write().await = grants;
+    }
+
+    pub async fn add(&self, grant: ElevateGrant) {
+        let mut grants = self.grants.write().await;
+        if !grants.contains(&grant) {
+            grants.push(grant);
+        }
+    }
+
+    /// 撤销第 `index` 个授权 (从 0 开始).
+    pub async fn revoke(&self, index: usize) -> Option<ElevateGrant> {
+        let mut grants = self.grants.write().await;
+        (index < grants.len()).then(|| grants.remove(index))
+    }
+
+    pub async fn clear(&self) {
+        self.grants.write().await.clear();
+    }
+
+    /// 已有授权是否覆盖在 `cwd` 中以 `env` 参数执行的 `command`.
+    ///
+    /// 程序授权要求命令的每个片段都对应一个在该目录下被授权的程序,
+    /// 且其中没有 [`UNGRANTABLE`] 中的程序和变量赋值 (即使会话里保存了这样的授权).
+    pub async fn covers(&self, command: &str, env: Option<&[String]>, cwd: &Path) -> bool {
+        let grants = self.grants.read().await;
+        if grants.iter().any(|grant| {
+            matches!(
+                grant,
+                ElevateGrant::Command { command: granted, env: granted_env }
+                    if granted == command && granted_env.as_deref() == env
+            )
+        }) {
+            return true;
+        }
+        if !grantable(command) {
+            return false;
+        }
+        let cwd = cwd.to_string_lossy();
+        programs(command).iter().all(|program| {
+            grants.iter().any(|grant| {
+                matches!(
+                    grant,
+                    ElevateGrant::Program { program: granted, dir }
+                        if granted == program && *dir == cwd
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn command_grant_matches_exact_text_only() {
+        let grants = ElevateGrants::default();
+        grants
+            .add(ElevateGrant::Command {
+                command: "cargo build".to_string(),
+                env: None,
+            })
+            .await;
+        assert!(grants.covers("cargo build", None, Path::new("/work")).await);
+        assert!(!grants.covers("cargo build --release", None, Path::new("/work")).await);
+        let env = ["RUSTFLAGS".to_string()];
+        assert!(!grants.covers("cargo build", Some(&env), Path::new("/work")).await);
+    }
+
+    #[tokio::test]
+    async fn program_grant_is_scoped_to_directory_and_every_segment() {
+        let grants = ElevateGrants::default();
+        grants
+            .add(ElevateGrant::Program {
+                program: "cargo".to_string(),
+                dir: "/work".to_string(),
+            })
+            .await;
+        assert!(grants.covers("cargo build --release", None, Path::new("/work")).await);
+        assert!(!grants.covers("cargo build", None, Path::new("/other")).await);
+        let work = Path::new("/work");
+        assert!(!grants.covers("cargo build && rm -rf target", None, work).await);
+        assert!(!grants.covers("(rm -rf target)", None, Path::new("/work")).await);
+        assert!(grants.covers("{ cargo build; }", None, Path::new("/work")).await);
+        assert!(!grants.covers("cargo build $(cargo $(rm -rf ~))", None, work).await);
+        assert!(!grants.covers("LD_PRELOAD=/tmp/x.so cargo build", None, work).await);
+    }
+
+    #[tokio::test]
+    async fn wrapper_programs_cannot_be_granted() {
+        assert!(grantable("cargo build && git status"));
+        assert!(!grantable("git ls-files | xargs rm"));
+        assert!(!grantable("/usr/bin/env rm -rf ~"));
+        assert!(!grantable("find . -delete"));
+
+        // 即使恢复的会话中保存了这样的授权, 也不会覆盖任何命令.
+        let grants = ElevateGrants::default();
+        for program in ["env", "xargs", "rm"] {
+            grants
+                .add(ElevateGrant::Program {
+                    program: program.to_string(),
+                    dir: "/work".to_string(),
+                })
+                .await;
+        }
+        assert!(grants.covers("rm -rf target", None, Path::new("/work")).await);
+        assert!(!grants.covers("env rm -rf ~", None, Path::new("/work")).await);
+        assert!(!grants.covers("xargs rm < list", None, Path::new("/work")).await);
+    }
+
+    #[tokio::test]
+    async fn revoke_removes_by_index() {
+        let grants = ElevateGrants::default();
+        let grant = ElevateGrant::Command {
+            command: "make".to_string(),
+            env: None,
+        };
+        grants.add(grant.clone()).await;
+        grants.add(grant.clone()).await;
+        assert_eq!(grants.snapshot().await.len(), 1);
+        assert_eq!(grants.revoke(1).await, None);
+        assert_eq!(grants.revoke(0).await, Some(grant));
+        assert!(grants.snapshot().await.is_empty());
+    }
+
+    #[test]
+    fn grant_serialization_is_tagged() {
+        let value = serde_json::to_value(ElevateGrant::Program {
+            program: "cargo".to_string(),
+            dir: "/work".to_string(),
+        })
+        .unwrap();
+        assert_eq!(value["kind"], "program");
+        assert_eq!(programs("cargo fmt; /usr/bin/cargo test | tee log"), ["cargo", "tee"]);
+    }
+}
@@ -0,0 +1,212 @@
+//! 单次请求的预算: 对话轮数, 每个工具的调用次数, 工具累计耗时和总耗时.
+//!
+//! 预算由 [`super::answer`] 中的 hook 在每次对话和工具调用前检查. 超出后跳过后续工具调用,
+//! 并在下一次对话前终止, 由 finalizer 根据已有对话生成最终回答.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::config::LimitsConfig;
+
+/// 触发的限制.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum LimitHit {
+    Turns(usize),
+    ToolCalls { tool: String, limit: usize },
+    ToolTime(Duration),
+    WallClock(Duration),
+}
+
+impl fmt::Display for LimitHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Turns(limit) => write!(f, "max_turns ({limit})"),
+            Self::ToolCalls { tool, limit } => write!(f, "max_tool_calls ({limit} for `{tool}`)"),
+            Self::ToolTime(limit) => write!(f, "max_tool_secs ({}s)", limit.as_secs()),
+            Self::WallClock(limit) => write!(f, "max_wall_secs ({}s)", limit.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    started: Instant,
+    turns: usize,
+    tool_calls: HashMap<String, usize>,
+    tool_time: Duration,
+    /// 正在执行的工具调用的开始时间, 按 internal call id 记录.
+    running: HashMap<String, Instant>,
+    hit: Option<LimitHit>,
+}
+
+impl BudgetState {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            turns: 0,
+            tool_calls: HashMap::new(),
+            tool_time: Duration::ZERO,
+            running: HashMap::new(),
+            hit: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct Budget {
+    config: LimitsConfig,
+    state: Mutex<BudgetState>,
+}
+
+impl Budget {
+    pub(super) fn new(config: LimitsConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BudgetState::new()),
+        }
+    }
+
+    /// 开始新的请求.
+    pub(super) fn reset(&self) {
+        *self.state() = BudgetState::new();
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        self.state
+            .lock()
+            .expect("budget state lock should not be poisoned")
+    }
+
+    pub(super) fn hit(&self) -> Option<LimitHit> {
+        self.state().hit.clone()
+    }
+
+    /// 请求剩余的时间, 不限时返回 None.
+    pub(super) fn remaining_wall(&self) -> Option<Duration> {
+        let limit = self.wall_limit()?;
+        Some(limit.saturating_sub(self.state().started.elapsed()))
+    }
+
+    /// 记录请求已超时.
+    pub(super) fn expire(&self) {
+        if let Some(limit) = self.wall_limit() {
+            self.state().hit.get_or_insert(LimitHit::WallClock(limit));
+        }
+    }
+
+    fn wall_limit(&self) -> Option<Duration> {
+        (self.config.max_wall_secs > 0).then(|| Duration::from_secs(self.config.max_wall_secs))
+    }
+
+    /// 即将发起一轮对话, 超出预算时返回触发的限制.
+    pub(super) fn start_turn(&self) -> Option<LimitHit> {
+        let wall_limit = self.wall_limit();
+        let mut state = self.state();
+        if state.hit.is_none() {
+            state.turns += 1;
+            if self.config.max_turns > 0 && state.turns > self.config.max_turns {
+                state.hit = Some(LimitHit::Turns(self.config.max_turns));
+            } else if let Some(limit) = wall_limit
+                && state.started.elapsed() >= limit
+            {
+                state.hit = Some(LimitHit::WallClock(limit));
+            }
+        }
+        state.hit.clone()
+    }
+
+    /// 即将调用工具, 超出预算时返回触发的限制, 此时不应执行该调用.
+    pub(super) fn start_tool(&self, tool: &str, call_id: &str) -> Option<LimitHit> {
+        let wall_limit = self.wall_limit();
+        let mut state = self.state();
+        if state.hit.is_none() {
+            let calls = state.tool_calls.entry(tool.to_string()).or_default();
+            *calls += 1;
+            let calls = *calls;
+            let tool_limit = Duration::from_secs(self.config.max_tool_secs);
+            if self.config.max_tool_calls > 0 && calls > self.config.max_tool_calls {
+                state.hit = Some(LimitHit::ToolCalls {
+                    tool: tool.to_string(),
+                    limit: self.config.max_tool_calls,
+                });
+            } else if self.config.max_tool_secs > 0 && state.tool_time >= tool_limit {
+                state.hit = Some(LimitHit::ToolTime(tool_limit));
+            } else if let Some(limit) = wall_limit
+                && state.started.elapsed() >= limit
+            {
+                state.hit = Some(LimitHit::WallClock(limit));
+            }
+        }
+        if state.hit.is_none() {
+            state.running.insert(call_id.to_string(), Instant::now());
+        }
+        state.hit.clone()
+    }
+
+    /// 工具调用结束, 累计耗时.
+    pub(super) fn finish_tool(&self, call_id: &str) {
+        let mut state = self.state();
+        if let Some(started) = state.running.remove(call_id) {
+            state.tool_time += started.elapsed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_turns: usize, max_tool_calls: usize, max_tool_secs: u64) -> LimitsConfig {
+        LimitsConfig {
+            max_turns,
+            max_tool_calls,
+            max_tool_secs,
+            max_wall_secs: 0,
+        }
+    }
+
+    #[test]
+    fn turns_are_limited_and_the_hit_is_sticky() {
+        let budget = Budget::new(limits(2, 0, 0));
+        assert_eq!(budget.start_turn(), None);
+        assert_eq!(budget.start_turn(), None);
+        assert_eq!(budget.start_turn(), Some(LimitHit::Turns(2)));
+        assert_eq!(budget.start_tool("explore", "a"), Some(LimitHit::Turns(2)));
+        budget.reset();
+        assert_eq!(budget.start_turn(), None);
+    }
+
+    #[test]
+    fn tool_calls_are_counted_per_tool() {
+        let budget = Budget::new(limits(0, 1, 0));
+        assert_eq!(budget.start_tool("explore", "a"), None);
+        assert_eq!(budget.start_tool("elevate", "b"), None);
+        assert_eq!(
+            budget.start_tool("explore", "c"),
+            Some(LimitHit::ToolCalls {
+                tool: "explore".to_string(),
+                limit: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn tool_time_accumulates_between_start_and_finish() {
+        let budget = Budget::new(limits(0, 0, 1));
+        assert_eq!(budget.start_tool("explore", "a"), None);
+        budget
+            .state()
+            .running
+            .insert("a".to_string(), Instant::now() - Duration::from_secs(2));
+        budget.finish_tool("a");
+        assert_eq!(
+            budget.start_tool("explore", "b"),
+            Some(LimitHit::ToolTime(Duration::from_secs(1)))
+        );
+        assert_eq!(budget.hit().unwrap().to_string(), "max_tool_secs (1s)");
+    }
+}
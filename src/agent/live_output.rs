@@ -0,0 +1,263 @@
+//! 工具命令运行时的实时输出.
+//!
+//! 命令的 stdout/stderr 在读取的同时显示在进度区域中: 默认只显示最新一行,
+//! 按 `t` 切换为最近若干行的 tail 视图. 返回给模型的内容仍按原有的首尾截断规则处理,
+//! 与这里的显示无关.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    os::fd::AsRawFd,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    event::{Event, EventStream, KeyCode, KeyEventKind},
+    tty::IsTty,
+};
+use tokio_stream::StreamExt;
+use tracing::{Span, info_span};
+use tracing_indicatif::{span_ext::IndicatifSpanExt, style::ProgressStyle};
+use unicode_width::UnicodeWidthChar;
+
+const SPINNER: [&str; 7] = [
+    "\u{280b}", "\u{2819}", "\u{2838}", "\u{2834}", "\u{2826}", "\u{2807}", "",
+];
+const TAIL_LINES: usize = 8;
+const COMMAND_WIDTH: usize = 32;
+const LINE_WIDTH: usize = 80;
+
+/// 同一时间只有一个 [`TailToggle`] 修改终端设置, 避免并发命令互相覆盖恢复时的状态.
+static TOGGLE_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub(super) struct LiveOutput {
+    span: Span,
+    command: String,
+    /// 最近的完整行.
+    lines: VecDeque<String>,
+    /// stdout 和 stderr 各自尚未结束的行.
+    partials: [String; 2],
+    /// 最近有输出的流, 决定单行视图显示哪一行.
+    latest: usize,
+    /// 正在跳过 ANSI 转义序列.
+    in_escape: bool,
+    show_tail: bool,
+    toggle_hint: bool,
+}
+
+impl LiveOutput {
+    pub(super) fn start(command: &str, toggle_hint: bool) -> Self {
+        let span = info_span!("tool-output");
+        span.pb_set_style(
+            &ProgressStyle::with_template("  {spinner:.blue} {msg}")
+                .expect("spinner template should be valid")
+                .tick_strings(&SPINNER),
+        );
+        let live = Self {
+            span,
+            command: fit_width(command.lines().next().unwrap_or_default(), COMMAND_WIDTH),
+            lines: VecDeque::with_capacity(TAIL_LINES),
+            partials: Default::default(),
+            latest: 0,
+            in_escape: false,
+            show_tail: false,
+            toggle_hint,
+        };
+        live.span.pb_set_message(&live.render());
+        live.span.pb_start();
+        live
+    }
+
+    pub(super) fn push_stdout(&mut self, bytes: &[u8]) {
+        self.push(0, bytes);
+    }
+
+    pub(super) fn push_stderr(&mut self, bytes: &[u8]) {
+        self.push(1, bytes);
+    }
+
+    pub(super) fn toggle_tail(&mut self) {
+        self.show_tail = !self.show_tail;
+        self.span.pb_set_message(&self.render());
+    }
+
+    fn push(&mut self, stream: usize, bytes: &[u8]) {
+        self.latest = stream;
+        for character in String::from_utf8_lossy(bytes).chars() {
+            if self.in_escape {
+                self.in_escape = !(character.is_ascii_alphabetic() || character == '~');
+                continue;
+            }
+            match character {
+                '\n' => {
+                    let line = std::mem::take(&mut self.partials[stream]);
+                    if self.lines.len() == TAIL_LINES {
+                        self.lines.pop_front();
+                    }
+                    self.lines.push_back(line);
+                }
+                // 进度条类输出用 `\r` 覆盖当前行.
+                '\r' => self.partials[stream].clear(),
+                '\t' => self.partials[stream].push(' '),
+                '\x1b' => self.in_escape = true,
+                character if character.is_control() => {}
+                character => self.partials[stream].push(character),
+            }
+        }
+        self.span.pb_set_message(&self.render());
+    }
+
+    fn current_line(&self) -> &str {
+        let partial = &self.partials[self.latest];
+        if partial.is_empty() {
+            self.lines.back().map(String::as_str).unwrap_or_default()
+        } else {
+            partial
+        }
+    }
+
+    fn render(&self) -> String {
+        let hint = match (self.toggle_hint, self.show_tail) {
+            (false, _) => "",
+            (true, false) => "  [t: tail]",
+            (true, true) => "  [t: hide]",
+        };
+        if !self.show_tail {
+            return format!(
+                "$ {} | {}{hint}",
+                self.command,
+                fit_width(self.current_line(), LINE_WIDTH)
+            );
+        }
+        let mut output = format!("$ {}{hint}", self.command);
+        let partials = self.partials.iter().filter(|partial| !partial.is_empty());
+        let lines = self.lines.iter().chain(partials).collect::<Vec<_>>();
+        for line in &lines[lines.len().saturating_sub(TAIL_LINES)..] {
+            output.push_str("\n    \u{2502} ");
+            output.push_str(&fit_width(line, LINE_WIDTH));
+        }
+        output
+    }
+}
+
+fn fit_width(text: &str, width: usize) -> String {
+    let mut output = String::new();
+    let mut used = 0;
+    for character in text.chars() {
+        let character_width = character.width_cjk().unwrap_or(0);
+        if used + character_width > width.saturating_sub(1) {
+            output.push('\u{2026}');
+            return output;
+        }
+        used += character_width;
+        output.push(character);
+    }
+    output
+}
+
+/// 命令运行期间读取切换 tail 视图的按键.
+///
+/// 只关闭终端的行缓冲和回显, 不进入 raw mode: 进度区域和日志的换行仍然正常,
+/// Ctrl+C 也仍然会发送 SIGINT. drop 时恢复原来的终端设置.
+pub(super) struct TailToggle {
+    events: EventStream,
+    tty: File,
+    original: libc::termios,
+}
+
+impl TailToggle {
+    /// stderr 不是终端, 无法打开 `/dev/tty` 或已有其他命令在读取按键时返回 None.
+    pub(super) fn open() -> Option<Self> {
+        if !std::io::stderr().is_tty() {
+            return None;
+        }
+        if TOGGLE_ACTIVE.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        let toggle = Self::open_tty();
+        if toggle.is_none() {
+            TOGGLE_ACTIVE.store(false, Ordering::Release);
+        }
+        toggle
+    }
+
+    fn open_tty() -> Option<Self> {
+        let tty = File::options().read(true).write(true).open("/dev/tty").ok()?;
+        let mut original = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tty 在调用期间保持打开, tcgetattr 成功时会完整初始化 original.
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), original.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: 上面的 tcgetattr 已成功.
+        let original = unsafe { original.assume_init() };
+        let mut termios = original;
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        // SAFETY: termios 是从 tcgetattr 的结果复制修改而来.
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+            return None;
+        }
+        Some(Self {
+            events: EventStream::new(),
+            tty,
+            original,
+        })
+    }
+
+    /// 等待下一次切换按键, 事件流结束后永远挂起.
+    pub(super) async fn next(&mut self) {
+        while let Some(event) = self.events.next().await {
+            if let Ok(Event::Key(key)) = event
+                && key.kind == KeyEventKind::Press
+                && matches!(key.code, KeyCode::Char('t' | 'T'))
+            {
+                return;
+            }
+        }
+        std::future::pending::<()>().await;
+    }
+}
+
+impl Drop for TailToggle {
+    fn drop(&mut self) {
+        // SAFETY: original 来自同一个 tty 的 tcgetattr.
+        unsafe {
+            libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original);
+        }
+        TOGGLE_ACTIVE.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LiveOutput;
+
+    #[test]
+    fn single_line_view_follows_partial_and_carriage_returns() {
+        let mut live = LiveOutput::start("cargo build", true);
+        live.push_stdout(b"Compiling a\nDownloading 10%");
+        assert_eq!(live.render(), "$ cargo build | Downloading 10%  [t: tail]");
+        live.push_stdout(b"\rDownloading 90%");
+        assert_eq!(live.render(), "$ cargo build | Downloading 90%  [t: tail]");
+        live.push_stderr(b"warning: unused\n");
+        assert_eq!(live.render(), "$ cargo build | warning: unused  [t: tail]");
+    }
+
+    #[test]
+    fn tail_view_keeps_recent_lines() {
+        let mut live = LiveOutput::start("make", false);
+        for index in 0..20 {
+            live.push_stdout(format!("line {index}\n").as_bytes());
+        }
+        live.push_stdout(b"\x1b[1mpartial\t!");
+        live.toggle_tail();
+        let rendered = live.render();
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1 + super::TAIL_LINES);
+        assert_eq!(lines[0], "$ make");
+        assert!(lines[1].ends_with("line 13"));
+        assert!(lines.last().unwrap().ends_with("\u{2502} partial !"));
+    }
+}
@@ -1,11 +1,27 @@
 pub mod answer;
+pub mod cache;
 pub mod command;
+pub mod completion;
+pub mod custom_tool;
+pub mod env;
+pub mod explain;
+pub mod grant;
+mod limits;
+mod live_output;
+mod parallel;
+pub mod policy;
+pub mod preview;
+mod pty;
 pub mod sandbox;
+mod script_hook;
 mod scroll;
+pub mod search_favorites;
 mod stream;
 pub mod submit_commands;
 mod tool_call_log;
 mod tool_schema;
+pub mod transform;
+pub mod translate;
 
 pub fn detect_os() -> String {
     sysinfo::System::name().unwrap_or(std::env::consts::OS.to_string())
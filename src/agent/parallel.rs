@@ -0,0 +1,320 @@
+//! 并发执行同一轮中相互独立的只读工具调用.
+//!
+//! rig 按顺序逐个执行一轮中的工具调用. 模型一次发出多个 `explore` 调用时,
+//! hook 在该轮第一个工具调用执行前, 根据流式收到的工具调用把其中可以无人值守执行的
+//! `explore` 调用一起提交到后台, 并发数受配置限制. 之后 rig 逐个执行时, hook 直接用
+//! 后台的结果作为工具结果返回 (rig 的 `Skip`), 因此历史中的结果顺序仍与调用顺序一致.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use rig_core::tool::{Tool, ToolDyn};
+use serde_json::Value;
+use tokio::{sync::Semaphore, task::JoinHandle};
+use tracing::{Instrument, Span, debug};
+
+use crate::agent::{
+    command::{Explore, ExploreArgs},
+    script_hook::{ScriptHooks, ToolVerdict},
+};
+
+const LABEL_WIDTH: usize = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CallState {
+    Queued,
+    Running,
+    Done,
+}
+
+#[derive(Debug)]
+struct CallStatus {
+    id: String,
+    label: String,
+    state: CallState,
+}
+
+/// 本轮工具调用的状态, 由 [`super::stream::collect`] 显示在进度区域.
+#[derive(Debug, Default)]
+pub(super) struct CallStatuses {
+    calls: Mutex<Vec<CallStatus>>,
+}
+
+impl CallStatuses {
+    fn calls(&self) -> MutexGuard<'_, Vec<CallStatus>> {
+        self.calls
+            .lock()
+            .expect("call status lock should not be poisoned")
+    }
+
+    pub(super) fn clear(&self) {
+        self.calls().clear();
+    }
+
+    pub(super) fn queue(&self, id: &str, label: String) {
+        let mut calls = self.calls();
+        if !calls.iter().any(|call| call.id == id) {
+            calls.push(CallStatus {
+                id: id.to_string(),
+                label,
+                state: CallState::Queued,
+            });
+        }
+    }
+
+    fn update(&self, id: &str, label: Option<String>, state: CallState) {
+        let mut calls = self.calls();
+        match calls.iter_mut().find(|call| call.id == id) {
+            Some(call) => {
+                if let Some(label) = label {
+                    call.label = label;
+                }
+                call.state = state;
+            }
+            None => calls.push(CallStatus {
+                id: id.to_string(),
+                label: label.unwrap_or_default(),
+                state,
+            }),
+        }
+    }
+
+    pub(super) fn running(&self, id: &str, label: String) {
+        self.update(id, Some(label), CallState::Running);
+    }
+
+    pub(super) fn done(&self, id: &str) {
+        self.update(id, None, CallState::Done);
+    }
+
+    /// 一轮中有多个工具调用时, 每个调用一行; 否则为空.
+    pub(super) fn render(&self) -> String {
+        let calls = self.calls();
+        if calls.len() < 2 {
+            return String::new();
+        }
+        let mut output = String::new();
+        for call in calls.iter() {
+            let symbol = match call.state {
+                CallState::Queued => "\u{00b7}",
+                CallState::Running => "\u{25b8}",
+                CallState::Done => "\u{2713}",
+            };
+            output.push_str("\n    ");
+            output.push_str(symbol);
+            output.push(' ');
+            output.extend(call.label.chars().take(LABEL_WIDTH));
+        }
+        output
+    }
+}
+
+/// 工具调用在进度区域中的显示文本.
+pub(super) fn label(tool: &str, arguments: &str) -> String {
+    let command = serde_json::from_str::<Value>(arguments)
+        .ok()
+        .and_then(|arguments| arguments["command"].as_str().map(str::to_owned));
+    match command {
+        Some(command) => format!("{tool} {}", command.lines().next().unwrap_or_default()),
+        None => tool.to_string(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct StreamedCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+#[derive(Debug)]
+struct Prefetch {
+    arguments: Value,
+    task: JoinHandle<String>,
+}
+
+#[derive(Debug, Default)]
+struct Turn {
+    /// 按流式顺序收到的工具调用.
+    calls: Vec<StreamedCall>,
+    started: bool,
+    prefetched: HashMap<String, Prefetch>,
+}
+
+#[derive(Debug)]
+pub(super) struct ParallelTools {
+    explore: Option<Arc<Explore>>,
+    scripts: Arc<ScriptHooks>,
+    permits: Arc<Semaphore>,
+    statuses: Arc<CallStatuses>,
+    turn: Mutex<Turn>,
+}
+
+impl ParallelTools {
+    /// `concurrency` 为 1 (或 0) 时不并发执行. 只有 `scripts` 原样放行的调用才会提前执行.
+    pub(super) fn new(
+        explore: Option<Explore>,
+        concurrency: usize,
+        scripts: Arc<ScriptHooks>,
+    ) -> Self {
+        Self {
+            explore: explore.filter(|_| concurrency > 1).map(Arc::new),
+            scripts,
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            statuses: Arc::default(),
+            turn: Mutex::new(Turn::default()),
+        }
+    }
+
+    pub(super) fn statuses(&self) -> &CallStatuses {
+        &self.statuses
+    }
+
+    fn turn(&self) -> MutexGuard<'_, Turn> {
+        self.turn
+            .lock()
+            .expect("parallel tool lock should not be poisoned")
+    }
+
+    /// 新的一轮对话开始, 丢弃上一轮未被使用的结果.
+    pub(super) fn reset(&self) {
+        let turn = std::mem::take(&mut *self.turn());
+        for prefetch in turn.prefetched.into_values() {
+            prefetch.task.abort();
+        }
+        self.statuses.clear();
+    }
+
+    /// 记录流式收到的工具调用名称或参数片段.
+    pub(super) fn record(&self, id: &str, name: Option<&str>, delta: &str) {
+        let mut turn = self.turn();
+        let index = match turn.calls.iter().position(|call| call.id == id) {
+            Some(index) => index,
+            None => {
+                turn.calls.push(StreamedCall {
+                    id: id.to_string(),
+                    ..StreamedCall::default()
+                });
+                turn.calls.len() - 1
+            }
+        };
+        let call = &mut turn.calls[index];
+        if let Some(name) = name {
+            call.name = name.to_string();
+            self.statuses.queue(id, name.to_string());
+        }
+        call.arguments.push_str(delta);
+    }
+
+    /// rig 即将执行工具调用 `id`. 若该调用已在后台执行, 等待并返回其结果.
+    pub(super) async fn take(&self, name: &str, id: &str, arguments: &str) -> Option<String> {
+        let prefetch = {
+            let mut turn = self.turn();
+            if !turn.started {
+                turn.started = true;
+                self.start(&mut turn);
+            }
+            turn.prefetched.remove(id)
+        };
+        let Some(prefetch) = prefetch else {
+            self.statuses.running(id, label(name, arguments));
+            return None;
+        };
+        // 最终的参数与流式拼接的不一致时, 按正常流程重新执行.
+        if serde_json::from_str::<Value>(arguments).ok() != Some(prefetch.arguments) {
+            prefetch.task.abort();
+            self.statuses.running(id, label(name, arguments));
+            return None;
+        }
+        match prefetch.task.await {
+            Ok(result) => Some(result),
+            Err(error) => Some(format!("Tool execution failed: {error}")),
+        }
+    }
+
+    /// 把本轮中可以无人值守执行的 `explore` 调用提交到后台.
+    fn start(&self, turn: &mut Turn) {
+        let Some(explore) = &self.explore else {
+            return;
+        };
+        let candidates = turn
+            .calls
+            .iter()
+            .filter(|call| call.name == Explore::NAME)
+            .filter_map(|call| {
+                let arguments = serde_json::from_str::<Value>(&call.arguments).ok()?;
+                let args = serde_json::from_value::<ExploreArgs>(arguments.clone()).ok()?;
+                let unattended = !explore.needs_confirmation(&args)
+                    && self.scripts.before_tool(&call.name, &call.arguments) == ToolVerdict::Allow;
+                unattended.then_some((call, arguments))
+            })
+            .collect::<Vec<_>>();
+        if candidates.len() < 2 {
+            return;
+        }
+        debug!(
+            calls = candidates.len(),
+            "Running explore calls concurrently."
+        );
+        for (call, arguments) in candidates {
+            let explore = explore.clone();
+            let permits = self.permits.clone();
+            let statuses = self.statuses.clone();
+            let id = call.id.clone();
+            let raw_arguments = call.arguments.clone();
+            let call_label = label(&call.name, &call.arguments);
+            let task = tokio::spawn(
+                async move {
+                    let _permit = permits.acquire_owned().await;
+                    statuses.running(&id, call_label);
+                    let result = match ToolDyn::call(explore.as_ref(), raw_arguments).await {
+                        Ok(result) => result,
+                        Err(error) => error.to_string(),
+                    };
+                    statuses.done(&id);
+                    result
+                }
+                .instrument(Span::current()),
+            );
+            turn.prefetched
+                .insert(call.id.clone(), Prefetch { arguments, task });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_render_one_line_per_call() {
+        let statuses = CallStatuses::default();
+        statuses.queue("a", "explore".to_string());
+        assert_eq!(statuses.render(), "");
+        statuses.queue("b", "explore".to_string());
+        statuses.running("a", label("explore", r#"{"command":"git --version"}"#));
+        statuses.done("b");
+        assert_eq!(
+            statuses.render(),
+            "\n    \u{25b8} explore git --version\n    \u{2713} explore"
+        );
+        statuses.clear();
+        assert_eq!(statuses.render(), "");
+    }
+
+    #[test]
+    fn streamed_deltas_are_joined_per_call() {
+        let parallel = ParallelTools::new(None, 4, Arc::default());
+        parallel.record("a", Some("explore"), "");
+        parallel.record("b", Some("explore"), r#"{"command":"#);
+        parallel.record("a", None, r#"{"command":"ls"}"#);
+        parallel.record("b", None, r#""pwd"}"#);
+        let turn = parallel.turn();
+        assert_eq!(turn.calls.len(), 2);
+        assert_eq!(turn.calls[0].arguments, r#"{"command":"ls"}"#);
+        assert_eq!(turn.calls[1].arguments, r#"{"command":"pwd"}"#);
+        assert_eq!(parallel.statuses().calls().len(), 2);
+    }
+}
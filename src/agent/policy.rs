@@ -0,0 +1,282 @@
+//! explore / elevate 的命令策略.
+//!
+//! 规则来自 `[agent.policy]`, 按 [`shell::parse::segments`](crate::shell::parse::segments)
+//! 拆出的每个片段分别匹配. 多个片段的结论按 deny > confirm > 默认 > allow 合并:
+//! 只有所有片段都被 allow 时, elevate 才会跳过确认. 片段开头的变量赋值不会让 allow 规则命中.
+
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    config::{NonTtyDecision, PolicyAction, PolicyConfig, PolicyRuleConfig},
+    error::{Error, Result},
+    shell::parse,
+};
+
+#[derive(Debug)]
+enum Matcher {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, segment: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob.is_match(segment),
+            Self::Regex(regex) => regex.is_match(segment),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: String,
+    matcher: Matcher,
+    action: PolicyAction,
+    tools: Vec<String>,
+    reason: Option<String>,
+}
+
+impl Rule {
+    fn compile(config: &PolicyRuleConfig) -> Result<Self> {
+        let (pattern, matcher) = match (&config.glob, &config.regex) {
+            (Some(glob), None) => (
+                glob.clone(),
+                Matcher::Glob(
+                    Glob::new(glob)
+                        .map_err(|error| Error::PolicyRule {
+                            rule: glob.clone(),
+                            message: error.to_string(),
+                        })?
+                        .compile_matcher(),
+                ),
+            ),
+            (None, Some(regex)) => (
+                regex.clone(),
+                Matcher::Regex(Regex::new(regex).map_err(|error| Error::PolicyRule {
+                    rule: regex.clone(),
+                    message: error.to_string(),
+                })?),
+            ),
+            (glob, regex) => {
+                return Err(Error::PolicyRule {
+                    rule: glob.clone().or(regex.clone()).unwrap_or_default(),
+                    message: "exactly one of `glob` and `regex` must be set".to_string(),
+                });
+            }
+        };
+        Ok(Self {
+            pattern,
+            matcher,
+            action: config.action,
+            tools: config.tools.clone(),
+            reason: config.reason.clone(),
+        })
+    }
+
+    fn applies_to(&self, tool: &str) -> bool {
+        self.tools.is_empty() || self.tools.iter().any(|name| name == tool)
+    }
+}
+
+/// 命中的规则, 会作为拒绝原因的一部分返回给模型.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub segment: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// 没有规则命中, 按工具的默认行为处理.
+    Default,
+    /// 所有片段都被允许, elevate 无需确认.
+    Allow,
+    /// 需要用户确认.
+    Confirm(RuleMatch),
+    /// 直接拒绝.
+    Deny(RuleMatch),
+}
+
+#[derive(Debug, Default)]
+pub struct CommandPolicy {
+    rules: Vec<Rule>,
+    non_tty: NonTtyDecision,
+}
+
+impl CommandPolicy {
+    pub fn new(config: &PolicyConfig) -> Result<Self> {
+        Ok(Self {
+            rules: config
+                .rules
+                .iter()
+                .map(Rule::compile)
+                .collect::<Result<_>>()?,
+            non_tty: config.non_tty,
+        })
+    }
+
+    pub fn non_tty(&self) -> NonTtyDecision {
+        self.non_tty
+    }
+
+    /// 对 `tool` 将要执行的 `command` 求值.
+    pub fn evaluate(&self, tool: &str, command: &str) -> Verdict {
+        let mut confirm = None;
+        let mut all_allowed = true;
+        let segments = parse::split(command);
+        for segment in &segments {
+            let full = segment.full();
+            // 带变量赋值 (`LD_PRELOAD=... git fetch`) 的片段, deny 和 confirm 规则按剥离赋值前后
+            // 分别匹配, allow 规则只按包含赋值的完整片段匹配.
+            let Some(rule) = self.rules.iter().find(|rule| {
+                rule.applies_to(tool)
+                    && (rule.matcher.is_match(&full)
+                        || rule.action != PolicyAction::Allow
+                            && rule.matcher.is_match(&segment.command))
+            }) else {
+                all_allowed = false;
+                continue;
+            };
+            let matched = || RuleMatch {
+                rule: rule.pattern.clone(),
+                segment: full.clone(),
+                reason: rule.reason.clone(),
+            };
+            match rule.action {
+                PolicyAction::Deny => return Verdict::Deny(matched()),
+                PolicyAction::Confirm => {
+                    all_allowed = false;
+                    confirm.get_or_insert_with(matched);
+                }
+                PolicyAction::Allow => {}
+            }
+        }
+        match confirm {
+            Some(matched) => Verdict::Confirm(matched),
+            None if all_allowed && !segments.is_empty() => Verdict::Allow,
+            None => Verdict::Default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(glob: Option<&str>, regex: Option<&str>, action: PolicyAction) -> PolicyRuleConfig {
+        PolicyRuleConfig {
+            glob: glob.map(str::to_owned),
+            regex: regex.map(str::to_owned),
+            action,
+            tools: Vec::new(),
+            reason: None,
+        }
+    }
+
+    fn policy(rules: Vec<PolicyRuleConfig>) -> CommandPolicy {
+        CommandPolicy::new(&PolicyConfig {
+            non_tty: NonTtyDecision::Reject,
+            rules,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn every_segment_must_be_allowed() {
+        let policy = policy(vec![
+            rule(Some("git fetch*"), None, PolicyAction::Allow),
+            rule(None, Some(r"^cargo (check|build)\b"), PolicyAction::Allow),
+        ]);
+        assert_eq!(
+            policy.evaluate("elevate", "git fetch origin && cargo check"),
+            Verdict::Allow
+        );
+        assert_eq!(
+            policy.evaluate("elevate", "git fetch origin && cargo publish"),
+            Verdict::Default
+        );
+    }
+
+    #[test]
+    fn deny_wins_over_other_segments() {
+        let policy = policy(vec![
+            rule(Some("git fetch*"), None, PolicyAction::Allow),
+            rule(Some("rm -rf *"), None, PolicyAction::Deny),
+            rule(Some("git push*"), None, PolicyAction::Confirm),
+        ]);
+        let Verdict::Deny(matched) = policy.evaluate("elevate", "git push; git fetch | rm -rf /")
+        else {
+            panic!("expected a deny verdict");
+        };
+        assert_eq!(matched.rule, "rm -rf *");
+        assert_eq!(matched.segment, "rm -rf /");
+        assert!(matches!(
+            policy.evaluate("explore", "echo $(git push --dry-run)"),
+            Verdict::Confirm(_)
+        ));
+    }
+
+    #[test]
+    fn subshells_and_groups_do_not_hide_segments() {
+        let policy = policy(vec![rule(Some("rm -rf *"), None, PolicyAction::Deny)]);
+        for command in [
+            "(rm -rf x)",
+            "{ rm -rf x; }",
+            "true && (cd / && rm -rf x)",
+            "cat <(rm -rf x)",
+        ] {
+            let Verdict::Deny(matched) = policy.evaluate("elevate", command) else {
+                panic!("expected `{command}` to be denied");
+            };
+            assert_eq!(matched.segment, "rm -rf x");
+        }
+    }
+
+    #[test]
+    fn assignments_are_not_allowed_implicitly() {
+        let policy = policy(vec![
+            rule(Some("git fetch*"), None, PolicyAction::Allow),
+            rule(Some("RUST_LOG=* cargo test*"), None, PolicyAction::Allow),
+            rule(Some("rm -rf *"), None, PolicyAction::Deny),
+        ]);
+        assert_eq!(
+            policy.evaluate("elevate", "LD_PRELOAD=/tmp/x.so git fetch"),
+            Verdict::Default
+        );
+        assert_eq!(
+            policy.evaluate("elevate", "RUST_LOG=debug cargo test"),
+            Verdict::Allow
+        );
+        let Verdict::Deny(matched) = policy.evaluate("elevate", "FOO=1 rm -rf /") else {
+            panic!("expected a deny verdict");
+        };
+        assert_eq!(matched.segment, "FOO=1 rm -rf /");
+    }
+
+    #[test]
+    fn rules_can_target_a_single_tool() {
+        let mut only_explore = rule(Some("cat *"), None, PolicyAction::Deny);
+        only_explore.tools = vec!["explore".to_string()];
+        let policy = policy(vec![only_explore]);
+        assert!(matches!(policy.evaluate("explore", "cat a"), Verdict::Deny(_)));
+        assert_eq!(policy.evaluate("elevate", "cat a"), Verdict::Default);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let both = rule(Some("a"), Some("b"), PolicyAction::Deny);
+        let broken = rule(None, Some("("), PolicyAction::Deny);
+        for rule in [both, broken] {
+            let error = CommandPolicy::new(&PolicyConfig {
+                non_tty: NonTtyDecision::Reject,
+                rules: vec![rule],
+            })
+            .unwrap_err();
+            assert!(matches!(error, Error::PolicyRule { .. }));
+        }
+    }
+}
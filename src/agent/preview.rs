@@ -0,0 +1,430 @@
+//! elevate 的预演.
+//!
+//! 批准后的命令先在沙箱中执行, 工作目录以 overlay 方式挂载, 所有写入只落在宿主上临时的
+//! upper 目录中. 执行结束后对比 upper 与真实目录, 列出新建, 修改和删除的文件,
+//! 用户再次确认后才把变更应用到真实目录.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::agent::sandbox::Overlay;
+
+/// 对话框中最多展示的变更行数.
+const MAX_SUMMARY_LINES: usize = 10;
+/// overlayfs 标记 "目录被整体替换" 的扩展属性, 分别对应非特权和特权挂载.
+const OPAQUE_XATTRS: [&str; 2] = ["user.overlay.opaque", "trusted.overlay.opaque"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    /// 相对于工作目录的路径.
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// 新增与删除的行数, 二进制文件和目录为 None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<(usize, usize)>,
+}
+
+impl FileChange {
+    fn summary(&self) -> String {
+        let sign = match self.kind {
+            ChangeKind::Created => '+',
+            ChangeKind::Modified => '~',
+            ChangeKind::Deleted => '-',
+        };
+        let path = self.path.display();
+        match (self.kind, self.lines) {
+            (ChangeKind::Deleted, _) => format!("{sign} {path}"),
+            (_, Some((added, 0))) => format!("{sign} {path} (+{added})"),
+            (_, Some((added, removed))) => format!("{sign} {path} (+{added} -{removed})"),
+            (_, None) => format!("{sign} {path} (binary)"),
+        }
+    }
+}
+
+/// 一次预演的临时目录与变更结果, drop 时清理临时目录.
+#[derive(Debug)]
+pub struct Preview {
+    dir: PathBuf,
+    root: PathBuf,
+    upper: PathBuf,
+    work: PathBuf,
+    changes: Vec<FileChange>,
+}
+
+impl Preview {
+    /// 为 `dir` 创建 upper/work 临时目录.
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        let root = std::env::temp_dir().join(format!("howlto-preview-{}", Uuid::new_v4()));
+        let upper = root.join("upper");
+        let work = root.join("work");
+        fs::create_dir_all(&upper)?;
+        fs::create_dir_all(&work)?;
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o700))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            root,
+            upper,
+            work,
+            changes: Vec::new(),
+        })
+    }
+
+    pub fn overlay(&self, network: bool) -> Overlay<'_> {
+        Overlay {
+            dir: &self.dir,
+            upper: &self.upper,
+            work: &self.work,
+            network,
+        }
+    }
+
+    pub fn changes(&self) -> &[FileChange] {
+        &self.changes
+    }
+
+    /// 对比 upper 与真实目录, 收集变更.
+    pub fn scan(&mut self) -> io::Result<()> {
+        let mut changes = Vec::new();
+        scan_dir(&self.upper, &self.dir, Path::new(""), &mut changes)?;
+        changes.sort_by(|left, right| left.path.cmp(&right.path));
+        self.changes = changes;
+        Ok(())
+    }
+
+    /// 展示给用户的变更摘要, 超过 [`MAX_SUMMARY_LINES`] 时折叠.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = self
+            .changes
+            .iter()
+            .take(MAX_SUMMARY_LINES)
+            .map(FileChange::summary)
+            .collect::<Vec<_>>();
+        if self.changes.len() > MAX_SUMMARY_LINES {
+            lines.push(format!(
+                "... and {} more",
+                self.changes.len() - MAX_SUMMARY_LINES
+            ));
+        }
+        lines
+    }
+
+    /// 把 upper 中的变更应用到真实目录.
+    pub fn apply(&self) -> io::Result<()> {
+        apply_dir(&self.upper, &self.dir)
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        // overlayfs 会在 work 目录中留下权限为 000 的目录, 先恢复权限再删除.
+        let _ = fs::set_permissions(self.work.join("work"), fs::Permissions::from_mode(0o700));
+        if let Err(error) = fs::remove_dir_all(&self.root) {
+            warn!(error = %error, root = %self.root.display(), "Failed to remove preview directory.");
+        }
+    }
+}
+
+/// overlayfs 用 0/0 号字符设备表示被删除的文件.
+fn is_whiteout(metadata: &fs::Metadata) -> bool {
+    metadata.file_type().is_char_device() && metadata.rdev() == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_opaque(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn is_opaque(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    OPAQUE_XATTRS.iter().any(|name| {
+        let name = std::ffi::CString::new(*name).expect("static xattr name");
+        let mut value = [0_u8; 1];
+        // SAFETY: 两个 C 字符串在调用期间有效, value 的长度与传入的 size 一致.
+        let size = unsafe {
+            libc::lgetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        size == 1 && value[0] == b'y'
+    })
+}
+
+fn scan_dir(
+    upper: &Path,
+    lower: &Path,
+    relative: &Path,
+    changes: &mut Vec<FileChange>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(upper)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let upper_path = entry.path();
+        let lower_path = lower.join(&name);
+        let relative = relative.join(&name);
+        let metadata = fs::symlink_metadata(&upper_path)?;
+        let lower_metadata = fs::symlink_metadata(&lower_path).ok();
+
+        if is_whiteout(&metadata) {
+            if let Some(lower_metadata) = lower_metadata {
+                collect_deleted(&lower_path, &lower_metadata, &relative, changes)?;
+            }
+            continue;
+        }
+        if metadata.is_dir() {
+            if let Some(lower_metadata) = &lower_metadata {
+                if !lower_metadata.is_dir() {
+                    changes.push(FileChange {
+                        path: relative.clone(),
+                        kind: ChangeKind::Deleted,
+                        lines: None,
+                    });
+                } else if is_opaque(&upper_path) {
+                    // 目录被整体替换, 下层中未出现在 upper 里的文件都被删除了.
+                    for lower_entry in fs::read_dir(&lower_path)? {
+                        let lower_entry = lower_entry?;
+                        if fs::symlink_metadata(upper_path.join(lower_entry.file_name())).is_err() {
+                            collect_deleted(
+                                &lower_entry.path(),
+                                &fs::symlink_metadata(lower_entry.path())?,
+                                &relative.join(lower_entry.file_name()),
+                                changes,
+                            )?;
+                        }
+                    }
+                }
+            }
+            scan_dir(&upper_path, &lower_path, &relative, changes)?;
+            continue;
+        }
+
+        let Some(lower_metadata) = lower_metadata.filter(|metadata| !metadata.is_dir()) else {
+            changes.push(FileChange {
+                lines: line_changes(None, &upper_path),
+                path: relative,
+                kind: ChangeKind::Created,
+            });
+            continue;
+        };
+        if unchanged(&upper_path, &metadata, &lower_path, &lower_metadata) {
+            // overlayfs 在只读打开以外的情况也可能 copy-up, 内容相同的文件不算变更.
+            debug!(path = %relative.display(), "Skipping unchanged copy-up.");
+            continue;
+        }
+        changes.push(FileChange {
+            lines: line_changes(Some(&lower_path), &upper_path),
+            path: relative,
+            kind: ChangeKind::Modified,
+        });
+    }
+    Ok(())
+}
+
+fn collect_deleted(
+    path: &Path,
+    metadata: &fs::Metadata,
+    relative: &Path,
+    changes: &mut Vec<FileChange>,
+) -> io::Result<()> {
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            collect_deleted(
+                &entry.path(),
+                &fs::symlink_metadata(entry.path())?,
+                &relative.join(entry.file_name()),
+                changes,
+            )?;
+        }
+        return Ok(());
+    }
+    changes.push(FileChange {
+        path: relative.to_path_buf(),
+        kind: ChangeKind::Deleted,
+        lines: None,
+    });
+    Ok(())
+}
+
+fn unchanged(
+    upper: &Path,
+    upper_metadata: &fs::Metadata,
+    lower: &Path,
+    lower_metadata: &fs::Metadata,
+) -> bool {
+    if upper_metadata.mode() != lower_metadata.mode() {
+        return false;
+    }
+    if upper_metadata.file_type().is_symlink() {
+        return fs::read_link(upper).ok() == fs::read_link(lower).ok();
+    }
+    upper_metadata.len() == lower_metadata.len()
+        && matches!((fs::read(upper), fs::read(lower)), (Ok(upper), Ok(lower)) if upper == lower)
+}
+
+/// 按行统计新增与删除的数量, 不关心顺序. 任意一侧不是文本时返回 None.
+fn line_changes(old: Option<&Path>, new: &Path) -> Option<(usize, usize)> {
+    let read = |path: &Path| {
+        if fs::symlink_metadata(path).ok()?.file_type().is_symlink() {
+            return None;
+        }
+        String::from_utf8(fs::read(path).ok()?).ok()
+    };
+    let new = read(new)?;
+    let old = match old {
+        Some(old) => read(old)?,
+        None => String::new(),
+    };
+    let mut counts = HashMap::<&str, isize>::new();
+    for line in old.lines() {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in new.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    Some(counts.values().fold((0, 0), |(added, removed), count| {
+        if *count > 0 {
+            (added + count.unsigned_abs(), removed)
+        } else {
+            (added, removed + count.unsigned_abs())
+        }
+    }))
+}
+
+fn remove_any(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn apply_dir(upper: &Path, lower: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(upper)? {
+        let entry = entry?;
+        let upper_path = entry.path();
+        let lower_path = lower.join(entry.file_name());
+        let metadata = fs::symlink_metadata(&upper_path)?;
+        if is_whiteout(&metadata) {
+            remove_any(&lower_path)?;
+        } else if metadata.is_dir() {
+            let replace = is_opaque(&upper_path)
+                || fs::symlink_metadata(&lower_path).is_ok_and(|lower| !lower.is_dir());
+            if replace {
+                remove_any(&lower_path)?;
+            }
+            fs::create_dir_all(&lower_path)?;
+            fs::set_permissions(&lower_path, metadata.permissions())?;
+            apply_dir(&upper_path, &lower_path)?;
+        } else if metadata.file_type().is_symlink() {
+            remove_any(&lower_path)?;
+            std::os::unix::fs::symlink(fs::read_link(&upper_path)?, &lower_path)?;
+        } else {
+            if fs::symlink_metadata(&lower_path).is_ok_and(|lower| !lower.is_file()) {
+                remove_any(&lower_path)?;
+            }
+            fs::copy(&upper_path, &lower_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 用普通目录模拟 overlay 的 upper 层 (无法在测试中创建 whiteout 设备).
+    fn fixture() -> (PathBuf, Preview) {
+        let lower = std::env::temp_dir().join(format!("howlto-preview-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(lower.join("src")).unwrap();
+        fs::write(lower.join("README.md"), "a\nb\n").unwrap();
+        fs::write(lower.join("src/lib.rs"), "fn main() {}\n").unwrap();
+        let preview = Preview::create(&lower).unwrap();
+        fs::create_dir_all(preview.upper.join("src")).unwrap();
+        fs::write(preview.upper.join("README.md"), "a\nc\nd\n").unwrap();
+        fs::write(preview.upper.join("src/lib.rs"), "fn main() {}\n").unwrap();
+        fs::write(preview.upper.join("new.bin"), [0xff, 0x00]).unwrap();
+        (lower, preview)
+    }
+
+    #[test]
+    fn scan_reports_created_and_modified_files() {
+        let (lower, mut preview) = fixture();
+        preview.scan().unwrap();
+        assert_eq!(
+            preview.changes(),
+            [
+                FileChange {
+                    path: "README.md".into(),
+                    kind: ChangeKind::Modified,
+                    lines: Some((2, 1)),
+                },
+                FileChange {
+                    path: "new.bin".into(),
+                    kind: ChangeKind::Created,
+                    lines: None,
+                },
+            ]
+        );
+        assert_eq!(
+            preview.summary(),
+            ["~ README.md (+2 -1)", "+ new.bin (binary)"]
+        );
+        fs::remove_dir_all(lower).unwrap();
+    }
+
+    #[test]
+    fn apply_copies_upper_into_the_real_directory() {
+        let (lower, preview) = fixture();
+        preview.apply().unwrap();
+        assert_eq!(
+            fs::read_to_string(lower.join("README.md")).unwrap(),
+            "a\nc\nd\n"
+        );
+        assert_eq!(fs::read(lower.join("new.bin")).unwrap(), [0xff, 0x00]);
+        let root = preview.root.clone();
+        drop(preview);
+        assert!(!root.exists());
+        fs::remove_dir_all(lower).unwrap();
+    }
+
+    #[test]
+    fn deleted_directories_list_every_file() {
+        let (lower, _preview) = fixture();
+        let mut changes = Vec::new();
+        collect_deleted(
+            &lower.join("src"),
+            &fs::symlink_metadata(lower.join("src")).unwrap(),
+            Path::new("src"),
+            &mut changes,
+        )
+        .unwrap();
+        assert_eq!(changes[0].path, Path::new("src/lib.rs"));
+        assert_eq!(changes[0].kind, ChangeKind::Deleted);
+        fs::remove_dir_all(lower).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+//! 在伪终端中交互式执行 elevate 命令.
+//!
+//! 用户终端进入 raw mode, 按键原样转发给伪终端, 程序输出原样写回用户终端,
+//! 同时记录一份去掉终端控制序列的 transcript 作为返回给模型的输出.
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    time::Instant,
+};
+
+const POLL_INTERVAL_MS: i32 = 50;
+
+/// 在新的伪终端中启动 `<shell> -c <command>`, 返回子进程和伪终端主端.
+pub(super) fn spawn(
+    shell: &Path,
+    command: &str,
+    prepare: impl FnOnce(&mut Command),
+) -> io::Result<(Child, File)> {
+    let (columns, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let mut window = libc::winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut master = -1;
+    let mut slave = -1;
+    // SAFETY: 输出参数都指向有效的局部变量, name 和 termp 允许为空.
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            // macOS 上参数类型是 `*mut winsize`, Linux 上是 `*const winsize`.
+            std::ptr::addr_of_mut!(window),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: openpty 成功时返回两个新打开且归我们所有的文件描述符.
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    let mut child = Command::new(shell);
+    child
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    prepare(&mut child);
+    // SAFETY: pre_exec 中只调用 async-signal-safe 的 setsid 和 ioctl.
+    unsafe {
+        child.pre_exec(|| {
+            // 成为新会话的首进程, 并把伪终端设为控制终端, 程序才能读取密码等输入.
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = child.spawn()?;
+    Ok((child, File::from(master)))
+}
+
+/// 交互式执行的结束方式.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Outcome {
+    Exited(ExitStatus),
+    TimedOut,
+    /// transcript 超过了输出大小限制, 命令被终止.
+    OutputLimit,
+}
+
+/// 在用户输入, 伪终端和 `output` 之间转发数据, 直到子进程退出, 超过 `deadline`,
+/// 或 transcript 超过 `max_output` 字节.
+///
+/// 返回结束方式和原始 transcript. 超时或超出限制时终止整个会话 (进程组),
+/// 子进程启动的后台进程不会残留.
+pub(super) fn relay(
+    child: &mut Child,
+    mut master: File,
+    input: Option<BorrowedFd<'_>>,
+    output: &mut impl Write,
+    deadline: Instant,
+    max_output: usize,
+) -> io::Result<(Outcome, Vec<u8>)> {
+    let mut transcript = Vec::new();
+    let mut buffer = [0_u8; 4096];
+    let mut master_open = true;
+    loop {
+        let mut fds = vec![libc::pollfd {
+            fd: master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        if let Some(input) = input {
+            fds.push(libc::pollfd {
+                fd: input.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+        let polled = if master_open {
+            // SAFETY: fds 在调用期间有效, 长度与传入的数量一致.
+            unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, POLL_INTERVAL_MS) }
+        } else {
+            std::thread::sleep(std::time::Duration::from_millis(POLL_INTERVAL_MS as u64));
+            0
+        };
+        if polled < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+
+        if master_open && fds[0].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+            match master.read(&mut buffer) {
+                // 子进程关闭伪终端后, Linux 上读取主端会返回 EIO.
+                Ok(0) | Err(_) => master_open = false,
+                Ok(size) => {
+                    transcript.extend_from_slice(&buffer[..size]);
+                    output.write_all(&buffer[..size])?;
+                    output.flush()?;
+                }
+            }
+        }
+        if let Some(input) = input
+            && fds.get(1).is_some_and(|fd| fd.revents & libc::POLLIN != 0)
+        {
+            // SAFETY: input 在本函数期间保持有效, buffer 长度与传入的 size 一致.
+            let size = unsafe {
+                libc::read(input.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len())
+            };
+            if size > 0 {
+                master.write_all(&buffer[..size as usize])?;
+            }
+        }
+
+        if transcript.len() > max_output {
+            transcript.truncate(max_output);
+            kill_session(child);
+            return Ok((Outcome::OutputLimit, transcript));
+        }
+        if let Some(status) = child.try_wait()? {
+            if master_open {
+                drain(&mut master, &mut transcript, output)?;
+                transcript.truncate(max_output);
+            }
+            return Ok((Outcome::Exited(status), transcript));
+        }
+        if Instant::now() >= deadline {
+            kill_session(child);
+            return Ok((Outcome::TimedOut, transcript));
+        }
+    }
+}
+
+/// 终止以 `child` 为首进程的会话中的所有进程, 并回收 `child`.
+fn kill_session(child: &mut Child) {
+    // SAFETY: kill 只发送信号. child 在 spawn 时调用了 setsid, 进程组号就是它的 pid.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// 子进程退出后读取伪终端中剩余的输出.
+fn drain(master: &mut File, transcript: &mut Vec<u8>, output: &mut impl Write) -> io::Result<()> {
+    let mut buffer = [0_u8; 4096];
+    loop {
+        let mut fd = libc::pollfd {
+            fd: master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: fd 在调用期间有效.
+        if unsafe { libc::poll(&mut fd, 1, 0) } <= 0 {
+            return Ok(());
+        }
+        match master.read(&mut buffer) {
+            Ok(0) | Err(_) => return Ok(()),
+            Ok(size) => {
+                transcript.extend_from_slice(&buffer[..size]);
+                output.write_all(&buffer[..size])?;
+            }
+        }
+    }
+}
+
+/// 用用户终端交互式执行命令. 期间用户终端处于 raw mode, Ctrl+C 等按键直接交给程序.
+pub(super) fn run_interactive(
+    shell: &Path,
+    command: &str,
+    deadline: Instant,
+    max_output: usize,
+    prepare: impl FnOnce(&mut Command),
+) -> io::Result<(Outcome, Vec<u8>)> {
+    let tty = File::open("/dev/tty")?;
+    let (mut child, master) = spawn(shell, command, prepare)?;
+    crossterm::terminal::enable_raw_mode()?;
+    let result = relay(
+        &mut child,
+        master,
+        Some(tty.as_fd()),
+        &mut io::stderr(),
+        deadline,
+        max_output,
+    );
+    let restored = crossterm::terminal::disable_raw_mode();
+    let result = result?;
+    restored?;
+    Ok(result)
+}
+
+/// 把终端输出整理为纯文本: 去掉控制序列, 按 `\r` 和退格模拟覆盖.
+pub(super) fn clean_transcript(raw: &[u8]) -> String {
+    let text = String::from_utf8_lossy(raw);
+    let mut output = String::with_capacity(text.len());
+    let mut line = String::new();
+    let mut characters = text.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '\x1b' => match characters.next() {
+                // CSI: 参数之后以 0x40..=0x7e 结束.
+                Some('[') => {
+                    for next in characters.by_ref() {
+                        if ('\x40'..='\x7e').contains(&next) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: 以 BEL 或 ST (`ESC \`) 结束.
+                Some(']') => {
+                    while let Some(next) = characters.next() {
+                        if next == '\x07' {
+                            break;
+                        }
+                        if next == '\x1b' {
+                            characters.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' => {
+                output.push_str(line.trim_end());
+                output.push('\n');
+                line.clear();
+            }
+            '\r' if characters.peek() != Some(&'\n') => line.clear(),
+            '\x08' => {
+                line.pop();
+            }
+            '\t' => line.push('\t'),
+            character if character.is_control() => {}
+            character => line.push(character),
+        }
+    }
+    output.push_str(line.trim_end());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn transcript_strips_escapes_and_overwrites() {
+        let raw = b"\x1b[1mName\x1b[0m: abc\x08d\r\n\x1b]0;title\x07progress 10%\rprogress 100%\r\ndone";
+        assert_eq!(clean_transcript(raw), "Name: abd\nprogress 100%\ndone");
+    }
+
+    #[test]
+    fn commands_see_a_terminal() {
+        let (mut child, master) =
+            spawn(Path::new("/bin/sh"), "[ -t 0 ] && [ -t 1 ] && echo on-tty", |_| {}).unwrap();
+        let mut output = Vec::new();
+        let (outcome, transcript) = relay(
+            &mut child,
+            master,
+            None,
+            &mut output,
+            Instant::now() + Duration::from_secs(5),
+            usize::MAX,
+        )
+        .unwrap();
+        assert!(matches!(outcome, Outcome::Exited(status) if status.success()));
+        assert_eq!(clean_transcript(&transcript), "on-tty\n");
+        assert_eq!(output, transcript);
+    }
+
+    #[test]
+    fn relay_stops_the_whole_session_at_the_deadline() {
+        let marker = std::env::temp_dir().join(format!("howlto-pty-{}", uuid::Uuid::new_v4()));
+        let command = format!("(trap '' HUP; sleep 1; touch {}) & sleep 5", marker.display());
+        let (mut child, master) = spawn(Path::new("/bin/sh"), &command, |_| {}).unwrap();
+        let (outcome, _) = relay(
+            &mut child,
+            master,
+            None,
+            &mut Vec::new(),
+            Instant::now() + Duration::from_millis(200),
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(outcome, Outcome::TimedOut);
+        // 后台的孙进程也随会话一起被终止.
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn relay_stops_chatty_commands_at_the_output_limit() {
+        let (mut child, master) = spawn(Path::new("/bin/sh"), "yes", |_| {}).unwrap();
+        let (outcome, transcript) = relay(
+            &mut child,
+            master,
+            None,
+            &mut std::io::sink(),
+            Instant::now() + Duration::from_secs(5),
+            64 * 1024,
+        )
+        .unwrap();
+        assert_eq!(outcome, Outcome::OutputLimit);
+        assert_eq!(transcript.len(), 64 * 1024);
+    }
+}
@@ -0,0 +1,305 @@
+//! 进程内的 Landlock + seccomp 后端.
+//!
+//! 不依赖 user namespace, 在加固过的发行版 (禁用了非特权 user namespace) 上也能使用:
+//! 父进程按策略构造 Landlock 规则集, 子进程在 exec 之前 (pre-exec) 依次设置
+//! `no_new_privs`, 用 seccomp 禁止创建 socket (禁网, 允许联网时仍然禁止 `AF_UNIX`), 再进入规则集.
+//! 规则集同时限定信号和抽象 unix socket 的作用范围, 沙箱内的命令不能向外面的进程发信号,
+//! 也不能连接 D-Bus, ssh-agent 或 docker 这类本地服务, 因此要求 Landlock ABI 6 (Linux 6.12) 及以上.
+//!
+//! 与挂载型后端的区别: 无法挂载 tmpfs, `build` 策略下 `/tmp` 和 `~/.cache` 的写入是真实的.
+//! Landlock 只能放行而不能在放行的目录中再禁止, 因此屏蔽敏感路径时沿着通往它们的目录
+//! 逐层展开, 只放行旁边的条目; 这些中间目录只能列出, 被屏蔽目录中的文件名仍然可见.
+
+use std::{
+    fs::File,
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{fs::OpenOptionsExt, process::CommandExt},
+    },
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use super::{SYSTEM_DIRS, SandboxOptions, SandboxPolicy, home_cache};
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+/// ABI 1 中的全部文件系统权限 (`EXECUTE` 到 `MAKE_SYM`).
+const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+/// ABI 2: 跨目录 link/rename.
+const ACCESS_FS_REFER: u64 = 1 << 13;
+/// ABI 3: truncate.
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+/// ABI 6: 只能连接同一沙箱中创建的抽象 unix socket, 只能向同一沙箱中的进程发信号.
+const SCOPE_ABSTRACT_UNIX_SOCKET: u64 = 1 << 0;
+const SCOPE_SIGNAL: u64 = 1 << 1;
+/// 支持作用范围限定的最低 ABI, 更旧的内核上不提供此后端.
+const MIN_ABI: i32 = 6;
+
+const ACCESS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+/// 可以授予普通文件 (而非目录) 的权限.
+const ACCESS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+    /// ABI 4 起支持, 这里不使用 (禁网由 seccomp 负责).
+    handled_access_net: u64,
+    /// ABI 6 起支持.
+    scoped: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// 内核支持的 Landlock ABI 版本, 不支持, 被禁用或低于 [`MIN_ABI`] 时返回 None.
+pub(super) fn abi() -> Option<i32> {
+    // SAFETY: 查询版本时 attr 为空指针, size 为 0.
+    let version = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    (version >= MIN_ABI as libc::c_long && seccomp::AUDIT_ARCH.is_some())
+        .then_some(version as i32)
+}
+
+/// 构造直接执行 `program` 的命令, 子进程在 exec 前进入沙箱.
+pub(super) fn command(
+    program: &Path,
+    args: &[String],
+    options: &SandboxOptions<'_>,
+) -> io::Result<Command> {
+    let abi = abi()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "landlock is not available"))?;
+    let ruleset = ruleset(abi, program, options)?;
+    let filter = seccomp::filter(options.policy == SandboxPolicy::Build && options.network);
+    let mut command = Command::new(program);
+    command.args(args);
+    // SAFETY: 闭包只执行 prctl 与 landlock 系统调用, fork 之后不分配内存也不加锁.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if let Some(filter) = &filter {
+                let program = libc::sock_fprog {
+                    len: filter.len() as u16,
+                    filter: filter.as_ptr() as *mut libc::sock_filter,
+                };
+                if libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                ) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(command)
+}
+
+/// 按策略创建规则集: 列出的路径之外一律不可访问.
+fn ruleset(abi: i32, program: &Path, options: &SandboxOptions<'_>) -> io::Result<OwnedFd> {
+    let mut handled = ACCESS_FS_V1;
+    if abi >= 2 {
+        handled |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        handled |= ACCESS_FS_TRUNCATE;
+    }
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+        handled_access_net: 0,
+        scoped: SCOPE_ABSTRACT_UNIX_SOCKET | SCOPE_SIGNAL,
+    };
+    // SAFETY: attr 在调用期间有效, size 与结构体一致.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            size_of::<RulesetAttr>(),
+            0u32,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: 系统调用成功时返回新的文件描述符, 所有权归我们.
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+    match options.policy {
+        SandboxPolicy::Strict => {
+            for dir in SYSTEM_DIRS.iter().chain(&["/dev", "/proc"]) {
+                allow(&ruleset, Path::new(dir), ACCESS_READ, handled)?;
+            }
+            allow(&ruleset, program, ACCESS_READ, handled)?;
+            allow_except(&ruleset, options.cwd, options.masked, handled)?;
+        }
+        SandboxPolicy::Default | SandboxPolicy::Build => {
+            allow_except(&ruleset, Path::new("/"), options.masked, handled)?;
+        }
+    }
+    allow(&ruleset, Path::new("/dev/null"), ACCESS_FILE, handled)?;
+    // 与挂载型后端不同, 这里放行的是真实目录, 写入不会被丢弃, explore 会先请求用户确认.
+    if options.policy == SandboxPolicy::Build {
+        allow(&ruleset, Path::new("/tmp"), handled, handled)?;
+        if let Some(cache) = home_cache() {
+            allow(&ruleset, &cache, handled, handled)?;
+        }
+    }
+    Ok(ruleset)
+}
+
+/// 允许读取 `dir` 下除 `masked` 之外的一切.
+///
+/// 包含被屏蔽路径的目录本身只允许列出, 其中的条目逐个放行. 符号链接被跳过:
+/// Landlock 按解析后的真实路径检查权限, 放行链接本身没有意义.
+fn allow_except(ruleset: &OwnedFd, dir: &Path, masked: &[PathBuf], handled: u64) -> io::Result<()> {
+    let inside: Vec<_> = masked
+        .iter()
+        .filter(|path| path.starts_with(dir))
+        .cloned()
+        .collect();
+    if inside.is_empty() {
+        return allow(ruleset, dir, ACCESS_READ, handled);
+    }
+    if inside.iter().any(|path| path == dir) {
+        return Ok(());
+    }
+    allow(ruleset, dir, ACCESS_FS_READ_DIR, handled)?;
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        if entry.file_type().is_ok_and(|kind| kind.is_symlink()) {
+            continue;
+        }
+        allow_except(ruleset, &entry.path(), &inside, handled)?;
+    }
+    Ok(())
+}
+
+/// 允许 `path` (及其下所有文件) 的 `access` 权限, 不存在的路径会被跳过.
+fn allow(ruleset: &OwnedFd, path: &Path, access: u64, handled: u64) -> io::Result<()> {
+    let file = match File::options()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let mut access = access & handled;
+    if !file.metadata()?.is_dir() {
+        access &= ACCESS_FILE;
+    }
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: file.as_raw_fd(),
+    };
+    // SAFETY: attr 与 file 在调用期间有效.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0u32,
+        )
+    };
+    if result != 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(
+            err.kind(),
+            format!("landlock rule for `{}`: {err}", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// 禁网和禁止连接本地 unix socket 的 seccomp 过滤器.
+mod seccomp {
+    use libc::{
+        BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W, SECCOMP_RET_ALLOW,
+        SECCOMP_RET_ERRNO, sock_filter,
+    };
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    pub(super) const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    /// 其他架构上没有校验过系统调用号, 不提供此后端.
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub(super) const AUDIT_ARCH: Option<u32> = None;
+
+    /// x86_64 上 x32 ABI 的系统调用号带有此标记, 一律拒绝以免绕过过滤.
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// `seccomp_data` 中 `nr`, `arch` 和 `args[0]` 低 32 位 (小端) 的偏移.
+    const OFFSET_NR: u32 = 0;
+    const OFFSET_ARCH: u32 = 4;
+    const OFFSET_ARG0: u32 = 16;
+
+    const fn stmt(code: u32, k: u32) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    const fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    /// 拒绝创建 socket 和 io_uring (可以绕过 `socket()` 创建 socket). 允许联网时只拒绝 `AF_UNIX`:
+    /// Landlock 不限制连接文件系统中的 unix socket, 如 `/var/run/docker.sock` 和 ssh-agent.
+    pub(super) fn filter(network: bool) -> Option<Vec<sock_filter>> {
+        let arch = AUDIT_ARCH?;
+        let deny = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        let allow = SECCOMP_RET_ALLOW;
+        Some(vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARCH),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 1, 0),
+            stmt(BPF_RET | BPF_K, deny),
+            stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_NR),
+            jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 6, 0),
+            jump(
+                BPF_JMP | BPF_JEQ | BPF_K,
+                libc::SYS_io_uring_setup as u32,
+                5,
+                0,
+            ),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_socket as u32, 0, 3),
+            stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARG0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::AF_UNIX as u32, 2, 0),
+            stmt(BPF_RET | BPF_K, if network { allow } else { deny }),
+            stmt(BPF_RET | BPF_K, allow),
+            stmt(BPF_RET | BPF_K, deny),
+        ])
+    }
+}
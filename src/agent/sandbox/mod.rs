@@ -0,0 +1,946 @@
+//! 系统级只读沙箱后端.
+//!
+//! 用于安全地执行 agent 触发的外部程序 (主要是 `Explore` 工具),
+//! 避免具有副作用的命令在静默状态下造成破坏 (e.g. `mkdir --help` 真的去创建目录).
+//!
+//! 安全策略默认为: **只读 + 禁网**, 可以按 [`SandboxPolicy`] 收紧或放宽:
+//! - `strict`: 只能读取工作目录和系统目录.
+//! - `default`: 整个文件系统只读.
+//! - `build`: 在 `default` 的基础上允许写 `/tmp` 和 `~/.cache`, 可选联网. 挂载型后端的写入
+//!   落在随沙箱丢弃的临时目录中, Landlock 和 Seatbelt 则会写入真实目录.
+//!
+//! 无论哪种策略, [`SandboxOptions::masked`] 中的敏感路径 (`~/.ssh`, 浏览器配置,
+//! howlto 自己的配置等) 在沙箱内都不可读, 由系统而不是模型的自觉来保证隐私.
+//!
+//! 后端:
+//! - macOS: Seatbelt (`sandbox-exec`).
+//! - Linux: 按顺序探测 Bubblewrap (`bwrap`), `nsjail`, `firejail` 和进程内的
+//!   Landlock + seccomp, 使用第一个能实际运行的. 前三者依赖非特权 user namespace,
+//!   在加固过的发行版上经常被禁用, 因此只检查二进制是否存在是不够的.
+//!
+//! 不使用 trait object (`dyn`), 直接通过条件编译让 [`Sandbox`] 在不同平台持有
+//! 不同字段与实现. 不支持的平台直接编译失败 (见下方的 [`compile_error!`]).
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+compile_error!("sandbox backend is only supported on macOS (Seatbelt) and Linux (Bubblewrap)");
+
+#[cfg(target_os = "linux")]
+mod landlock;
+mod resources;
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use tracing::{debug, info, warn};
+
+pub use crate::config::SandboxPolicy;
+pub use resources::{ResourceHit, ResourceLimits};
+
+/// `strict` 策略下可读的系统目录, 不存在的会被跳过.
+#[cfg(target_os = "linux")]
+const SYSTEM_DIRS: [&str; 9] = [
+    "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/nix",
+];
+
+#[cfg(target_os = "macos")]
+const SYSTEM_DIRS: [&str; 9] = [
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/System",
+    "/Library",
+    "/private/etc",
+    "/private/var/db",
+    "/dev",
+    "/opt",
+];
+
+/// 系统沙箱后端.
+///
+/// 字段在不同平台下不同 (条件编译), 调用方无需关心具体平台, 只需通过
+/// [`Sandbox::wrap`] 包装命令, [`detect`] 探测可用性.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    #[cfg(target_os = "macos")]
+    sandbox_exec: PathBuf,
+    #[cfg(target_os = "linux")]
+    backend: Backend,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone)]
+enum Backend {
+    Bubblewrap(PathBuf),
+    Nsjail(PathBuf),
+    Firejail(PathBuf),
+    Landlock,
+}
+
+/// 单次沙箱执行的选项.
+#[derive(Debug, Clone, Copy)]
+pub struct SandboxOptions<'a> {
+    pub policy: SandboxPolicy,
+    /// 命令的工作目录, `strict` 策略下只有它和系统目录可读.
+    pub cwd: &'a Path,
+    /// 是否允许联网, 只对 `build` 策略生效.
+    pub network: bool,
+    /// 沙箱内不可读的敏感路径, 不存在的会被跳过.
+    pub masked: &'a [PathBuf],
+}
+
+impl Sandbox {
+    /// 包装命令, 使其按 `options` 中的策略在沙箱里执行.
+    pub fn wrap(
+        &self,
+        program: &Path,
+        args: &[String],
+        options: &SandboxOptions<'_>,
+    ) -> io::Result<tokio::process::Command> {
+        let masked = existing_masks(options);
+        let options = SandboxOptions {
+            masked: &masked,
+            ..*options
+        };
+        let mut command = wrap_impl(self, program, args, &options)?;
+        command.current_dir(options.cwd);
+        Ok(command.into())
+    }
+
+    /// 包装命令, 使其在沙箱里执行, 但 `overlay.dir` 可写, 写入只落在 `overlay.upper` 中.
+    ///
+    /// 用于 elevate 的预演, 目前只有 Linux (Bubblewrap) 支持.
+    pub fn wrap_overlay(
+        &self,
+        program: &Path,
+        args: &[String],
+        overlay: &Overlay<'_>,
+    ) -> io::Result<tokio::process::Command> {
+        wrap_overlay_impl(self, program, args, overlay).map(Into::into)
+    }
+
+    /// 是否支持 [`Sandbox::wrap_overlay`].
+    pub fn supports_overlay(&self) -> bool {
+        supports_overlay_impl(self)
+    }
+
+    /// `build` 策略下对 `/tmp` 和 `~/.cache` 的写入是否随沙箱丢弃.
+    ///
+    /// 挂载型后端把它们换成临时目录; Landlock 和 Seatbelt 只能放行真实目录,
+    /// 写入会保留下来.
+    pub fn discards_build_writes(&self) -> bool {
+        discards_build_writes_impl(self)
+    }
+
+    /// 后端的人类可读名称, 用于日志.
+    pub fn name(&self) -> &'static str {
+        name_impl(self)
+    }
+}
+
+/// `build` 策略下可写的缓存目录.
+fn home_cache() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache"))
+}
+
+/// 实际存在且在沙箱内可见的敏感路径 (规范化后), 沙箱后端只需处理这些路径.
+/// `strict` 策略下工作目录之外的路径本来就不可见.
+fn existing_masks(options: &SandboxOptions<'_>) -> Vec<PathBuf> {
+    let mut masked = Vec::new();
+    for path in options.masked.iter().filter_map(|path| path.canonicalize().ok()) {
+        let visible = options.policy != SandboxPolicy::Strict || path.starts_with(options.cwd);
+        if visible && !masked.contains(&path) {
+            masked.push(path);
+        }
+    }
+    masked
+}
+
+/// 预演用的 overlay 挂载.
+#[derive(Debug)]
+pub struct Overlay<'a> {
+    /// 被覆盖的目录, 作为 overlay 的只读下层.
+    pub dir: &'a Path,
+    /// 宿主上的 upper 目录, 沙箱内的写入都落在这里.
+    pub upper: &'a Path,
+    /// overlayfs 需要的 work 目录, 必须与 `upper` 在同一文件系统.
+    pub work: &'a Path,
+    /// 是否允许联网.
+    pub network: bool,
+}
+
+// ---------------------------------------------------------------------------
+// macOS: Seatbelt (sandbox-exec)
+// ---------------------------------------------------------------------------
+
+#[cfg(target_os = "macos")]
+fn wrap_impl(
+    sb: &Sandbox,
+    program: &Path,
+    args: &[String],
+    options: &SandboxOptions<'_>,
+) -> io::Result<Command> {
+    let mut command = Command::new(&sb.sandbox_exec);
+    command
+        .arg("-p")
+        .arg(seatbelt_profile(options))
+        .arg(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// 按策略生成 Seatbelt profile.
+///
+/// 注意: 具体的 allow 规则和 `(deny ...)` 必须在 `(allow default)` 之前, 否则 default 会先放行.
+/// 屏蔽敏感路径的 deny 放在最前面, 优先于 `strict` 对工作目录的 allow.
+/// `build` 策略无法挂载 tmpfs, 允许写入的目录是真实目录.
+#[cfg(target_os = "macos")]
+fn seatbelt_profile(options: &SandboxOptions<'_>) -> String {
+    let quote = |path: &Path| path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+    let subpath = |path: &Path| format!(" (subpath \"{}\")", quote(path));
+    let mut profile = String::from("(version 1)\n(allow file-write-data (literal \"/dev/null\"))\n");
+    if !options.masked.is_empty() {
+        profile.push_str("(deny file-read* file-write*");
+        for path in options.masked {
+            if path.is_dir() {
+                profile.push_str(&subpath(path));
+            } else {
+                profile.push_str(&format!(" (literal \"{}\")", quote(path)));
+            }
+        }
+        profile.push_str(")\n");
+    }
+    match options.policy {
+        SandboxPolicy::Strict => {
+            profile.push_str("(allow file-read-metadata)\n(allow file-read* (literal \"/\")");
+            for dir in SYSTEM_DIRS {
+                profile.push_str(&subpath(Path::new(dir)));
+            }
+            profile.push_str(&subpath(options.cwd));
+            profile.push_str(")\n(deny file-read*)\n");
+        }
+        SandboxPolicy::Default => {}
+        SandboxPolicy::Build => {
+            profile.push_str("(allow file-write*");
+            profile.push_str(&subpath(Path::new("/private/tmp")));
+            profile.push_str(&subpath(&std::env::temp_dir()));
+            if let Some(cache) = home_cache() {
+                profile.push_str(&subpath(&cache));
+            }
+            profile.push_str(")\n");
+        }
+    }
+    profile.push_str("(deny file-write*)\n");
+    if !(options.policy == SandboxPolicy::Build && options.network) {
+        profile.push_str("(deny network*)\n");
+    }
+    profile.push_str("(allow default)\n");
+    profile
+}
+
+#[cfg(target_os = "macos")]
+fn wrap_overlay_impl(
+    _sb: &Sandbox,
+    _program: &Path,
+    _args: &[String],
+    _overlay: &Overlay<'_>,
+) -> io::Result<Command> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "overlay preview is not supported by seatbelt",
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn supports_overlay_impl(_sb: &Sandbox) -> bool {
+    false
+}
+
+#[cfg(target_os = "macos")]
+fn discards_build_writes_impl(_sb: &Sandbox) -> bool {
+    false
+}
+
+#[cfg(target_os = "macos")]
+fn name_impl(_sb: &Sandbox) -> &'static str {
+    "seatbelt"
+}
+
+// ---------------------------------------------------------------------------
+// Linux: Bubblewrap (bwrap) / nsjail / firejail / Landlock
+// ---------------------------------------------------------------------------
+
+#[cfg(target_os = "linux")]
+fn wrap_impl(
+    sb: &Sandbox,
+    program: &Path,
+    args: &[String],
+    options: &SandboxOptions<'_>,
+) -> io::Result<Command> {
+    let mut command = match &sb.backend {
+        Backend::Bubblewrap(bwrap) => bwrap_command(bwrap, program, options),
+        Backend::Nsjail(nsjail) => nsjail_command(nsjail, program, options),
+        Backend::Firejail(firejail) => firejail_command(firejail, options),
+        Backend::Landlock => return landlock::command(program, args, options),
+    };
+    command.arg(program);
+    command.args(args);
+    Ok(command)
+}
+
+#[cfg(target_os = "linux")]
+fn bwrap_command(bwrap: &Path, program: &Path, options: &SandboxOptions<'_>) -> Command {
+    let mut command = Command::new(bwrap);
+    match options.policy {
+        SandboxPolicy::Strict => {
+            // 只挂载系统目录, 程序本身和工作目录, 均为只读.
+            for dir in SYSTEM_DIRS {
+                command.arg("--ro-bind-try").arg(dir).arg(dir);
+            }
+            command
+                .arg("--ro-bind-try")
+                .arg(program)
+                .arg(program)
+                .arg("--ro-bind")
+                .arg(options.cwd)
+                .arg(options.cwd);
+            bwrap_common(&mut command);
+        }
+        SandboxPolicy::Default | SandboxPolicy::Build => bwrap_root(&mut command),
+    }
+    // 隔离一切命名空间, 其中包含 --unshare-net 实现禁网.
+    command.arg("--unshare-all");
+    if options.policy == SandboxPolicy::Build {
+        if options.network {
+            command.arg("--share-net");
+        }
+        // 写入落在随沙箱丢弃的 tmpfs 上. tmpfs 的挂载点必须已存在于只读的根文件系统中.
+        command.arg("--tmpfs").arg("/tmp");
+        if let Some(cache) = home_cache().filter(|cache| cache.is_dir()) {
+            command.arg("--tmpfs").arg(cache);
+        }
+    }
+    // 敏感目录用空的 tmpfs 覆盖, 敏感文件用 /dev/null 覆盖. 必须在其他挂载之后.
+    for path in options.masked {
+        if path.is_dir() {
+            command.arg("--tmpfs").arg(path);
+        } else {
+            command.arg("--ro-bind").arg("/dev/null").arg(path);
+        }
+    }
+    command.arg("--chdir").arg(options.cwd);
+    command
+}
+
+/// nsjail 的一次性模式 (`-Mo`). 默认会隔离网络, 清空环境变量并施加自己的 rlimit,
+/// 这里保留环境变量 (由调用方的白名单负责), 取消时间限制, rlimit 沿用继承的硬限制
+/// (见 [`ResourceLimits`]).
+#[cfg(target_os = "linux")]
+fn nsjail_command(nsjail: &Path, program: &Path, options: &SandboxOptions<'_>) -> Command {
+    let mut command = Command::new(nsjail);
+    command
+        .args(["-Mo", "--quiet", "--keep_env", "--time_limit", "0"])
+        .args(["--rlimit_as", "hard", "--rlimit_cpu", "hard", "--rlimit_fsize", "hard"])
+        .args(["--rlimit_nofile", "hard", "--rlimit_nproc", "hard"]);
+    match options.policy {
+        SandboxPolicy::Strict => {
+            for dir in SYSTEM_DIRS.iter().map(Path::new).filter(|dir| dir.exists()) {
+                command.arg("-R").arg(dir);
+            }
+            command.arg("-R").arg(program).arg("-R").arg(options.cwd);
+        }
+        SandboxPolicy::Default | SandboxPolicy::Build => {
+            command.arg("-R").arg("/");
+        }
+    }
+    command.arg("-B").arg("/dev/null");
+    if options.policy == SandboxPolicy::Build {
+        if options.network {
+            command.arg("--disable_clone_newnet");
+        }
+        command.arg("-T").arg("/tmp");
+        if let Some(cache) = home_cache().filter(|cache| cache.is_dir()) {
+            command.arg("-T").arg(cache);
+        }
+    }
+    for path in options.masked {
+        if path.is_dir() {
+            command.arg("-T").arg(path);
+        } else {
+            let mut bind = std::ffi::OsString::from("/dev/null:");
+            bind.push(path);
+            command.arg("-R").arg(bind);
+        }
+    }
+    command.arg("--cwd").arg(options.cwd).arg("--");
+    command
+}
+
+/// firejail 没有 "只挂载这些目录" 的选项, `strict` 用 `--whitelist` 近似:
+/// 家目录和 `/tmp` 中只保留工作目录, 其余系统目录仍然可见.
+#[cfg(target_os = "linux")]
+fn firejail_command(firejail: &Path, options: &SandboxOptions<'_>) -> Command {
+    let mut command = Command::new(firejail);
+    command.args(["--quiet", "--noprofile", "--read-only=/"]);
+    match options.policy {
+        SandboxPolicy::Strict => {
+            command.arg(format!("--whitelist={}", options.cwd.display()));
+            command.arg("--private-tmp");
+        }
+        SandboxPolicy::Default => {}
+        SandboxPolicy::Build => {
+            command.args(["--private-tmp", "--private-cache"]);
+        }
+    }
+    for path in options.masked {
+        command.arg(format!("--blacklist={}", path.display()));
+    }
+    if !(options.policy == SandboxPolicy::Build && options.network) {
+        command.arg("--net=none");
+    }
+    command.arg("--");
+    command
+}
+
+#[cfg(target_os = "linux")]
+fn wrap_overlay_impl(
+    sb: &Sandbox,
+    program: &Path,
+    args: &[String],
+    overlay: &Overlay<'_>,
+) -> io::Result<Command> {
+    let Backend::Bubblewrap(bwrap) = &sb.backend else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("overlay preview is not supported by {}", sb.name()),
+        ));
+    };
+    let mut command = Command::new(bwrap);
+    bwrap_root(&mut command);
+    command.arg("--unshare-all");
+    if overlay.network {
+        command.arg("--share-net");
+    }
+    command
+        // 很多程序需要可写的 /tmp, 预演结束后随沙箱一起丢弃.
+        .arg("--tmpfs")
+        .arg("/tmp")
+        .arg("--overlay-src")
+        .arg(overlay.dir)
+        .arg("--overlay")
+        .arg(overlay.upper)
+        .arg(overlay.work)
+        .arg(overlay.dir)
+        .arg("--chdir")
+        .arg(overlay.dir);
+    command.arg(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// 以只读方式挂载根文件系统, 保证无写副作用.
+#[cfg(target_os = "linux")]
+fn bwrap_root(command: &mut Command) {
+    command.arg("--ro-bind").arg("/").arg("/");
+    bwrap_common(command);
+}
+
+#[cfg(target_os = "linux")]
+fn bwrap_common(command: &mut Command) {
+    command
+        // 提供 /dev /proc, 保证程序能基本启动与读取自身信息.
+        .arg("--dev")
+        .arg("/dev")
+        .arg("--proc")
+        .arg("/proc")
+        // 与父进程生命周期绑定, 防止僵尸进程.
+        .arg("--die-with-parent");
+}
+
+#[cfg(target_os = "linux")]
+fn supports_overlay_impl(sb: &Sandbox) -> bool {
+    matches!(sb.backend, Backend::Bubblewrap(_))
+}
+
+#[cfg(target_os = "linux")]
+fn discards_build_writes_impl(sb: &Sandbox) -> bool {
+    !matches!(sb.backend, Backend::Landlock)
+}
+
+#[cfg(target_os = "linux")]
+fn name_impl(sb: &Sandbox) -> &'static str {
+    match sb.backend {
+        Backend::Bubblewrap(_) => "bubblewrap",
+        Backend::Nsjail(_) => "nsjail",
+        Backend::Firejail(_) => "firejail",
+        Backend::Landlock => "landlock",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// 自动探测
+// ---------------------------------------------------------------------------
+
+fn which<S: AsRef<OsStr>>(name: S) -> Option<PathBuf> {
+    which::which(name).ok()
+}
+
+/// 根据当前平台自动探测沙箱后端, 结果在进程内缓存, 选中的后端会写入日志.
+///
+/// - macOS: 基于 `sandbox-exec` 的 [`Sandbox`].
+/// - Linux: [`available`] 中的第一个.
+///
+/// 返回 [`None`] 表示没有可用的后端 (运行时探测失败),
+/// 不支持的平台会在编译期由 `compile_error!` 拦截, 不会走到这里.
+pub fn detect() -> Option<Sandbox> {
+    static DETECTED: OnceLock<Option<Sandbox>> = OnceLock::new();
+    DETECTED
+        .get_or_init(|| {
+            let sandbox = candidates().into_iter().find(probe);
+            match &sandbox {
+                Some(sandbox) => info!(backend = sandbox.name(), "Using sandbox backend."),
+                None => warn!("No working sandbox backend found."),
+            }
+            sandbox
+        })
+        .clone()
+}
+
+/// 所有能实际运行的后端, 按优先级排列.
+pub fn available() -> Vec<Sandbox> {
+    candidates().into_iter().filter(probe).collect()
+}
+
+#[cfg(target_os = "macos")]
+fn candidates() -> Vec<Sandbox> {
+    which("sandbox-exec")
+        .map(|sandbox_exec| Sandbox { sandbox_exec })
+        .into_iter()
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn candidates() -> Vec<Sandbox> {
+    let mut backends: Vec<Backend> = [
+        ("bwrap", Backend::Bubblewrap as fn(PathBuf) -> Backend),
+        ("nsjail", Backend::Nsjail),
+        ("firejail", Backend::Firejail),
+    ]
+    .into_iter()
+    .filter_map(|(binary, backend)| which(binary).map(backend))
+    .collect();
+    if landlock::abi().is_some() {
+        backends.push(Backend::Landlock);
+    }
+    backends
+        .into_iter()
+        .map(|backend| Sandbox { backend })
+        .collect()
+}
+
+/// 在默认策略下执行 `true`, 确认后端确实能启动进程.
+fn probe(sb: &Sandbox) -> bool {
+    match try_probe(sb) {
+        Ok(()) => true,
+        Err(err) => {
+            debug!(backend = sb.name(), %err, "Sandbox backend probe failed.");
+            false
+        }
+    }
+}
+
+fn try_probe(sb: &Sandbox) -> Result<(), String> {
+    use std::process::Stdio;
+    let options = SandboxOptions {
+        policy: SandboxPolicy::Default,
+        cwd: Path::new("/"),
+        network: false,
+        masked: &[],
+    };
+    let output = wrap_impl(sb, Path::new("/bin/sh"), &["-c".into(), "true".into()], &options)
+        .and_then(|mut command| {
+            command
+                .current_dir("/")
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .output()
+        })
+        .map_err(|err| err.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// 当前平台的全部候选后端及其探测结果, 按优先级排列, 用于诊断.
+pub fn probe_all() -> Vec<(Sandbox, Result<(), String>)> {
+    candidates()
+        .into_iter()
+        .map(|sb| {
+            let result = try_probe(&sb);
+            (sb, result)
+        })
+        .collect()
+}
+
+/// 非特权 user namespace 是否可用, 以及做出判断所依据的内核参数.
+///
+/// Bubblewrap, nsjail 和 firejail 都依赖它. 返回 [`None`] 表示当前平台不适用.
+pub fn user_namespaces() -> Option<(bool, String)> {
+    #[cfg(target_os = "linux")]
+    {
+        let read = |path: &str| {
+            std::fs::read_to_string(path)
+                .ok()
+                .map(|value| value.trim().to_string())
+        };
+        let sysctls = [
+            ("kernel.unprivileged_userns_clone", "0"),
+            ("user.max_user_namespaces", "0"),
+            ("kernel.apparmor_restrict_unprivileged_userns", "1"),
+        ];
+        let mut enabled = true;
+        let mut details = Vec::new();
+        for (name, disabling) in sysctls {
+            let path = format!("/proc/sys/{}", name.replace('.', "/"));
+            if let Some(value) = read(&path) {
+                enabled &= value != disabling;
+                details.push(format!("{name}={value}"));
+            }
+        }
+        Some((enabled, details.join(", ")))
+    }
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(policy: SandboxPolicy) -> SandboxOptions<'static> {
+        SandboxOptions {
+            policy,
+            cwd: Path::new("/"),
+            network: false,
+            masked: &[],
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[tokio::test]
+    async fn seatbelt_denies_writes_allows_reads() {
+        use std::process::Stdio;
+        let sb = detect().expect("macos should have seatbelt backend");
+        assert_eq!(sb.name(), "seatbelt");
+        // Skip on hosts where macOS denies sandbox-exec itself.
+        let mut probe = sb
+            .wrap(Path::new("/bin/echo"), &["seatbelt-preflight".into()], &options(SandboxPolicy::Default))
+            .unwrap();
+        probe
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let probe_out = probe.output().await.unwrap();
+        if !probe_out.status.success()
+            && String::from_utf8_lossy(&probe_out.stderr).contains("sandbox_apply")
+        {
+            return;
+        }
+        assert!(
+            probe_out.status.success(),
+            "seatbelt preflight should succeed, status={:?}, stderr={}",
+            probe_out.status,
+            String::from_utf8_lossy(&probe_out.stderr)
+        );
+        let mut null_cmd = sb
+            .wrap(
+                Path::new("/bin/sh"),
+                &["-c".into(), "echo x > /dev/null".into()],
+                &options(SandboxPolicy::Default),
+            )
+            .unwrap();
+        null_cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let null_out = null_cmd.output().await.unwrap();
+        assert!(
+            null_out.status.success(),
+            "write to /dev/null should be allowed, status={:?}, stderr={}",
+            null_out.status,
+            String::from_utf8_lossy(&null_out.stderr)
+        );
+        // 写文件应被拒.
+        let tmp = format!("/tmp/howlto_sb_test_{}", std::process::id());
+        let mut cmd = sb
+            .wrap(
+                Path::new("/bin/sh"),
+                &["-c".into(), format!("echo x > {tmp}")],
+                &options(SandboxPolicy::Default),
+            )
+            .unwrap();
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let out = cmd.output().await.unwrap();
+        assert!(
+            !out.status.success(),
+            "write inside seatbelt sandbox should fail, status={:?}",
+            out.status
+        );
+        assert!(
+            !Path::new(&tmp).exists(),
+            "file should not have been created"
+        );
+        // 读应正常.
+        let mut cmd = sb
+            .wrap(Path::new("/bin/echo"), &["hi-from-seatbelt".into()], &options(SandboxPolicy::Default))
+            .unwrap();
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let out = cmd.output().await.unwrap();
+        assert!(out.status.success(), "echo should succeed in sandbox");
+        assert!(String::from_utf8_lossy(&out.stdout).contains("hi-from-seatbelt"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn only_mount_backends_discard_build_writes() {
+        let sandbox = |backend| Sandbox { backend };
+        assert!(sandbox(Backend::Bubblewrap(PathBuf::from("bwrap"))).discards_build_writes());
+        assert!(sandbox(Backend::Firejail(PathBuf::from("firejail"))).discards_build_writes());
+        assert!(!sandbox(Backend::Landlock).discards_build_writes());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn every_backend_denies_writes_allows_reads() {
+        use std::process::Stdio;
+        // 当前环境没有的后端会被跳过.
+        for sb in available() {
+            let tmp = format!("/tmp/howlto_{}_test_{}", sb.name(), std::process::id());
+            let mut cmd = sb
+                .wrap(
+                    Path::new("/bin/sh"),
+                    &["-c".into(), format!("echo x > {tmp}")],
+                    &options(SandboxPolicy::Default),
+                )
+                .unwrap();
+            cmd.stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            let out = cmd.output().await.unwrap();
+            assert!(
+                !out.status.success(),
+                "write inside {} sandbox should fail, status={:?}",
+                sb.name(),
+                out.status
+            );
+            assert!(
+                !Path::new(&tmp).exists(),
+                "file should not have been created by {}",
+                sb.name()
+            );
+            let mut cmd = sb
+                .wrap(
+                    Path::new("/bin/sh"),
+                    &["-c".into(), "echo hi-from-sandbox > /dev/null && echo hi-from-sandbox".into()],
+                    &options(SandboxPolicy::Default),
+                )
+                .unwrap();
+            cmd.stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            let out = cmd.output().await.unwrap();
+            assert!(out.status.success(), "echo should succeed in {}", sb.name());
+            assert!(String::from_utf8_lossy(&out.stdout).contains("hi-from-sandbox"));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn every_backend_applies_policies() {
+        use std::process::Stdio;
+        let outside = std::env::temp_dir().join(format!("howlto_policy_test_{}", std::process::id()));
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret"), "x").unwrap();
+        let secret = outside.join("secret").display().to_string();
+        let cwd = std::env::current_dir().unwrap();
+        for sb in available() {
+            let run = |policy: SandboxPolicy, script: String| {
+                let (sb, cwd) = (sb.clone(), cwd.clone());
+                async move {
+                    let options = SandboxOptions {
+                        policy,
+                        cwd: &cwd,
+                        network: false,
+                        masked: &[],
+                    };
+                    let mut cmd = sb
+                        .wrap(Path::new("/bin/sh"), &["-c".into(), script], &options)
+                        .unwrap();
+                    cmd.stdin(Stdio::null())
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped());
+                    cmd.output().await.unwrap()
+                }
+            };
+            let name = sb.name();
+
+            // strict 只能看到工作目录和系统目录.
+            let out = run(SandboxPolicy::Strict, "ls . >/dev/null".into()).await;
+            assert!(out.status.success(), "cwd should be readable under strict ({name})");
+            let out = run(SandboxPolicy::Strict, format!("cat {secret}")).await;
+            assert!(!out.status.success(), "paths outside cwd should be hidden ({name})");
+            let out = run(SandboxPolicy::Default, format!("cat {secret}")).await;
+            assert!(out.status.success(), "default policy should read everything ({name})");
+
+            // build 的 /tmp 可写, 挂载型后端的写入不会落到宿主上.
+            let tmp = format!("/tmp/howlto_build_test_{name}_{}", std::process::id());
+            let out = run(SandboxPolicy::Build, format!("echo x > {tmp} && cat {tmp}")).await;
+            assert!(out.status.success(), "build policy should allow writes to /tmp ({name})");
+            if name == "landlock" {
+                std::fs::remove_file(&tmp).unwrap();
+            } else {
+                assert!(!Path::new(&tmp).exists(), "tmpfs writes should be discarded ({name})");
+            }
+            let out = run(SandboxPolicy::Build, "echo x > ./howlto_build_write".into()).await;
+            assert!(!out.status.success(), "cwd should stay read-only under build ({name})");
+        }
+        std::fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn every_backend_masks_sensitive_paths() {
+        use std::process::Stdio;
+        let home = std::env::temp_dir().join(format!("howlto_mask_test_{}", std::process::id()));
+        std::fs::create_dir_all(home.join(".ssh")).unwrap();
+        std::fs::create_dir_all(home.join("project")).unwrap();
+        std::fs::write(home.join(".ssh/id_ed25519"), "ssh-secret").unwrap();
+        std::fs::write(home.join("config.toml"), "api-key-secret").unwrap();
+        std::fs::write(home.join("project/notes"), "visible").unwrap();
+        let masked = [
+            home.join(".ssh"),
+            home.join("config.toml"),
+            home.join("missing"),
+        ];
+        for sb in available() {
+            for (policy, cwd) in [
+                (SandboxPolicy::Default, Path::new("/")),
+                (SandboxPolicy::Strict, home.as_path()),
+            ] {
+                let options = SandboxOptions {
+                    policy,
+                    cwd,
+                    network: false,
+                    masked: &masked,
+                };
+                let script = format!(
+                    "cat {home}/.ssh/id_ed25519 {home}/config.toml; cat {home}/project/notes",
+                    home = home.display()
+                );
+                let mut cmd = sb
+                    .wrap(Path::new("/bin/sh"), &["-c".into(), script], &options)
+                    .unwrap();
+                cmd.stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+                let out = cmd.output().await.unwrap();
+                let stdout = String::from_utf8_lossy(&out.stdout);
+                let name = sb.name();
+                assert_eq!(stdout, "visible", "{name} {policy:?}");
+            }
+        }
+        std::fs::remove_dir_all(home).unwrap();
+    }
+
+    /// Landlock 后端用 seccomp 禁网, 只有 `build` 策略开启联网时才能连接.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn landlock_backend_blocks_network_unless_allowed() {
+        use std::process::Stdio;
+        let Some(bash) = which("bash") else {
+            return; // 需要 bash 的 /dev/tcp, 跳过.
+        };
+        if landlock::abi().is_none() {
+            return; // 内核不支持 Landlock, 跳过.
+        }
+        let sb = Sandbox {
+            backend: Backend::Landlock,
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connect = |options: SandboxOptions<'_>| {
+            let script = format!("exec 3<>/dev/tcp/127.0.0.1/{port}");
+            let mut cmd = wrap_impl(&sb, &bash, &["-c".into(), script], &options).unwrap();
+            cmd.stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap()
+                .success()
+        };
+        assert!(!connect(options(SandboxPolicy::Default)));
+        assert!(!connect(options(SandboxPolicy::Build)));
+        assert!(connect(SandboxOptions {
+            network: true,
+            ..options(SandboxPolicy::Build)
+        }));
+    }
+
+    /// Landlock 后端不能向沙箱外的进程发信号, 也不能连接本地 unix socket.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn landlock_backend_blocks_signals_and_unix_sockets() {
+        use std::process::Stdio;
+        let (Some(bash), Some(python)) = (which("bash"), which("python3")) else {
+            return; // 需要 bash 和 python3, 跳过.
+        };
+        if landlock::abi().is_none() {
+            return; // 内核不支持 Landlock ABI 6, 跳过.
+        }
+        let sb = Sandbox {
+            backend: Backend::Landlock,
+        };
+        let socket =
+            std::env::temp_dir().join(format!("howlto_unix_test_{}.sock", std::process::id()));
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let run = |program: &Path, args: Vec<String>, options: SandboxOptions<'_>| {
+            let mut cmd = wrap_impl(&sb, program, &args, &options).unwrap();
+            cmd.stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap()
+                .success()
+        };
+        let shell = |script: &str| vec!["-c".to_string(), script.to_string()];
+        assert!(run(&bash, shell("kill -0 $$"), options(SandboxPolicy::Default)));
+        assert!(!run(&bash, shell("kill -0 $PPID"), options(SandboxPolicy::Default)));
+        let connect = vec![
+            "-c".to_string(),
+            "import socket, sys; socket.socket(socket.AF_UNIX).connect(sys.argv[1])".to_string(),
+            socket.display().to_string(),
+        ];
+        assert!(!run(&python, connect.clone(), options(SandboxPolicy::Default)));
+        assert!(!run(
+            &python,
+            connect,
+            SandboxOptions {
+                network: true,
+                ..options(SandboxPolicy::Build)
+            }
+        ));
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! 沙箱命令的资源限制.
+//!
+//! 墙钟超时之外, 用 rlimit 限制 CPU 时间, 地址空间和进程数, 防止 `rg` 遍历 `/`
+//! 或 fork 炸弹占满机器. rlimit 在 exec 之前 (pre-exec) 设置, 由沙箱后端和命令继承.
+//! 输出大小由读取输出的一方检查, 见 [`ResourceLimits::max_output`].
+//!
+//! rlimit 只能让超限的进程失败或收到信号, 无法直接得知是哪个限制触发的:
+//! CPU 时间按 `SIGXCPU` 判断, 地址空间和进程数按失败命令的 stderr 中常见的报错判断.
+
+use std::{
+    io,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus},
+};
+
+use crate::config::ResourcesConfig;
+
+const MIB: u64 = 1024 * 1024;
+
+/// 地址空间耗尽时常见的报错 (小写).
+const MEMORY_ERRORS: [&str; 6] = [
+    "cannot allocate memory",
+    "out of memory",
+    "memory exhausted",
+    "memory allocation of",
+    "bad_alloc",
+    "memoryerror",
+];
+
+/// shell 和解释器在 fork 因进程数限制 (EAGAIN) 失败时的完整报错 (小写).
+/// 只写 `fork: ` 或 `resource temporarily unavailable` 会把网络超时等无关失败也算进来.
+const FORK_ERRORS: [&str; 5] = [
+    "fork: retry: resource temporarily unavailable",
+    "fork: resource temporarily unavailable",
+    "fork failed: resource temporarily unavailable",
+    "can't fork: resource temporarily unavailable",
+    "cannot fork",
+];
+
+/// 触发的资源限制.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceHit {
+    Cpu,
+    Memory,
+    Processes,
+    Output,
+}
+
+/// 单条命令的资源限制, 0 表示不限.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    pub cpu_secs: u64,
+    pub memory_mb: u64,
+    pub max_processes: u64,
+    pub max_output_mb: u64,
+}
+
+impl From<&ResourcesConfig> for ResourceLimits {
+    fn from(config: &ResourcesConfig) -> Self {
+        Self {
+            cpu_secs: config.cpu_secs,
+            memory_mb: config.memory_mb,
+            max_processes: config.max_processes,
+            max_output_mb: config.max_output_mb,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// 在 exec 之前设置 rlimit.
+    pub fn apply(&self, command: &mut Command) {
+        let mut limits = Vec::new();
+        if self.cpu_secs > 0 {
+            // 软限制触发 SIGXCPU, 忽略该信号的进程在 1 秒后被 SIGKILL.
+            limits.push((libc::RLIMIT_CPU, self.cpu_secs, self.cpu_secs + 1));
+        }
+        if self.memory_mb > 0 {
+            let bytes = self.memory_mb.saturating_mul(MIB);
+            limits.push((libc::RLIMIT_AS, bytes, bytes));
+        }
+        #[cfg(target_os = "linux")]
+        if self.max_processes > 0 {
+            // RLIMIT_NPROC 统计的是用户的全部进程 (包括线程), 因此在现有数量之上增加.
+            let limit = user_tasks().saturating_add(self.max_processes);
+            limits.push((libc::RLIMIT_NPROC, limit, limit));
+        }
+        if limits.is_empty() {
+            return;
+        }
+        // SAFETY: 闭包只调用 setrlimit, fork 之后不分配内存也不加锁.
+        unsafe {
+            command.pre_exec(move || {
+                for &(resource, soft, hard) in &limits {
+                    let limit = libc::rlimit {
+                        rlim_cur: soft as libc::rlim_t,
+                        rlim_max: hard as libc::rlim_t,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// 收集的 stdout 与 stderr 的最大总字节数, 超出后终止命令.
+    pub fn max_output(&self) -> usize {
+        match self.max_output_mb {
+            0 => usize::MAX,
+            mb => usize::try_from(mb.saturating_mul(MIB)).unwrap_or(usize::MAX),
+        }
+    }
+
+    /// 推断失败的命令是否因为资源限制而终止.
+    pub fn hit(&self, status: ExitStatus, stderr: &[u8]) -> Option<ResourceHit> {
+        if status.success() {
+            return None;
+        }
+        // 被信号终止的进程经过 shell 或沙箱后端转发后, 退出码为 128 + 信号.
+        let signal = status
+            .signal()
+            .or_else(|| status.code().map(|code| code - 128));
+        if self.cpu_secs > 0 && signal == Some(libc::SIGXCPU) {
+            return Some(ResourceHit::Cpu);
+        }
+        let stderr = String::from_utf8_lossy(stderr).to_lowercase();
+        if self.memory_mb > 0 && MEMORY_ERRORS.iter().any(|error| stderr.contains(error)) {
+            return Some(ResourceHit::Memory);
+        }
+        if self.max_processes > 0 && FORK_ERRORS.iter().any(|error| stderr.contains(error)) {
+            return Some(ResourceHit::Processes);
+        }
+        None
+    }
+
+    /// 告诉模型触发了哪个限制.
+    pub fn message(&self, hit: ResourceHit) -> String {
+        match hit {
+            ResourceHit::Cpu => format!(
+                "Command exceeded the CPU time limit of {} seconds.",
+                self.cpu_secs
+            ),
+            ResourceHit::Memory => format!(
+                "Command ran out of memory under the address-space limit of {} MiB.",
+                self.memory_mb
+            ),
+            ResourceHit::Processes => format!(
+                "Command could not create more processes (limit: {} new processes).",
+                self.max_processes
+            ),
+            ResourceHit::Output => format!(
+                "Command output exceeded {} MiB and the command was stopped.",
+                self.max_output_mb
+            ),
+        }
+    }
+}
+
+/// 当前用户 (real uid) 的任务数, 与内核检查 RLIMIT_NPROC 时的口径一致.
+#[cfg(target_os = "linux")]
+fn user_tasks() -> u64 {
+    // SAFETY: getuid 总是成功.
+    let uid = unsafe { libc::getuid() }.to_string();
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("status")).ok())
+        .filter_map(|status| {
+            let real_uid = status
+                .lines()
+                .find_map(|line| line.strip_prefix("Uid:"))?
+                .split_whitespace()
+                .next()?;
+            if real_uid != uid {
+                return None;
+            }
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Threads:"))?
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(limits: &ResourceLimits, script: &str) -> (ExitStatus, Vec<u8>) {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(script);
+        limits.apply(&mut command);
+        let output = command.output().unwrap();
+        (output.status, output.stderr)
+    }
+
+    #[test]
+    fn cpu_limit_is_reported() {
+        let limits = ResourceLimits {
+            cpu_secs: 1,
+            ..ResourceLimits::default()
+        };
+        let (status, stderr) = run(&limits, "while :; do :; done");
+        assert_eq!(limits.hit(status, &stderr), Some(ResourceHit::Cpu));
+        assert_eq!(ResourceLimits::default().hit(status, &stderr), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn memory_limit_is_reported() {
+        let limits = ResourceLimits {
+            memory_mb: 64,
+            ..ResourceLimits::default()
+        };
+        // 一行 100 MB 的输入超过了 grep 的地址空间限制.
+        let (status, stderr) = run(
+            &limits,
+            "head -c 100000000 /dev/zero | tr '\\0' x | grep -c x",
+        );
+        assert!(!status.success());
+        assert_eq!(limits.hit(status, &stderr), Some(ResourceHit::Memory));
+    }
+
+    #[test]
+    fn failures_without_limits_are_not_attributed() {
+        let limits = ResourceLimits {
+            cpu_secs: 10,
+            memory_mb: 1024,
+            max_processes: 64,
+            max_output_mb: 1,
+        };
+        for script in [
+            "echo nope >&2; exit 3",
+            "echo 'curl: (7) Resource temporarily unavailable' >&2; exit 7",
+            "echo 'error: fork: not a git repository' >&2; exit 128",
+        ] {
+            let (status, stderr) = run(&limits, script);
+            assert_eq!(limits.hit(status, &stderr), None, "{script}");
+        }
+        let (status, stderr) = run(
+            &limits,
+            "echo 'sh: fork: retry: Resource temporarily unavailable' >&2; exit 254",
+        );
+        assert_eq!(limits.hit(status, &stderr), Some(ResourceHit::Processes));
+        assert_eq!(limits.max_output(), 1024 * 1024);
+        assert_eq!(ResourceLimits::default().max_output(), usize::MAX);
+    }
+}
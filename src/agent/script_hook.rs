@@ -0,0 +1,392 @@
+//! 用户脚本钩子.
+//!
+//! 配置中 `[agent] hooks` 列出的 Rhai 脚本可以定义以下函数, 未定义的钩子直接跳过:
+//!
+//! - `before_prompt(prompt)`: 发送请求前改写提示词, 返回新的字符串.
+//! - `before_tool(tool, args)`: 工具调用前放行 (`()`/`true`), 拒绝 (`false` 或拒绝原因字符串)
+//!   或改写参数 (返回新的参数 map).
+//! - `after_submit(commands)`: 过滤, 重排或标注 `submit_commands` 提交的候选命令,
+//!   返回新的 `#{command, description}` 数组.
+//! - `after_response(text)`: 改写最终回答.
+//!
+//! 返回 `()` 表示不修改. 多个脚本按配置顺序依次执行, 前一个脚本的结果作为后一个的输入.
+
+use std::{path::PathBuf, time::Instant};
+
+use rhai::{AST, CallFnOptions, Dynamic, Engine, Scope};
+use rig_core::tool::{ToolDyn, ToolSet};
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::{
+    agent::{command::CommandResult, submit_commands::CommandItem},
+    error::{Error, Result},
+};
+
+/// 单次钩子调用最多执行的操作数, 防止脚本死循环.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const HOOKS: [(&str, usize); 4] = [
+    ("before_prompt", 1),
+    ("before_tool", 2),
+    ("after_submit", 1),
+    ("after_response", 1),
+];
+
+#[derive(Debug)]
+struct Script {
+    path: PathBuf,
+    ast: AST,
+}
+
+impl Script {
+    fn defines(&self, hook: &str) -> bool {
+        self.ast.iter_functions().any(|function| function.name == hook)
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// `before_tool` 的结果.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum ToolVerdict {
+    Allow,
+    Deny(String),
+    Rewrite(Value),
+}
+
+pub(super) struct ScriptHooks {
+    engine: Engine,
+    scripts: Vec<Script>,
+    /// 执行参数被改写的工具调用.
+    tools: ToolSet,
+}
+
+impl std::fmt::Debug for ScriptHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptHooks")
+            .field("scripts", &self.scripts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ScriptHooks {
+    fn default() -> Self {
+        Self {
+            engine: Engine::new(),
+            scripts: Vec::new(),
+            tools: ToolSet::default(),
+        }
+    }
+}
+
+impl ScriptHooks {
+    /// 编译脚本并检查钩子函数的参数个数. `tools` 用于执行被改写参数的调用.
+    pub(super) fn load(paths: &[PathBuf], tools: Vec<Box<dyn ToolDyn>>) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!(target: "howlto::hook", "{text}"));
+        engine.on_debug(|text, _, position| {
+            debug!(target: "howlto::hook", %position, "{text}");
+        });
+        let scripts = paths
+            .iter()
+            .map(|path| {
+                let invalid = |message: String| Error::HookScript {
+                    script: path.display().to_string(),
+                    message,
+                };
+                let ast = engine
+                    .compile_file(path.clone())
+                    .map_err(|error| invalid(error.to_string()))?;
+                for function in ast.iter_functions() {
+                    if let Some((name, params)) =
+                        HOOKS.iter().find(|(name, _)| *name == function.name)
+                        && function.params.len() != *params
+                    {
+                        return Err(invalid(format!(
+                            "`{name}` must take {params} parameter(s), found {}.",
+                            function.params.len()
+                        )));
+                    }
+                }
+                Ok(Script {
+                    path: path.clone(),
+                    ast,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !scripts.is_empty() {
+            info!(scripts = scripts.len(), "Loaded hook scripts.");
+        }
+        Ok(Self {
+            engine,
+            scripts,
+            tools: ToolSet::from_tools_boxed(tools),
+        })
+    }
+
+    fn call(&self, script: &Script, hook: &str, args: Vec<Dynamic>) -> Result<Dynamic> {
+        self.engine
+            .call_fn_with_options(
+                CallFnOptions::new().eval_ast(false),
+                &mut Scope::new(),
+                &script.ast,
+                hook,
+                args,
+            )
+            .map_err(|error| Error::HookScript {
+                script: script.name(),
+                message: format!("`{hook}` failed: {error}"),
+            })
+    }
+
+    /// 依次调用返回字符串的钩子, `()` 表示不修改.
+    fn rewrite_text(&self, hook: &str, mut text: String) -> Result<String> {
+        for script in self.scripts.iter().filter(|script| script.defines(hook)) {
+            let result = self.call(script, hook, vec![text.clone().into()])?;
+            if result.is_unit() {
+                continue;
+            }
+            text = result.into_string().map_err(|kind| Error::HookScript {
+                script: script.name(),
+                message: format!("`{hook}` must return a string or (), found {kind}."),
+            })?;
+        }
+        Ok(text)
+    }
+
+    pub(super) fn before_prompt(&self, prompt: String) -> Result<String> {
+        self.rewrite_text("before_prompt", prompt)
+    }
+
+    pub(super) fn after_response(&self, text: String) -> Result<String> {
+        self.rewrite_text("after_response", text)
+    }
+
+    pub(super) fn after_submit(&self, mut commands: Vec<CommandItem>) -> Result<Vec<CommandItem>> {
+        for script in self
+            .scripts
+            .iter()
+            .filter(|script| script.defines("after_submit"))
+        {
+            let invalid = |message: String| Error::HookScript {
+                script: script.name(),
+                message: format!("`after_submit` {message}"),
+            };
+            let input = rhai::serde::to_dynamic(&commands)
+                .map_err(|error| invalid(format!("input: {error}")))?;
+            let result = self.call(script, "after_submit", vec![input])?;
+            if result.is_unit() {
+                continue;
+            }
+            commands = rhai::serde::from_dynamic::<Vec<CommandItem>>(&result)
+                .map_err(|error| {
+                    invalid(format!(
+                        "must return an array of #{{command, description}}: {error}"
+                    ))
+                })?
+                .into_iter()
+                .filter(|item| !item.command.trim().is_empty())
+                .collect();
+        }
+        Ok(commands)
+    }
+
+    /// 决定是否执行工具调用. 脚本出错时拒绝该调用, 避免绕过规则.
+    pub(super) fn before_tool(&self, tool: &str, arguments: &str) -> ToolVerdict {
+        let mut arguments = serde_json::from_str::<Value>(arguments)
+            .unwrap_or_else(|_| Value::String(arguments.to_string()));
+        let mut rewritten = false;
+        for script in self
+            .scripts
+            .iter()
+            .filter(|script| script.defines("before_tool"))
+        {
+            let verdict = rhai::serde::to_dynamic(&arguments)
+                .map_err(|error| error.to_string())
+                .and_then(|input| {
+                    self.call(script, "before_tool", vec![tool.into(), input])
+                        .map_err(|error| error.to_string())
+                })
+                .and_then(|result| verdict(result, script));
+            match verdict {
+                Ok(ToolVerdict::Allow) => {}
+                Ok(ToolVerdict::Rewrite(value)) => {
+                    arguments = value;
+                    rewritten = true;
+                }
+                Ok(deny @ ToolVerdict::Deny(_)) => return deny,
+                Err(error) => {
+                    warn!(tool, error = %error, "Hook script failed, denying the tool call.");
+                    return ToolVerdict::Deny(error);
+                }
+            }
+        }
+        if rewritten {
+            ToolVerdict::Rewrite(arguments)
+        } else {
+            ToolVerdict::Allow
+        }
+    }
+
+    /// 执行 `before_tool` 的决定, 返回代替原调用的工具结果; 放行时返回 None.
+    pub(super) async fn apply(&self, tool: &str, verdict: ToolVerdict) -> Option<String> {
+        match verdict {
+            ToolVerdict::Allow => None,
+            ToolVerdict::Deny(reason) => {
+                info!(tool, reason = %reason, "Hook script denied the tool call.");
+                let result = CommandResult::hook_denied(reason, Instant::now());
+                Some(serde_json::to_string(&result).unwrap_or_default())
+            }
+            ToolVerdict::Rewrite(arguments) => {
+                debug!(tool, %arguments, "Hook script rewrote the tool call.");
+                Some(
+                    match self.tools.call(tool, arguments.to_string()).await {
+                        Ok(result) => result,
+                        Err(error) => error.to_string(),
+                    },
+                )
+            }
+        }
+    }
+}
+
+fn verdict(result: Dynamic, script: &Script) -> std::result::Result<ToolVerdict, String> {
+    if result.is_unit() {
+        return Ok(ToolVerdict::Allow);
+    }
+    if let Ok(allow) = result.as_bool() {
+        return Ok(if allow {
+            ToolVerdict::Allow
+        } else {
+            ToolVerdict::Deny(format!("Denied by hook script `{}`.", script.name()))
+        });
+    }
+    if result.is_string() {
+        return Ok(ToolVerdict::Deny(result.into_string().unwrap_or_default()));
+    }
+    if result.is_map() {
+        return rhai::serde::from_dynamic::<Value>(&result)
+            .map(ToolVerdict::Rewrite)
+            .map_err(|error| error.to_string());
+    }
+    Err(format!(
+        "Hook script `{}`: `before_tool` must return (), a bool, a string or a map, found {}.",
+        script.name(),
+        result.type_name()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn hooks(source: &str) -> ScriptHooks {
+        let path = std::env::temp_dir().join(format!("howlto-hook-test-{}.rhai", Uuid::new_v4()));
+        std::fs::write(&path, source).unwrap();
+        let hooks = ScriptHooks::load(std::slice::from_ref(&path), Vec::new());
+        std::fs::remove_file(path).unwrap();
+        hooks.unwrap()
+    }
+
+    fn item(command: &str) -> CommandItem {
+        CommandItem {
+            command: command.to_string(),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn text_hooks_rewrite_or_keep_their_input() {
+        let hooks = hooks(
+            r#"
+            fn before_prompt(prompt) { prompt + " (use long flags)" }
+            fn after_response(text) { if text == "" { "empty" } }
+            "#,
+        );
+        assert_eq!(
+            hooks.before_prompt("list files".to_string()).unwrap(),
+            "list files (use long flags)"
+        );
+        assert_eq!(hooks.after_response("ok".to_string()).unwrap(), "ok");
+        assert_eq!(hooks.after_response(String::new()).unwrap(), "empty");
+    }
+
+    #[test]
+    fn tool_verdicts_cover_allow_deny_and_rewrite() {
+        let hooks = hooks(
+            r#"
+            fn before_tool(tool, args) {
+                if tool == "submit_commands" { return; }
+                if args.command.contains("sudo") { return "sudo is not allowed."; }
+                if args.command == "ls" { args.command = "ls -A"; return args; }
+                args.command != "rm"
+            }
+            "#,
+        );
+        assert_eq!(hooks.before_tool("submit_commands", "{}"), ToolVerdict::Allow);
+        assert_eq!(
+            hooks.before_tool("explore", r#"{"command":"pwd"}"#),
+            ToolVerdict::Allow
+        );
+        assert_eq!(
+            hooks.before_tool("elevate", r#"{"command":"sudo true"}"#),
+            ToolVerdict::Deny("sudo is not allowed.".to_string())
+        );
+        assert!(matches!(
+            hooks.before_tool("elevate", r#"{"command":"rm"}"#),
+            ToolVerdict::Deny(_)
+        ));
+        assert_eq!(
+            hooks.before_tool("explore", r#"{"command":"ls","timeout_secs":5}"#),
+            ToolVerdict::Rewrite(serde_json::json!({"command": "ls -A", "timeout_secs": 5}))
+        );
+        // 参数不是 map 时脚本出错, 调用被拒绝.
+        assert!(matches!(
+            hooks.before_tool("explore", "not json"),
+            ToolVerdict::Deny(_)
+        ));
+    }
+
+    #[test]
+    fn submitted_commands_can_be_filtered_and_reordered() {
+        let hooks = hooks(
+            r#"
+            fn after_submit(commands) {
+                let kept = commands.filter(|item| !item.command.starts_with("rm"));
+                kept.reverse();
+                kept.map(|item| { item.description = "[checked]"; item })
+            }
+            "#,
+        );
+        let commands = hooks
+            .after_submit(vec![item("ls"), item("rm -rf build"), item("pwd")])
+            .unwrap();
+        assert_eq!(
+            commands
+                .iter()
+                .map(|item| item.command.as_str())
+                .collect::<Vec<_>>(),
+            ["pwd", "ls"]
+        );
+        assert_eq!(commands[0].description, "[checked]");
+    }
+
+    #[test]
+    fn scripts_are_validated_when_loaded() {
+        let path = std::env::temp_dir().join(format!("howlto-hook-test-{}.rhai", Uuid::new_v4()));
+        std::fs::write(&path, "fn before_tool(tool) { true }").unwrap();
+        let error = ScriptHooks::load(std::slice::from_ref(&path), Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("must take 2 parameter(s)"));
+        std::fs::write(&path, "fn before_prompt(prompt) {").unwrap();
+        assert!(matches!(
+            ScriptHooks::load(std::slice::from_ref(&path), Vec::new()),
+            Err(Error::HookScript { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
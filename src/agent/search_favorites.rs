@@ -0,0 +1,117 @@
+use std::convert::Infallible;
+
+use rig_core::{completion::ToolDefinition, tool::Tool};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::tool_schema::parameters_for,
+    favorites::{Favorite, FavoriteStore},
+};
+
+const MAX_RESULTS: usize = 5;
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SearchFavoritesArgs {
+    #[schemars(
+        description = "Keywords such as program names, flags or the task, separated by spaces. An empty query lists the first favorites."
+    )]
+    #[serde(default)]
+    pub query: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchFavoritesResult {
+    favorites: Vec<Favorite>,
+    message: String,
+}
+
+/// 检索用户收藏的命令, 每次调用都重新读取收藏文件.
+#[derive(Debug, Clone)]
+pub struct SearchFavorites {
+    store: FavoriteStore,
+}
+
+impl SearchFavorites {
+    pub fn new(store: FavoriteStore) -> Self {
+        Self { store }
+    }
+}
+
+impl Tool for SearchFavorites {
+    const NAME: &'static str = "search_favorites";
+
+    type Error = Infallible;
+    type Args = SearchFavoritesArgs;
+    type Output = SearchFavoritesResult;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: format!(
+                "Search the commands the user bookmarked as canonical invocations. Returns at most {MAX_RESULTS} favorites with the command, its description and the prompt it was saved for, best matches first."
+            ),
+            parameters: parameters_for::<SearchFavoritesArgs>(),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let favorites = match self.store.load().await {
+            Ok(favorites) => favorites,
+            Err(error) => {
+                return Ok(SearchFavoritesResult {
+                    favorites: Vec::new(),
+                    message: format!("Could not read favorites: {error}"),
+                });
+            }
+        };
+        let matches = favorites
+            .search(&args.query, MAX_RESULTS)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let message = if matches.is_empty() {
+            "No favorite matches the query.".to_string()
+        } else {
+            format!("Found {} favorite(s).", matches.len())
+        };
+        Ok(SearchFavoritesResult {
+            favorites: matches,
+            message,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rig_core::tool::Tool;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::favorites::Favorites;
+
+    #[tokio::test]
+    async fn search_reads_the_latest_favorites() {
+        let dir = std::env::temp_dir().join(format!("howlto-search-favorites-{}", Uuid::new_v4()));
+        let store = FavoriteStore::new(&dir);
+        let tool = SearchFavorites::new(store.clone());
+        let search = |query: &str| {
+            tool.call(SearchFavoritesArgs {
+                query: query.to_string(),
+            })
+        };
+        assert!(search("pods").await.unwrap().favorites.is_empty());
+
+        let mut favorites = Favorites::default();
+        favorites.upsert(Favorite {
+            command: "kubectl get pods -A".into(),
+            description: "all namespaces".into(),
+            prompt: String::new(),
+        });
+        store.save(&favorites).await.unwrap();
+        let result = search("pods").await.unwrap();
+        assert_eq!(result.favorites, favorites.items);
+        assert_eq!(result.message, "Found 1 favorite(s).");
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
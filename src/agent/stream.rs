@@ -13,7 +13,7 @@ use tracing_indicatif::{span_ext::IndicatifSpanExt, style::ProgressStyle};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::{
-    agent::scroll::ScrollWindow,
+    agent::{parallel::CallStatuses, scroll::ScrollWindow},
     error::{Error, Result},
 };
 
@@ -117,6 +117,14 @@ fn tool_status(name: &str) -> String {
     format!("Tool {name}")
 }
 
+/// 在进度消息下方附加本轮每个工具调用的状态.
+fn with_calls(message: String, calls: Option<&CallStatuses>) -> String {
+    match calls {
+        Some(calls) => message + calls.render().as_str(),
+        None => message,
+    }
+}
+
 #[derive(Debug)]
 pub(super) struct StreamOutcome {
     pub final_text: String,
@@ -128,6 +136,7 @@ pub(super) async fn collect<R>(
     mut stream: StreamingResult<R>,
     title: &str,
     scroll_char_speed_limit: usize,
+    calls: Option<&CallStatuses>,
 ) -> Result<StreamOutcome> {
     let span = info_span!("agent-progress", status = title);
     span.pb_set_style(
@@ -136,7 +145,7 @@ pub(super) async fn collect<R>(
             .tick_strings(&SPINNER),
     );
     let mut activity = ActivityMessage::new("Waiting", scroll_char_speed_limit);
-    span.pb_set_message(&activity.render());
+    span.pb_set_message(&with_calls(activity.render(), calls));
     let _entered = span.enter();
     let mut scrolling_interval = tokio::time::interval_at(
         Instant::now() + SCROLL_INTERVAL,
@@ -157,7 +166,7 @@ pub(super) async fn collect<R>(
                     MultiTurnStreamItem::StreamAssistantItem(content) => match content {
                         StreamedAssistantContent::Text(text) => {
                             activity.push_text(&text.text);
-                            span.pb_set_message(&activity.render());
+                            span.pb_set_message(&with_calls(activity.render(), calls));
                         }
                         StreamedAssistantContent::Reasoning(reasoning) => {
                             let reasoning = reasoning.display_text();
@@ -166,15 +175,15 @@ pub(super) async fn collect<R>(
                             } else {
                                 activity.push_reasoning(&reasoning);
                             }
-                            span.pb_set_message(&activity.render());
+                            span.pb_set_message(&with_calls(activity.render(), calls));
                         }
                         StreamedAssistantContent::ReasoningDelta { reasoning, .. } => {
                             activity.push_reasoning(&reasoning);
-                            span.pb_set_message(&activity.render());
+                            span.pb_set_message(&with_calls(activity.render(), calls));
                         }
                         StreamedAssistantContent::ToolCall { tool_call, .. } => {
                             activity.set_status(&tool_status(&tool_call.function.name));
-                            span.pb_set_message(&activity.render());
+                            span.pb_set_message(&with_calls(activity.render(), calls));
                             super::tool_call_log::log(
                                 &tool_call.function.name,
                                 &tool_call.function.arguments,
@@ -185,13 +194,13 @@ pub(super) async fn collect<R>(
                             ..
                         } => {
                             activity.set_status(&tool_status(&name));
-                            span.pb_set_message(&activity.render());
+                            span.pb_set_message(&with_calls(activity.render(), calls));
                         }
                         _ => {}
                     },
                     MultiTurnStreamItem::StreamUserItem(content) => {
                         activity.set_status("Tool result");
-                        span.pb_set_message(&activity.render());
+                        span.pb_set_message(&with_calls(activity.render(), calls));
                         let StreamedUserContent::ToolResult { tool_result, .. } = content;
                         for content in tool_result.content {
                             if let ToolResultContent::Text(text) = content {
@@ -211,13 +220,13 @@ pub(super) async fn collect<R>(
             }
             _ = scrolling_interval.tick() => {
                 let now = Instant::now();
-                span.pb_set_message(&activity.tick(now - last_scroll));
+                span.pb_set_message(&with_calls(activity.tick(now - last_scroll), calls));
                 last_scroll = now;
             }
         }
     }
 
-    span.pb_set_message(&activity.finish());
+    span.pb_set_message(&with_calls(activity.finish(), calls));
 
     let final_response = final_response.ok_or_else(|| {
         Error::AgentResponse("Agent stream ended without a final response.".to_string())
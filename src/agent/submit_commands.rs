@@ -77,14 +77,16 @@ impl SubmitCommands {
         }
     }
 
-    fn should_check_syntax(&self) -> bool {
+    /// 当前 shell 是否支持 `-n` 语法检查.
+    pub fn should_check_syntax(&self) -> bool {
         matches!(
             self.shell_path.file_name().and_then(|name| name.to_str()),
             Some("sh" | "bash" | "zsh" | "dash" | "ksh" | "fish")
         )
     }
 
-    async fn syntax_error(&self, command_body: &str) -> Option<String> {
+    /// 用 shell 的 `-n` 检查命令语法, 返回错误信息. 不支持检查的 shell 总是返回 [`None`].
+    pub async fn syntax_error(&self, command_body: &str) -> Option<String> {
         if !self.should_check_syntax() {
             return None;
         }
@@ -0,0 +1,346 @@
+//! `-i` 转换模式: 按用户指令转换标准输入, 把结果原样流式写到标准输出.
+//!
+//! 不注册任何工具, 也不经过候选命令选择界面, 可以放在管道中间使用.
+
+use std::io::Write;
+
+use rig_core::{
+    agent::{Agent as RigAgent, MultiTurnStreamItem},
+    message::Message,
+    providers::openai::CompletionModel,
+    streaming::{StreamedAssistantContent, StreamingChat},
+};
+use tokio_stream::StreamExt;
+use tracing::{debug, info};
+
+use crate::{
+    agent::answer::completion_model,
+    config::{AppConfig, profile::AnswerProfile},
+    error::{Error, Result},
+    shell::Shell,
+};
+
+pub struct TransformAgent {
+    profile: AnswerProfile,
+    agent: RigAgent<CompletionModel>,
+    chunk_chars: usize,
+}
+
+#[bon::bon]
+impl TransformAgent {
+    #[builder]
+    pub fn new(
+        os: String,
+        shell: &Shell,
+        profile: AnswerProfile,
+        config: AppConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            agent: build_agent(os, shell, &profile, &config)?,
+            profile,
+            chunk_chars: config.agent.transform.chunk_chars,
+        })
+    }
+}
+
+impl TransformAgent {
+    /// 按 `instruction` 转换 `input`, 边接收边写入 `out`.
+    ///
+    /// 输入超过 `[agent.transform] chunk_chars` 时按行分块依次请求, 输出顺序与输入一致.
+    /// 任一分块失败或没有输出时返回错误, 已经写出的内容不会撤回.
+    pub async fn transform(
+        &self,
+        instruction: &str,
+        input: &str,
+        out: &mut impl Write,
+    ) -> Result<()> {
+        let chunks = chunks(input, self.chunk_chars);
+        let total = chunks.len();
+        info!(
+            chunks = total,
+            input_len = input.len(),
+            "Transforming input."
+        );
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let prompt = if total == 1 {
+                instruction.to_string()
+            } else {
+                format!(
+                    "{instruction}\n\n(This is part {} of {total} of the input.)",
+                    index + 1
+                )
+            };
+            let mut output = String::new();
+            stream_chunk(&self.agent, &self.profile, prompt, chunk, |text| {
+                out.write_all(text.as_bytes())?;
+                out.flush()?;
+                output.push_str(text);
+                Ok(())
+            })
+            .await?;
+            if output.trim().is_empty() {
+                return Err(empty_result(index, total));
+            }
+            let last = index + 1 == total;
+            if !output.ends_with('\n') && (!last || input.ends_with('\n')) {
+                out.write_all(b"\n")?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 创建不带工具的 agent, system prompt 由 `profile` 生成.
+pub(super) fn build_agent(
+    os: String,
+    shell: &Shell,
+    profile: &AnswerProfile,
+    config: &AppConfig,
+) -> Result<RigAgent<CompletionModel>> {
+    let model = completion_model(&config.llm)?;
+    let system_prompt = profile
+        .system()
+        .os(os)
+        .shell(shell.path().display())
+        .text_lang(&config.agent.language)
+        .maybe_max_tokens(config.llm.max_tokens)
+        .output_n(config.agent.answer.output_n)
+        .explore(false)
+        .elevate(false)
+        .finish();
+    let mut builder = rig_core::agent::AgentBuilder::new(model).preamble(&system_prompt);
+    if let Some(max_tokens) = config.llm.max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    if let Some(temperature) = config.llm.temperature {
+        builder = builder.temperature(temperature);
+    }
+    Ok(builder.build())
+}
+
+/// 请求转换一个分块, 去掉外层代码围栏后把收到的文本依次交给 `emit`.
+pub(super) async fn stream_chunk(
+    agent: &RigAgent<CompletionModel>,
+    profile: &AnswerProfile,
+    prompt: String,
+    chunk: &str,
+    mut emit: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let history = vec![Message::user(profile.attach(chunk).fmt())];
+    let mut stream = agent.stream_chat(prompt, history).await;
+    let mut fences = FenceStripper::default();
+    while let Some(item) = stream.next().await {
+        let item = item.map_err(|error| Error::StreamingError(error.to_string()))?;
+        match item {
+            MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text)) => {
+                let text = fences.push(&text.text);
+                if !text.is_empty() {
+                    emit(&text)?;
+                }
+            }
+            MultiTurnStreamItem::FinalResponse(response) => {
+                debug!(usage = ?response.usage(), "Chunk completed.");
+            }
+            _ => {}
+        }
+    }
+    let text = fences.finish();
+    if !text.is_empty() {
+        emit(&text)?;
+    }
+    Ok(())
+}
+
+pub(super) fn empty_result(index: usize, total: usize) -> Error {
+    Error::AgentResponse(format!(
+        "Agent returned an empty result for part {} of {total}.",
+        index + 1
+    ))
+}
+
+/// 按行把 `input` 切成不超过 `max_chars` 个字符的分块, 单独一行过长时在字符边界处切开.
+/// `max_chars` 为 0 表示不分块.
+pub(super) fn chunks(input: &str, max_chars: usize) -> Vec<&str> {
+    if max_chars == 0 || input.chars().count() <= max_chars {
+        return vec![input];
+    }
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut chars = 0;
+    let mut end = 0;
+    for line in input.split_inclusive('\n') {
+        let line_chars = line.chars().count();
+        if chars > 0 && chars + line_chars > max_chars {
+            chunks.push(&input[start..end]);
+            start = end;
+            chars = 0;
+        }
+        if line_chars > max_chars {
+            let mut rest = line;
+            while rest.chars().count() > max_chars {
+                let split = rest
+                    .char_indices()
+                    .nth(max_chars)
+                    .map(|(index, _)| index)
+                    .unwrap_or(rest.len());
+                chunks.push(&rest[..split]);
+                rest = &rest[split..];
+            }
+            start = end + line.len() - rest.len();
+            chars = rest.chars().count();
+        } else {
+            chars += line_chars;
+        }
+        end += line.len();
+    }
+    if start < input.len() {
+        chunks.push(&input[start..]);
+    }
+    chunks
+}
+
+/// 流式去掉包裹整个输出的 markdown 代码围栏.
+///
+/// 只有第一个非空行是围栏时才去掉它和最后一个非空的围栏行, 输出内部的代码块保持原样.
+/// 可能是围栏的行会暂缓输出, 其他内容收到即输出.
+#[derive(Debug, Default)]
+struct FenceStripper {
+    /// 当前行中尚未输出的部分.
+    line: String,
+    /// 当前行的开头已经输出.
+    streaming_line: bool,
+    /// 已经遇到第一个非空行.
+    started: bool,
+    /// 去掉了开头的围栏.
+    opened: bool,
+    /// 暂缓输出的围栏行和它之后的空行, 后面还有内容时才输出.
+    held: String,
+}
+
+impl FenceStripper {
+    fn push(&mut self, text: &str) -> String {
+        let mut output = String::new();
+        for piece in text.split_inclusive('\n') {
+            if self.streaming_line {
+                output.push_str(piece);
+                self.streaming_line = !piece.ends_with('\n');
+                continue;
+            }
+            self.line.push_str(piece);
+            if self.line.ends_with('\n') {
+                let line = std::mem::take(&mut self.line);
+                self.complete_line(line, &mut output);
+            } else if !could_be_fence(&self.line) {
+                self.content(&mut output);
+                output.push_str(&std::mem::take(&mut self.line));
+                self.streaming_line = true;
+            }
+        }
+        output
+    }
+
+    fn finish(&mut self) -> String {
+        let mut output = String::new();
+        let line = std::mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.complete_line(line, &mut output);
+        }
+        if !self.opened || !is_fence(&self.held) {
+            output.push_str(&std::mem::take(&mut self.held));
+        }
+        self.held.clear();
+        output
+    }
+
+    fn complete_line(&mut self, line: String, output: &mut String) {
+        let blank = line.trim().is_empty();
+        if !self.started {
+            if blank {
+                return;
+            }
+            self.started = true;
+            if is_fence(&line) {
+                self.opened = true;
+                return;
+            }
+            output.push_str(&line);
+        } else if !self.held.is_empty() && blank {
+            self.held.push_str(&line);
+        } else if is_fence(&line) {
+            output.push_str(&std::mem::take(&mut self.held));
+            self.held = line;
+        } else {
+            self.content(output);
+            output.push_str(&line);
+        }
+    }
+
+    /// 当前行确定是内容, 先输出暂缓的部分.
+    fn content(&mut self, output: &mut String) {
+        self.started = true;
+        output.push_str(&std::mem::take(&mut self.held));
+    }
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// 不完整的行继续接收后可能成为围栏行.
+fn could_be_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.is_empty() || "```".starts_with(line) || "~~~".starts_with(line) || is_fence(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FenceStripper, chunks};
+
+    fn strip(pieces: &[&str]) -> String {
+        let mut stripper = FenceStripper::default();
+        let mut output = pieces
+            .iter()
+            .map(|piece| stripper.push(piece))
+            .collect::<String>();
+        output.push_str(&stripper.finish());
+        output
+    }
+
+    #[test]
+    fn wrapping_fences_are_stripped_while_streaming() {
+        assert_eq!(
+            strip(&["``", "`pyt", "hon\nprint(", "1)\n", "``", "`\n"]),
+            "print(1)\n"
+        );
+        assert_eq!(strip(&["\n```\na\n\nb\n```"]), "a\n\nb\n");
+        assert_eq!(strip(&["plain ", "text"]), "plain text");
+
+        let mut stripper = FenceStripper::default();
+        assert_eq!(stripper.push("```rust\nfn main"), "fn main");
+        assert_eq!(stripper.push("() {}\n``"), "() {}\n");
+        assert_eq!(stripper.push("`"), "");
+        assert_eq!(stripper.finish(), "");
+    }
+
+    #[test]
+    fn inner_code_blocks_are_kept() {
+        let markdown = "# Title\n\n```sh\nls\n```\n";
+        assert_eq!(strip(&[markdown]), markdown);
+        assert_eq!(
+            strip(&["```md\n# T\n```sh\nls\n```\nend\n```\n"]),
+            "# T\n```sh\nls\n```\nend\n"
+        );
+    }
+
+    #[test]
+    fn chunks_split_on_lines_in_order() {
+        assert_eq!(chunks("a\nb\n", 0), ["a\nb\n"]);
+        assert_eq!(chunks("ab\ncd\nef", 6), ["ab\ncd\n", "ef"]);
+        assert_eq!(chunks("ab\ncdefgh\ni", 3), ["ab\n", "cde", "fgh", "\ni"]);
+        assert_eq!(chunks("你好世界\n", 2), ["你好", "世界", "\n"]);
+        let input = "line\n".repeat(100);
+        assert_eq!(chunks(&input, 12).concat(), input);
+    }
+}
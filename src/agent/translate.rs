@@ -0,0 +1,379 @@
+//! 翻译模式: 把标准输入或文件翻译成 `[agent] language` 或 `--to` 指定的语言.
+//!
+//! 代码块原样保留, 不发送给模型; 其余文本按段落分块依次翻译, 输出顺序与输入一致.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use rig_core::{agent::Agent as RigAgent, providers::openai::CompletionModel};
+use tracing::info;
+
+use crate::{
+    agent::transform::{build_agent, chunks, empty_result, stream_chunk},
+    config::{AppConfig, profile::AnswerProfile},
+    error::Result,
+    shell::Shell,
+};
+
+pub struct TranslateAgent {
+    profile: AnswerProfile,
+    agent: RigAgent<CompletionModel>,
+    chunk_chars: usize,
+}
+
+#[bon::bon]
+impl TranslateAgent {
+    #[builder]
+    pub fn new(
+        os: String,
+        shell: &Shell,
+        profile: AnswerProfile,
+        config: AppConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            agent: build_agent(os, shell, &profile, &config)?,
+            profile,
+            chunk_chars: config.agent.transform.chunk_chars,
+        })
+    }
+}
+
+impl TranslateAgent {
+    /// 翻译 `input`, 边接收边写入 `out`. `note` 是用户附加的要求, 可以为空.
+    ///
+    /// 源语言按整个输入检测一次, 所有分块使用相同的结果. 任一分块失败或没有输出时返回错误.
+    pub async fn translate(&self, input: &str, note: &str, out: &mut impl Write) -> Result<()> {
+        let pieces = pieces(input, self.chunk_chars);
+        let total = pieces
+            .iter()
+            .filter(|piece| matches!(piece, Piece::Translate(_)))
+            .count();
+        let source = detect_script(input);
+        info!(
+            parts = total,
+            source = source.unwrap_or("unknown"),
+            input_len = input.len(),
+            "Translating input."
+        );
+        let mut index = 0;
+        for piece in pieces {
+            let text = match piece {
+                Piece::Verbatim(text) => {
+                    out.write_all(text.as_bytes())?;
+                    out.flush()?;
+                    continue;
+                }
+                Piece::Translate(text) => text,
+            };
+            let core = text.trim();
+            let leading = &text[..text.len() - text.trim_start().len()];
+            let trailing = &text[text.trim_end().len()..];
+            out.write_all(leading.as_bytes())?;
+            let mut trim = TrimEnds::default();
+            stream_chunk(
+                &self.agent,
+                &self.profile,
+                instruction(source, note, index, total),
+                core,
+                |text| {
+                    let text = trim.push(text);
+                    if !text.is_empty() {
+                        out.write_all(text.as_bytes())?;
+                        out.flush()?;
+                    }
+                    Ok(())
+                },
+            )
+            .await?;
+            if !trim.started {
+                return Err(empty_result(index, total));
+            }
+            out.write_all(trailing.as_bytes())?;
+            out.flush()?;
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// 译文写到原文件旁边时的路径, 后缀加在扩展名之前, 比如 `README.md` -> `README.en.md`.
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let suffix = suffix.trim_matches('.');
+    let stem = path
+        .file_stem()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.{suffix}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{suffix}"),
+    };
+    path.with_file_name(name)
+}
+
+fn instruction(source: Option<&str>, note: &str, index: usize, total: usize) -> String {
+    let mut instruction = match source {
+        Some(source) => format!("Translate the input. The source language is {source}."),
+        None => "Translate the input.".to_string(),
+    };
+    if !note.trim().is_empty() {
+        instruction.push_str(&format!(" Also follow this request: {}", note.trim()));
+    }
+    if total > 1 {
+        instruction.push_str(&format!(
+            "\n\n(This is part {} of {total} of the input.)",
+            index + 1
+        ));
+    }
+    instruction
+}
+
+/// 按文字系统粗略判断源语言, 拉丁字母等无法区分语言时返回 [`None`] 交给模型判断.
+fn detect_script(text: &str) -> Option<&'static str> {
+    let mut counts = [0usize; 7];
+    for character in text.chars() {
+        let index = match character {
+            '\u{3040}'..='\u{30ff}' => 0,
+            '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => 1,
+            '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => 2,
+            '\u{0400}'..='\u{04ff}' => 3,
+            '\u{0600}'..='\u{06ff}' => 4,
+            '\u{0370}'..='\u{03ff}' => 5,
+            character if character.is_ascii_alphabetic() => 6,
+            _ => continue,
+        };
+        counts[index] += 1;
+    }
+    let [kana, hangul, han, cyrillic, arabic, greek, latin] = counts;
+    // 中文夹杂英文术语很常见, 汉字按较低的比例即可判定.
+    if kana > 0 && kana * 10 >= han {
+        Some("Japanese")
+    } else if hangul * 2 > latin {
+        Some("Korean")
+    } else if han * 4 > latin {
+        Some("Chinese")
+    } else if cyrillic > latin {
+        Some("Russian")
+    } else if arabic > latin {
+        Some("Arabic")
+    } else if greek > latin {
+        Some("Greek")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Piece<'a> {
+    /// 代码块和空白, 原样输出.
+    Verbatim(&'a str),
+    /// 需要翻译的文本.
+    Translate(&'a str),
+}
+
+/// 把 `input` 拆成按顺序排列的原样部分和翻译分块.
+fn pieces(input: &str, max_chars: usize) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    for (code, text) in code_blocks(input) {
+        if code || text.trim().is_empty() {
+            pieces.push(Piece::Verbatim(text));
+        } else {
+            pieces.extend(
+                paragraph_chunks(text, max_chars)
+                    .into_iter()
+                    .map(Piece::Translate),
+            );
+        }
+    }
+    pieces
+}
+
+/// 按 markdown 围栏代码块切分, `true` 表示代码块 (包括围栏行). 未闭合的代码块延续到末尾.
+fn code_blocks(input: &str) -> Vec<(bool, &str)> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut fence: Option<(char, usize)> = None;
+    for line in input.split_inclusive('\n') {
+        let trimmed = line.trim_start_matches(' ');
+        let marker = trimmed
+            .chars()
+            .next()
+            .filter(|character| ['`', '~'].contains(character) && line.len() - trimmed.len() <= 3)
+            .map(|character| {
+                let count = trimmed.chars().take_while(|c| *c == character).count();
+                (character, count)
+            })
+            .filter(|(_, count)| *count >= 3);
+        match (fence, marker) {
+            (None, Some(marker)) => {
+                if start < offset {
+                    blocks.push((false, &input[start..offset]));
+                }
+                start = offset;
+                fence = Some(marker);
+            }
+            (Some((character, count)), Some((marker, marker_count)))
+                if character == marker
+                    && marker_count >= count
+                    && trimmed[marker_count..].trim().is_empty() =>
+            {
+                let end = offset + line.len();
+                blocks.push((true, &input[start..end]));
+                start = end;
+                fence = None;
+            }
+            _ => {}
+        }
+        offset += line.len();
+    }
+    if start < input.len() {
+        blocks.push((fence.is_some(), &input[start..]));
+    }
+    blocks
+}
+
+/// 按段落 (空行分隔) 把 `text` 合并成不超过 `max_chars` 个字符的分块,
+/// 单个段落过长时退回到按行切分. `max_chars` 为 0 表示不分块.
+fn paragraph_chunks(text: &str, max_chars: usize) -> Vec<&str> {
+    if max_chars == 0 || text.chars().count() <= max_chars {
+        return vec![text];
+    }
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut blank = false;
+    for line in text.split_inclusive('\n') {
+        let is_blank = line.trim().is_empty();
+        if blank && !is_blank {
+            paragraphs.push(&text[start..offset]);
+            start = offset;
+        }
+        blank = is_blank;
+        offset += line.len();
+    }
+    paragraphs.push(&text[start..]);
+
+    let mut grouped = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    let mut count = 0;
+    for paragraph in paragraphs {
+        let paragraph_chars = paragraph.chars().count();
+        if count > 0 && count + paragraph_chars > max_chars {
+            grouped.push(&text[start..end]);
+            start = end;
+            count = 0;
+        }
+        if paragraph_chars > max_chars {
+            grouped.extend(chunks(paragraph, max_chars));
+            start = end + paragraph.len();
+        } else {
+            count += paragraph_chars;
+        }
+        end += paragraph.len();
+    }
+    if start < text.len() {
+        grouped.push(&text[start..]);
+    }
+    grouped
+}
+
+/// 去掉模型输出首尾的空白, 由调用方补上原文的首尾空白. 末尾的空白暂缓输出.
+#[derive(Debug, Default)]
+struct TrimEnds {
+    started: bool,
+    pending: String,
+}
+
+impl TrimEnds {
+    fn push(&mut self, text: &str) -> String {
+        let text = if self.started {
+            text
+        } else {
+            text.trim_start()
+        };
+        if text.is_empty() {
+            return String::new();
+        }
+        self.started = true;
+        let content = text.trim_end();
+        if content.is_empty() {
+            self.pending.push_str(text);
+            return String::new();
+        }
+        let output = std::mem::take(&mut self.pending) + content;
+        self.pending.push_str(&text[content.len()..]);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{
+        Piece, TrimEnds, code_blocks, detect_script, paragraph_chunks, pieces, suffixed_path,
+    };
+
+    #[test]
+    fn code_blocks_are_kept_verbatim() {
+        let input = "# 标题\n\n说明\n\n```sh\n# 注释\nls\n```\n\n结尾\n";
+        assert_eq!(
+            pieces(input, 0),
+            [
+                Piece::Translate("# 标题\n\n说明\n\n"),
+                Piece::Verbatim("```sh\n# 注释\nls\n```\n"),
+                Piece::Translate("\n结尾\n"),
+            ]
+        );
+        assert_eq!(
+            code_blocks("a\n~~~~\n```\n~~~~\nb"),
+            [(false, "a\n"), (true, "~~~~\n```\n~~~~\n"), (false, "b")]
+        );
+        assert_eq!(
+            code_blocks("a\n```\nunclosed"),
+            [(false, "a\n"), (true, "```\nunclosed")]
+        );
+    }
+
+    #[test]
+    fn paragraphs_are_grouped_in_order() {
+        let text = "one\n\ntwo\n\nthree three\n";
+        assert_eq!(paragraph_chunks(text, 0), [text]);
+        assert_eq!(
+            paragraph_chunks(text, 10),
+            ["one\n\ntwo\n\n", "three thre", "e\n"]
+        );
+        assert_eq!(paragraph_chunks(text, 20).concat(), text);
+    }
+
+    #[test]
+    fn source_script_is_detected() {
+        assert_eq!(
+            detect_script("运行 `cargo build` 构建项目"),
+            Some("Chinese")
+        );
+        assert_eq!(detect_script("ファイルを開く"), Some("Japanese"));
+        assert_eq!(detect_script("Ошибка: файл не найден"), Some("Russian"));
+        assert_eq!(detect_script("error: file not found"), None);
+    }
+
+    #[test]
+    fn output_files_get_the_suffix_before_the_extension() {
+        let path = |path: &str, suffix| suffixed_path(Path::new(path), suffix);
+        assert_eq!(path("docs/README.md", "en"), Path::new("docs/README.en.md"));
+        assert_eq!(path("error.log.txt", ".zh"), Path::new("error.log.zh.txt"));
+        assert_eq!(path("NOTES", "en"), Path::new("NOTES.en"));
+    }
+
+    #[test]
+    fn output_whitespace_is_trimmed_while_streaming() {
+        let mut trim = TrimEnds::default();
+        let output = ["\n\n", "Hello", " ", "\n", "world", "\n\n"]
+            .map(|text| trim.push(text))
+            .concat();
+        assert_eq!(output, "Hello \nworld");
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     io::ErrorKind,
     path::{Path, PathBuf},
 };
@@ -9,6 +10,7 @@ use tokio::{fs, io::AsyncWriteExt};
 
 pub mod profile;
 pub mod migration;
+pub mod template;
 
 #[cfg(windows)]
 pub const DEFAULT_CONFIG_DIR: &str = "~\\.config\\howlto\\";
@@ -20,7 +22,7 @@ pub const PROFILES_TOML_FILE: &str = "profiles.toml";
 pub const CONFIG_TOML_FILE: &str = "config.toml";
 pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct AppConfig {
     #[serde(default = "default_config_version")]
     pub version: u32,
@@ -30,9 +32,15 @@ pub struct AppConfig {
     pub agent: AgentConfig,
     #[serde(default)]
     pub session: SessionConfig,
+    /// profile 模板中可以使用的自定义变量, 如 `{{team}}`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    /// 被环境变量覆盖的配置项和对应的变量名, 如 `("llm.model", "HOWLTO_MODEL")`.
+    #[serde(skip)]
+    pub env_overrides: Vec<(&'static str, &'static str)>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmConfig {
     /// LLM api key.
     #[serde(default)]
@@ -50,7 +58,7 @@ pub struct LlmConfig {
     // todo gemini, anthropic api ...
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     /// 是否启用只读命令工具.
     #[serde(default = "default_use_tool_explore")]
@@ -60,26 +68,241 @@ pub struct AgentConfig {
     /// 每次执行都会向用户询问确认.
     #[serde(default = "default_use_tool_elevate")]
     pub use_tool_elevate: bool,
+    /// 是否在伪终端中交互式执行 elevate 命令, 让用户可以回应命令中的提示 (密码, 确认等).
+    #[serde(default)]
+    pub interactive_elevate: bool,
+    /// 同一轮中多个 explore 调用并发执行的最大数量, 1 表示依次执行.
+    #[serde(default = "default_tool_concurrency")]
+    pub tool_concurrency: usize,
     /// 滚动进度字符速度限制, 单位是字符/秒, 0 表示不限制.
     #[serde(default)]
     pub scroll_char_speed_limit: usize,
+    /// 是否在会话内缓存 explore 命令的结果, 相关文件未变化时直接复用.
     #[serde(default = "default_cache")]
-    /// 是否使用对话缓存. todo 缓存对话
     pub cache: bool,
     /// 模型输出语言.
     #[serde(default = "default_language")]
     pub language: String,
     #[serde(default)]
     pub answer: AnswerConfig,
+    #[serde(default)]
+    pub transform: TransformConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub preview: PreviewConfig,
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    /// 在内置白名单之外传给工具命令的环境变量名, 以 `*` 结尾表示前缀匹配.
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// 用户自定义的命令工具.
+    #[serde(default)]
+    pub tools: Vec<CustomToolConfig>,
+    /// 钩子脚本 (Rhai), 相对路径相对于配置目录, 按顺序执行.
+    #[serde(default)]
+    pub hooks: Vec<PathBuf>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnswerConfig {
     /// Answer agent 输出的命令/回答个数.
     #[serde(default = "default_output_n")]
     pub output_n: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformConfig {
+    /// 转换 (`-i`) 和翻译模式下单次请求最多携带的输入字符数, 超出时分块依次处理.
+    #[serde(default = "default_transform_chunk_chars")]
+    pub chunk_chars: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    /// 无法弹出确认对话框 (stderr 不是 TTY) 时, 需要确认的命令如何处理.
+    #[serde(default)]
+    pub non_tty: NonTtyDecision,
+    /// 命令规则, 对管道中的每一段按顺序匹配, 第一条匹配的规则生效.
+    #[serde(default)]
+    pub rules: Vec<PolicyRuleConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NonTtyDecision {
+    /// 拒绝执行, 并把结构化的拒绝结果返回给模型.
+    #[default]
+    Reject,
+    /// 不经确认直接执行.
+    Approve,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRuleConfig {
+    /// glob 模式, 与 `regex` 二选一.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glob: Option<String>,
+    /// 正则表达式 (非锚定匹配), 与 `glob` 二选一.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    pub action: PolicyAction,
+    /// 仅对这些工具生效, 为空表示对 explore 和 elevate 都生效.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// 返回给模型的说明.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// 直接拒绝.
+    Deny,
+    /// 总是向用户确认, 即使是 explore.
+    Confirm,
+    /// elevate 无需确认直接执行.
+    Allow,
+}
+
+/// elevate 的预演模式, 目前只支持 Linux (Bubblewrap).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PreviewConfig {
+    /// 批准后先在工作目录的 overlay 上预演命令, 展示文件变更并再次确认后才真正生效.
+    #[serde(default)]
+    pub enabled: bool,
+    /// 预演时是否允许联网.
+    #[serde(default)]
+    pub network: bool,
+}
+
+/// explore 的沙箱策略.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxConfig {
+    /// 沙箱后端. `none` 表示不使用沙箱, explore 的命令需要逐条确认.
+    #[serde(default)]
+    pub backend: SandboxBackend,
+    /// 默认策略.
+    #[serde(default)]
+    pub policy: SandboxPolicy,
+    /// 模型可以为单次调用请求的策略, 为空表示只能使用默认策略.
+    #[serde(default = "default_allowed_policies")]
+    pub allowed_policies: Vec<SandboxPolicy>,
+    /// `build` 策略是否允许联网.
+    #[serde(default)]
+    pub build_network: bool,
+    /// 沙箱内不可读的敏感路径, 支持 `~`. howlto 自己的 `config.toml` 和 `sessions/` 总是被屏蔽.
+    #[serde(default = "default_masked_paths")]
+    pub masked_paths: Vec<String>,
+    /// 沙箱命令的资源限制.
+    #[serde(default)]
+    pub resources: ResourcesConfig,
+}
+
+/// 沙箱命令的资源限制, 0 表示不限.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ResourcesConfig {
+    /// CPU 时间 (秒).
+    #[serde(default = "default_cpu_secs")]
+    pub cpu_secs: u64,
+    /// 地址空间 (MiB). 默认不限, node 和 JVM 等运行时启动时就会预留很大的地址空间.
+    #[serde(default)]
+    pub memory_mb: u64,
+    /// 命令运行期间最多新建的进程数 (线程也计入), 仅 Linux.
+    #[serde(default = "default_max_processes")]
+    pub max_processes: u64,
+    /// 收集的 stdout 与 stderr 的总大小 (MiB), 超出后终止命令.
+    #[serde(default = "default_max_output_mb")]
+    pub max_output_mb: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxBackend {
+    /// 自动探测可用的后端, 没有可用后端时 explore 不可用.
+    #[default]
+    Auto,
+    /// 不使用沙箱. 用于没有 user namespace 的远程开发容器等环境,
+    /// 除了内置的无副作用命令 (`--version`, `man`, `tldr`) 外, 每条命令都需要用户确认.
+    None,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxPolicy {
+    /// 只能读取工作目录和系统目录.
+    Strict,
+    /// 整个文件系统只读.
+    #[default]
+    Default,
+    /// 在 `default` 的基础上, `/tmp` 和 `~/.cache` 可写 (写入随沙箱丢弃), 可选联网.
+    Build,
+}
+
+impl SandboxPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Default => "default",
+            Self::Build => "build",
+        }
+    }
+}
+
+/// 在配置中声明的命令工具, 调用时把参数填入命令模板后执行.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomToolConfig {
+    /// 提供给模型的工具名, 只能包含字母, 数字, `_` 和 `-`.
+    pub name: String,
+    /// 提供给模型的工具说明.
+    pub description: String,
+    /// 命令模板, `{{arg}}` 会被替换为按当前 shell 语法引用后的参数值.
+    pub command: String,
+    /// 执行方式, 与内置的同名工具相同.
+    #[serde(default)]
+    pub mode: CustomToolMode,
+    /// 参数的 JSON Schema, 顶层必须是 object. 省略表示没有参数.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+    /// 超时秒数, 省略时使用内置工具的默认值.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomToolMode {
+    /// 在只读, 禁网的沙箱中执行, 无需确认.
+    #[default]
+    Explore,
+    /// 在沙箱之外执行, 执行前需要确认.
+    Elevate,
+}
+
+/// 单次请求的预算, 超出后不再调用工具, 直接根据已有对话生成最终回答. 0 表示不限.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// 最多向模型发起的对话轮数.
+    #[serde(default = "default_max_turns")]
+    pub max_turns: usize,
+    /// 每个工具最多被调用的次数.
+    #[serde(default = "default_max_tool_calls")]
+    pub max_tool_calls: usize,
+    /// 所有工具调用累计的最长耗时 (秒), 包括等待用户确认的时间.
+    #[serde(default)]
+    pub max_tool_secs: u64,
+    /// 整个请求的最长耗时 (秒).
+    #[serde(default)]
+    pub max_wall_secs: u64,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct SessionConfig {
     /// 整个 sessions 目录的最大字节数, 0 表示禁用.
@@ -117,6 +340,30 @@ impl Default for AnswerConfig {
     }
 }
 
+impl Default for TransformConfig {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+impl Default for ResourcesConfig {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
 impl Default for SessionConfig {
     fn default() -> Self {
         Self {
@@ -135,10 +382,26 @@ fn default_session_max_bytes() -> u64 {
     64 * 1024 * 1024
 }
 
+fn default_tool_concurrency() -> usize {
+    4
+}
+
+fn default_max_turns() -> usize {
+    32
+}
+
+fn default_max_tool_calls() -> usize {
+    16
+}
+
 fn default_output_n() -> u32 {
     3
 }
 
+fn default_transform_chunk_chars() -> usize {
+    24_000
+}
+
 fn default_language() -> String {
     "en".into()
 }
@@ -155,6 +418,57 @@ fn default_base_url() -> String {
     DEFAULT_OPENAI_BASE_URL.to_string()
 }
 
+fn default_masked_paths() -> Vec<String> {
+    [
+        // 凭据与密钥.
+        "~/.ssh",
+        "~/.gnupg",
+        "~/.aws",
+        "~/.azure",
+        "~/.config/gcloud",
+        "~/.kube",
+        "~/.docker/config.json",
+        "~/.netrc",
+        "~/.git-credentials",
+        "~/.password-store",
+        "~/.npmrc",
+        "~/.pypirc",
+        "~/.cargo/credentials.toml",
+        "~/.config/gh/hosts.yml",
+        // 浏览器配置 (cookie, 保存的密码).
+        "~/.mozilla",
+        "~/.config/google-chrome",
+        "~/.config/chromium",
+        "~/.config/BraveSoftware",
+        "~/Library/Application Support/Google/Chrome",
+        "~/Library/Application Support/Firefox",
+        "~/Library/Keychains",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_cpu_secs() -> u64 {
+    60
+}
+
+fn default_max_processes() -> u64 {
+    256
+}
+
+fn default_max_output_mb() -> u64 {
+    16
+}
+
+fn default_allowed_policies() -> Vec<SandboxPolicy> {
+    vec![
+        SandboxPolicy::Strict,
+        SandboxPolicy::Default,
+        SandboxPolicy::Build,
+    ]
+}
+
 fn default_cache() -> bool {
     true
 }
@@ -163,10 +477,15 @@ fn default_use_tool_elevate() -> bool {
     true
 }
 
-fn first_env(get: &impl Fn(&str) -> Option<String>, names: &[&str]) -> Option<String> {
-    names
-        .iter()
-        .find_map(|name| get(name).filter(|value| !value.trim().is_empty()))
+fn first_env(
+    get: &impl Fn(&str) -> Option<String>,
+    names: &[&'static str],
+) -> Option<(&'static str, String)> {
+    names.iter().find_map(|&name| {
+        get(name)
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| (name, value))
+    })
 }
 
 impl AppConfig {
@@ -181,31 +500,33 @@ impl AppConfig {
         let howlto_base_url = first_env(&get, &["HOWLTO_BASE_URL"]);
         let use_openai_fallbacks = howlto_base_url.is_none() && default_base_url;
 
-        if let Some(base_url) = howlto_base_url {
-            self.llm.base_url = base_url;
-        } else if use_openai_fallbacks
-            && let Some(base_url) = first_env(&get, &["OPENAI_BASE_URL"])
-        {
+        if let Some((name, base_url)) = howlto_base_url.or_else(|| {
+            use_openai_fallbacks
+                .then(|| first_env(&get, &["OPENAI_BASE_URL"]))
+                .flatten()
+        }) {
             self.llm.base_url = base_url;
+            self.env_overrides.push(("llm.base_url", name));
         }
-        if let Some(api_key) = first_env(&get, &["HOWLTO_API_KEY"]) {
-            self.llm.api_key = api_key;
-        } else if self.llm.api_key.is_empty()
-            && use_openai_fallbacks
-            && let Some(api_key) = first_env(&get, &["OPENAI_API_KEY"])
-        {
+        if let Some((name, api_key)) = first_env(&get, &["HOWLTO_API_KEY"]).or_else(|| {
+            (self.llm.api_key.is_empty() && use_openai_fallbacks)
+                .then(|| first_env(&get, &["OPENAI_API_KEY"]))
+                .flatten()
+        }) {
             self.llm.api_key = api_key;
+            self.env_overrides.push(("llm.api_key", name));
         }
-        if let Some(model) = first_env(&get, &["HOWLTO_MODEL"]) {
-            self.llm.model = model;
-        } else if default_model
-            && use_openai_fallbacks
-            && let Some(model) = first_env(&get, &["OPENAI_MODEL"])
-        {
+        if let Some((name, model)) = first_env(&get, &["HOWLTO_MODEL"]).or_else(|| {
+            (default_model && use_openai_fallbacks)
+                .then(|| first_env(&get, &["OPENAI_MODEL"]))
+                .flatten()
+        }) {
             self.llm.model = model;
+            self.env_overrides.push(("llm.model", name));
         }
-        if let Some(language) = first_env(&get, &["HOWLTO_LANGUAGE"]) {
+        if let Some((name, language)) = first_env(&get, &["HOWLTO_LANGUAGE"]) {
             self.agent.language = language;
+            self.env_overrides.push(("agent.language", name));
         }
     }
 }
@@ -230,16 +551,32 @@ impl AppConfigLoader {
         };
         migration::ensure_supported_version(config.version)?;
         config.apply_env();
+        for hook in &mut config.agent.hooks {
+            *hook = self.config_dir.join(&*hook);
+        }
+        // 配置中可能有 API key, 会话中有完整的对话历史.
+        for private in [CONFIG_TOML_FILE, "sessions"] {
+            let path = self.config_dir.join(private);
+            config
+                .agent
+                .sandbox
+                .masked_paths
+                .push(path.to_string_lossy().into_owned());
+        }
         Ok(config)
     }
 
-    pub async fn load_profiles(&self) -> Result<Profiles> {
+    /// 加载 `profiles.toml` 并解析其中的模板, `vars` 是 `config.toml` 中的 `[vars]`.
+    pub async fn load_profiles(&self, vars: &BTreeMap<String, String>) -> Result<Profiles> {
         let profile_path = self.config_dir.join(PROFILES_TOML_FILE);
-        if profile_path.is_file() {
-            Ok(toml::from_str(&fs::read_to_string(profile_path).await?)?)
+        let mut profiles = if profile_path.is_file() {
+            let user = toml::from_str(&fs::read_to_string(profile_path).await?)?;
+            Profiles::default().with(user)
         } else {
-            Ok(Profiles::default())
-        }
+            Profiles::default()
+        };
+        profiles.compile(&self.config_dir, vars)?;
+        Ok(profiles)
     }
 
     async fn create_default_file(
@@ -284,7 +621,7 @@ impl AppConfigLoader {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     use tokio::fs;
     use uuid::Uuid;
@@ -315,6 +652,15 @@ mod tests {
         assert_eq!(config.llm.base_url, "https://example.test/v1");
         assert_eq!(config.llm.model, "test-model");
         assert_eq!(config.agent.language, "zh");
+        assert_eq!(
+            config.env_overrides,
+            [
+                ("llm.base_url", "OPENAI_BASE_URL"),
+                ("llm.api_key", "HOWLTO_API_KEY"),
+                ("llm.model", "HOWLTO_MODEL"),
+                ("agent.language", "HOWLTO_LANGUAGE"),
+            ]
+        );
     }
 
     #[test]
@@ -405,11 +751,59 @@ version = 2
         let loader = AppConfigLoader::new(&config_dir);
 
         loader.load_config().await.unwrap();
-        loader.load_profiles().await.unwrap();
+        loader.load_profiles(&BTreeMap::new()).await.unwrap();
 
         assert!(!config_dir.exists());
     }
 
+    #[tokio::test]
+    async fn profile_templates_are_validated_when_loading() {
+        let config_dir = temp_config_dir();
+        fs::create_dir_all(config_dir.join("prompts")).await.unwrap();
+        fs::write(
+            config_dir.join("prompts/team.md"),
+            "Follow the {{team}} conventions.",
+        )
+        .await
+        .unwrap();
+        fs::write(
+            config_dir.join(PROFILES_TOML_FILE),
+            r#"
+[answer]
+system = """
+Use {{shell}}.
+{{> prompts/team.md}}"""
+"#,
+        )
+        .await
+        .unwrap();
+        let loader = AppConfigLoader::new(&config_dir);
+        let vars = BTreeMap::from([("team".to_string(), "infra".to_string())]);
+
+        let profiles = loader.load_profiles(&vars).await.unwrap();
+        let system = profiles
+            .get("answer")
+            .unwrap()
+            .system()
+            .os("linux")
+            .shell("fish")
+            .text_lang("en")
+            .output_n(3)
+            .explore(true)
+            .elevate(true)
+            .finish();
+        assert_eq!(system, "Use fish.\nFollow the infra conventions.");
+
+        let error = loader.load_profiles(&BTreeMap::new()).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("team.md, line 1, column 12: unknown variable `team`"),
+            "{error}"
+        );
+        fs::remove_dir_all(config_dir).await.unwrap();
+    }
+
     #[tokio::test]
     async fn explicit_initialization_creates_missing_files_without_overwriting() {
         let config_dir = temp_config_dir();
@@ -1,35 +1,204 @@
-use std::fmt::Display;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::Path,
+    sync::Arc,
+};
 
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
-use template::*;
+use crate::{
+    config::{
+        AppConfig, PROFILES_TOML_FILE,
+        template::{Context, Template},
+    },
+    error::{Error, Result},
+};
 
-mod template {
-    pub(super) const TEXT_LANG: &str = "{{text_lang}}";
-    pub(super) const SHELL: &str = "{{shell}}";
-    pub(super) const OS: &str = "{{os}}";
-    pub(super) const MAX_TOKENS: &str = "{{max_tokens}}";
-    pub(super) const OUTPUT_N: &str = "{{output_n}}";
-    pub(super) const COMMAND: &str = "{{command}}";
-    pub(super) const ATTACHED: &str = "{{attached}}";
-}
+/// `system` 模板的内置变量. `explore`, `elevate` 和 `favorites` 表示对应工具是否启用,
+/// 未设置 `max_tokens` 时为空.
+const SYSTEM_VARS: [&str; 8] = [
+    "os",
+    "shell",
+    "text_lang",
+    "max_tokens",
+    "output_n",
+    "explore",
+    "elevate",
+    "favorites",
+];
+const MODIFY_VARS: [&str; 1] = ["command"];
+const ATTACHED_VARS: [&str; 1] = ["attached"];
+
+/// 没有指定, 也没有自动识别出其他 profile 时使用的 profile.
+pub const DEFAULT_PROFILE: &str = "answer";
+/// `-i` 转换模式默认使用的 profile.
+pub const TRANSFORM_PROFILE: &str = "transform";
+/// 翻译模式默认使用的 profile.
+pub const TRANSLATE_PROFILE: &str = "translate";
+/// `--completion` 默认使用的 profile.
+pub const COMPLETION_PROFILE: &str = "completion";
+/// `--explain` 默认使用的 profile.
+pub const EXPLAIN_PROFILE: &str = "explain";
 
-#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+/// `profiles.toml` 中的全部 profile, 以表名为 profile 名.
+///
+/// 内置 `answer`, `command`, `chat`, `explain`, `transform`, `translate` 和 `completion` 七个 profile,
+/// 文件中的同名表会整体替换内置的.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(transparent)]
 pub struct Profiles {
-    pub answer: AnswerProfile,
+    profiles: BTreeMap<String, AnswerProfile>,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        let profiles = [
+            (DEFAULT_PROFILE, AnswerProfile::default()),
+            (
+                "command",
+                AnswerProfile::builtin(COMMAND_MODE, &["command", "cmd", "c"], None),
+            ),
+            (
+                "chat",
+                AnswerProfile::builtin(CHAT_MODE, &["text", "txt", "t", "chat"], Some(false)),
+            ),
+            (
+                EXPLAIN_PROFILE,
+                AnswerProfile::builtin(EXPLAIN_MODE, &["explain"], Some(false)),
+            ),
+            (TRANSFORM_PROFILE, AnswerProfile::pipe(TRANSFORM_SYSTEM)),
+            (TRANSLATE_PROFILE, AnswerProfile::pipe(TRANSLATE_SYSTEM)),
+            (
+                COMPLETION_PROFILE,
+                AnswerProfile {
+                    explore: Some(true),
+                    ..AnswerProfile::builtin(COMPLETION_MODE, &[], Some(false))
+                },
+            ),
+        ];
+        Self {
+            profiles: profiles
+                .into_iter()
+                .map(|(name, profile)| (name.to_string(), profile))
+                .collect(),
+        }
+    }
+}
+
+impl Profiles {
+    /// 解析全部 profile 的模板, 见 [`AnswerProfile::compile`].
+    pub fn compile(&mut self, dir: &Path, vars: &BTreeMap<String, String>) -> Result<()> {
+        for (name, profile) in &mut self.profiles {
+            profile.compile(name, Some(dir), vars)?;
+        }
+        Ok(())
+    }
+
+    /// 在内置 profile 之上加入 `user` 中的 profile, 同名的以 `user` 为准.
+    pub fn with(mut self, user: Profiles) -> Self {
+        self.profiles.extend(user.profiles);
+        self
+    }
+
+    /// 全部 profile 的名称, 按字母顺序.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&AnswerProfile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| Error::profile_not_found(name, self.names()))
+    }
+
+    /// 选择本次使用的 profile.
+    ///
+    /// 指定了 `name` 时按名称查找; 否则按提示词的第一个词匹配各 profile 的 `triggers`,
+    /// 都不匹配时使用 [`DEFAULT_PROFILE`].
+    pub fn select(&self, name: Option<&str>, prompt: &str) -> Result<(&str, &AnswerProfile)> {
+        if let Some(name) = name {
+            return self
+                .profiles
+                .get_key_value(name)
+                .map(|(name, profile)| (name.as_str(), profile))
+                .ok_or_else(|| Error::profile_not_found(name, self.names()));
+        }
+        let first = prompt
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let detected = self.profiles.iter().find(|(_, profile)| {
+            profile
+                .triggers
+                .iter()
+                .any(|trigger| trigger.to_lowercase() == first)
+        });
+        match detected {
+            Some((name, profile)) => Ok((name, profile)),
+            None => Ok((DEFAULT_PROFILE, self.get(DEFAULT_PROFILE)?)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct AnswerProfile {
+    #[serde(default = "default_system")]
     system: String,
+    #[serde(default = "default_modify")]
     modify: String,
+    #[serde(default = "default_attached")]
     attached: String,
+    /// 提示词的第一个词是其中之一 (不区分大小写) 时自动选择该 profile.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<String>,
+    /// 覆盖 `[agent] use_tool_explore`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explore: Option<bool>,
+    /// 覆盖 `[agent] use_tool_elevate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elevate: Option<bool>,
+    /// 覆盖 `[agent.answer] output_n`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_n: Option<u32>,
+    /// 覆盖 `[agent] language`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// 覆盖 `[llm] model`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip)]
+    compiled: Option<Arc<Compiled>>,
+}
+
+/// 解析后的模板和 `config.toml` 中的 `[vars]`.
+#[derive(Debug)]
+struct Compiled {
+    system: Template,
+    modify: Template,
+    attached: Template,
+    vars: BTreeMap<String, String>,
+}
+
+impl Compiled {
+    /// 加入用户变量后渲染. 同名时内置变量优先.
+    fn render<'a>(&'a self, template: &Template, mut builtin: BTreeMap<&'a str, String>) -> String {
+        for (name, value) in &self.vars {
+            builtin
+                .entry(name.as_str())
+                .or_insert_with(|| value.clone());
+        }
+        template.render(&builtin)
+    }
 }
 
 #[bon::bon]
 impl AnswerProfile {
     #[builder(finish_fn = finish)]
+    #[allow(clippy::too_many_arguments)]
     pub fn system(
         &self,
         os: impl Display,
@@ -37,50 +206,174 @@ impl AnswerProfile {
         text_lang: impl Display,
         max_tokens: Option<u64>,
         output_n: u32,
+        explore: bool,
+        elevate: bool,
+        #[builder(default)] favorites: bool,
     ) -> String {
-        self.system_internal(os, shell, text_lang, max_tokens, output_n)
+        let compiled = self.compiled();
+        let vars = BTreeMap::from([
+            ("os", os.to_string()),
+            ("shell", shell.to_string()),
+            ("text_lang", text_lang.to_string()),
+            (
+                "max_tokens",
+                max_tokens.map(|tokens| tokens.to_string()).unwrap_or_default(),
+            ),
+            ("output_n", output_n.to_string()),
+            ("explore", explore.to_string()),
+            ("elevate", elevate.to_string()),
+            ("favorites", favorites.to_string()),
+        ]);
+        compiled.render(&compiled.system, vars)
     }
 
     #[builder(finish_fn = fmt)]
     pub fn modify(&self, #[builder(start_fn)] command: impl Display) -> String {
-        self.modify.replace(COMMAND, &command.to_string())
+        let compiled = self.compiled();
+        let vars = BTreeMap::from([("command", command.to_string())]);
+        compiled.render(&compiled.modify, vars)
     }
 
     #[builder(finish_fn = fmt)]
     pub fn attach(&self, #[builder(start_fn)] attached: impl Display) -> String {
-        self.attached.replace(ATTACHED, &attached.to_string())
+        let compiled = self.compiled();
+        let vars = BTreeMap::from([("attached", attached.to_string())]);
+        compiled.render(&compiled.attached, vars)
     }
 }
 
 impl AnswerProfile {
-    fn system_internal(
-        &self,
-        os: impl Display,
-        shell: impl Display,
-        text_lang: impl Display,
-        max_tokens: Option<u64>,
-        output_n: u32,
-    ) -> String {
-        self.system
-            .replace(SHELL, &shell.to_string())
-            .replace(OS, &os.to_string())
-            .replace(
-                MAX_TOKENS,
-                &max_tokens
-                    .map(|tokens| tokens.to_string())
-                    .unwrap_or_else(|| "[none]".to_string()),
+    /// 在默认 system 模板后追加 `mode` 的内置 profile.
+    fn builtin(mode: &str, triggers: &[&str], elevate: Option<bool>) -> Self {
+        Self {
+            system: format!("{ANSWER_SYSTEM}{mode}"),
+            triggers: triggers.iter().map(ToString::to_string).collect(),
+            elevate,
+            ..Self::default()
+        }
+    }
+
+    /// 不使用工具, 直接处理输入内容的内置 profile.
+    fn pipe(system: &str) -> Self {
+        Self {
+            system: system.to_string(),
+            attached: INPUT_ATTACHED.to_string(),
+            explore: Some(false),
+            elevate: Some(false),
+            ..Self::default()
+        }
+    }
+
+    /// 解析模板, 检查其中的变量是否都已定义.
+    ///
+    /// `dir` 是 `{{> path}}` 引入文件所在的目录, [`None`] 表示不允许引入;
+    /// `vars` 是 `config.toml` 中的 `[vars]`. 错误信息中带有 profile 名, 字段和行列号.
+    pub fn compile(
+        &mut self,
+        name: &str,
+        dir: Option<&Path>,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let template = |field: &str, source: &str, builtin: &[&str]| {
+            let known = |var: &str| builtin.contains(&var) || vars.contains_key(var);
+            let origin = format!("{PROFILES_TOML_FILE} [{name}].{field}");
+            Template::parse(
+                source,
+                &origin,
+                &Context {
+                    known: &known,
+                    include_dir: dir,
+                },
             )
-            .replace(OUTPUT_N, &output_n.to_string())
-            .replace(TEXT_LANG, &text_lang.to_string())
+        };
+        self.compiled = Some(Arc::new(Compiled {
+            system: template("system", &self.system, &SYSTEM_VARS)?,
+            modify: template("modify", &self.modify, &MODIFY_VARS)?,
+            attached: template("attached", &self.attached, &ATTACHED_VARS)?,
+            vars: vars.clone(),
+        }));
+        Ok(())
+    }
+
+    /// 未经 [`AnswerProfile::compile`] 的 profile (如内置的默认值) 在使用时解析,
+    /// 此时不能引入文件, 也没有用户变量; 解析失败时原样输出模板.
+    fn compiled(&self) -> Arc<Compiled> {
+        if let Some(compiled) = &self.compiled {
+            return compiled.clone();
+        }
+        let mut profile = self.clone();
+        match profile.compile("?", None, &BTreeMap::new()) {
+            Ok(()) => profile.compiled.expect("compiled profile"),
+            Err(error) => {
+                warn!(%error, "Failed to parse profile templates, using them verbatim.");
+                Arc::new(Compiled {
+                    system: Template::text(&self.system),
+                    modify: Template::text(&self.modify),
+                    attached: Template::text(&self.attached),
+                    vars: BTreeMap::new(),
+                })
+            }
+        }
+    }
+
+    /// 把 profile 中的覆盖项写入 `config`.
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(explore) = self.explore {
+            config.agent.use_tool_explore = explore;
+        }
+        if let Some(elevate) = self.elevate {
+            config.agent.use_tool_elevate = elevate;
+        }
+        if let Some(output_n) = self.output_n {
+            config.agent.answer.output_n = output_n;
+        }
+        if let Some(language) = &self.language {
+            config.agent.language = language.clone();
+        }
+        if let Some(model) = &self.model {
+            config.llm.model = model.clone();
+        }
     }
 }
 
 impl Default for AnswerProfile {
     fn default() -> Self {
         Self {
-            system: r#"# Role
+            system: default_system(),
+            modify: default_modify(),
+            attached: default_attached(),
+            triggers: Vec::new(),
+            explore: None,
+            elevate: None,
+            output_n: None,
+            language: None,
+            model: None,
+            compiled: None,
+        }
+    }
+}
 
-You are a command-line assistant. Always answer in {{text_lang}}. The user runs {{shell}} on {{os}}. Keep the final response concise and try to stay within max_tokens={{max_tokens}}, where [none] means no explicit limit.
+fn default_system() -> String {
+    ANSWER_SYSTEM.to_string()
+}
+
+fn default_modify() -> String {
+    r#"Modify this command according to the next user request:
+```
+{{command}}
+```"#
+        .to_string()
+}
+
+fn default_attached() -> String {
+    r#"The user attached this additional input:
+{{attached}}"#
+        .to_string()
+}
+
+const ANSWER_SYSTEM: &str = r#"# Role
+
+You are a command-line assistant. Always answer in {{text_lang}}. The user runs {{shell}} on {{os}}. Keep the final response concise{{#if max_tokens}} and try to stay within {{max_tokens}} tokens{{/if}}.
 
 # Workflow
 
@@ -102,28 +395,79 @@ You are a command-line assistant. Always answer in {{text_lang}}. The user runs
 
 # Tools
 
+{{#if explore}}
 - `explore` runs a command in a read-only, network-disabled sandbox. Prefer it for help, inspection, search, status, and version checks.
+{{/if}}
+{{#if elevate}}
 - `elevate` asks the user to approve a command before running it with writes, network, and other side effects enabled. Use it only when those capabilities are required.
+{{/if}}
+{{#if favorites}}
+- `search_favorites` searches the commands the user bookmarked as their team's canonical invocations. Search it with a few keywords before composing a command, and when a favorite fits the task, prefer it and adapt only what the request requires.
+{{/if}}
+- A `rejected` tool result carries a `rejection` object. `by: policy` means a configured rule matched the shown `segment`; do not retry that segment, choose another approach or explain the limitation. `by: user` means the user declined; respect the stated reason.
 - `submit_commands` accepts at most {{output_n}} command candidates. Each candidate is independent and directly runnable. Combine dependent steps into one candidate using valid {{shell}} syntax.
 
 Do not invent tool names. Do not expose hidden reasoning or raw tool arguments in the final response. If no command is useful, answer with text only.
-"#
-            .to_string(),
-            modify: r#"Modify this command according to the next user request:
-```
-{{command}}
-```"#
-                .to_string(),
-            attached: r#"The user attached this additional input:
-{{attached}}"#
-                .to_string(),
-        }
-    }
-}
+"#;
+
+const COMMAND_MODE: &str = r#"
+# Mode
+
+The user wants runnable commands. Always call `submit_commands` with the best candidates before answering, then finish with a one-sentence summary.
+"#;
+
+const CHAT_MODE: &str = r#"
+# Mode
+
+The user wants a conversational text answer. Do not call `submit_commands`; put any command worth showing in a shell code block instead.{{#if explore}} You may still use `explore` to check facts.{{/if}}
+"#;
+
+const EXPLAIN_MODE: &str = r#"
+# Mode
+
+The user wants to understand a command or concept. Break a command down into its programs, subcommands, flags and redirections and explain each part{{#if explore}}, reading the relevant `--help` or `man` page with `explore` instead of guessing{{/if}}. Do not call `submit_commands` unless the user asks for an alternative command.
+"#;
+
+const COMPLETION_MODE: &str = r#"
+# Mode
+
+The user wants a {{shell}} completion script for a program. Before writing anything, read the program's `--help` (or `-h`), the help of every subcommand it lists, and its `man` page when one exists{{#if explore}} with `explore`{{/if}}. Complete only subcommands, flags and values that the documentation confirms, with their descriptions where the shell supports them. Do not call `submit_commands`. Reply with the complete script in one code block and nothing else.
+"#;
+
+const TRANSFORM_SYSTEM: &str = r#"# Role
+
+You are a text transformer in the middle of a shell pipeline. The user gives an instruction and the input read from stdin. The user runs {{shell}} on {{os}}.
+
+# Output
+
+- Output only the transformed content. It is written to stdout verbatim and consumed by another program.
+- Do not add explanations, greetings, headings, or notes before or after the content.
+- Do not wrap the content in markdown code fences.
+- Write any prose that the instruction asks you to produce in {{text_lang}}; keep code, identifiers, and data in the form the instruction requires.
+- The input may be one part of a larger document. Transform only the given part and do not repeat or invent content outside it.
+"#;
+
+const TRANSLATE_SYSTEM: &str = r#"# Role
+
+You are a translator. Translate the input into {{text_lang}}. The input is usually documentation, such as a README, or a log with error messages.
+
+# Output
+
+- Output only the translation. It is written to stdout or a file verbatim.
+- If the source language is not given, detect it. Leave text that is already in {{text_lang}} unchanged.
+- Preserve the markdown structure exactly: headings, lists, tables, block quotes, emphasis, links, images, HTML tags, and line breaks.
+- Do not translate inline code, URLs, file paths, commands, identifiers, environment variables, or log fields such as timestamps, levels, and error codes.
+- Do not add explanations or notes, and do not wrap the output in markdown code fences.
+- The input may be one part of a larger document. Translate only the given part.
+"#;
+
+const INPUT_ATTACHED: &str = r#"Input:
+{{attached}}"#;
 
 #[cfg(test)]
 mod tests {
-    use super::AnswerProfile;
+    use super::{AnswerProfile, DEFAULT_PROFILE, Profiles, TRANSFORM_PROFILE, TRANSLATE_PROFILE};
+    use crate::config::AppConfig;
 
     #[test]
     fn default_profile_contains_only_current_fields() {
@@ -147,4 +491,140 @@ check_finish = "old"
 "#;
         assert!(toml::from_str::<AnswerProfile>(legacy).is_err());
     }
+
+    #[test]
+    fn profiles_are_selected_by_name_or_first_word() {
+        let profiles = Profiles::default();
+        let name = |prompt| profiles.select(None, prompt).unwrap().0;
+        assert_eq!(name("cmd list open ports"), "command");
+        assert_eq!(name("Explain tar -xzvf a.tgz"), "explain");
+        assert_eq!(name("t why is my disk full"), "chat");
+        assert_eq!(name("c++ compile flags"), DEFAULT_PROFILE);
+        assert_eq!(name(""), DEFAULT_PROFILE);
+        assert_eq!(
+            profiles.select(Some("explain"), "cmd ls").unwrap().0,
+            "explain"
+        );
+
+        let error = profiles.select(Some("review"), "").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Profile `review` not found. Available profiles: answer, chat, command, completion, explain, transform, translate."
+        );
+    }
+
+    #[test]
+    fn user_profiles_extend_and_replace_builtin_ones() {
+        let user: Profiles = toml::from_str(
+            r#"
+[chat]
+system = "Just chat in {{text_lang}}."
+
+[review]
+system = "Review the command."
+triggers = ["review"]
+explore = false
+elevate = false
+output_n = 1
+language = "de"
+model = "small-model"
+"#,
+        )
+        .unwrap();
+        let profiles = Profiles::default().with(user);
+        assert_eq!(
+            profiles.names().collect::<Vec<_>>(),
+            [
+                "answer",
+                "chat",
+                "command",
+                "completion",
+                "explain",
+                "review",
+                "transform",
+                "translate"
+            ]
+        );
+        // 替换后的 chat 没有 triggers, `t` 不再选中它.
+        assert_eq!(profiles.select(None, "t hi").unwrap().0, DEFAULT_PROFILE);
+
+        let (name, review) = profiles.select(None, "review rm -rf build").unwrap();
+        assert_eq!(name, "review");
+        assert_eq!(review.modify("ls").fmt(), AnswerProfile::default().modify("ls").fmt());
+        let mut config = AppConfig::default();
+        review.apply(&mut config);
+        assert!(!config.agent.use_tool_explore);
+        assert!(!config.agent.use_tool_elevate);
+        assert_eq!(config.agent.answer.output_n, 1);
+        assert_eq!(config.agent.language, "de");
+        assert_eq!(config.llm.model, "small-model");
+    }
+
+    #[test]
+    fn profiles_mentioning_other_template_languages_load() {
+        let mut profile: AnswerProfile = toml::from_str(
+            r#"system = "Set {{ .Values.image }} in Helm charts and ${{ github.sha }} in workflows, not \\{{ tag }}. Use {{shell}}.""#,
+        )
+        .unwrap();
+        profile
+            .compile("helm", None, &std::collections::BTreeMap::new())
+            .unwrap();
+        let system = profile
+            .system()
+            .os("linux")
+            .shell("/bin/bash")
+            .text_lang("en")
+            .output_n(3)
+            .explore(true)
+            .elevate(false)
+            .finish();
+        assert!(system.starts_with(
+            "Set {{ .Values.image }} in Helm charts and ${{ github.sha }} in workflows, not {{ tag }}. Use"
+        ));
+    }
+
+    #[test]
+    fn builtin_prompts_only_mention_enabled_tools() {
+        let profiles = Profiles::default();
+        for (name, profile) in &profiles.profiles {
+            if [TRANSFORM_PROFILE, TRANSLATE_PROFILE].contains(&name.as_str()) {
+                continue;
+            }
+            let system = |elevate| {
+                profile
+                    .system()
+                    .os("linux")
+                    .shell("/bin/bash")
+                    .text_lang("en")
+                    .output_n(3)
+                    .explore(true)
+                    .elevate(elevate)
+                    .finish()
+            };
+            let enabled = system(true);
+            let disabled = system(false);
+            assert!(!enabled.contains("{{"), "{name}");
+            assert!(enabled.contains("- `elevate` asks"), "{name}");
+            assert!(!disabled.contains("- `elevate` asks"), "{name}");
+            assert!(!disabled.contains("\n\n\n"), "{name}");
+            assert!(disabled.contains("Keep the final response concise."));
+        }
+
+        for name in [TRANSFORM_PROFILE, TRANSLATE_PROFILE] {
+            let system = profiles
+                .get(name)
+                .unwrap()
+                .system()
+                .os("linux")
+                .shell("/bin/bash")
+                .text_lang("en")
+                .output_n(3)
+                .explore(false)
+                .elevate(false)
+                .finish();
+            assert!(!system.contains("{{"), "{name}");
+            assert!(!system.contains("submit_commands"), "{name}");
+            assert!(!system.contains("`explore`"), "{name}");
+        }
+    }
 }
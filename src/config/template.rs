@@ -0,0 +1,441 @@
+//! profile 提示词模板.
+//!
+//! 语法:
+//! - `{{name}}`: 变量, 只能使用内置变量和 `config.toml` 中 `[vars]` 定义的变量.
+//! - `{{#if name}}...{{else}}...{{/if}}`, `{{#unless name}}...{{/unless}}`:
+//!   变量非空且不是 `false` 或 `0` 时为真, `{{else}}` 可选.
+//! - `{{> path}}`: 引入配置目录中的文件, 被引入的文件同样按模板解析.
+//! - `\{{`: 原样输出 `{{`. 其他模板语言的标签, 如 Helm 的 `{{ .Values.image }}` 和
+//!   GitHub Actions 的 `${{ github.sha }}`, 不是合法的变量名, 也会原样输出;
+//!   Jinja 的 `{{ name }}` 这样看起来像变量的标签需要转义.
+//!
+//! 模板在加载 `profiles.toml` 时解析, 未知变量, 不成对的块和无法读取的引入文件
+//! 都会连同所在位置一起报错, 而不是在渲染时原样输出.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+
+/// 引入文件的最大嵌套层数.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// 解析后的模板.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Text(String),
+    Var(String),
+    If {
+        name: String,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// 解析模板所需的上下文.
+pub struct Context<'a> {
+    /// 模板中可以使用的变量名.
+    pub known: &'a dyn Fn(&str) -> bool,
+    /// `{{> path}}` 相对的目录, [`None`] 表示不允许引入.
+    pub include_dir: Option<&'a Path>,
+}
+
+/// 正在解析的块.
+struct Block {
+    /// `if` 或 `unless`, 根节点为空.
+    kind: &'static str,
+    name: String,
+    location: String,
+    then: Vec<Node>,
+    otherwise: Vec<Node>,
+    in_else: bool,
+}
+
+impl Block {
+    fn nodes(&mut self) -> &mut Vec<Node> {
+        if self.in_else {
+            &mut self.otherwise
+        } else {
+            &mut self.then
+        }
+    }
+}
+
+impl Template {
+    /// 不含任何标签的模板, 原样输出 `text`.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            nodes: vec![Node::Text(text.into())],
+        }
+    }
+
+    /// 解析 `source`, `origin` 用于错误信息中的位置, 如 `profiles.toml [answer].system`.
+    pub fn parse(source: &str, origin: &str, context: &Context<'_>) -> Result<Self> {
+        parse(source, origin, context, &mut Vec::new()).map(|nodes| Self { nodes })
+    }
+
+    /// 用 `vars` 渲染模板, 未提供的变量视为空.
+    pub fn render(&self, vars: &BTreeMap<&str, String>) -> String {
+        let mut output = String::new();
+        render(&self.nodes, vars, &mut output);
+        output
+    }
+}
+
+fn parse(
+    source: &str,
+    origin: &str,
+    context: &Context<'_>,
+    includes: &mut Vec<PathBuf>,
+) -> Result<Vec<Node>> {
+    let error = |offset: usize, message: String| Error::Template {
+        location: location(source, origin, offset),
+        message,
+    };
+    let mut stack = vec![Block {
+        kind: "",
+        name: String::new(),
+        location: String::new(),
+        then: Vec::new(),
+        otherwise: Vec::new(),
+        in_else: false,
+    }];
+    let mut rest = 0;
+    while let Some(start) = source[rest..].find("{{").map(|index| rest + index) {
+        if source[..start].ends_with('\\') {
+            let top = stack.last_mut().expect("root block");
+            top.nodes()
+                .push(Node::Text(format!("{}{{{{", &source[rest..start - 1])));
+            rest = start + 2;
+            continue;
+        }
+        let Some(end) = source[start..].find("}}").map(|index| start + index) else {
+            return Err(error(start, "unclosed `{{`".to_string()));
+        };
+        let tag = source[start + 2..end].trim();
+        if source[..start].ends_with('$') || !is_tag(tag) {
+            let top = stack.last_mut().expect("root block");
+            top.nodes()
+                .push(Node::Text(source[rest..end + 2].to_string()));
+            rest = end + 2;
+            continue;
+        }
+        let (text_end, next) = standalone(source, rest, start, end + 2, tag);
+        if text_end > rest {
+            let top = stack.last_mut().expect("root block");
+            top.nodes()
+                .push(Node::Text(source[rest..text_end].to_string()));
+        }
+        rest = next;
+        if let Some(condition) = tag.strip_prefix('#') {
+            let (kind, name) = match condition.split_once(char::is_whitespace) {
+                Some(("if", name)) => ("if", name.trim()),
+                Some(("unless", name)) => ("unless", name.trim()),
+                _ => return Err(error(start, format!("invalid block `{{{{{tag}}}}}`"))),
+            };
+            check_variable(name, context).map_err(|message| error(start, message))?;
+            stack.push(Block {
+                kind,
+                name: name.to_string(),
+                location: location(source, origin, start),
+                then: Vec::new(),
+                otherwise: Vec::new(),
+                in_else: false,
+            });
+        } else if tag == "else" {
+            let top = stack.last_mut().expect("root block");
+            if top.kind.is_empty() || top.in_else {
+                return Err(error(start, "`{{else}}` outside of a block".to_string()));
+            }
+            top.in_else = true;
+        } else if let Some(kind) = tag.strip_prefix('/') {
+            let top = stack.last().expect("root block");
+            if top.kind.is_empty() || top.kind != kind.trim() {
+                return Err(error(start, format!("unexpected `{{{{{tag}}}}}`")));
+            }
+            let block = stack.pop().expect("non-root block");
+            stack
+                .last_mut()
+                .expect("root block")
+                .nodes()
+                .push(Node::If {
+                    name: block.name,
+                    negate: block.kind == "unless",
+                    then: block.then,
+                    otherwise: block.otherwise,
+                });
+        } else if let Some(path) = tag.strip_prefix('>') {
+            let nodes = include(path.trim(), context, includes)
+                .map_err(|message| error(start, message))??;
+            stack.last_mut().expect("root block").nodes().extend(nodes);
+        } else {
+            check_variable(tag, context).map_err(|message| error(start, message))?;
+            stack
+                .last_mut()
+                .expect("root block")
+                .nodes()
+                .push(Node::Var(tag.to_string()));
+        }
+    }
+    if stack.len() > 1 {
+        let block = stack.pop().expect("non-root block");
+        return Err(Error::Template {
+            location: block.location,
+            message: format!("`{{{{#{} {}}}}}` is never closed", block.kind, block.name),
+        });
+    }
+    let mut root = stack.pop().expect("root block");
+    if rest < source.len() {
+        root.then.push(Node::Text(source[rest..].to_string()));
+    }
+    Ok(root.then)
+}
+
+/// 独占一行的块标签连同所在的行一起去掉, 避免在输出中留下空行.
+///
+/// 返回标签前文本的结束位置和标签后继续解析的位置.
+fn standalone(source: &str, rest: usize, start: usize, end: usize, tag: &str) -> (usize, usize) {
+    if !(tag.starts_with(['#', '/']) || tag == "else") {
+        return (start, end);
+    }
+    let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+    let after = &source[end..];
+    let line_end = after.find('\n').map_or(after.len(), |index| index + 1);
+    // 同一行中更早的标签已经解析过时 (`rest` 在行内), 这一行不是独占的.
+    if line_start >= rest
+        && source[line_start..start].trim().is_empty()
+        && after[..line_end].trim().is_empty()
+    {
+        (line_start, end + line_end)
+    } else {
+        (start, end)
+    }
+}
+
+/// 是否是本模板的标签. 其余的 `{{...}}` 属于其他模板语言, 原样输出.
+fn is_tag(tag: &str) -> bool {
+    tag.starts_with(['#', '/', '>'])
+        || tag
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+fn check_variable(name: &str, context: &Context<'_>) -> std::result::Result<(), String> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_');
+    if !valid {
+        Err(format!("invalid tag `{{{{{name}}}}}`"))
+    } else if !(context.known)(name) {
+        Err(format!("unknown variable `{name}`"))
+    } else {
+        Ok(())
+    }
+}
+
+/// 解析引入的文件. 外层错误是引入本身的问题 (位置在引入标签), 内层是文件内容的解析错误.
+fn include(
+    path: &str,
+    context: &Context<'_>,
+    includes: &mut Vec<PathBuf>,
+) -> std::result::Result<Result<Vec<Node>>, String> {
+    let Some(dir) = context.include_dir else {
+        return Err("includes are not available in built-in profiles".to_string());
+    };
+    let path = dir.join(path);
+    if includes.contains(&path) {
+        return Err(format!("`{}` includes itself", path.display()));
+    }
+    if includes.len() >= MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "includes are nested more than {MAX_INCLUDE_DEPTH} levels deep"
+        ));
+    }
+    let source = std::fs::read_to_string(&path)
+        .map_err(|error| format!("cannot read `{}`: {error}", path.display()))?;
+    includes.push(path.clone());
+    let nodes = parse(&source, &path.display().to_string(), context, includes);
+    includes.pop();
+    Ok(nodes)
+}
+
+fn location(source: &str, origin: &str, offset: usize) -> String {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+    format!("{origin}, line {line}, column {column}")
+}
+
+fn render(nodes: &[Node], vars: &BTreeMap<&str, String>, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Var(name) => output.push_str(vars.get(name.as_str()).map_or("", String::as_str)),
+            Node::If {
+                name,
+                negate,
+                then,
+                otherwise,
+            } => {
+                let truthy = vars
+                    .get(name.as_str())
+                    .is_some_and(|value| !matches!(value.as_str(), "" | "false" | "0"));
+                let branch = if truthy != *negate { then } else { otherwise };
+                render(branch, vars, output);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known(name: &str) -> bool {
+        ["shell", "elevate", "team"].contains(&name)
+    }
+
+    fn parse(source: &str) -> Result<Template> {
+        Template::parse(
+            source,
+            "test",
+            &Context {
+                known: &known,
+                include_dir: None,
+            },
+        )
+    }
+
+    fn vars(pairs: &[(&'static str, &str)]) -> BTreeMap<&'static str, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn renders_variables_and_conditionals() {
+        let template = parse(
+            "Use {{ shell }}.{{#if elevate}} Ask before writes.{{else}} Read only.{{/if}}{{#unless team}} Solo.{{/unless}}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&vars(&[("shell", "fish"), ("elevate", "true")])),
+            "Use fish. Ask before writes. Solo."
+        );
+        assert_eq!(
+            template.render(&vars(&[("elevate", "false"), ("team", "infra")])),
+            "Use . Read only."
+        );
+    }
+
+    #[test]
+    fn standalone_block_tags_leave_no_blank_lines() {
+        let template = parse("Tools:\n{{#if elevate}}\n- elevate\n{{else}}\n- none\n{{/if}}\nEnd {{#if elevate}}x{{/if}}\n").unwrap();
+        assert_eq!(
+            template.render(&vars(&[("elevate", "true")])),
+            "Tools:\n- elevate\nEnd x\n"
+        );
+        assert_eq!(template.render(&vars(&[])), "Tools:\n- none\nEnd \n");
+    }
+
+    #[test]
+    fn foreign_tags_and_escapes_are_kept_as_text() {
+        let template =
+            parse("image: {{ .Values.image }}\nsha: ${{ github.sha }}\n\\{{ name }} in {{shell}}")
+                .unwrap();
+        assert_eq!(
+            template.render(&vars(&[("shell", "bash")])),
+            "image: {{ .Values.image }}\nsha: ${{ github.sha }}\n{{ name }} in bash"
+        );
+        // 看起来像变量的标签仍然按变量检查, 需要转义.
+        assert_eq!(
+            parse("{{ end }}").unwrap_err().to_string(),
+            "test, line 1, column 1: unknown variable `end`"
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_location() {
+        let cases = [
+            (
+                "a\nb {{shel}}",
+                "test, line 2, column 3: unknown variable `shel`",
+            ),
+            (
+                "{{#if elevate}}x",
+                "test, line 1, column 1: `{{#if elevate}}` is never closed",
+            ),
+            (
+                "{{#if elevate}}x{{/unless}}",
+                "test, line 1, column 17: unexpected `{{/unless}}`",
+            ),
+            (
+                "x {{else}}",
+                "test, line 1, column 3: `{{else}}` outside of a block",
+            ),
+            ("{{ 1st }}", "test, line 1, column 1: invalid tag `{{1st}}`"),
+            ("{{shell", "test, line 1, column 1: unclosed `{{`"),
+            (
+                "{{> rules.md}}",
+                "test, line 1, column 1: includes are not available in built-in profiles",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(parse(source).unwrap_err().to_string(), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn includes_are_parsed_relative_to_the_directory() {
+        let dir = std::env::temp_dir().join(format!("howlto-template-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("prompts")).unwrap();
+        std::fs::write(
+            dir.join("prompts/rules.md"),
+            "Team {{team}}.\n{{> prompts/loop.md}}",
+        )
+        .unwrap();
+        std::fs::write(dir.join("prompts/loop.md"), "{{> prompts/loop.md}}").unwrap();
+        std::fs::write(dir.join("prompts/ok.md"), "Team {{team}}.").unwrap();
+        std::fs::write(dir.join("prompts/bad.md"), "\n{{missing}}").unwrap();
+        let context = Context {
+            known: &known,
+            include_dir: Some(&dir),
+        };
+        let parse = |source| Template::parse(source, "test", &context);
+
+        let template = parse("Rules: {{> prompts/ok.md}}").unwrap();
+        assert_eq!(
+            template.render(&vars(&[("team", "infra")])),
+            "Rules: Team infra."
+        );
+        let error = parse("{{> prompts/rules.md}}").unwrap_err().to_string();
+        assert!(error.contains("loop.md` includes itself"), "{error}");
+        let error = parse("{{> prompts/bad.md}}").unwrap_err().to_string();
+        assert!(
+            error.ends_with("bad.md, line 2, column 1: unknown variable `missing`"),
+            "{error}"
+        );
+        let error = parse("{{> prompts/none.md}}").unwrap_err().to_string();
+        assert!(error.contains("cannot read"), "{error}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,532 @@
+//! `howlto --doctor`: 逐项检查配置和运行环境, 帮助用户定位 "为什么用不了".
+//!
+//! 每一项检查都独立运行, 前面的失败不会中断后面的检查 (依赖配置的检查除外),
+//! 最终输出一份报告. 存在 [`Status::Fail`] 时进程以非零状态退出.
+
+use std::{
+    fmt::{self, Display},
+    path::Path,
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::fs;
+
+use crate::{
+    agent::{
+        answer::AnswerAgent,
+        detect_os,
+        sandbox::{self, SandboxOptions, SandboxPolicy},
+    },
+    config::{
+        AppConfig, AppConfigLoader, CONFIG_TOML_FILE, LlmConfig, PROFILES_TOML_FILE,
+        SandboxBackend,
+        profile::{DEFAULT_PROFILE, Profiles},
+    },
+    shell::Shell,
+};
+
+/// 检查 API 时的超时.
+const API_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 报告来源的配置项.
+const PROVENANCE_KEYS: [&str; 4] = ["llm.base_url", "llm.api_key", "llm.model", "agent.language"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// 一项检查的结果.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    /// 结果说明, 可以有多行.
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|check| check.status == Status::Fail)
+    }
+
+    /// 名为 `name` 的检查.
+    pub fn get(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                Status::Ok => " OK ",
+                Status::Warn => "WARN",
+                Status::Fail => "FAIL",
+            };
+            let mut lines = check.detail.lines();
+            writeln!(
+                f,
+                "[{status}] {}: {}",
+                check.name,
+                lines.next().unwrap_or_default()
+            )?;
+            for line in lines {
+                writeln!(f, "       {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 运行全部检查.
+///
+/// `integration_loaded` 表示本次调用是否经过 `howlto --init` 的 shell 函数
+/// (即是否带有 `--htcmd-file`).
+#[bon::builder]
+pub async fn run(config_dir: &Path, shell: &Shell, integration_loaded: bool) -> Report {
+    let mut report = Report::default();
+    let loader = AppConfigLoader::new(config_dir);
+
+    let config_path = config_dir.join(CONFIG_TOML_FILE);
+    let config = match loader.load_config().await {
+        Ok(config) => {
+            report.checks.push(if config_path.is_file() {
+                Check::new("配置文件", Status::Ok, config_path.display().to_string())
+            } else {
+                Check::new(
+                    "配置文件",
+                    Status::Warn,
+                    format!(
+                        "{} 不存在, 使用默认配置. 可以运行 `howlto --init-config` 创建",
+                        config_path.display()
+                    ),
+                )
+            });
+            Some(config)
+        }
+        Err(error) => {
+            report.checks.push(Check::new(
+                "配置文件",
+                Status::Fail,
+                format!("{}: {error}", config_path.display()),
+            ));
+            None
+        }
+    };
+
+    let profiles_path = config_dir.join(PROFILES_TOML_FILE);
+    let vars = config
+        .as_ref()
+        .map(|config| config.vars.clone())
+        .unwrap_or_default();
+    let profiles = match loader.load_profiles(&vars).await {
+        Ok(profiles) => {
+            report.checks.push(if profiles_path.is_file() {
+                Check::new("Profiles", Status::Ok, profiles_path.display().to_string())
+            } else {
+                Check::new(
+                    "Profiles",
+                    Status::Ok,
+                    format!("{} 不存在, 使用内置 Profile", profiles_path.display()),
+                )
+            });
+            profiles
+        }
+        Err(error) => {
+            report.checks.push(Check::new(
+                "Profiles",
+                Status::Fail,
+                format!("{}: {error}", profiles_path.display()),
+            ));
+            Profiles::default()
+        }
+    };
+
+    let backend = config
+        .as_ref()
+        .map_or(SandboxBackend::Auto, |config| config.agent.sandbox.backend);
+    if let Some(config) = config {
+        let file = fs::read_to_string(&config_path)
+            .await
+            .ok()
+            .and_then(|content| content.parse::<toml::Table>().ok())
+            .unwrap_or_default();
+        report.checks.push(provenance(&config, &file));
+        report.checks.extend(check_api(&config.llm).await);
+        report.checks.push(check_agent(config, &profiles, shell));
+    } else {
+        report.checks.push(Check::new(
+            "API",
+            Status::Warn,
+            "配置无法加载, 跳过 API 和工具配置的检查",
+        ));
+    }
+
+    report.checks.extend(check_sandbox(backend).await);
+    report.checks.push(check_shell(shell));
+    report.checks.push(if integration_loaded {
+        Check::new("Shell 集成", Status::Ok, "当前 shell 已加载 `howlto --init`")
+    } else {
+        Check::new(
+            "Shell 集成",
+            Status::Warn,
+            "当前 shell 没有加载 `howlto --init`, 选中的命令无法放入输入框\n配置方法见 docs/shell-integration.md",
+        )
+    });
+    report.checks.push(check_clipboard());
+    report
+}
+
+/// 各配置项的生效值来自环境变量, config.toml 还是默认值.
+fn provenance(config: &AppConfig, file: &toml::Table) -> Check {
+    let detail = PROVENANCE_KEYS
+        .iter()
+        .map(|&key| {
+            let value = match key {
+                "llm.base_url" => config.llm.base_url.clone(),
+                "llm.api_key" => mask(&config.llm.api_key),
+                "llm.model" => config.llm.model.clone(),
+                _ => config.agent.language.clone(),
+            };
+            let source = if let Some((_, name)) =
+                config.env_overrides.iter().find(|(field, _)| *field == key)
+            {
+                format!("环境变量 {name}")
+            } else if lookup(file, key).is_some() {
+                CONFIG_TOML_FILE.to_string()
+            } else {
+                "默认值".to_string()
+            };
+            format!("{key} = {value} ({source})")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Check::new("配置来源", Status::Ok, detail)
+}
+
+fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let (section, field) = key.split_once('.')?;
+    table.get(section)?.as_table()?.get(field)
+}
+
+/// 只显示 API key 的开头, 避免出现在终端记录中.
+fn mask(api_key: &str) -> String {
+    if api_key.is_empty() {
+        return "(空)".to_string();
+    }
+    if api_key.chars().count() < 12 {
+        return "****".to_string();
+    }
+    let prefix: String = api_key.chars().take(4).collect();
+    format!("{prefix}****")
+}
+
+/// 请求 `{base_url}/models`, 检查 API 是否可达, key 是否有效, 模型是否存在.
+pub async fn check_api(llm: &LlmConfig) -> Vec<Check> {
+    if llm.base_url.is_empty() {
+        return vec![Check::new(
+            "API",
+            Status::Fail,
+            "llm.base_url 为空. 请设置 HOWLTO_BASE_URL 或编辑 config.toml",
+        )];
+    }
+    let url = format!("{}/models", llm.base_url.trim_end_matches('/'));
+    let client = match reqwest::Client::builder().timeout(API_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(error) => return vec![Check::new("API", Status::Fail, error.to_string())],
+    };
+    let mut request = client.get(&url);
+    if !llm.api_key.is_empty() {
+        request = request.bearer_auth(&llm.api_key);
+    }
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(error) => {
+            return vec![Check::new(
+                "API",
+                Status::Fail,
+                format!("无法连接 {url}: {}", error_chain(&error)),
+            )];
+        }
+    };
+    let status = response.status();
+    if matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    ) {
+        return vec![Check::new(
+            "API",
+            Status::Fail,
+            format!("{url} 拒绝了 API key ({status})"),
+        )];
+    }
+    let api = Check::new("API", Status::Ok, format!("{} 可以访问", llm.base_url));
+    let unverified = |reason: String| {
+        Check::new(
+            "模型",
+            Status::Warn,
+            format!("{reason}, 无法确认模型 {} 是否存在", llm.model),
+        )
+    };
+    if !status.is_success() {
+        return vec![api, unverified(format!("{url} 返回 {status}"))];
+    }
+    let ids = response.json::<Value>().await.ok().and_then(|body| {
+        body.get("data")?.as_array().map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model.get("id")?.as_str().map(ToString::to_string))
+                .collect::<Vec<_>>()
+        })
+    });
+    let Some(ids) = ids else {
+        return vec![api, unverified(format!("{url} 的响应不是模型列表"))];
+    };
+    let model = if ids.contains(&llm.model) {
+        Check::new("模型", Status::Ok, llm.model.clone())
+    } else {
+        Check::new(
+            "模型",
+            Status::Fail,
+            format!(
+                "提供商的 {} 个模型中没有 {}. 请检查 llm.model 或 HOWLTO_MODEL",
+                ids.len(),
+                llm.model
+            ),
+        )
+    };
+    vec![api, model]
+}
+
+/// reqwest 的错误信息不包括底层原因 (DNS, TLS, 连接被拒绝等), 逐层展开.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// 构建 agent, 检查策略规则, 自定义工具和钩子脚本的配置.
+fn check_agent(config: AppConfig, profiles: &Profiles, shell: &Shell) -> Check {
+    let result = AnswerAgent::builder()
+        .profile(profiles.get(DEFAULT_PROFILE).cloned().unwrap_or_default())
+        .os(detect_os())
+        .shell(shell)
+        .config(config)
+        .build();
+    match result {
+        Ok(_) => Check::new("工具配置", Status::Ok, "策略规则, 自定义工具和钩子均有效"),
+        Err(error) => Check::new("工具配置", Status::Fail, error.to_string()),
+    }
+}
+
+async fn check_sandbox(backend: SandboxBackend) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if backend == SandboxBackend::None {
+        checks.push(Check::new(
+            "沙箱",
+            Status::Warn,
+            "已通过 `backend = \"none\"` 关闭, explore 的命令需要逐条确认",
+        ));
+    }
+    let probed = sandbox::probe_all();
+    let detail = if probed.is_empty() {
+        "没有找到任何沙箱后端".to_string()
+    } else {
+        probed
+            .iter()
+            .map(|(sb, result)| match result {
+                Ok(()) => format!("{}: 可用", sb.name()),
+                Err(error) => format!("{}: 不可用 ({error})", sb.name()),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let working = probed
+        .into_iter()
+        .find_map(|(sb, result)| result.is_ok().then_some(sb));
+    checks.push(Check::new(
+        "沙箱后端",
+        match (&working, backend) {
+            (Some(_), _) => Status::Ok,
+            (None, SandboxBackend::None) => Status::Warn,
+            (None, SandboxBackend::Auto) => Status::Fail,
+        },
+        detail,
+    ));
+    if let Some((enabled, sysctls)) = sandbox::user_namespaces() {
+        checks.push(Check::new(
+            "User namespace",
+            if enabled { Status::Ok } else { Status::Warn },
+            match (enabled, sysctls.is_empty()) {
+                (true, true) => "已启用".to_string(),
+                (true, false) => format!("已启用 ({sysctls})"),
+                (false, _) => {
+                    format!("已被禁用 ({sysctls}), Bubblewrap, nsjail 和 firejail 无法使用")
+                }
+            },
+        ));
+    }
+    if let Some(sb) = working {
+        checks.push(write_probe(&sb).await);
+    }
+    checks
+}
+
+/// 在沙箱里尝试写文件, 写入必须被拒绝.
+async fn write_probe(sb: &sandbox::Sandbox) -> Check {
+    let dir = std::env::temp_dir();
+    let target = dir.join(format!("howlto-doctor-{}", uuid::Uuid::new_v4()));
+    let options = SandboxOptions {
+        policy: SandboxPolicy::Default,
+        cwd: &dir,
+        network: false,
+        masked: &[],
+    };
+    let script = format!("echo howlto > '{}'", target.display());
+    let output = match sb.wrap(Path::new("/bin/sh"), &["-c".into(), script], &options) {
+        Ok(mut command) => command.output().await,
+        Err(error) => Err(error),
+    };
+    let written = target.exists();
+    let _ = fs::remove_file(&target).await;
+    match output {
+        _ if written => Check::new(
+            "写入探测",
+            Status::Fail,
+            format!("{} 在沙箱内写入了 {}", sb.name(), target.display()),
+        ),
+        Ok(output) if !output.status.success() => Check::new(
+            "写入探测",
+            Status::Ok,
+            format!("{} 拒绝了对 {} 的写入", sb.name(), dir.display()),
+        ),
+        Ok(output) => Check::new(
+            "写入探测",
+            Status::Fail,
+            format!("写入命令在沙箱内成功退出 ({})", output.status),
+        ),
+        Err(error) => Check::new("写入探测", Status::Fail, error.to_string()),
+    }
+}
+
+fn check_shell(shell: &Shell) -> Check {
+    let detail = format!("{} ({})", shell.name(), shell.path().display());
+    if shell.supports_integration() {
+        Check::new("Shell", Status::Ok, detail)
+    } else {
+        Check::new(
+            "Shell",
+            Status::Warn,
+            format!("{detail}, howlto 没有为它提供 shell 集成"),
+        )
+    }
+}
+
+fn check_clipboard() -> Check {
+    match clipboard_rs::ClipboardContext::new() {
+        Ok(_) => Check::new("剪贴板", Status::Ok, "可用"),
+        Err(error) => Check::new(
+            "剪贴板",
+            Status::Warn,
+            format!("不可用 ({error}), 无法复制候选命令"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("howlto-doctor-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).await.unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn config_errors_name_the_file_and_line() {
+        let dir = temp_dir().await;
+        fs::write(dir.join(CONFIG_TOML_FILE), "[llm]\nmodel = \n")
+            .await
+            .unwrap();
+        fs::write(dir.join(PROFILES_TOML_FILE), "[answer\n")
+            .await
+            .unwrap();
+        let report = run()
+            .config_dir(&dir)
+            .shell(&Shell::detect_shell())
+            .integration_loaded(false)
+            .call()
+            .await;
+        fs::remove_dir_all(&dir).await.unwrap();
+
+        assert!(report.has_failures());
+        let config = report.get("配置文件").unwrap();
+        assert_eq!(config.status, Status::Fail);
+        assert!(
+            config.detail.contains(CONFIG_TOML_FILE),
+            "{}",
+            config.detail
+        );
+        assert!(config.detail.contains("line 2"), "{}", config.detail);
+        let profiles = report.get("Profiles").unwrap();
+        assert_eq!(profiles.status, Status::Fail);
+        assert!(profiles.detail.contains("line 1"), "{}", profiles.detail);
+        assert_eq!(report.get("Shell 集成").unwrap().status, Status::Warn);
+    }
+
+    #[test]
+    fn provenance_names_the_source_of_each_value() {
+        let mut config = AppConfig::default();
+        config.llm.api_key = "sk-secret-value".to_string();
+        config.llm.model = "from-env".to_string();
+        config.env_overrides.push(("llm.model", "HOWLTO_MODEL"));
+        let file: toml::Table = "[llm]\napi_key = \"sk-secret-value\"\n".parse().unwrap();
+
+        let detail = provenance(&config, &file).detail;
+
+        assert!(
+            detail.contains("llm.api_key = sk-s**** (config.toml)"),
+            "{detail}"
+        );
+        assert!(
+            detail.contains("llm.model = from-env (环境变量 HOWLTO_MODEL)"),
+            "{detail}"
+        );
+        assert!(detail.contains("agent.language = en (默认值)"), "{detail}");
+        assert!(!detail.contains("sk-secret-value"));
+    }
+
+    #[tokio::test]
+    async fn sandbox_denies_the_write_probe() {
+        let Some(sb) = sandbox::detect() else {
+            return;
+        };
+        let check = write_probe(&sb).await;
+        assert_eq!(check.status, Status::Ok, "{}", check.detail);
+    }
+}
@@ -10,8 +10,8 @@ pub enum Error {
     TomlDeError(#[from] toml::de::Error),
     #[error(transparent)]
     JsonError(#[from] serde_json::Error),
-    #[error("Profile {profile} not found.")]
-    ProfileNotFound { profile: &'static str },
+    #[error("Profile `{profile}` not found. Available profiles: {available}.")]
+    ProfileNotFound { profile: String, available: String },
     #[error(transparent)]
     RigError(#[from] rig_core::http_client::Error),
     #[error(transparent)]
@@ -26,6 +26,14 @@ pub enum Error {
     ConfigVersion { version: u32, current: u32 },
     #[error("{0}")]
     ClipboardError(String),
+    #[error("Invalid policy rule `{rule}`: {message}")]
+    PolicyRule { rule: String, message: String },
+    #[error("Invalid custom tool `{tool}`: {message}")]
+    CustomTool { tool: String, message: String },
+    #[error("{location}: {message}")]
+    Template { location: String, message: String },
+    #[error("Hook script `{script}`: {message}")]
+    HookScript { script: String, message: String },
     #[error(transparent)]
     TokioJoinError(#[from] tokio::task::JoinError),
 }
@@ -33,7 +41,13 @@ pub enum Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl Error {
-    pub fn profile_not_found(profile: &'static str) -> Self {
-        Self::ProfileNotFound { profile }
+    pub fn profile_not_found<'a>(
+        profile: impl Into<String>,
+        available: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        Self::ProfileNotFound {
+            profile: profile.into(),
+            available: available.into_iter().collect::<Vec<_>>().join(", "),
+        }
     }
 }
@@ -0,0 +1,535 @@
+//! `howlto --eval <suite.toml>`: 用一组提示词检查 profile, shell 和模型的组合是否符合预期.
+//!
+//! 每个用例独立运行一次 [`AnswerAgent`], 按 `expect` 中的规则给结果打分.
+//! 人类可读的报告由 [`Display`] 输出, JSON 结果直接序列化 [`Report`].
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use regex::Regex;
+use rig_core::message::{AssistantContent, Message};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::{
+        answer::{AnswerAgent, AnswerAgentResponse},
+        detect_os,
+        submit_commands::{CommandSubmissions, SubmitCommands},
+    },
+    config::{AppConfig, profile::Profiles},
+    error::Result,
+    shell::Shell,
+};
+
+/// 不计入工具调用次数的工具, 候选命令的个数由 `min_commands` 和 `max_commands` 检查.
+const SUBMIT_TOOL: &str = "submit_commands";
+
+/// 评测集. 顶层的 `profile`, `shell`, `model` 和 `os` 是各用例的默认值.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Suite {
+    /// profile 名, 不指定时按提示词自动选择.
+    profile: Option<String>,
+    /// shell 名称或路径, 不指定时使用当前 shell.
+    shell: Option<String>,
+    /// 覆盖 `[llm] model`.
+    model: Option<String>,
+    /// system prompt 中的操作系统, 不指定时使用当前系统.
+    os: Option<String>,
+    cases: Vec<Case>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    prompt: String,
+    /// 模拟管道附加的输入.
+    attached: Option<String>,
+    profile: Option<String>,
+    shell: Option<String>,
+    model: Option<String>,
+    os: Option<String>,
+    #[serde(default)]
+    expect: Expect,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Expect {
+    /// 每个正则都至少匹配一个候选命令.
+    #[serde(default)]
+    commands: Vec<Pattern>,
+    /// 每个正则都匹配最终回答.
+    #[serde(default)]
+    text: Vec<Pattern>,
+    /// 任何候选命令和最终回答都不能匹配.
+    #[serde(default)]
+    forbid: Vec<Pattern>,
+    /// 用 shell 检查每个候选命令的语法.
+    #[serde(default = "default_syntax")]
+    syntax: bool,
+    min_commands: Option<usize>,
+    max_commands: Option<usize>,
+    /// 工具调用次数, 不含 `submit_commands`.
+    min_tool_calls: Option<usize>,
+    max_tool_calls: Option<usize>,
+    max_latency_ms: Option<u64>,
+}
+
+impl Default for Expect {
+    fn default() -> Self {
+        toml::from_str("").unwrap()
+    }
+}
+
+fn default_syntax() -> bool {
+    true
+}
+
+/// 加载时即编译的正则.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> std::result::Result<Self, Self::Error> {
+        Regex::new(&pattern).map(Self)
+    }
+}
+
+impl Suite {
+    pub async fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&tokio::fs::read_to_string(path).await?)?)
+    }
+}
+
+/// 一项检查的结果.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: impl Into<String>, passed: bool, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub profile: String,
+    pub shell: String,
+    pub model: String,
+    pub passed: bool,
+    pub latency_ms: u64,
+    /// 各工具的调用次数.
+    pub tool_calls: BTreeMap<String, usize>,
+    pub commands: Vec<String>,
+    pub text: String,
+    pub checks: Vec<CheckResult>,
+    /// 用例无法运行或 agent 出错时的错误信息.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub passed: usize,
+    pub failed: usize,
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn has_failures(&self) -> bool {
+        self.failed > 0
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            let status = if case.passed { "PASS" } else { "FAIL" };
+            let tool_calls = case
+                .tool_calls
+                .iter()
+                .filter(|(name, _)| *name != SUBMIT_TOOL)
+                .map(|(_, count)| count)
+                .sum::<usize>();
+            writeln!(
+                f,
+                "[{status}] {} ({}, {}, {}): {} ms, {tool_calls} 次工具调用, {} 个候选命令",
+                case.name,
+                case.profile,
+                case.shell,
+                case.model,
+                case.latency_ms,
+                case.commands.len()
+            )?;
+            if let Some(error) = &case.error {
+                writeln!(f, "       错误: {error}")?;
+            }
+            for check in case.checks.iter().filter(|check| !check.passed) {
+                let mut lines = check.detail.lines();
+                writeln!(
+                    f,
+                    "       {}: {}",
+                    check.name,
+                    lines.next().unwrap_or_default()
+                )?;
+                for line in lines {
+                    writeln!(f, "         {line}")?;
+                }
+            }
+        }
+        writeln!(f, "{} 个通过, {} 个失败", self.passed, self.failed)
+    }
+}
+
+/// 依次运行 `suite` 中的全部用例.
+///
+/// `config` 和 `profiles` 是加载好的配置, 每个用例在其副本上应用 profile 和覆盖项;
+/// `shell` 是用例没有指定 shell 时使用的 shell.
+#[bon::builder]
+pub async fn run(suite: &Suite, config: &AppConfig, profiles: &Profiles, shell: &Shell) -> Report {
+    let mut report = Report::default();
+    for case in &suite.cases {
+        let result = run_case(suite, case, config, profiles, shell).await;
+        if result.passed {
+            report.passed += 1;
+        } else {
+            report.failed += 1;
+        }
+        report.cases.push(result);
+    }
+    report
+}
+
+async fn run_case(
+    suite: &Suite,
+    case: &Case,
+    config: &AppConfig,
+    profiles: &Profiles,
+    default_shell: &Shell,
+) -> CaseResult {
+    // 用例中的设置优先于评测集顶层的默认值.
+    let pick = |case: &Option<String>, suite: &Option<String>| case.clone().or(suite.clone());
+    let mut result = CaseResult {
+        name: case.name.clone(),
+        profile: String::new(),
+        shell: default_shell.name().to_string(),
+        model: config.llm.model.clone(),
+        passed: false,
+        latency_ms: 0,
+        tool_calls: BTreeMap::new(),
+        commands: Vec::new(),
+        text: String::new(),
+        checks: Vec::new(),
+        error: None,
+    };
+
+    let (profile_name, profile) =
+        match profiles.select(pick(&case.profile, &suite.profile).as_deref(), &case.prompt) {
+            Ok(selected) => selected,
+            Err(error) => {
+                result.error = Some(error.to_string());
+                return result;
+            }
+        };
+    result.profile = profile_name.to_string();
+    let custom_shell = match pick(&case.shell, &suite.shell) {
+        Some(name) => match Shell::from_name(&name) {
+            Some(shell) => Some(shell),
+            None => {
+                result.shell = name.clone();
+                result.error = Some(format!("找不到 shell `{name}`"));
+                return result;
+            }
+        },
+        None => None,
+    };
+    let shell = custom_shell.as_ref().unwrap_or(default_shell);
+    result.shell = shell.name().to_string();
+
+    let mut config = config.clone();
+    profile.apply(&mut config);
+    if let Some(model) = pick(&case.model, &suite.model) {
+        config.llm.model = model;
+    }
+    result.model = config.llm.model.clone();
+    let agent = AnswerAgent::builder()
+        .os(pick(&case.os, &suite.os).unwrap_or_else(detect_os))
+        .shell(shell)
+        .profile(profile.clone())
+        .config(config)
+        .build();
+    let agent = match agent {
+        Ok(agent) => agent,
+        Err(error) => {
+            result.error = Some(error.to_string());
+            return result;
+        }
+    };
+
+    let started = Instant::now();
+    let response = agent
+        .resolve()
+        .prompt(case.prompt.clone())
+        .maybe_attached(case.attached.clone())
+        .call()
+        .await;
+    result.latency_ms = started.elapsed().as_millis() as u64;
+    match response {
+        Ok(response) => {
+            result.tool_calls = tool_calls(&response);
+            result.commands = response
+                .commands
+                .into_iter()
+                .map(|item| item.command)
+                .collect();
+            result.text = response.final_text;
+            result.checks = check(&case.expect, &result, shell).await;
+            result.passed = result.checks.iter().all(|check| check.passed);
+        }
+        Err(error) => result.error = Some(error.to_string()),
+    }
+    result
+}
+
+fn tool_calls(response: &AnswerAgentResponse) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for message in &response.messages {
+        let Message::Assistant { content, .. } = message else {
+            continue;
+        };
+        for content in content.iter() {
+            if let AssistantContent::ToolCall(call) = content {
+                *counts.entry(call.function.name.clone()).or_default() += 1;
+            }
+        }
+    }
+    counts
+}
+
+async fn check(expect: &Expect, result: &CaseResult, shell: &Shell) -> Vec<CheckResult> {
+    let mut checks = Vec::new();
+    for Pattern(pattern) in &expect.commands {
+        let matched = result
+            .commands
+            .iter()
+            .any(|command| pattern.is_match(command));
+        checks.push(CheckResult::new(
+            format!("commands /{pattern}/"),
+            matched,
+            if matched {
+                ""
+            } else {
+                "没有候选命令匹配"
+            },
+        ));
+    }
+    for Pattern(pattern) in &expect.text {
+        let matched = pattern.is_match(&result.text);
+        checks.push(CheckResult::new(
+            format!("text /{pattern}/"),
+            matched,
+            if matched { "" } else { "最终回答不匹配" },
+        ));
+    }
+    for Pattern(pattern) in &expect.forbid {
+        let hits = result
+            .commands
+            .iter()
+            .chain([&result.text])
+            .filter(|output| pattern.is_match(output))
+            .cloned()
+            .collect::<Vec<_>>();
+        checks.push(CheckResult::new(
+            format!("forbid /{pattern}/"),
+            hits.is_empty(),
+            hits.join("\n"),
+        ));
+    }
+    if expect.syntax {
+        let checker = SubmitCommands::new(
+            shell.path().to_path_buf(),
+            0,
+            Arc::new(CommandSubmissions::default()),
+        );
+        if checker.should_check_syntax() {
+            let mut errors = Vec::new();
+            for command in &result.commands {
+                if let Some(error) = checker.syntax_error(command).await {
+                    errors.push(format!("`{command}`: {error}"));
+                }
+            }
+            checks.push(CheckResult::new(
+                "syntax",
+                errors.is_empty(),
+                errors.join("\n"),
+            ));
+        }
+    }
+    let commands = result.commands.len();
+    checks.extend(range_check(
+        "commands",
+        commands,
+        expect.min_commands,
+        expect.max_commands,
+    ));
+    let tool_calls = result
+        .tool_calls
+        .iter()
+        .filter(|(name, _)| *name != SUBMIT_TOOL)
+        .map(|(_, count)| count)
+        .sum();
+    checks.extend(range_check(
+        "tool_calls",
+        tool_calls,
+        expect.min_tool_calls,
+        expect.max_tool_calls,
+    ));
+    if let Some(max) = expect.max_latency_ms {
+        let passed = result.latency_ms <= max;
+        checks.push(CheckResult::new(
+            "latency",
+            passed,
+            format!("{} ms, 上限 {max} ms", result.latency_ms),
+        ));
+    }
+    checks
+}
+
+fn range_check(
+    name: &str,
+    value: usize,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Option<CheckResult> {
+    if min.is_none() && max.is_none() {
+        return None;
+    }
+    let passed = min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max);
+    let range = match (min, max) {
+        (Some(min), Some(max)) => format!("{min}..={max}"),
+        (Some(min), None) => format!(">= {min}"),
+        (None, Some(max)) => format!("<= {max}"),
+        (None, None) => unreachable!(),
+    };
+    Some(CheckResult::new(
+        name,
+        passed,
+        format!("实际 {value}, 期望 {range}"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{CaseResult, Suite, check};
+    use crate::shell::Shell;
+
+    fn result(commands: &[&str], text: &str, tool_calls: &[(&str, usize)]) -> CaseResult {
+        CaseResult {
+            name: "case".to_string(),
+            profile: "command".to_string(),
+            shell: "sh".to_string(),
+            model: "mock".to_string(),
+            passed: false,
+            latency_ms: 1200,
+            tool_calls: tool_calls
+                .iter()
+                .map(|(name, count)| (name.to_string(), *count))
+                .collect::<BTreeMap<_, _>>(),
+            commands: commands.iter().map(ToString::to_string).collect(),
+            text: text.to_string(),
+            checks: Vec::new(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn suites_reject_invalid_patterns_and_unknown_fields() {
+        let suite: Suite = toml::from_str(
+            r#"
+profile = "command"
+shell = "bash"
+
+[[cases]]
+name = "list"
+prompt = "list files"
+model = "small"
+expect = { commands = ["^ls"], max_tool_calls = 2 }
+"#,
+        )
+        .unwrap();
+        assert_eq!(suite.cases.len(), 1);
+        assert_eq!(suite.profile.as_deref(), Some("command"));
+        assert_eq!(suite.cases[0].model.as_deref(), Some("small"));
+        assert!(suite.cases[0].expect.syntax);
+
+        let error = toml::from_str::<Suite>(
+            "[[cases]]\nname = \"a\"\nprompt = \"b\"\nexpect = { text = [\"(\"] }\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("regex parse error"));
+        assert!(
+            toml::from_str::<Suite>(
+                "[[cases]]\nname = \"a\"\nprompt = \"b\"\nexpect = { txt = [] }\n"
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn expectations_are_scored() {
+        let suite: Suite = toml::from_str(
+            r#"
+[[cases]]
+name = "a"
+prompt = "b"
+expect = { commands = ["^du "], text = ["size"], forbid = ["rm -rf"], max_commands = 1, min_tool_calls = 1, max_latency_ms = 1000 }
+"#,
+        )
+        .unwrap();
+        let shell = Shell::from_name("sh").unwrap();
+        let result = result(
+            &["du -sh * | sort -h", "echo 'unterminated"],
+            "Sorted by size. Never run rm -rf here.",
+            &[("explore", 1), ("submit_commands", 1)],
+        );
+        let checks = check(&suite.cases[0].expect, &result, &shell).await;
+        let status = checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.passed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            status,
+            [
+                ("commands /^du /", true),
+                ("text /size/", true),
+                ("forbid /rm -rf/", false),
+                ("syntax", false),
+                ("commands", false),
+                ("tool_calls", true),
+                ("latency", false),
+            ]
+        );
+    }
+}
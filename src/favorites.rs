@@ -0,0 +1,262 @@
+//! 收藏夹: 在选择界面中收藏的候选命令, 连同说明和当时的提示词保存在配置目录的 `favorites.toml`.
+//!
+//! 导出和导入使用相同的 TOML 格式, 团队可以共享一份收藏文件.
+//! agent 通过 `search_favorites` 工具检索收藏, 优先使用其中的规范用法.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::debug;
+
+use crate::error::Result;
+
+pub const FAVORITES_TOML_FILE: &str = "favorites.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Favorite {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// 收藏时的提示词.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub prompt: String,
+}
+
+/// 全部收藏, 按收藏的先后排列, 同一个命令只保留一项.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Favorites {
+    #[serde(default, rename = "favorite")]
+    pub items: Vec<Favorite>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upsert {
+    Added,
+    Updated,
+    Unchanged,
+}
+
+impl Favorites {
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn contains(&self, command: &str) -> bool {
+        self.items.iter().any(|item| item.command == command)
+    }
+
+    /// 加入一项收藏. 命令已存在时用非空的说明和提示词更新它.
+    pub fn upsert(&mut self, favorite: Favorite) -> Upsert {
+        let Some(item) = self
+            .items
+            .iter_mut()
+            .find(|item| item.command == favorite.command)
+        else {
+            self.items.push(favorite);
+            return Upsert::Added;
+        };
+        let before = item.clone();
+        if !favorite.description.is_empty() {
+            item.description = favorite.description;
+        }
+        if !favorite.prompt.is_empty() {
+            item.prompt = favorite.prompt;
+        }
+        if *item == before {
+            Upsert::Unchanged
+        } else {
+            Upsert::Updated
+        }
+    }
+
+    /// 删除命令为 `command` 的收藏, 返回是否存在.
+    pub fn remove_command(&mut self, command: &str) -> bool {
+        let len = self.items.len();
+        self.items.retain(|item| item.command != command);
+        self.items.len() != len
+    }
+
+    /// 合并 `other` 中的收藏, 返回新增和更新的数量.
+    pub fn merge(&mut self, other: Favorites) -> (usize, usize) {
+        let mut added = 0;
+        let mut updated = 0;
+        for favorite in other.items {
+            match self.upsert(favorite) {
+                Upsert::Added => added += 1,
+                Upsert::Updated => updated += 1,
+                Upsert::Unchanged => {}
+            }
+        }
+        (added, updated)
+    }
+
+    /// 按关键词检索, 返回最相关的至多 `limit` 项. `query` 为空时按顺序返回.
+    ///
+    /// 每个关键词出现在命令中计 2 分, 出现在说明或提示词中计 1 分, 不区分大小写.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<&Favorite> {
+        let terms = query
+            .split(|character: char| character.is_whitespace() || ",;|".contains(character))
+            .filter(|term| !term.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            return self.items.iter().take(limit).collect();
+        }
+        let mut scored = self
+            .items
+            .iter()
+            .filter_map(|item| {
+                let command = item.command.to_lowercase();
+                let context = format!("{}\n{}", item.description, item.prompt).to_lowercase();
+                let score = terms
+                    .iter()
+                    .map(|term| {
+                        2 * usize::from(command.contains(term.as_str()))
+                            + usize::from(context.contains(term.as_str()))
+                    })
+                    .sum::<usize>();
+                (score > 0).then_some((score, item))
+            })
+            .collect::<Vec<_>>();
+        // 稳定排序, 同分时保持收藏顺序.
+        scored.sort_by(|(left, _), (right, _)| right.cmp(left));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+/// 配置目录中的收藏文件.
+#[derive(Debug, Clone)]
+pub struct FavoriteStore {
+    path: PathBuf,
+}
+
+impl FavoriteStore {
+    pub fn new(config_dir: impl AsRef<Path>) -> Self {
+        Self {
+            path: config_dir.as_ref().join(FAVORITES_TOML_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 读取全部收藏, 文件不存在时为空.
+    pub async fn load(&self) -> Result<Favorites> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => Favorites::parse(&content),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Favorites::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// 是否有收藏, 读取失败时视为没有.
+    pub async fn has_favorites(&self) -> bool {
+        self.load()
+            .await
+            .is_ok_and(|favorites| !favorites.is_empty())
+    }
+
+    /// 先写入临时文件再替换, 避免中断时留下不完整的文件.
+    pub async fn save(&self, favorites: &Favorites) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("toml.tmp");
+        fs::write(&tmp, favorites.to_toml()?).await?;
+        fs::rename(&tmp, &self.path).await?;
+        debug!(path = %self.path.display(), count = favorites.items.len(), "Favorites saved.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{Favorite, FavoriteStore, Favorites};
+
+    fn favorite(command: &str, description: &str, prompt: &str) -> Favorite {
+        Favorite {
+            command: command.to_string(),
+            description: description.to_string(),
+            prompt: prompt.to_string(),
+        }
+    }
+
+    #[test]
+    fn search_ranks_command_matches_first() {
+        let favorites = Favorites {
+            items: vec![
+                favorite("kubectl get pods -A", "all pods", "list pods"),
+                favorite("docker ps -a", "containers, not pods", ""),
+                favorite("git log --oneline", "", "short history"),
+            ],
+        };
+        let commands = |query| {
+            favorites
+                .search(query, 5)
+                .into_iter()
+                .map(|item| item.command.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(commands("PODS"), ["kubectl get pods -A", "docker ps -a"]);
+        assert_eq!(commands("history"), ["git log --oneline"]);
+        assert!(commands("terraform").is_empty());
+        assert_eq!(favorites.search("", 2).len(), 2);
+    }
+
+    #[test]
+    fn merging_adds_new_and_updates_existing_commands() {
+        let mut favorites = Favorites {
+            items: vec![favorite("ls -la", "", "list")],
+        };
+        let shared = Favorites::parse(
+            r#"
+[[favorite]]
+command = "ls -la"
+description = "long listing"
+
+[[favorite]]
+command = "du -sh *"
+"#,
+        )
+        .unwrap();
+        assert_eq!(favorites.merge(shared.clone()), (1, 1));
+        assert_eq!(
+            favorites.items[0],
+            favorite("ls -la", "long listing", "list")
+        );
+        assert_eq!(favorites.merge(shared), (0, 0));
+        assert!(favorites.remove_command("du -sh *"));
+        assert!(!favorites.contains("du -sh *"));
+        assert!(Favorites::parse("[[favorite]]\ncmd = \"ls\"\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn store_round_trips_and_defaults_to_empty() {
+        let dir = std::env::temp_dir().join(format!("howlto-favorites-test-{}", Uuid::new_v4()));
+        let store = FavoriteStore::new(&dir);
+        assert!(store.load().await.unwrap().is_empty());
+        let mut favorites = Favorites::default();
+        favorites.upsert(favorite("printf 'a b'", "quoted", "print"));
+        store.save(&favorites).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), favorites);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -1,6 +1,9 @@
 pub mod agent;
 pub mod config;
+pub mod doctor;
 pub mod error;
+pub mod eval;
+pub mod favorites;
 pub mod logging;
 pub mod session;
 pub mod shell;
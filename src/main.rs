@@ -4,37 +4,127 @@ use std::path::PathBuf;
 use anyhow::Context;
 use clap::Parser;
 use crossterm::tty::IsTty;
+use howlto::agent::detect_os;
+use howlto::agent::explain::ExplainAgent;
+use howlto::agent::transform::TransformAgent;
+use howlto::agent::translate::{TranslateAgent, suffixed_path};
 use howlto::config::AppConfigLoader;
 use howlto::config::CONFIG_TOML_FILE;
 use howlto::config::DEFAULT_CONFIG_DIR;
 use howlto::config::DEFAULT_OPENAI_BASE_URL;
+use howlto::config::profile::{
+    COMPLETION_PROFILE, EXPLAIN_PROFILE, TRANSFORM_PROFILE, TRANSLATE_PROFILE,
+};
+use howlto::favorites::{FavoriteStore, Favorites};
 use howlto::logging;
 use howlto::session::{Session, SessionStore};
 use howlto::shell::Shell;
 use howlto::tui;
-use tracing::warn;
+use tracing::{info, warn};
 use tokio::io::AsyncReadExt;
 
 #[derive(clap::Parser)]
 #[clap(about = "一个能帮你找到心仪命令的 CLI 工具.", long_about=None, version = env!("HOWLTO_VERSION"), author)]
+#[clap(group = clap::ArgGroup::new("mode").multiple(false))]
+// 各种模式都是互斥的 flag, 不使用子命令, 以免吞掉以 `eval`, `doctor` 等词开头的提示词.
 struct AppArgs {
     /// 命令生成提示词, 当其为空的时候, 进入交互模式.
     #[clap(num_args=0..)]
     prompt: Vec<String>,
-    #[clap(short, long, help = "配置文件所在的目录", default_value = DEFAULT_CONFIG_DIR)]
+    #[clap(short, long, global = true, help = "配置文件所在的目录", default_value = DEFAULT_CONFIG_DIR)]
     config: PathBuf,
     #[clap(short, long, help = "直接输出所有候选命令, 无需交互选择.")]
     plain: bool,
-    #[clap(short, long, help = "不在标准错误流输出进度信息.")]
+    #[clap(short, long, global = true, help = "不在标准错误流输出进度信息.")]
     quiet: bool,
-    #[clap(long, help = "输出额外的调试信息, 比如工具调用的结果")]
+    #[clap(
+        short = 'i',
+        long,
+        group = "mode",
+        help = "按提示词转换标准输入, 只把结果输出到标准输出, 默认使用 transform profile."
+    )]
+    transform: bool,
+    #[clap(
+        long,
+        group = "mode",
+        help = "翻译标准输入或 --file 指定的文件, 提示词作为附加要求, 默认使用 translate profile."
+    )]
+    translate: bool,
+    #[clap(
+        long,
+        group = "mode",
+        help = "按帮助和 man 手册逐项解释提示词中的命令, 输出带说明的树, 默认使用 explain profile."
+    )]
+    explain: bool,
+    #[clap(long = "file", value_name = "PATH", requires = "translate", help = "要翻译的文件, 可以指定多次.")]
+    files: Vec<PathBuf>,
+    #[clap(long, value_name = "LANG", requires = "translate", help = "翻译的目标语言, 默认使用 [agent] language.")]
+    to: Option<String>,
+    #[clap(
+        long,
+        value_name = "SUFFIX",
+        requires = "files",
+        help = "把译文写到原文件旁边并加上后缀, 比如 README.md -> README.en.md, 不指定时输出到标准输出."
+    )]
+    suffix: Option<String>,
+    #[clap(long, global = true, help = "输出额外的调试信息, 比如工具调用的结果")]
     debug: bool,
-    #[clap(long, help = "输出 shell 集成初始化脚本")]
+    #[clap(long, group = "mode", help = "输出 shell 集成初始化脚本")]
     init: bool,
-    #[clap(long, help = "创建缺失的默认 config.toml 和 profiles.toml, 不覆盖已有文件.")]
+    #[clap(long, group = "mode", help = "创建缺失的默认 config.toml 和 profiles.toml, 不覆盖已有文件.")]
     init_config: bool,
+    #[clap(long, help = "使用 profiles.toml 中指定名称的 profile, 不指定时根据提示词自动选择.")]
+    profile: Option<String>,
+    #[clap(long, group = "mode", help = "检查配置, API, 沙箱和 shell 集成等是否正常工作.")]
+    doctor: bool,
     #[clap(long, help = "[Shell 集成参数]")]
     htcmd_file: Option<PathBuf>,
+    #[clap(long, value_name = "SUITE", group = "mode", help = "运行提示词评测集 (TOML), 报告输出到标准错误流, JSON 结果输出到标准输出.")]
+    eval: Option<PathBuf>,
+    #[clap(long, value_name = "PROGRAM", group = "mode", help = "阅读程序的帮助和 man 手册, 生成补全脚本并在确认后安装.")]
+    completion: Option<String>,
+    #[clap(
+        long = "shell",
+        value_name = "SHELL",
+        requires = "completion",
+        help = "补全脚本的目标 shell (fish, bash, zsh 或 nu), 默认使用当前 shell."
+    )]
+    completion_shell: Option<String>,
+    #[clap(long, requires = "completion", help = "只把补全脚本输出到标准输出, 不安装.")]
+    print: bool,
+    #[clap(
+        long,
+        num_args = 0..,
+        value_name = "ACTION",
+        group = "mode",
+        help = "管理在选择界面中按 f 收藏的命令: list (默认), edit, delete <N>..., export [PATH], import <PATH>."
+    )]
+    favorites: Option<Vec<String>>,
+}
+
+/// `--favorites` 之后的动作, 单独解析以复用子命令的参数检查和帮助.
+#[derive(clap::Parser)]
+#[clap(name = "howlto --favorites")]
+struct FavoritesArgs {
+    #[command(subcommand)]
+    action: Option<FavoritesAction>,
+}
+
+#[derive(clap::Subcommand)]
+enum FavoritesAction {
+    /// 列出全部收藏.
+    List,
+    /// 用 $VISUAL 或 $EDITOR 编辑收藏文件, 保存后检查格式.
+    Edit,
+    /// 按 list 中的序号删除收藏.
+    Delete {
+        #[clap(required = true, value_name = "N")]
+        numbers: Vec<usize>,
+    },
+    /// 导出全部收藏, 不指定路径时输出到标准输出.
+    Export { path: Option<PathBuf> },
+    /// 从共享的 TOML 文件导入收藏, 已有的命令会更新说明和提示词.
+    Import { path: PathBuf },
 }
 
 #[tokio::main]
@@ -44,10 +134,23 @@ async fn main() -> anyhow::Result<()> {
         config: config_dir,
         plain,
         quiet,
+        transform,
+        translate,
+        explain,
+        files,
+        to,
+        suffix,
         init,
         init_config,
+        doctor,
+        profile,
         htcmd_file,
         debug,
+        eval,
+        completion,
+        completion_shell,
+        print,
+        favorites,
     } = AppArgs::parse();
 
     let shell = Shell::detect_shell();
@@ -88,17 +191,77 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let config = config_loader
+    if doctor {
+        let report = howlto::doctor::run()
+            .config_dir(&config_dir)
+            .shell(&shell)
+            .integration_loaded(htcmd_file.is_some())
+            .call()
+            .await;
+        print!("{report}");
+        if report.has_failures() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // 收藏管理不依赖配置文件, 配置有误时也可以使用.
+    if let Some(action) = favorites {
+        let args = std::iter::once("howlto --favorites".to_string()).chain(action);
+        let action = FavoritesArgs::try_parse_from(args)
+            .unwrap_or_else(|error| error.exit())
+            .action
+            .unwrap_or(FavoritesAction::List);
+        return favorites_command(FavoriteStore::new(&config_dir), action).await;
+    }
+
+    let mut config = config_loader
         .load_config()
         .await
         .with_context(|| format!("无法加载配置: {}", config_dir.display()))?;
     let profiles = config_loader
-        .load_profiles()
+        .load_profiles(&config.vars)
         .await
         .with_context(|| format!("无法加载 Profiles: {}", config_dir.display()))?;
 
-    // 提前检查
-    if config.llm.base_url.is_empty() {
+    if let Some(suite) = &eval {
+        let suite = howlto::eval::Suite::load(suite)
+            .await
+            .with_context(|| format!("无法加载评测集: {}", suite.display()))?;
+        let _guard = logging::init(&config_dir, !quiet, debug)
+            .await
+            .with_context(|| format!("无法初始化日志: {}", config_dir.display()))?;
+        let report = howlto::eval::run()
+            .suite(&suite)
+            .config(&config)
+            .profiles(&profiles)
+            .shell(&shell)
+            .call()
+            .await;
+        eprint!("{report}");
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if report.has_failures() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let prompt = prompt.join(" ");
+    let profile = profile
+        .as_deref()
+        .or(transform.then_some(TRANSFORM_PROFILE))
+        .or(translate.then_some(TRANSLATE_PROFILE))
+        .or(explain.then_some(EXPLAIN_PROFILE))
+        .or(completion.is_some().then_some(COMPLETION_PROFILE));
+    let (profile_name, profile) = profiles.select(profile, &prompt)?;
+    let profile = profile.clone();
+    profile.apply(&mut config);
+    if let Some(to) = to {
+        config.agent.language = to;
+    }
+
+    // 提前检查. `--explain` 没有模型时只输出帮助和 man 手册中的说明, 不需要这些设置.
+    if config.llm.base_url.is_empty() && !explain {
         Err(anyhow::anyhow!(
             "LLM Base URL 为空. 请设置 HOWLTO_BASE_URL 或 OPENAI_BASE_URL, 或运行 `howlto --init-config` 后编辑: {}",
             config_dir.join(CONFIG_TOML_FILE).display()
@@ -106,6 +269,7 @@ async fn main() -> anyhow::Result<()> {
     }
     if config.llm.api_key.is_empty()
         && config.llm.base_url.trim_end_matches('/') == DEFAULT_OPENAI_BASE_URL
+        && !explain
     {
         Err(anyhow::anyhow!(
             "LLM API key 为空. 请设置 HOWLTO_API_KEY 或 OPENAI_API_KEY, 或运行 `howlto --init-config` 后编辑: {}",
@@ -116,18 +280,114 @@ async fn main() -> anyhow::Result<()> {
     let _guard = logging::init(&config_dir, !quiet, debug)
         .await
         .with_context(|| format!("无法初始化日志: {}", config_dir.display()))?;
+    info!(profile = profile_name, "Using profile.");
+
+    if let Some(program) = completion {
+        let target = match completion_shell {
+            Some(name) => Shell::from_name(&name).with_context(|| format!("找不到 shell: {name}"))?,
+            None => shell,
+        };
+        tui::completion::run()
+            .program(&program)
+            .shell(&target)
+            .profile(profile)
+            .config(config)
+            .print(print)
+            .call()
+            .await?;
+        return Ok(());
+    }
+
+    if transform {
+        let mut stdin = tokio::io::stdin();
+        if prompt.is_empty() {
+            Err(anyhow::anyhow!("-i 需要提示词来说明如何转换输入"))?
+        }
+        if stdin.is_tty() {
+            Err(anyhow::anyhow!("-i 需要从标准输入读取待转换的内容"))?
+        }
+        let mut input = String::new();
+        stdin.read_to_string(&mut input).await?;
+        if input.trim().is_empty() {
+            Err(anyhow::anyhow!("标准输入为空, 没有可转换的内容"))?
+        }
+        let agent = TransformAgent::builder()
+            .os(detect_os())
+            .shell(&shell)
+            .profile(profile)
+            .config(config)
+            .build()?;
+        agent
+            .transform(&prompt, &input, &mut io::stdout().lock())
+            .await?;
+        return Ok(());
+    }
+
+    if explain {
+        if prompt.trim().is_empty() {
+            Err(anyhow::anyhow!("--explain 需要要解释的命令"))?
+        }
+        let agent = ExplainAgent::builder().shell(&shell).config(config).build()?;
+        print!("{}", agent.explain(&prompt).await?);
+        return Ok(());
+    }
+
+    if translate {
+        let agent = TranslateAgent::builder()
+            .os(detect_os())
+            .shell(&shell)
+            .profile(profile)
+            .config(config)
+            .build()?;
+        if files.is_empty() {
+            let mut stdin = tokio::io::stdin();
+            if stdin.is_tty() {
+                Err(anyhow::anyhow!("--translate 需要从标准输入读取内容, 或用 --file 指定文件"))?
+            }
+            let mut input = String::new();
+            stdin.read_to_string(&mut input).await?;
+            if input.trim().is_empty() {
+                Err(anyhow::anyhow!("标准输入为空, 没有可翻译的内容"))?
+            }
+            agent
+                .translate(&input, &prompt, &mut io::stdout().lock())
+                .await?;
+        }
+        for path in &files {
+            let input = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("无法读取文件: {}", path.display()))?;
+            let Some(suffix) = &suffix else {
+                agent
+                    .translate(&input, &prompt, &mut io::stdout().lock())
+                    .await
+                    .with_context(|| format!("无法翻译: {}", path.display()))?;
+                continue;
+            };
+            let mut output = Vec::new();
+            agent
+                .translate(&input, &prompt, &mut output)
+                .await
+                .with_context(|| format!("无法翻译: {}", path.display()))?;
+            let target = suffixed_path(path, suffix);
+            tokio::fs::write(&target, output)
+                .await
+                .with_context(|| format!("无法写入文件: {}", target.display()))?;
+            println!("已写入: {}", target.display());
+        }
+        return Ok(());
+    }
 
     if prompt.is_empty() {
         tui::chatter::run()
             .config_dir(config_dir.clone())
             .config(config)
-            .profiles(profiles)
+            .profile(profile)
             .shell(&shell)
             .maybe_htcmd_file(htcmd_file)
             .call()
             .await?;
     } else {
-        let prompt: String = prompt.join(" ");
         // attach stdin
         let mut stdin = tokio::io::stdin();
         let attached = if !stdin.is_tty() {
@@ -144,9 +404,10 @@ async fn main() -> anyhow::Result<()> {
             .prompt(&prompt)
             .maybe_htcmd_file(htcmd_file)
             .shell(&shell)
-            .profiles(profiles)
+            .profile(profile)
             .plain(plain)
             .maybe_attached(attached)
+            .favorites(FavoriteStore::new(&config_dir))
             .call()
             .await?;
         let cwd = std::env::current_dir()?;
@@ -158,3 +419,86 @@ async fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+async fn favorites_command(store: FavoriteStore, action: FavoritesAction) -> anyhow::Result<()> {
+    let load = || async {
+        store
+            .load()
+            .await
+            .with_context(|| format!("无法读取收藏: {}", store.path().display()))
+    };
+    match action {
+        FavoritesAction::List => {
+            let favorites = load().await?;
+            if favorites.is_empty() {
+                println!("还没有收藏, 在选择界面中按 f 收藏候选命令.");
+            }
+            for (index, favorite) in favorites.items.iter().enumerate() {
+                println!("{}. {}", index + 1, favorite.command.replace('\n', "\n   "));
+                if !favorite.description.is_empty() {
+                    println!("   说明: {}", favorite.description);
+                }
+                if !favorite.prompt.is_empty() {
+                    println!("   提示词: {}", favorite.prompt);
+                }
+            }
+        }
+        FavoritesAction::Edit => {
+            if !store.path().exists() {
+                store.save(&Favorites::default()).await?;
+            }
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+            let mut words = editor.split_whitespace();
+            let program = words.next().context("$EDITOR 为空")?;
+            let status = tokio::process::Command::new(program)
+                .args(words)
+                .arg(store.path())
+                .status()
+                .await
+                .with_context(|| format!("无法启动编辑器: {editor}"))?;
+            anyhow::ensure!(status.success(), "编辑器异常退出: {status}");
+            let favorites = load().await?;
+            println!("共 {} 项收藏.", favorites.items.len());
+        }
+        FavoritesAction::Delete { mut numbers } => {
+            let mut favorites = load().await?;
+            let total = favorites.items.len();
+            if let Some(number) = numbers.iter().find(|number| !(1..=total).contains(*number)) {
+                anyhow::bail!("没有序号为 {number} 的收藏, 共 {total} 项");
+            }
+            numbers.sort_unstable();
+            numbers.dedup();
+            for number in numbers.into_iter().rev() {
+                let favorite = favorites.items.remove(number - 1);
+                println!("已删除: {}", favorite.command);
+            }
+            store.save(&favorites).await?;
+        }
+        FavoritesAction::Export { path } => {
+            let content = load().await?.to_toml()?;
+            match path {
+                Some(path) => {
+                    tokio::fs::write(&path, content)
+                        .await
+                        .with_context(|| format!("无法写入文件: {}", path.display()))?;
+                    println!("已导出: {}", path.display());
+                }
+                None => print!("{content}"),
+            }
+        }
+        FavoritesAction::Import { path } => {
+            let content = tokio::fs::read_to_string(&path)
+                .await
+                .with_context(|| format!("无法读取文件: {}", path.display()))?;
+            let shared = Favorites::parse(&content)
+                .with_context(|| format!("收藏文件格式错误: {}", path.display()))?;
+            let mut favorites = load().await?;
+            let (added, updated) = favorites.merge(shared);
+            store.save(&favorites).await?;
+            println!("已导入: 新增 {added} 项, 更新 {updated} 项.");
+        }
+    }
+    Ok(())
+}
@@ -10,7 +10,7 @@ use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use crate::{
-    agent::{answer::AnswerAgentResponse, submit_commands::CommandItem},
+    agent::{answer::AnswerAgentResponse, grant::ElevateGrant, submit_commands::CommandItem},
     config::SessionConfig,
     error::Result,
 };
@@ -27,6 +27,8 @@ pub struct Session {
     pub final_text: String,
     pub commands: Vec<CommandItem>,
     pub messages: Vec<Message>,
+    #[serde(default)]
+    pub grants: Vec<ElevateGrant>,
 }
 
 impl Session {
@@ -41,6 +43,7 @@ impl Session {
             final_text: response.final_text.clone(),
             commands: response.commands.clone(),
             messages: response.messages.clone(),
+            grants: response.grants.clone(),
         }
     }
 
@@ -52,6 +55,7 @@ impl Session {
         self.final_text = response.final_text.clone();
         self.commands = response.commands.clone();
         self.messages = response.messages.clone();
+        self.grants = response.grants.clone();
     }
 }
 
@@ -386,6 +390,7 @@ mod tests {
             messages: vec![Message::User {
                 content: OneOrMany::one(UserContent::Text(Text::new(prompt))),
             }],
+            grants: Vec::new(),
         }
     }
 
@@ -395,12 +400,17 @@ mod tests {
         let store = SessionStore::new(&root, SessionConfig::default());
         let cwd = std::env::current_dir().unwrap();
         let mut session = sample_session(&cwd, "first", now_seconds());
+        session.grants.push(ElevateGrant::Command {
+            command: "cargo build".into(),
+            env: Some(vec!["RUSTFLAGS".into()]),
+        });
 
         store.save(&mut session).await.unwrap();
         let loaded = store.load(&cwd, session.id).await.unwrap().unwrap();
         assert_eq!(loaded.last_prompt, "first");
         assert_eq!(loaded.commands[0].command, "printf ok");
         assert_eq!(loaded.messages.len(), 1);
+        assert_eq!(loaded.grants, session.grants);
 
         let summaries = store.list(&cwd).await.unwrap();
         assert_eq!(summaries.len(), 1);
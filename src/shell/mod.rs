@@ -6,6 +6,8 @@ use std::{
 use sysinfo::{ProcessRefreshKind, RefreshKind, System, get_current_pid};
 use tracing::debug;
 
+pub mod parse;
+
 mod init_scripts {
     use crate::error::Result;
 
@@ -155,6 +157,25 @@ fn shell_from_path(path: PathBuf) -> Shell {
         .build()
 }
 
+/// 按 `shell_name` 对应 shell 的语法把 `value` 引用为单个参数, 其中的任何字符都不会被解释.
+pub fn quote(shell_name: &str, value: &str) -> String {
+    match shell_name.trim_end_matches(".exe") {
+        "fish" => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+        "nu" => {
+            // raw string 以 `'` 加上与开头相同数量的 `#` 结束, `#` 需要比值中最长的连续 `#` 多.
+            let longest = value
+                .split(|character| character != '#')
+                .map(str::len)
+                .max()
+                .unwrap_or(0);
+            let hashes = "#".repeat(longest + 1);
+            format!("r{hashes}'{value}'{hashes}")
+        }
+        "pwsh" | "powershell" => format!("'{}'", value.replace('\'', "''")),
+        _ => format!("'{}'", value.replace('\'', "'\\''")),
+    }
+}
+
 #[bon::bon]
 impl Shell {
     #[builder]
@@ -176,10 +197,23 @@ impl Shell {
         &self.name
     }
 
+    /// `howlto --init` 是否能为当前 shell 输出集成脚本.
+    pub fn supports_integration(&self) -> bool {
+        matches!(
+            self.integration,
+            Some(Integration::Fish | Integration::Bash | Integration::Zsh | Integration::Nushell)
+        )
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    /// 按名称 (在 `PATH` 中查找) 或路径指定 shell, 找不到可执行文件时返回 [`None`].
+    pub fn from_name(name: &str) -> Option<Shell> {
+        which::which(name).ok().map(shell_from_path)
+    }
+
     /// 获取当前 shell 的字符串表示和可执行文件路径.
     pub fn detect_shell() -> Shell {
         let default_shell_path: PathBuf = std::env::var("SHELL").unwrap_or("/bin/sh".into()).into();
@@ -224,7 +258,7 @@ impl Shell {
 mod tests {
     use std::path::PathBuf;
 
-    use super::{Integration, shell_from_path};
+    use super::{Integration, quote, shell_from_path};
 
     #[test]
     fn fallback_shell_keeps_known_integration() {
@@ -233,4 +267,12 @@ mod tests {
         assert_eq!(shell.integration, Some(Integration::Fish));
         assert_eq!(shell.name(), "fish");
     }
+
+    #[test]
+    fn quoting_follows_shell_syntax() {
+        assert_eq!(quote("bash", "it's $HOME"), r"'it'\''s $HOME'");
+        assert_eq!(quote("fish", r"it's \n"), r"'it\'s \\n'");
+        assert_eq!(quote("nu", "a'## b"), "r###'a'## b'###");
+        assert_eq!(quote("pwsh.exe", "it's"), "'it''s'");
+    }
 }
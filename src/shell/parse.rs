@@ -0,0 +1,500 @@
+//! 轻量的 shell 命令切分.
+//!
+//! 不是完整的 shell 解析器, 只用于把一条命令拆成可以独立判断的片段 (管道, `&&`, `||`, `;`,
+//! 换行, 后台 `&`, 子 shell `( ... )` 和命令组 `{ ...; }`, 以及 `$(...)` / 反引号 /
+//! `<(...)` 中的子命令), 供命令策略等逻辑按片段匹配;
+//! 以及按单词, 控制操作符和重定向切分 ([`tokens`]), 供解释命令时使用.
+
+/// 片段开头会被忽略的 shell 关键字, 避免 `if git push` 或 fish 的 `and git push` 绕过规则.
+/// `{` 和 `}` 是命令组的开头和结尾.
+const LEADING_KEYWORDS: [&str; 14] = [
+    "!", "if", "then", "elif", "else", "while", "until", "do", "and", "or", "not", "time", "{", "}",
+];
+
+/// [`split`] 拆出的片段.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// 剥离开头的变量赋值和关键字后的命令.
+    pub command: String,
+    /// 开头的变量赋值, 如 `LD_PRELOAD=/tmp/x.so`.
+    pub assignments: Vec<String>,
+}
+
+impl Segment {
+    /// 包含变量赋值的完整片段.
+    pub fn full(&self) -> String {
+        let mut words = self.assignments.clone();
+        words.push(self.command.clone());
+        words.join(" ")
+    }
+}
+
+/// 把命令拆成独立的片段, 只保留剥离变量赋值后的命令, 见 [`split`].
+pub fn segments(command: &str) -> Vec<String> {
+    split(command)
+        .into_iter()
+        .map(|segment| segment.command)
+        .collect()
+}
+
+/// 把命令拆成独立的片段.
+///
+/// 每个片段会去掉首尾空白, 合并连续空白, 并把开头的环境变量赋值 (`FOO=1 cmd`)
+/// 单独记录, 剥离 [`LEADING_KEYWORDS`]. 子 shell 的括号是片段边界, 其中的命令是普通片段;
+/// 命令替换和进程替换中的子命令 (包括多层嵌套的) 作为额外的片段追加在后面.
+pub fn split(command: &str) -> Vec<Segment> {
+    let mut output = Vec::new();
+    let mut nested = Vec::new();
+    split_into(command, &mut output, &mut nested);
+    output.extend(nested);
+    output
+}
+
+/// 拆分替换中的子命令, 把各层的片段都追加到 `nested`.
+fn split_nested(inner: &str, nested: &mut Vec<Segment>) {
+    let mut deeper = Vec::new();
+    split_into(inner, nested, &mut deeper);
+    nested.extend(deeper);
+}
+
+fn split_into(command: &str, output: &mut Vec<Segment>, nested: &mut Vec<Segment>) {
+    let characters = command.chars().collect::<Vec<_>>();
+    let mut current = String::new();
+    let mut index = 0;
+    let mut single_quoted = false;
+    let mut double_quoted = false;
+    while index < characters.len() {
+        let character = characters[index];
+        if single_quoted {
+            current.push(character);
+            single_quoted = character != '\'';
+            index += 1;
+            continue;
+        }
+        match character {
+            '\\' => {
+                current.push(character);
+                if let Some(next) = characters.get(index + 1) {
+                    current.push(*next);
+                }
+                index += 2;
+                continue;
+            }
+            '\'' if !double_quoted => single_quoted = true,
+            '"' => double_quoted = !double_quoted,
+            // 命令替换 `$(...)` 和进程替换 `<(...)`, `>(...)`.
+            '$' | '<' | '>'
+                if characters.get(index + 1) == Some(&'(')
+                    && (character == '$' || !double_quoted) =>
+            {
+                let end = matching_paren(&characters, index + 1);
+                let inner = characters[index + 2..end.min(characters.len())]
+                    .iter()
+                    .collect::<String>();
+                split_nested(&inner, nested);
+                current.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            // 数组赋值 `a=(1 2)` 中的括号不是子 shell.
+            '(' if !double_quoted && current.ends_with('=') => {
+                let end = matching_paren(&characters, index);
+                current.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            '(' | ')' if !double_quoted => {
+                push_segment(&mut current, output);
+                index += 1;
+                continue;
+            }
+            '`' => {
+                let end = characters[index + 1..]
+                    .iter()
+                    .position(|character| *character == '`')
+                    .map(|offset| index + 1 + offset)
+                    .unwrap_or(characters.len());
+                let inner = characters[index + 1..end].iter().collect::<String>();
+                split_nested(&inner, nested);
+                current.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            '|' | ';' | '\n' if !double_quoted => {
+                push_segment(&mut current, output);
+                index += 1;
+                continue;
+            }
+            '&' if !double_quoted && !is_redirection_ampersand(&characters, index) => {
+                push_segment(&mut current, output);
+                index += 1;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(character);
+        index += 1;
+    }
+    push_segment(&mut current, output);
+}
+
+/// `2>&1`, `>&2`, `<&0`, `&>file` 中的 `&` 属于重定向, 不是分隔符.
+fn is_redirection_ampersand(characters: &[char], index: usize) -> bool {
+    let previous = index.checked_sub(1).map(|index| characters[index]);
+    let next = characters.get(index + 1).copied();
+    matches!(previous, Some('>' | '<')) || next == Some('>')
+}
+
+/// 返回与 `open` 位置的 `(` 匹配的 `)` 的位置, 找不到时返回末尾.
+fn matching_paren(characters: &[char], open: usize) -> usize {
+    let mut depth = 0_usize;
+    let mut single_quoted = false;
+    let mut double_quoted = false;
+    let mut index = open;
+    while index < characters.len() {
+        match characters[index] {
+            '\\' if !single_quoted => index += 1,
+            '\'' if !double_quoted => single_quoted = !single_quoted,
+            '"' if !single_quoted => double_quoted = !double_quoted,
+            '(' if !single_quoted && !double_quoted => depth += 1,
+            ')' if !single_quoted && !double_quoted => {
+                depth -= 1;
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    characters.len()
+}
+
+fn push_segment(current: &mut String, output: &mut Vec<Segment>) {
+    let segment = normalize(current);
+    current.clear();
+    if !segment.command.is_empty() {
+        output.push(segment);
+    }
+}
+
+fn normalize(segment: &str) -> Segment {
+    let mut assignments = Vec::new();
+    let mut words = segment.split_whitespace().peekable();
+    while let Some(word) = words.peek() {
+        if LEADING_KEYWORDS.contains(word) {
+            words.next();
+        } else if is_assignment(word) {
+            let mut assignment = words.next().unwrap_or_default().to_string();
+            // 数组赋值 `a=(1 2)` 的值包含空白.
+            if assignment.contains("=(") && !assignment.ends_with(')') {
+                for word in words.by_ref() {
+                    assignment.push(' ');
+                    assignment.push_str(word);
+                    if word.ends_with(')') {
+                        break;
+                    }
+                }
+            }
+            assignments.push(assignment);
+        } else {
+            break;
+        }
+    }
+    Segment {
+        command: words.collect::<Vec<_>>().join(" "),
+        assignments,
+    }
+}
+
+/// `NAME=value` 形式的变量赋值.
+pub(crate) fn is_assignment(word: &str) -> bool {
+    let Some((name, _)) = word.split_once('=') else {
+        return false;
+    };
+    !name.is_empty()
+        && !name.starts_with(|character: char| character.is_ascii_digit())
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+/// 重定向操作符, 较长的在前.
+const REDIRECTIONS: [&str; 12] = [
+    "&>>", "<<<", "<<-", "&>", ">>", ">|", ">&", "<<", "<>", "<&", ">", "<",
+];
+
+/// [`tokens`] 切分出的单元.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// 单词, 保留原样的引号, 转义和命令替换.
+    Word(String),
+    /// 控制操作符: `|`, `|&`, `||`, `&&`, `;` 和 `&`, 换行记为 `;`; 以及子 shell 的 `(` 和 `)`.
+    Operator(String),
+    /// 重定向操作符, 包括前面的文件描述符, 如 `>`, `2>>`, `&>`, `<<<`, `2>&1`.
+    Redirection(String),
+}
+
+/// 把命令切分成单词, 控制操作符和重定向. 引号, `$(...)`, `<(...)` 和反引号中的内容
+/// 属于同一个单词, 单词开头的 `#` 之后到行尾是注释. 命令组的 `{` 和 `}` 是普通单词.
+pub fn tokens(command: &str) -> Vec<Token> {
+    let characters = command.chars().collect::<Vec<_>>();
+    let mut output = Vec::new();
+    let mut word = String::new();
+    let mut index = 0;
+    let flush = |word: &mut String, output: &mut Vec<Token>| {
+        if !word.is_empty() {
+            output.push(Token::Word(std::mem::take(word)));
+        }
+    };
+    while index < characters.len() {
+        let character = characters[index];
+        let next = characters.get(index + 1).copied();
+        match character {
+            '\\' => {
+                word.push(character);
+                word.extend(next);
+                index += 2;
+                continue;
+            }
+            '\'' | '"' => {
+                let end = characters[index + 1..]
+                    .iter()
+                    .enumerate()
+                    .find(|(offset, quote)| {
+                        **quote == character
+                            && (character == '\''
+                                || characters[index + offset] != '\\')
+                    })
+                    .map(|(offset, _)| index + 1 + offset)
+                    .unwrap_or(characters.len());
+                word.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            '$' | '<' | '>' if next == Some('(') => {
+                let end = matching_paren(&characters, index + 1);
+                word.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            // 数组赋值 `a=(1 2)` 的值.
+            '(' if !word.is_empty() => {
+                let end = matching_paren(&characters, index);
+                word.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            '(' | ')' => {
+                flush(&mut word, &mut output);
+                output.push(Token::Operator(character.to_string()));
+            }
+            '`' => {
+                let end = characters[index + 1..]
+                    .iter()
+                    .position(|character| *character == '`')
+                    .map(|offset| index + 1 + offset)
+                    .unwrap_or(characters.len());
+                word.extend(&characters[index..(end + 1).min(characters.len())]);
+                index = end + 1;
+                continue;
+            }
+            '#' if word.is_empty() => {
+                index = characters[index..]
+                    .iter()
+                    .position(|character| *character == '\n')
+                    .map_or(characters.len(), |offset| index + offset);
+                continue;
+            }
+            character if character.is_whitespace() && character != '\n' => {
+                flush(&mut word, &mut output);
+            }
+            '|' | ';' | '\n' => {
+                flush(&mut word, &mut output);
+                let operator = match (character, next) {
+                    ('|', Some('|')) => "||",
+                    ('|', Some('&')) => "|&",
+                    ('|', _) => "|",
+                    _ => ";",
+                };
+                output.push(Token::Operator(operator.to_string()));
+                index += operator.len().max(1);
+                continue;
+            }
+            '&' if next == Some('&') => {
+                flush(&mut word, &mut output);
+                output.push(Token::Operator("&&".to_string()));
+                index += 2;
+                continue;
+            }
+            '&' if next != Some('>') => {
+                flush(&mut word, &mut output);
+                output.push(Token::Operator("&".to_string()));
+            }
+            '&' | '<' | '>' => {
+                // 紧挨在前面的数字是文件描述符, 属于重定向.
+                let mut operator = if word.chars().all(|character| character.is_ascii_digit()) {
+                    std::mem::take(&mut word)
+                } else {
+                    flush(&mut word, &mut output);
+                    String::new()
+                };
+                let rest = characters[index..].iter().take(3).collect::<String>();
+                let symbol = REDIRECTIONS
+                    .into_iter()
+                    .find(|symbol| rest.starts_with(symbol))
+                    .unwrap_or(">");
+                operator.push_str(symbol);
+                index += symbol.len();
+                // `2>&1`, `>&-` 这样复制或关闭文件描述符的重定向没有单独的目标.
+                if symbol.ends_with('&') {
+                    let target = characters[index..]
+                        .iter()
+                        .take_while(|character| character.is_ascii_digit() || **character == '-')
+                        .count();
+                    operator.extend(&characters[index..index + target]);
+                    index += target;
+                }
+                output.push(Token::Redirection(operator));
+                continue;
+            }
+            _ => word.push(character),
+        }
+        index += 1;
+    }
+    flush(&mut word, &mut output);
+    output
+}
+
+/// 片段中的程序名, 即第一个单词 (去掉路径前缀).
+pub fn program(segment: &str) -> Option<&str> {
+    let word = segment.split_whitespace().next()?;
+    Some(word.rsplit('/').next().unwrap_or(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Segment, Token, program, segments, split, tokens};
+
+    #[test]
+    fn splits_pipelines_and_lists() {
+        assert_eq!(
+            segments("git status && cargo check || echo  failed; ls | wc -l"),
+            ["git status", "cargo check", "echo failed", "ls", "wc -l"]
+        );
+        assert_eq!(segments("sleep 1 & echo done\nfalse"), ["sleep 1", "echo done", "false"]);
+    }
+
+    #[test]
+    fn keeps_quoted_operators_and_redirections() {
+        assert_eq!(
+            segments(r#"echo "a | b; c" 'x && y' 2>&1 >&2"#),
+            [r#"echo "a | b; c" 'x && y' 2>&1 >&2"#]
+        );
+        assert_eq!(segments(r"echo a\;b"), [r"echo a\;b"]);
+    }
+
+    #[test]
+    fn command_substitutions_become_extra_segments() {
+        assert_eq!(
+            segments("echo $(rm -rf build | tee log) `git push`"),
+            ["echo $(rm -rf build | tee log) `git push`", "rm -rf build", "tee log", "git push"]
+        );
+    }
+
+    #[test]
+    fn nested_substitutions_become_extra_segments() {
+        assert_eq!(
+            segments("echo $(echo $(rm -rf x))"),
+            ["echo $(echo $(rm -rf x))", "echo $(rm -rf x)", "rm -rf x"]
+        );
+        assert_eq!(
+            segments("cargo build $(cargo `rm -rf ~`)"),
+            ["cargo build $(cargo `rm -rf ~`)", "cargo `rm -rf ~`", "rm -rf ~"]
+        );
+    }
+
+    #[test]
+    fn subshells_groups_and_process_substitutions_are_split() {
+        assert_eq!(segments("(rm -rf x)"), ["rm -rf x"]);
+        assert_eq!(segments("{ rm -rf x; }"), ["rm -rf x"]);
+        assert_eq!(
+            segments("( cd / && ls ) | wc -l"),
+            ["cd /", "ls", "wc -l"]
+        );
+        assert_eq!(
+            segments("diff <(sort a) >(tee log)"),
+            ["diff <(sort a) >(tee log)", "sort a", "tee log"]
+        );
+        assert_eq!(segments("files=(a b) ls"), ["ls"]);
+        assert_eq!(segments("files=(a b); ls"), ["ls"]);
+        assert_eq!(segments(r#"echo "(x)" '{ y; }'"#), [r#"echo "(x)" '{ y; }'"#]);
+    }
+
+    #[test]
+    fn strips_assignments_and_keywords() {
+        assert_eq!(segments("FOO=1 BAR=x git push"), ["git push"]);
+        assert_eq!(
+            split("LD_PRELOAD=/tmp/x.so files=(a b) git fetch"),
+            [Segment {
+                command: "git fetch".to_string(),
+                assignments: vec!["LD_PRELOAD=/tmp/x.so".to_string(), "files=(a b)".to_string()],
+            }]
+        );
+        assert_eq!(segments("test -d x; and git push"), ["test -d x", "git push"]);
+        assert_eq!(program("/usr/bin/cargo build"), Some("cargo"));
+    }
+
+    #[test]
+    fn tokens_keep_operators_and_redirections() {
+        let word = |word: &str| Token::Word(word.to_string());
+        let operator = |operator: &str| Token::Operator(operator.to_string());
+        let redirection = |redirection: &str| Token::Redirection(redirection.to_string());
+        assert_eq!(
+            tokens(r#"grep -v "a | b" log 2>&1 | tee out>>all && echo $(date) # done"#),
+            [
+                word("grep"),
+                word("-v"),
+                word(r#""a | b""#),
+                word("log"),
+                redirection("2>&1"),
+                operator("|"),
+                word("tee"),
+                word("out"),
+                redirection(">>"),
+                word("all"),
+                operator("&&"),
+                word("echo"),
+                word("$(date)"),
+            ]
+        );
+        assert_eq!(
+            tokens("(cd / && ls) | diff <(sort a) b"),
+            [
+                operator("("),
+                word("cd"),
+                word("/"),
+                operator("&&"),
+                word("ls"),
+                operator(")"),
+                operator("|"),
+                word("diff"),
+                word("<(sort a)"),
+                word("b"),
+            ]
+        );
+        assert_eq!(
+            tokens("cat <<< 'x;y' &> /dev/null &\nls"),
+            [
+                word("cat"),
+                redirection("<<<"),
+                word("'x;y'"),
+                redirection("&>"),
+                word("/dev/null"),
+                operator("&"),
+                operator(";"),
+                word("ls"),
+            ]
+        );
+    }
+}
@@ -10,15 +10,19 @@ use tokio::{fs, io, io::AsyncWriteExt};
 use crate::{
     agent::{
         answer::{AnswerAgent, AnswerAgentResponse, ModifyOption},
+        explain::ExplainAgent,
         submit_commands::CommandItem,
     },
+    config::AppConfig,
     error::{Error, Result},
+    favorites::{Favorite, FavoriteStore, Favorites},
+    shell::Shell,
     tui::{
         command_helper::{modify::App as ModifyApp, select::App as SelectApp},
         markdown,
     },
 };
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Action {
@@ -31,6 +35,7 @@ pub enum ActionKind {
     Copy,
     Execute,
     Modify,
+    Explain,
     PrintToInputBuffer,
 }
 
@@ -82,8 +87,59 @@ pub(crate) fn print_plain_commands(commands: &[CommandItem]) -> std::io::Result<
     stdout.flush()
 }
 
-pub(crate) async fn select(commands: Vec<CommandItem>) -> Result<Option<Action>> {
-    SelectApp::select(commands).await
+/// 显示选择界面, 并把界面中收藏或取消收藏的命令连同说明和 `prompt` 写回收藏夹.
+///
+/// 收藏文件无法读取时仍然可以选择, 但不会覆盖它.
+pub(crate) async fn select(
+    commands: Vec<CommandItem>,
+    favorites: &FavoriteStore,
+    prompt: &str,
+) -> Result<Option<Action>> {
+    let saved = match favorites.load().await {
+        Ok(saved) => Some(saved),
+        Err(error) => {
+            warn!(%error, path = %favorites.path().display(), "Failed to load favorites.");
+            None
+        }
+    };
+    let initial = commands
+        .iter()
+        .map(|item| saved.as_ref().is_some_and(|saved| saved.contains(&item.command)))
+        .collect::<Vec<_>>();
+    let selection = SelectApp::select(commands.clone(), initial.clone()).await?;
+    if let Some(mut saved) = saved
+        && apply_stars(&mut saved, &commands, &initial, &selection.starred, prompt)
+    {
+        favorites.save(&saved).await?;
+        info!(count = saved.items.len(), "Favorites updated.");
+    }
+    Ok(selection.action)
+}
+
+/// 按选择界面前后的收藏状态更新 `favorites`, 返回是否有变化.
+fn apply_stars(
+    favorites: &mut Favorites,
+    commands: &[CommandItem],
+    before: &[bool],
+    after: &[bool],
+    prompt: &str,
+) -> bool {
+    let mut changed = false;
+    for ((item, before), after) in commands.iter().zip(before).zip(after) {
+        match (before, after) {
+            (false, true) => {
+                favorites.upsert(Favorite {
+                    command: item.command.clone(),
+                    description: item.description.clone(),
+                    prompt: prompt.trim().to_string(),
+                });
+                changed = true;
+            }
+            (true, false) => changed |= favorites.remove_command(&item.command),
+            _ => {}
+        }
+    }
+    changed
 }
 
 pub(crate) async fn execute(command: String, shell_path: impl AsRef<Path>) -> Result<()> {
@@ -139,6 +195,18 @@ pub(crate) async fn modify(
     }
 }
 
+/// 按帮助和 man 手册解释选定的 command, 输出到标准错误流.
+pub(crate) async fn explain(command: &str, shell: &Shell, config: AppConfig) -> Result<()> {
+    let explanation = ExplainAgent::builder()
+        .shell(shell)
+        .config(config)
+        .build()?
+        .explain(command)
+        .await?;
+    eprintln!("\n{explanation}");
+    Ok(())
+}
+
 pub(crate) async fn print_to_input_buffer(
     htcmd_file: &Option<impl AsRef<Path>>,
     command: &str,
@@ -217,11 +285,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn stars_toggled_in_the_select_ui_update_favorites() {
+        let commands = vec![
+            CommandItem {
+                command: "du -sh *".into(),
+                description: "sizes".into(),
+            },
+            CommandItem {
+                command: "df -h".into(),
+                description: "disks".into(),
+            },
+        ];
+        let mut favorites = Favorites::default();
+        favorites.upsert(Favorite {
+            command: "df -h".into(),
+            description: "disks".into(),
+            prompt: "disk usage".into(),
+        });
+
+        assert!(!apply_stars(&mut favorites, &commands, &[false, true], &[false, true], "p"));
+        assert!(apply_stars(
+            &mut favorites,
+            &commands,
+            &[false, true],
+            &[true, false],
+            " folder sizes\n"
+        ));
+        assert_eq!(
+            favorites.items,
+            [Favorite {
+                command: "du -sh *".into(),
+                description: "sizes".into(),
+                prompt: "folder sizes".into(),
+            }]
+        );
+    }
+
     #[tokio::test]
     #[ignore = "需要真实 TTY 交互 (手动选择), 用 `cargo test select_app_print_to_input_buffer -- --ignored --nocapture` 运行"]
     async fn select_app_print_to_input_buffer() {
         println!("Manually select 3 with Copy action:");
-        let action = SelectApp::select(
+        let selection = SelectApp::select(
             [
                 CommandItem {
                     command: "1".into(),
@@ -238,11 +343,12 @@ mod tests {
                 },
             ]
             .into(),
+            Vec::new(),
         )
         .await
         .unwrap();
         assert_eq!(
-            action,
+            selection.action,
             Some(Action {
                 kind: ActionKind::Copy,
                 command: "3".to_string()
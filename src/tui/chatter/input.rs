@@ -25,7 +25,7 @@ const TITLE: &str = "Howlto Prompt";
 const TITLE_STYLE: Style = Style::new()
     .fg(Color::LightGreen)
     .add_modifier(Modifier::BOLD);
-const HINT: &str = "enter: send | ctrl+c/esc: quit | /exit: quit";
+const HINT: &str = "enter: send | ctrl+c/esc: quit | /grants /revoke <n|all> /exit";
 const HINT_STYLE: Style = Style::new().fg(Color::DarkGray);
 const INPUT_BORDER_STYLE: Style = Style::new().fg(Color::Blue);
 
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{io::Write, path::PathBuf};
 
 use tracing::{info, warn};
 
@@ -7,8 +7,9 @@ use crate::{
         answer::{AnswerAgent, AnswerAgentResponse},
         detect_os,
     },
-    config::{AppConfig, profile::Profiles},
+    config::{AppConfig, profile::AnswerProfile},
     error::Result,
+    favorites::FavoriteStore,
     session::{Session, SessionStore},
     shell::Shell,
     tui::candidate,
@@ -21,23 +22,24 @@ mod menu;
 pub async fn run(
     config_dir: PathBuf,
     config: AppConfig,
-    profiles: Profiles,
+    profile: AnswerProfile,
     shell: &Shell,
     htcmd_file: Option<PathBuf>,
 ) -> Result<()> {
-    run_internal(config_dir, config, profiles, shell, htcmd_file).await
+    run_internal(config_dir, config, profile, shell, htcmd_file).await
 }
 
 async fn run_internal(
     config_dir: PathBuf,
     config: AppConfig,
-    profiles: Profiles,
+    profile: AnswerProfile,
     shell: &Shell,
     htcmd_file: Option<PathBuf>,
 ) -> Result<()> {
     let cwd = std::env::current_dir()?;
     let store = SessionStore::new(&config_dir, config.session);
     store.cleanup().await;
+    let favorites = FavoriteStore::new(&config_dir);
     let sessions = store.list(&cwd).await?;
     let mut session = match menu::choose(sessions).await? {
         None => return Ok(()),
@@ -59,6 +61,7 @@ async fn run_internal(
             messages: session.messages.clone(),
             final_text: session.final_text.clone(),
             commands: session.commands.clone(),
+            grants: session.grants.clone(),
         };
         candidate::show_response_text(&preview, false)?;
         if !preview.commands.is_empty() {
@@ -66,12 +69,17 @@ async fn run_internal(
         }
     }
 
+    let explain_config = config.clone();
     let agent = AnswerAgent::builder()
-        .profile(profiles.answer.clone())
+        .profile(profile)
         .os(detect_os())
         .shell(shell)
         .config(config)
+        .maybe_favorites(favorites.has_favorites().await.then(|| favorites.clone()))
         .build()?;
+    if let Some(session) = &session {
+        agent.grants().replace(session.grants.clone()).await;
+    }
 
     loop {
         let Some(raw_prompt) = input::App::prompt().await? else {
@@ -84,6 +92,16 @@ async fn run_internal(
         if prompt == "/exit" {
             break;
         }
+        if prompt == "/grants" || prompt.starts_with("/revoke") {
+            grant_command(&agent, &prompt).await?;
+            if let Some(session) = session.as_mut() {
+                session.grants = agent.grants().snapshot().await;
+                if let Err(error) = store.save(session).await {
+                    warn!(error = %error, "Failed to save chat session.");
+                }
+            }
+            continue;
+        }
 
         let history = session
             .as_ref()
@@ -96,7 +114,16 @@ async fn run_internal(
             .history(history)
             .call()
             .await?;
-        handle_candidates(&agent, &mut response, shell, &htcmd_file).await?;
+        handle_candidates(
+            &agent,
+            &mut response,
+            shell,
+            &htcmd_file,
+            &favorites,
+            &prompt,
+            &explain_config,
+        )
+        .await?;
 
         if let Some(existing) = session.as_mut() {
             existing.update(&prompt, &response);
@@ -112,11 +139,52 @@ async fn run_internal(
     Ok(())
 }
 
+/// 处理 `/grants` 和 `/revoke <n|all>`, 列出或撤销本会话记住的 elevate 授权.
+async fn grant_command(agent: &AnswerAgent, prompt: &str) -> Result<()> {
+    let grants = agent.grants();
+    // 先完成所有 await 再写 stderr, 避免持有锁时阻塞其他任务的日志输出.
+    let message = if let Some(target) = prompt.strip_prefix("/revoke") {
+        match target.trim() {
+            "all" => {
+                grants.clear().await;
+                "Revoked all grants.".to_string()
+            }
+            number => match number.parse::<usize>() {
+                Ok(number) if number > 0 => match grants.revoke(number - 1).await {
+                    Some(grant) => {
+                        info!(%grant, "Revoked elevate grant.");
+                        format!("Revoked {grant}.")
+                    }
+                    None => format!("No grant numbered {number}."),
+                },
+                _ => "Usage: /revoke <n|all>".to_string(),
+            },
+        }
+    } else {
+        let snapshot = grants.snapshot().await;
+        if snapshot.is_empty() {
+            "No elevate grants in this session.".to_string()
+        } else {
+            snapshot
+                .iter()
+                .enumerate()
+                .map(|(index, grant)| format!("{}. {grant}", index + 1))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+    writeln!(std::io::stderr().lock(), "{message}")?;
+    Ok(())
+}
+
 async fn handle_candidates(
     agent: &AnswerAgent,
     response: &mut AnswerAgentResponse,
     shell: &Shell,
     htcmd_file: &Option<PathBuf>,
+    favorites: &FavoriteStore,
+    prompt: &str,
+    config: &AppConfig,
 ) -> Result<()> {
     loop {
         candidate::show_response_text(response, false)?;
@@ -124,7 +192,7 @@ async fn handle_candidates(
             return Ok(());
         }
         candidate::print_candidates(&response.commands)?;
-        let Some(action) = candidate::select(response.commands.clone()).await? else {
+        let Some(action) = candidate::select(response.commands.clone(), favorites, prompt).await? else {
             return Ok(());
         };
         match action.kind {
@@ -145,6 +213,9 @@ async fn handle_candidates(
                     return Ok(());
                 }
             }
+            candidate::ActionKind::Explain => {
+                candidate::explain(&action.command, shell, config.clone()).await?;
+            }
         }
     }
 }
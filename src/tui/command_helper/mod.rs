@@ -4,8 +4,9 @@ use tracing::debug;
 
 use crate::{
     agent::{answer::AnswerAgentResponse, detect_os},
-    config::{AppConfig, profile::Profiles},
+    config::{AppConfig, profile::AnswerProfile},
     error::Result,
+    favorites::FavoriteStore,
     shell::Shell,
     tui::candidate,
 };
@@ -22,26 +23,34 @@ pub async fn run(
     config: AppConfig,
     shell: &Shell,
     attached: Option<String>,
-    profiles: Profiles,
+    profile: AnswerProfile,
     htcmd_file: Option<PathBuf>,
+    favorites: FavoriteStore,
 ) -> Result<AnswerAgentResponse> {
-    run_internal(prompt, plain, config, shell, attached, profiles, htcmd_file).await
+    run_internal(
+        prompt, plain, config, shell, attached, profile, htcmd_file, favorites,
+    )
+    .await
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_internal(
     prompt: &str,
     plain: bool,
     config: AppConfig,
     shell: &Shell,
     attached: Option<String>,
-    profiles: Profiles,
+    profile: AnswerProfile,
     htcmd_file: Option<PathBuf>,
+    favorites: FavoriteStore,
 ) -> Result<AnswerAgentResponse> {
+    let explain_config = config.clone();
     let agent = crate::agent::answer::AnswerAgent::builder()
-        .profile(profiles.answer.clone())
+        .profile(profile)
         .os(detect_os())
         .shell(shell)
         .config(config)
+        .maybe_favorites(favorites.has_favorites().await.then(|| favorites.clone()))
         .build()?;
     let mut response = agent
         .resolve()
@@ -61,7 +70,7 @@ async fn run_internal(
         }
 
         candidate::print_candidates(&response.commands)?;
-        let action = candidate::select(response.commands.clone()).await?;
+        let action = candidate::select(response.commands.clone(), &favorites, prompt).await?;
         let Some(action) = action else {
             break;
         };
@@ -84,6 +93,9 @@ async fn run_internal(
                     break;
                 }
             }
+            candidate::ActionKind::Explain => {
+                candidate::explain(&action.command, shell, explain_config.clone()).await?;
+            }
         }
     }
     Ok(response)
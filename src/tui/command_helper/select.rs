@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     io,
     time::{Duration, Instant},
 };
@@ -27,16 +28,27 @@ use unicode_width::UnicodeWidthStr;
 
 const TITLE: &str = "Select Command";
 const TITLE_STYLE: Style = Style::new().fg(Color::Green).add_modifier(Modifier::BOLD);
-const HINT1: &str = "j/k: up/down | m: modify | c: copy";
-const HINT2: &str = "e: execute | enter: place to input | q/esc: quit";
+const HINT1: &str = "j/k: up/down | m: modify | c: copy | f: star";
+const HINT2: &str = "e: execute | x: explain | enter: place to input | q/esc: quit";
 const HINT_STYLE: Style = Style::new().fg(Color::DarkGray);
 const BORDER_STYLE: Style = Style::new().fg(Color::Blue);
+const STAR: &str = "★ ";
 
 struct AppWidget {
     items: Vec<CommandItem>,
+    /// 与 `items` 一一对应, 是否已收藏.
+    starred: Vec<bool>,
     list_state: ListState,
 }
 
+/// 选择界面的结果.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub action: Option<Action>,
+    /// 退出时每一项的收藏状态.
+    pub starred: Vec<bool>,
+}
+
 pub struct App {
     terminal: InlineTerminal,
     widget: AppWidget,
@@ -51,6 +63,8 @@ enum AppEvent {
     C,
     M,
     E,
+    F,
+    X,
     Err(io::Error),
 }
 
@@ -63,14 +77,20 @@ impl AppWidget {
         s.lines().count().max(1)
     }
 
-    fn command_item_height(&self, item: &CommandItem) -> usize {
-        if self.should_line_break(
-            self.calc_width() as usize - 2,
-            2,
-            item.command.as_str(),
-            item.description.as_str(),
-        ) {
-            Self::line_count(item.command.as_str()) + item.description.lines().count()
+    /// 显示的描述, 已收藏的项带有星标.
+    fn description(item: &CommandItem, starred: bool) -> Cow<'_, str> {
+        if starred {
+            Cow::Owned(format!("{STAR}{}", item.description))
+        } else {
+            Cow::Borrowed(item.description.as_str())
+        }
+    }
+
+    fn command_item_height(&self, idx: usize) -> usize {
+        let command = self.items[idx].command.as_str();
+        let description = Self::description(&self.items[idx], self.starred[idx]);
+        if self.should_line_break(self.calc_width() as usize - 2, 2, command, &description) {
+            Self::line_count(command) + description.lines().count()
         } else {
             1
         }
@@ -78,9 +98,8 @@ impl AppWidget {
 
     fn calc_height(&self) -> u16 {
         let height = 4usize.saturating_add(
-            self.items
-                .iter()
-                .map(|item| self.command_item_height(item))
+            (0..self.items.len())
+                .map(|idx| self.command_item_height(idx))
                 .sum::<usize>(),
         );
         height.min(u16::MAX as usize) as u16
@@ -93,7 +112,8 @@ impl AppWidget {
             .iter()
             .map(|x| {
                 // 命令项: content + desc 对齐; 文本项: 以 content 行宽为准 (markdown 原文).
-                Self::max_line_width(x.description.as_str())
+                // 预留星标的宽度, 收藏时界面宽度不变.
+                (Self::max_line_width(x.description.as_str()) + STAR.width_cjk())
                     .max(Self::max_line_width(x.command.as_str()))
                     + 5
             })
@@ -197,16 +217,15 @@ impl Widget for &mut AppWidget {
                         }
                         .fg(Color::LightCyan);
                         let content = x.command.as_str();
-                        let desc = x.description.as_str();
-                        if self.should_line_break(width as usize - 2, 2, content, desc) {
-                            AppWidget::render_line_break_item(content, desc, selected)
+                        let desc = AppWidget::description(x, self.starred[idx]);
+                        if self.should_line_break(width as usize - 2, 2, content, &desc) {
+                            AppWidget::render_line_break_item(content, &desc, selected)
                         } else {
                             let left = if selected {
                                 Span::from(content).fg(Color::LightCyan)
                             } else {
                                 content.into()
                             };
-                            let right = Span::from(desc).dark_gray();
                             // 分别左右对齐.
                             let spaces = Span::raw(
                                 " ".repeat(
@@ -214,6 +233,7 @@ impl Widget for &mut AppWidget {
                                         .saturating_sub(content.width_cjk() + desc.width_cjk()),
                                 ),
                             );
+                            let right = Span::from(desc).dark_gray();
                             Text::from(Line::from_iter([prefix, left, spaces, right]))
                         }
                     })
@@ -279,6 +299,12 @@ impl App {
                             KeyCode::Char('e') if kevt.modifiers.is_empty() => {
                                 send!(AppEvent::E);
                             }
+                            KeyCode::Char('f') if kevt.modifiers.is_empty() => {
+                                send!(AppEvent::F);
+                            }
+                            KeyCode::Char('x') if kevt.modifiers.is_empty() => {
+                                send!(AppEvent::X);
+                            }
                             KeyCode::Enter
                                 if kevt.modifiers.is_empty()
                                     && start_time.elapsed() > skip_enter_duration =>
@@ -297,7 +323,7 @@ impl App {
         })
     }
 
-    /// 产生动作 (复制/执行/修改/写入输入缓冲) 对当前选中的命令项.
+    /// 产生动作 (复制/执行/修改/解释/写入输入缓冲) 对当前选中的命令项.
     fn action_result(&self, kind: ActionKind) -> Option<Action> {
         let sel = self.widget.list_state.selected()?;
         let item = self.widget.items.get(sel)?;
@@ -305,7 +331,16 @@ impl App {
         Some(Action { command, kind })
     }
 
-    async fn run(mut self) -> Result<Option<Action>> {
+    /// 切换当前选中项的收藏状态.
+    fn toggle_star(&mut self) {
+        if let Some(sel) = self.widget.list_state.selected()
+            && let Some(starred) = self.widget.starred.get_mut(sel)
+        {
+            *starred = !*starred;
+        }
+    }
+
+    async fn run(mut self) -> Result<Selection> {
         let (evt_tx, mut evt_rx) = tokio::sync::mpsc::unbounded_channel();
         let handle = self.start_handling_events(evt_tx);
         let rst = loop {
@@ -324,28 +359,39 @@ impl App {
                 AppEvent::M => break Ok(self.action_result(ActionKind::Modify)),
                 AppEvent::Err(e) => break Err(e),
                 AppEvent::E => break Ok(self.action_result(ActionKind::Execute)),
+                AppEvent::F => self.toggle_star(),
+                AppEvent::X => break Ok(self.action_result(ActionKind::Explain)),
             }
         };
         handle.abort();
         handle.await.ok();
-        Ok(rst?)
+        Ok(Selection {
+            action: rst?,
+            starred: self.widget.starred,
+        })
     }
 
-    fn new(items: Vec<CommandItem>) -> io::Result<App> {
+    fn new(items: Vec<CommandItem>, mut starred: Vec<bool>) -> io::Result<App> {
         let mut list_state = ListState::default();
         list_state.select_first();
-        let widget = AppWidget { items, list_state };
+        starred.resize(items.len(), false);
+        let widget = AppWidget {
+            items,
+            starred,
+            list_state,
+        };
         let terminal = InlineTerminal::init_with_options(ratatui::TerminalOptions {
             viewport: Viewport::Inline(widget.calc_height()),
         })?;
         Ok(App { terminal, widget })
     }
 
-    pub async fn select(items: Vec<CommandItem>) -> Result<Option<Action>> {
+    /// 选择一项命令. `starred` 是每一项初始的收藏状态, 缺少的按未收藏处理.
+    pub async fn select(items: Vec<CommandItem>, starred: Vec<bool>) -> Result<Selection> {
         if items.is_empty() {
             return Err(Error::InvalidInput("items can't be empty".into()));
         }
-        let app = App::new(items.into_iter().collect())?;
+        let app = App::new(items.into_iter().collect(), starred)?;
         app.run().await
     }
 }
@@ -371,6 +417,7 @@ mod tests {
                 command: "for file in *.bak\n    mv $file fixed\nend".into(),
                 description: "batch rename".into(),
             }],
+            starred: vec![false],
             list_state,
         };
         let area = Rect::new(0, 0, 60, 8);
@@ -394,9 +441,38 @@ mod tests {
                 command: "cmd\n".repeat(u16::MAX as usize),
                 description: String::new(),
             }],
+            starred: vec![false],
             list_state,
         };
 
         assert_eq!(widget.calc_height(), u16::MAX);
     }
+
+    #[test]
+    fn starred_items_show_a_star_before_the_description() {
+        let mut list_state = ListState::default();
+        list_state.select_first();
+        let mut widget = AppWidget {
+            items: vec![
+                CommandItem {
+                    command: "ls -la".into(),
+                    description: "long listing".into(),
+                },
+                CommandItem {
+                    command: "ls -1".into(),
+                    description: "one per line".into(),
+                },
+            ],
+            starred: vec![false, true],
+            list_state,
+        };
+        let area = Rect::new(0, 0, 60, 6);
+        let mut buffer = Buffer::empty(area);
+
+        (&mut widget).render(area, &mut buffer);
+
+        assert!(!buffer_line(&buffer, 1).contains('★'));
+        assert!(buffer_line(&buffer, 2).contains("★"));
+        assert!(buffer_line(&buffer, 2).contains("one per line"));
+    }
 }
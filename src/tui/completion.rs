@@ -0,0 +1,93 @@
+use std::io::Write;
+
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    agent::{completion::CompletionAgent, detect_os},
+    config::{AppConfig, profile::AnswerProfile},
+    error::{Error, Result},
+    shell::Shell,
+    tui::elevate,
+};
+
+/// 确认对话框中预览的脚本行数和每行的最大字符数.
+const PREVIEW_LINES: usize = 8;
+const PREVIEW_WIDTH: usize = 72;
+
+/// 为 `program` 生成 `shell` 的补全脚本, 确认后安装到补全目录. `print` 时只输出到标准输出.
+#[bon::builder]
+pub async fn run(
+    program: &str,
+    shell: &Shell,
+    profile: AnswerProfile,
+    config: AppConfig,
+    print: bool,
+) -> Result<()> {
+    if which::which(program).is_err() {
+        return Err(Error::InvalidInput(format!(
+            "Program `{program}` was not found in PATH."
+        )));
+    }
+    let agent = CompletionAgent::builder()
+        .os(detect_os())
+        .shell(shell)
+        .profile(profile)
+        .config(config)
+        .build()?;
+    let script = agent.generate(program).await?;
+    if print {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(script.as_bytes())?;
+        return Ok(stdout.flush()?);
+    }
+
+    let kind = agent.kind();
+    let path = kind.install_path(program)?;
+    // 对话框只能预览开头几行, 完整的脚本先写入临时文件, 确认前可以在另一个终端中查看.
+    let review = std::env::temp_dir().join(format!(
+        "howlto-{}-{}",
+        Uuid::new_v4(),
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    tokio::fs::write(&review, &script).await?;
+    let mut details = Vec::new();
+    if path.exists() {
+        details.push("The existing file will be replaced.".to_string());
+    }
+    details.push(format!("Full script: {}", review.display()));
+    details.extend(preview(&script));
+    let decision = elevate::confirm_install(&path.display().to_string(), details).await;
+    tokio::fs::remove_file(&review).await.ok();
+    if let Err(reason) = decision {
+        info!(%reason, "Completion install declined.");
+        eprintln!("已取消安装, 可以使用 --print 输出脚本.");
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(&path, &script).await?;
+    info!(path = %path.display(), "Completion script installed.");
+    eprintln!("已安装: {}", path.display());
+    if let Some(note) = kind.install_note() {
+        eprintln!("{note}");
+    }
+    Ok(())
+}
+
+fn preview(script: &str) -> Vec<String> {
+    let mut lines = script
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(|line| match line.char_indices().nth(PREVIEW_WIDTH) {
+            Some((index, _)) => format!("{}…", &line[..index]),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let rest = script.lines().count().saturating_sub(PREVIEW_LINES);
+    if rest > 0 {
+        lines.push(format!("... {rest} more lines"));
+    }
+    lines
+}
@@ -26,6 +26,20 @@ const HINT_STYLE: Style = Style::new().fg(Color::DarkGray);
 const INPUT_BORDER_STYLE: Style = Style::new().fg(Color::Gray);
 const INPUT_STYLE: Style = Style::new();
 const MINIMUM_TUI_WIDTH: usize = 56;
+const ELEVATE_WARNING: &str = "This command runs with full privileges. Confirm before execution.";
+const SANDBOXED_WARNING: &str = "This sandboxed command requires confirmation before it runs.";
+const DECISION_HINT: &str =
+    "enter/y: approve | esc/n: reject | m: reject with reason\nPress m to input reject reason";
+const REMEMBER_HINT: &str =
+    "enter/y: once | s: this command | d: program in this dir\nesc/n: reject | m: reject with reason";
+const REMEMBER_COMMAND_HINT: &str =
+    "enter/y: once | s: this command\nesc/n: reject | m: reject with reason";
+const INSTALL_WARNING: &str = "Install the generated completion script to this path?";
+const CHANGES_WARNING: &str = "The preview made these changes. Apply them to the real directory?";
+const RERUN_WARNING: &str =
+    "The preview changed no files in this directory, so nothing can be applied. Run it for real?";
+const CHANGES_HINT: &str =
+    "enter/y: apply changes | r: re-run for real\nesc/n: discard | m: discard with reason";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Mode {
@@ -33,9 +47,37 @@ enum Mode {
     InputReason,
 }
 
+/// 对话框提供的选项.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DialogKind {
+    /// 只能批准一次或拒绝.
+    Once,
+    /// 额外提供记住授权的选项.
+    Remember,
+    /// 只能记住完全相同的命令, 用于含有不能按程序授权的程序的命令.
+    RememberCommand,
+    /// 预演结束后确认是否应用变更.
+    Changes,
+}
+
+/// 用户批准的范围.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Approval {
+    /// 只批准这一次.
+    Once,
+    /// 本会话中批准完全相同的命令.
+    Command,
+    /// 在当前目录中批准命令涉及的程序.
+    Program,
+}
+
 #[derive(Debug)]
 struct AppWidget {
     command: String,
+    warning: &'static str,
+    kind: DialogKind,
+    /// 展示在命令下方的额外信息, 例如预演的文件变更.
+    details: Vec<String>,
     mode: Mode,
     reason_input: TextArea<'static>,
 }
@@ -48,10 +90,20 @@ struct App {
 
 #[derive(Debug)]
 enum AppDecision {
-    Approve,
+    Approve(Approval),
+    Rerun,
     Reject(String),
 }
 
+/// 预演后用户的选择.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChangeDecision {
+    /// 把预演的变更应用到真实目录.
+    Apply,
+    /// 丢弃预演结果, 不经沙箱重新执行.
+    Rerun,
+}
+
 impl AppWidget {
     fn calc_width(&self) -> u16 {
         self.command
@@ -60,15 +112,40 @@ impl AppWidget {
             .max()
             .unwrap_or(0)
             .max(TITLE.width_cjk() + 6)
-            .max("enter/y: approve | esc/n: reject | m: reject with reason".width_cjk() + 6)
+            .max(self.warning.width_cjk() + 4)
+            .max(
+                self.details
+                    .iter()
+                    .map(|line| line.width_cjk() + 4)
+                    .max()
+                    .unwrap_or(0),
+            )
+            .max(
+                self.hint()
+                    .lines()
+                    .map(UnicodeWidthStr::width_cjk)
+                    .max()
+                    .unwrap_or(0)
+                    + 6,
+            )
             .max("Press enter to submit reject reason".width_cjk() + 6)
             .max(MINIMUM_TUI_WIDTH) as u16
     }
 
+    fn hint(&self) -> &'static str {
+        match self.kind {
+            DialogKind::Once => DECISION_HINT,
+            DialogKind::Remember => REMEMBER_HINT,
+            DialogKind::RememberCommand => REMEMBER_COMMAND_HINT,
+            DialogKind::Changes => CHANGES_HINT,
+        }
+    }
+
     fn calc_height(&self) -> u16 {
+        let details = self.details.len() as u16;
         match self.mode {
-            Mode::Decision => 6,
-            Mode::InputReason => 9,
+            Mode::Decision => 6 + details,
+            Mode::InputReason => 9 + details,
         }
     }
 }
@@ -88,9 +165,10 @@ impl Widget for &AppWidget {
         block.render(dialog_area, buf);
         match self.mode {
             Mode::Decision => {
-                let [warning_area, command_area, hint_area] = Layout::vertical([
+                let [warning_area, command_area, details_area, hint_area] = Layout::vertical([
                     Constraint::Length(1),
                     Constraint::Length(1),
+                    Constraint::Length(self.details.len() as u16),
                     Constraint::Length(2),
                 ])
                 .margin(1)
@@ -100,7 +178,7 @@ impl Widget for &AppWidget {
                     Layout::horizontal([Constraint::Length(2), Constraint::Fill(1)])
                         .areas(command_area);
 
-                Line::from("This command runs with full privileges. Confirm before execution.")
+                Line::from(self.warning)
                     .style(WARNING_STYLE)
                     .render(warning_area, buf);
                 Line::from("> ")
@@ -110,26 +188,29 @@ impl Widget for &AppWidget {
                     .style(Style::new().fg(Color::White))
                     .wrap(Wrap { trim: true })
                     .render(command_area, buf);
-                Paragraph::new("enter/y: approve | esc/n: reject | m: reject with reason\nPress m to input reject reason")
+                self.render_details(details_area, buf);
+                Paragraph::new(self.hint())
                     .style(HINT_STYLE)
                     .right_aligned()
                     .render(hint_area, buf);
             }
             Mode::InputReason => {
-                let [warning_area, command_area, input_area, hint_area] = Layout::vertical([
-                    Constraint::Length(1),
-                    Constraint::Length(1),
-                    Constraint::Length(3),
-                    Constraint::Length(2),
-                ])
-                .margin(1)
-                .areas(dialog_area);
+                let [warning_area, command_area, details_area, input_area, hint_area] =
+                    Layout::vertical([
+                        Constraint::Length(1),
+                        Constraint::Length(1),
+                        Constraint::Length(self.details.len() as u16),
+                        Constraint::Length(3),
+                        Constraint::Length(2),
+                    ])
+                    .margin(1)
+                    .areas(dialog_area);
 
                 let [command_prefix_area, command_area] =
                     Layout::horizontal([Constraint::Length(2), Constraint::Fill(1)])
                         .areas(command_area);
 
-                Line::from("This command runs with full privileges. Confirm before execution.")
+                Line::from(self.warning)
                     .style(WARNING_STYLE)
                     .render(warning_area, buf);
                 Line::from("> ")
@@ -139,6 +220,7 @@ impl Widget for &AppWidget {
                     .style(Style::new().fg(Color::White))
                     .wrap(Wrap { trim: true })
                     .render(command_area, buf);
+                self.render_details(details_area, buf);
                 self.reason_input.render(input_area, buf);
                 Paragraph::new("enter: submits rejection\nesc: back to decision mode")
                     .style(HINT_STYLE)
@@ -149,8 +231,26 @@ impl Widget for &AppWidget {
     }
 }
 
+impl AppWidget {
+    fn render_details(&self, area: Rect, buf: &mut Buffer) {
+        Paragraph::new(
+            self.details
+                .iter()
+                .map(|line| Line::from(line.as_str()))
+                .collect::<Vec<_>>(),
+        )
+        .style(Style::new().fg(Color::Gray))
+        .render(area, buf);
+    }
+}
+
 impl App {
-    fn new(command: String) -> io::Result<Self> {
+    fn new(
+        command: String,
+        warning: &'static str,
+        kind: DialogKind,
+        details: Vec<String>,
+    ) -> io::Result<Self> {
         let mut reason_input = TextArea::default();
         reason_input.set_block(
             Block::bordered()
@@ -162,6 +262,9 @@ impl App {
         reason_input.set_style(INPUT_STYLE);
         let widget = AppWidget {
             command,
+            warning,
+            kind,
+            details,
             mode: Mode::Decision,
             reason_input,
         };
@@ -184,7 +287,25 @@ impl App {
             KeyCode::Enter | KeyCode::Char('y') | KeyCode::Char('Y')
                 if key.modifiers.is_empty() =>
             {
-                Ok(Some(AppDecision::Approve))
+                Ok(Some(AppDecision::Approve(Approval::Once)))
+            }
+            KeyCode::Char('s') | KeyCode::Char('S')
+                if matches!(
+                    self.widget.kind,
+                    DialogKind::Remember | DialogKind::RememberCommand
+                ) && key.modifiers.is_empty() =>
+            {
+                Ok(Some(AppDecision::Approve(Approval::Command)))
+            }
+            KeyCode::Char('d') | KeyCode::Char('D')
+                if self.widget.kind == DialogKind::Remember && key.modifiers.is_empty() =>
+            {
+                Ok(Some(AppDecision::Approve(Approval::Program)))
+            }
+            KeyCode::Char('r') | KeyCode::Char('R')
+                if self.widget.kind == DialogKind::Changes && key.modifiers.is_empty() =>
+            {
+                Ok(Some(AppDecision::Rerun))
             }
             KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') if key.modifiers.is_empty() => {
                 Ok(Some(AppDecision::Reject("Rejected by user.".to_string())))
@@ -264,12 +385,73 @@ impl App {
 }
 
 /// 使用 tui 向用户确认是否提权执行某个命令.
-/// 如果用户同意, 返回 Ok(()), 如果用户拒绝, 返回 Err(String), 内含拒绝原因.
+/// 如果用户同意, 返回 Ok(Approval), 如果用户拒绝, 返回 Err(String), 内含拒绝原因.
 ///
 /// `display_command` 是展示给用户确认的命令字符串, 调用方负责按需拼接 (例如 shell 模式下
 /// 已将 `<shell> -c <cmd>` 还原为真实命令文本展示, 避免用户看到一层 shell 包装).
-pub(crate) async fn confirm_elevate(display_command: &str) -> Result<(), String> {
-    let app = App::new(display_command.to_string()).map_err(|e| {
+/// `details` 是展示在命令下方的工作目录和环境变量.
+/// `remember` 为 true 时额外提供本会话内记住授权的选项, 否则只能批准一次.
+/// `by_program` 为 false 时只能记住完全相同的命令, 不提供按程序授权的选项.
+pub(crate) async fn confirm_elevate(
+    display_command: &str,
+    details: Vec<String>,
+    remember: bool,
+    by_program: bool,
+) -> Result<Approval, String> {
+    let kind = match (remember, by_program) {
+        (false, _) => DialogKind::Once,
+        (true, false) => DialogKind::RememberCommand,
+        (true, true) => DialogKind::Remember,
+    };
+    match confirm(display_command, ELEVATE_WARNING, kind, details).await? {
+        AppDecision::Approve(approval) => Ok(approval),
+        _ => Ok(Approval::Once),
+    }
+}
+
+/// 命令策略要求确认, 或会写入真实目录的 explore 命令, 确认后仍在沙箱中执行.
+pub(crate) async fn confirm_sandboxed(
+    display_command: &str,
+    details: Vec<String>,
+) -> Result<(), String> {
+    confirm(display_command, SANDBOXED_WARNING, DialogKind::Once, details)
+        .await
+        .map(|_| ())
+}
+
+/// 确认把生成的补全脚本写入 `path`, `details` 是脚本的预览.
+pub(crate) async fn confirm_install(path: &str, details: Vec<String>) -> Result<(), String> {
+    confirm(path, INSTALL_WARNING, DialogKind::Once, details)
+        .await
+        .map(|_| ())
+}
+
+/// 展示预演产生的文件变更 `changes`, 询问是否应用到真实目录.
+pub(crate) async fn confirm_changes(
+    display_command: &str,
+    changes: Vec<String>,
+) -> Result<ChangeDecision, String> {
+    match confirm(display_command, CHANGES_WARNING, DialogKind::Changes, changes).await? {
+        AppDecision::Rerun => Ok(ChangeDecision::Rerun),
+        _ => Ok(ChangeDecision::Apply),
+    }
+}
+
+/// 预演没有捕获到文件变更时, 询问是否不经沙箱真正执行 `display_command`.
+pub(crate) async fn confirm_rerun(display_command: &str, details: Vec<String>) -> Result<(), String> {
+    confirm(display_command, RERUN_WARNING, DialogKind::Once, details)
+        .await
+        .map(|_| ())
+}
+
+/// 弹出对话框, 拒绝以外的决定原样返回, 拒绝时返回 Err(原因).
+async fn confirm(
+    display_command: &str,
+    warning: &'static str,
+    kind: DialogKind,
+    details: Vec<String>,
+) -> Result<AppDecision, String> {
+    let app = App::new(display_command.to_string(), warning, kind, details).map_err(|e| {
         warn!(error = %e, "failed to initialize elevation confirmation tui");
         format!("Failed to initialize confirmation dialog: {e}")
     })?;
@@ -288,14 +470,14 @@ pub(crate) async fn confirm_elevate(display_command: &str) -> Result<(), String>
     })?;
 
     match decision {
-        AppDecision::Approve => {
-            info!(command = %display_command, "elevation approved by user");
-            Ok(())
-        }
         AppDecision::Reject(reason) => {
             info!(command = %display_command, reason = %reason, "elevation rejected by user");
             Err(reason)
         }
+        decision => {
+            info!(command = %display_command, ?decision, "elevation approved by user");
+            Ok(decision)
+        }
     }
 }
 
@@ -307,7 +489,7 @@ mod tests {
     use tracing_subscriber::layer::SubscriberExt;
     use tracing_subscriber::util::SubscriberInitExt;
 
-    use crate::tui::elevate::confirm_elevate;
+    use crate::tui::elevate::{Approval, confirm_elevate};
 
     fn log_init() {
         let indicatif_layer = IndicatifLayer::new();
@@ -326,13 +508,19 @@ mod tests {
     #[ignore = "需要真实 TTY 交互 (手动确认), 用 `cargo test test_confirm_elevate -- --ignored --nocapture` 运行"]
     async fn test_confirm_elevate() {
         log_init();
-        confirm_elevate("approve hello world").await.unwrap();
+        confirm_elevate("approve hello world", Vec::new(), false, true).await.unwrap();
+        assert_eq!(
+            confirm_elevate("approve_for_session hello world", Vec::new(), true, true)
+                .await
+                .unwrap(),
+            Approval::Command
+        );
         assert_eq!(
-            confirm_elevate("reject hello worlds").await.unwrap_err(),
+            confirm_elevate("reject hello worlds", Vec::new(), false, true).await.unwrap_err(),
             "Rejected by user."
         );
         assert_eq!(
-            confirm_elevate("reject_with_reason reason: noicant")
+            confirm_elevate("reject_with_reason reason: noicant", Vec::new(), false, true)
                 .await
                 .unwrap_err(),
             "Rejected by user: noicant"
@@ -1,5 +1,6 @@
 pub mod command_helper;
 pub mod chatter;
+pub mod completion;
 pub(crate) mod candidate;
 pub(crate) mod elevate;
 pub(crate) mod markdown;
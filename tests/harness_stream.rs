@@ -1,8 +1,18 @@
 use std::{collections::VecDeque, sync::Arc, time::Duration};
 
 use howlto::{
-    agent::answer::AnswerAgent,
-    config::{AppConfig, profile::AnswerProfile},
+    agent::{
+        answer::AnswerAgent, completion::CompletionAgent, explain::ExplainAgent,
+        transform::TransformAgent, translate::TranslateAgent,
+    },
+    config::{
+        AppConfig,
+        profile::{
+            AnswerProfile, COMPLETION_PROFILE, Profiles, TRANSFORM_PROFILE, TRANSLATE_PROFILE,
+        },
+    },
+    doctor, eval,
+    favorites::{Favorite, FavoriteStore, Favorites},
     shell::Shell,
 };
 use serde_json::{Value, json};
@@ -175,7 +185,38 @@ fn tool_response(name: &str, arguments: &str) -> String {
     ])
 }
 
+fn tool_calls_response(calls: &[(&str, &str, &str)]) -> String {
+    let tool_calls = calls
+        .iter()
+        .enumerate()
+        .map(|(index, (id, name, arguments))| {
+            json!({
+                "index": index,
+                "id": id,
+                "type": "function",
+                "function": {"name": name, "arguments": arguments}
+            })
+        })
+        .collect::<Vec<_>>();
+    sse([json!({
+        "id": "mock",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "mock",
+        "choices": [{
+            "index": 0,
+            "delta": {"role": "assistant", "tool_calls": tool_calls},
+            "finish_reason": "tool_calls"
+        }],
+        "usage": null
+    })])
+}
+
 fn make_agent(base_url: &str) -> AnswerAgent {
+    make_agent_with(base_url, |_| {})
+}
+
+fn make_agent_with(base_url: &str, configure: impl FnOnce(&mut AppConfig)) -> AnswerAgent {
     let mut config = AppConfig::default();
     config.llm.base_url = base_url.to_string();
     config.llm.api_key = "test-key".to_string();
@@ -183,6 +224,7 @@ fn make_agent(base_url: &str) -> AnswerAgent {
     config.agent.use_tool_explore = false;
     config.agent.use_tool_elevate = false;
     config.agent.answer.output_n = 3;
+    configure(&mut config);
     AnswerAgent::builder()
         .os("test-os".to_string())
         .shell(&Shell::detect_shell())
@@ -438,3 +480,636 @@ async fn provider_error_after_tool_call_retries_with_structured_history() {
     assert!(final_messages.iter().any(|message| message["role"] == "tool"));
     server.finish().await;
 }
+
+#[tokio::test]
+async fn tool_call_limit_forces_the_finalizer() {
+    let server = MockServer::start(vec![
+        tool_response(
+            "submit_commands",
+            r#"{"commands":[{"command":"printf first"}]}"#,
+        ),
+        tool_response(
+            "submit_commands",
+            r#"{"commands":[{"command":"printf second"}]}"#,
+        ),
+        text_response("forced answer"),
+    ])
+    .await;
+    let agent = make_agent_with(&server.base_url, |config| {
+        config.agent.limits.max_tool_calls = 1;
+    });
+    let response = agent
+        .resolve()
+        .prompt("keep calling tools".to_string())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(response.final_text, "forced answer");
+    assert_eq!(response.commands.len(), 1);
+    assert_eq!(response.commands[0].command, "printf first");
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 3);
+    assert!(requests[2]["tools"].as_array().is_none_or(Vec::is_empty));
+    assert!(requests[2]["messages"].to_string().contains("max_tool_calls"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn turn_limit_forces_the_finalizer() {
+    let server = MockServer::start(vec![
+        tool_response(
+            "submit_commands",
+            r#"{"commands":[{"command":"printf once"}]}"#,
+        ),
+        text_response("forced after one turn"),
+    ])
+    .await;
+    let agent = make_agent_with(&server.base_url, |config| {
+        config.agent.limits.max_turns = 1;
+    });
+    let response = agent
+        .resolve()
+        .prompt("loop forever".to_string())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(response.final_text, "forced after one turn");
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|message| message["role"] == "tool"));
+    server.finish().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_explore_results_keep_call_order() {
+    let server = MockServer::start(vec![
+        tool_calls_response(&[
+            ("call-1", "explore", r#"{"command":"sleep 1; echo first"}"#),
+            ("call-2", "explore", r#"{"command":"sleep 1; echo second"}"#),
+        ]),
+        text_response("both finished"),
+    ])
+    .await;
+    let agent = make_agent_with(&server.base_url, |config| {
+        config.agent.use_tool_explore = true;
+        config.agent.tool_concurrency = 2;
+    });
+    let started_at = std::time::Instant::now();
+    let response = agent
+        .resolve()
+        .prompt("wait twice".to_string())
+        .call()
+        .await
+        .unwrap();
+    // 两条 `sleep 1` 依次执行至少需要 2 秒.
+    let elapsed = started_at.elapsed();
+    assert!(
+        elapsed < Duration::from_millis(1800),
+        "explore calls did not run concurrently: {elapsed:?}"
+    );
+    assert_eq!(response.final_text, "both finished");
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 2);
+    let tool_messages = requests[1]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|message| message["role"] == "tool")
+        .collect::<Vec<_>>();
+    let ids = tool_messages
+        .iter()
+        .map(|message| message["tool_call_id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["call-1", "call-2"]);
+    assert!(tool_messages[0]["content"].to_string().contains("first"));
+    assert!(tool_messages[1]["content"].to_string().contains("second"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn custom_tools_are_declared_and_return_command_results() {
+    let server = MockServer::start(vec![
+        tool_response("deploy-status", r#"{"service":"api"}"#),
+        tool_response("deploy-status", r#"{"service":7}"#),
+        text_response("status checked"),
+    ])
+    .await;
+    let agent = make_agent_with(&server.base_url, |config| {
+        config.agent.tools = vec![
+            toml::from_str(
+                r#"
+                name = "deploy-status"
+                description = "Show the deployment status of a service."
+                command = "printf '%s' {{service}}"
+                parameters = { type = "object", required = ["service"], properties = { service = { type = "string" } } }
+                "#,
+            )
+            .unwrap(),
+        ];
+    });
+    let response = agent
+        .resolve()
+        .prompt("is the api deployed?".to_string())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(response.final_text, "status checked");
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 3);
+    assert!(requests[0]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|tool| tool["function"]["name"] == "deploy-status"));
+    let tool_messages = requests[2]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|message| message["role"] == "tool")
+        .map(|message| message["content"].to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(tool_messages.len(), 2);
+    assert!(tool_messages.iter().all(|content| content.contains("status")));
+    assert!(tool_messages[1].contains("must be of type string"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn favorites_are_searchable_by_the_agent() {
+    let dir = std::env::temp_dir().join(format!("howlto-harness-favorites-{}", uuid::Uuid::new_v4()));
+    let store = FavoriteStore::new(&dir);
+    let mut favorites = Favorites::default();
+    favorites.upsert(Favorite {
+        command: "kubectl --context prod get pods -A".into(),
+        description: "team canonical pod listing".into(),
+        prompt: "list pods".into(),
+    });
+    store.save(&favorites).await.unwrap();
+    let server = MockServer::start(vec![
+        tool_response("search_favorites", r#"{"query":"pods"}"#),
+        text_response("use the favorite"),
+    ])
+    .await;
+    let mut config = AppConfig::default();
+    config.llm.base_url = server.base_url.clone();
+    config.llm.api_key = "test-key".to_string();
+    config.llm.model = "mock".to_string();
+    config.agent.use_tool_explore = false;
+    config.agent.use_tool_elevate = false;
+    let agent = AnswerAgent::builder()
+        .os("test-os".to_string())
+        .shell(&Shell::detect_shell())
+        .profile(AnswerProfile::default())
+        .config(config)
+        .favorites(store)
+        .build()
+        .unwrap();
+    let response = agent
+        .resolve()
+        .prompt("show all pods".to_string())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(response.final_text, "use the favorite");
+    let requests = server.requests().await;
+    assert!(requests[0]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|tool| tool["function"]["name"] == "search_favorites"));
+    assert!(requests[0]["messages"][0]["content"]
+        .to_string()
+        .contains("search_favorites"));
+    let tool_message = requests[1]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|message| message["role"] == "tool")
+        .unwrap()
+        .to_string();
+    assert!(tool_message.contains("kubectl --context prod get pods -A"));
+    server.finish().await;
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+}
+
+#[tokio::test]
+async fn hook_scripts_rewrite_prompts_tools_candidates_and_answers() {
+    let server = MockServer::start(vec![
+        tool_response("elevate", r#"{"command":"sudo reboot"}"#),
+        tool_response(
+            "submit_commands",
+            r#"{"commands":[{"command":"ls"},{"command":"rm -rf build"}]}"#,
+        ),
+        text_response("done"),
+    ])
+    .await;
+    let script = std::env::temp_dir().join(format!("howlto-hook-{}.rhai", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &script,
+        r#"
+        fn before_prompt(prompt) { prompt + "\nPrefer long options." }
+        fn before_tool(tool, args) {
+            if tool == "elevate" && args.command.starts_with("sudo") {
+                return "sudo is not allowed here.";
+            }
+            if tool == "submit_commands" {
+                args.commands.push(#{ command: "pwd" });
+                return args;
+            }
+        }
+        fn after_submit(commands) {
+            commands
+                .filter(|item| !item.command.starts_with("rm"))
+                .map(|item| { item.description = "[reviewed]"; item })
+        }
+        fn after_response(text) { text + " (house rules applied)" }
+        "#,
+    )
+    .unwrap();
+    let agent = make_agent_with(&server.base_url, |config| {
+        config.agent.use_tool_elevate = true;
+        config.agent.hooks = vec![script.clone()];
+    });
+    let response = agent
+        .resolve()
+        .prompt("clean up".to_string())
+        .call()
+        .await
+        .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    assert_eq!(response.final_text, "done (house rules applied)");
+    assert_eq!(
+        response
+            .commands
+            .iter()
+            .map(|item| (item.command.as_str(), item.description.as_str()))
+            .collect::<Vec<_>>(),
+        [("ls", "[reviewed]"), ("pwd", "[reviewed]")]
+    );
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 3);
+    assert!(requests[0]["messages"].to_string().contains("Prefer long options."));
+    let denial = requests[1]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|message| message["role"] == "tool")
+        .unwrap()["content"]
+        .to_string();
+    assert!(denial.contains(r#"\"by\":\"hook\""#));
+    assert!(denial.contains("sudo is not allowed here."));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn doctor_checks_api_and_model() {
+    let models = json!({"object": "list", "data": [{"id": "mock"}, {"id": "other"}]}).to_string();
+    let server = MockServer::start_with_status(vec![
+        (200, models.clone()),
+        (200, models),
+        (401, r#"{"error": "invalid key"}"#.to_string()),
+    ])
+    .await;
+    let mut llm = AppConfig::default().llm;
+    llm.base_url = server.base_url.clone();
+    llm.api_key = "test-key".to_string();
+    llm.model = "mock".to_string();
+
+    let checks = doctor::check_api(&llm).await;
+    assert_eq!(
+        checks.iter().map(|check| check.status).collect::<Vec<_>>(),
+        [doctor::Status::Ok, doctor::Status::Ok]
+    );
+
+    llm.model = "missing".to_string();
+    let checks = doctor::check_api(&llm).await;
+    assert_eq!(checks[1].status, doctor::Status::Fail);
+    assert!(checks[1].detail.contains("missing"));
+
+    let checks = doctor::check_api(&llm).await;
+    assert_eq!(checks.len(), 1);
+    assert_eq!(checks[0].status, doctor::Status::Fail);
+    assert!(checks[0].detail.contains("401"));
+    server.finish().await;
+}
+
+fn make_transform_agent(base_url: &str, chunk_chars: usize) -> TransformAgent {
+    let mut config = AppConfig::default();
+    config.llm.base_url = base_url.to_string();
+    config.llm.api_key = "test-key".to_string();
+    config.llm.model = "mock".to_string();
+    config.agent.transform.chunk_chars = chunk_chars;
+    TransformAgent::builder()
+        .os("test-os".to_string())
+        .shell(&Shell::detect_shell())
+        .profile(Profiles::default().get(TRANSFORM_PROFILE).unwrap().clone())
+        .config(config)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn transform_streams_chunks_in_order_without_fences_or_tools() {
+    let server = MockServer::start(vec![
+        text_response("```python\nprint(1)\n```"),
+        text_response("print(2)"),
+    ])
+    .await;
+    let agent = make_transform_agent(&server.base_url, 12);
+    let mut output = Vec::new();
+    agent
+        .transform("convert to python code", "puts(\"1\");\nputs(\"2\");\n", &mut output)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "print(1)\nprint(2)\n");
+
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 2);
+    for (index, request) in requests.iter().enumerate() {
+        assert!(request.get("tools").is_none_or(|tools| tools.as_array().unwrap().is_empty()));
+        let request = request.to_string();
+        assert!(request.contains(&format!("part {} of 2", index + 1)));
+        assert!(!request.contains("submit_commands"));
+    }
+    assert!(requests[0].to_string().contains(r#"puts(\"1\")"#));
+    assert!(requests[1].to_string().contains(r#"puts(\"2\")"#));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn transform_fails_on_provider_errors_and_empty_results() {
+    let server = MockServer::start_with_status(vec![(400, "bad request".to_string())]).await;
+    let agent = make_transform_agent(&server.base_url, 0);
+    let mut output = Vec::new();
+    assert!(agent.transform("upper case", "a\n", &mut output).await.is_err());
+    assert!(output.is_empty());
+    server.finish().await;
+
+    let server = MockServer::start(vec![text_response("```\n```")]).await;
+    let agent = make_transform_agent(&server.base_url, 0);
+    let error = agent
+        .transform("upper case", "a\n", &mut output)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("empty result"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn translate_keeps_code_blocks_and_chunk_order() {
+    let server = MockServer::start(vec![
+        text_response("\n# Build\n\nRun the command:\n"),
+        text_response("Done."),
+    ])
+    .await;
+    let mut config = AppConfig::default();
+    config.llm.base_url = server.base_url.clone();
+    config.llm.api_key = "test-key".to_string();
+    config.llm.model = "mock".to_string();
+    config.agent.language = "English".to_string();
+    config.agent.transform.chunk_chars = 16;
+    let agent = TranslateAgent::builder()
+        .os("test-os".to_string())
+        .shell(&Shell::detect_shell())
+        .profile(Profiles::default().get(TRANSLATE_PROFILE).unwrap().clone())
+        .config(config)
+        .build()
+        .unwrap();
+    let mut output = Vec::new();
+    agent
+        .translate(
+            "# 构建\n\n运行命令:\n\n```sh\n# 构建项目\ncargo build\n```\n\n完成.\n",
+            "",
+            &mut output,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "# Build\n\nRun the command:\n\n```sh\n# 构建项目\ncargo build\n```\n\nDone.\n"
+    );
+
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 2);
+    let first = requests[0].to_string();
+    assert!(first.contains("Translate the input into English"));
+    assert!(first.contains("The source language is Chinese."));
+    assert!(first.contains("part 1 of 2"));
+    assert!(first.contains("运行命令"));
+    assert!(!first.contains("cargo build"));
+    assert!(requests[1].to_string().contains("完成."));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn eval_scores_cases_against_the_mock_server() {
+    let server = MockServer::start(vec![
+        tool_response(
+            "submit_commands",
+            r#"{"commands":[{"command":"du -sh * | sort -h","description":"by size"}]}"#,
+        ),
+        text_response("Sorted by size."),
+        text_response("Use rm -rf to clean up."),
+    ])
+    .await;
+    let dir = std::env::temp_dir().join(format!("howlto-eval-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let suite_path = dir.join("suite.toml");
+    std::fs::write(
+        &suite_path,
+        r#"
+profile = "command"
+shell = "sh"
+
+[[cases]]
+name = "sizes"
+prompt = "list files by size"
+expect = { commands = ["^du "], text = ["size"], max_commands = 1, max_tool_calls = 0 }
+
+[[cases]]
+name = "cleanup"
+prompt = "clean the build directory"
+profile = "chat"
+expect = { forbid = ["rm -rf"], min_commands = 1 }
+"#,
+    )
+    .unwrap();
+    let suite = eval::Suite::load(&suite_path).await.unwrap();
+    let mut config = AppConfig::default();
+    config.llm.base_url = server.base_url.clone();
+    config.llm.api_key = "test-key".to_string();
+    config.llm.model = "mock".to_string();
+    config.agent.use_tool_explore = false;
+    config.agent.use_tool_elevate = false;
+    let report = eval::run()
+        .suite(&suite)
+        .config(&config)
+        .profiles(&Profiles::default())
+        .shell(&Shell::detect_shell())
+        .call()
+        .await;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((report.passed, report.failed), (1, 1));
+    let sizes = &report.cases[0];
+    assert!(sizes.passed, "{report}");
+    assert_eq!(sizes.profile, "command");
+    assert_eq!(sizes.shell, "sh");
+    assert_eq!(sizes.commands, ["du -sh * | sort -h"]);
+    assert_eq!(sizes.tool_calls.get("submit_commands"), Some(&1));
+    let cleanup = &report.cases[1];
+    assert_eq!(cleanup.profile, "chat");
+    let failed = cleanup
+        .checks
+        .iter()
+        .filter(|check| !check.passed)
+        .map(|check| check.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(failed, ["forbid /rm -rf/", "commands"]);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["cases"][1]["passed"], json!(false));
+    assert!(report.to_string().contains("1 个通过, 1 个失败"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn completion_scripts_are_fixed_until_the_syntax_check_passes() {
+    let Some(bash) = Shell::from_name("bash") else {
+        return;
+    };
+    let server = MockServer::start(vec![
+        text_response("```bash\n_deployctl() {\n  COMPREPLY=(\n```"),
+        text_response("Fixed:\n```bash\n_deployctl() { COMPREPLY=(status rollout); }\ncomplete -F _deployctl deployctl\n```"),
+    ])
+    .await;
+    let mut config = AppConfig::default();
+    config.llm.base_url = server.base_url.clone();
+    config.llm.api_key = "test-key".to_string();
+    config.llm.model = "mock".to_string();
+    config.agent.use_tool_explore = false;
+    let profile = Profiles::default().get(COMPLETION_PROFILE).unwrap().clone();
+    let agent = CompletionAgent::builder()
+        .os("test-os".to_string())
+        .shell(&bash)
+        .profile(profile)
+        .config(config)
+        .build()
+        .unwrap();
+    let script = agent.generate("deployctl").await.unwrap();
+    assert_eq!(
+        script,
+        "_deployctl() { COMPREPLY=(status rollout); }\ncomplete -F _deployctl deployctl\n"
+    );
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].to_string().contains("bash completion script for `deployctl`"));
+    assert!(requests[1].to_string().contains("failed the bash syntax check"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn explain_asks_the_model_only_for_parts_without_local_docs() {
+    let Some(bash) = Shell::from_name("bash") else {
+        return;
+    };
+    let server = MockServer::start(vec![text_response(
+        "```json\n{\"1\": \"deployment tool\", \"2\": \"roll out to canary hosts first\"}\n```",
+    )])
+    .await;
+    let mut config = AppConfig::default();
+    config.llm.base_url = server.base_url.clone();
+    config.llm.api_key = "test-key".to_string();
+    config.llm.model = "mock".to_string();
+    let agent = ExplainAgent::builder()
+        .shell(&bash)
+        .config(config)
+        .build()
+        .unwrap();
+    let explanation = agent
+        .explain("deployctl-missing --canary > out.log")
+        .await
+        .unwrap()
+        .to_string();
+    assert!(explanation.contains("deployctl-missing  deployment tool  [model]"));
+    assert!(explanation.contains("roll out to canary hosts first  [model]"));
+    assert!(explanation.contains("> out.log"));
+    assert!(explanation.contains("write standard output to the file, truncating it  [shell]"));
+    let requests = server.requests().await;
+    assert_eq!(requests.len(), 1);
+    let request = requests[0].to_string();
+    assert!(request.contains("1. `deployctl-missing` (program)"));
+    assert!(request.contains("2. `--canary` (option of `deployctl-missing`)"));
+    assert!(!request.contains("out.log`"));
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn prompts_starting_with_mode_names_reach_the_agent() {
+    for prompt in [
+        "eval how to use eval in bash",
+        "completion for git",
+        "doctor a corrupted ext4 superblock",
+    ] {
+        let server = MockServer::start(vec![text_response("Use the builtin.")]).await;
+        let dir = std::env::temp_dir().join(format!("howlto-cli-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_howlto"))
+            .arg("-c")
+            .arg(&dir)
+            .arg("-pq")
+            .args(prompt.split(' '))
+            .env("HOWLTO_BASE_URL", &server.base_url)
+            .env("HOWLTO_API_KEY", "test-key")
+            .env("HOWLTO_MODEL", "mock")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(
+            output.status.success(),
+            "{prompt}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Use the builtin."));
+        let requests = server.requests().await;
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].to_string().contains(prompt));
+        server.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn explain_works_without_an_api_key() {
+    let dir = std::env::temp_dir().join(format!("howlto-cli-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_howlto"))
+        .arg("-c")
+        .arg(&dir)
+        .arg("-q")
+        .arg("--explain")
+        .arg("( cd / && ls ) | wc -l")
+        .env_remove("HOWLTO_BASE_URL")
+        .env_remove("OPENAI_BASE_URL")
+        .env_remove("HOWLTO_API_KEY")
+        .env_remove("OPENAI_API_KEY")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("( cd / && ls ) | wc -l\n├── ( ... )"), "{stdout}");
+    assert!(stdout.contains("└── wc"), "{stdout}");
+}